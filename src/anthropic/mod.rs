@@ -0,0 +1,510 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::provider::{AuthScheme, Provider, StreamDecoder, env_api_key, known_endpoints};
+use crate::schema::{
+    ApiResponse, ChatCompletionChunk, Choice, ChunkChoice, Delta, Message, PromptTokensDetails, Usage,
+};
+use crate::{ContentPart, MessageContent, RequestPayload};
+
+/// Version of the Anthropic Messages API sent in the `anthropic-version`
+/// header.
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Header carrying the API key for the Anthropic Messages API.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Header carrying the API version for the Anthropic Messages API.
+pub const VERSION_HEADER: &str = "anthropic-version";
+
+/// Provider for the Anthropic Messages API.
+///
+/// Requests are translated with [`MessagesRequest::from_payload`] and
+/// authenticated with the `x-api-key` and `anthropic-version` headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anthropic {
+    name: String,
+    url: String,
+    api_key_env: String,
+    auth_scheme: AuthScheme,
+    headers: Vec<(String, String)>,
+}
+
+impl Default for Anthropic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Anthropic {
+    /// Creates the built-in Anthropic provider.
+    pub fn new() -> Self {
+        Self::with_endpoint("anthropic", known_endpoints("anthropic"), env_api_key("anthropic"))
+    }
+
+    /// Creates a provider for another endpoint speaking the Messages API,
+    /// such as a gateway.
+    ///
+    /// # Arguments
+    /// * `name` - The endpoint name
+    /// * `url` - The Messages API URL
+    /// * `api_key_env` - The environment variable holding the API token
+    pub fn with_endpoint(name: impl Into<String>, url: impl Into<String>, api_key_env: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            api_key_env: api_key_env.into(),
+            auth_scheme: AuthScheme::Header(API_KEY_HEADER.to_owned()),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Sets how the API token is attached to requests (defaults to the
+    /// `x-api-key` header).
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Sets headers sent with every request, besides authentication and the
+    /// `anthropic-version` header.
+    #[must_use]
+    pub fn with_headers(mut self, headers: Vec<(String, String)>) -> Self {
+        self.headers = headers;
+        self
+    }
+}
+
+impl Provider for Anthropic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn api_key_env(&self) -> &str {
+        &self.api_key_env
+    }
+
+    fn auth_scheme(&self) -> AuthScheme {
+        self.auth_scheme.clone()
+    }
+
+    fn default_headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![(VERSION_HEADER.to_owned(), ANTHROPIC_VERSION.to_owned())];
+        headers.extend(self.headers.iter().cloned());
+        headers
+    }
+
+    fn serialize_request(&self, payload: &RequestPayload) -> Result<Value> {
+        let request = MessagesRequest::from_payload(payload)?;
+        serde_json::to_value(request).context("Failed to serialize request payload")
+    }
+
+    fn deserialize_response(&self, body: &str) -> Result<ApiResponse> {
+        let response: MessagesResponse =
+            serde_json::from_str(body).context("Failed to parse JSON response from the API.")?;
+        Ok(response.into_api_response())
+    }
+
+    fn stream_decoder(&self) -> Box<dyn StreamDecoder> {
+        Box::new(StreamTranslator::new())
+    }
+}
+
+/// A single message in a Messages API request.
+///
+/// Only "user" and "assistant" roles are allowed; system instructions are sent
+/// through the top-level `system` field of [`MessagesRequest`]. The content is
+/// a string, or an array of `text` and `image` blocks.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MessagesMessage {
+    pub role: String,
+    pub content: Value,
+}
+
+/// The request payload sent to the Anthropic Messages API.
+///
+/// # Fields
+/// * `model` - Identifier of the model to use for generation
+/// * `system` - Optional system prompt
+/// * `messages` - Conversation turns, starting with a user message
+/// * `max_tokens` - Maximum number of tokens to generate (required by the API)
+/// * `stream` - Whether to stream the response as Server-Sent Events
+/// * `temperature` - Optional sampling temperature
+/// * `top_p` - Optional nucleus sampling probability mass
+/// * `stop_sequences` - Optional sequences that end the generation
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MessagesRequest {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub messages: Vec<MessagesMessage>,
+    pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+}
+
+impl MessagesRequest {
+    /// Translates a chat completion payload into a Messages API request.
+    ///
+    /// Messages with the "system" role, as well as an "assistant" prompt sent
+    /// before the first user message, are moved into the top-level `system`
+    /// field. The token limit is taken from either `max_tokens` or
+    /// `max_completion_tokens`, and `stop` is sent as `stop_sequences`.
+    ///
+    /// # Arguments
+    /// * `payload` - The chat completion payload to translate
+    ///
+    /// # Returns
+    /// * `Ok(MessagesRequest)` - The translated request
+    /// * `Err` - An error if the payload has no token limit or asks for a
+    ///   structured output, tools, a tool choice, audio input or a sampling
+    ///   parameter that are not supported here
+    pub fn from_payload(payload: &RequestPayload) -> Result<Self> {
+        let Some(max_tokens) = payload.max_tokens.or(payload.max_completion_tokens) else {
+            bail!("The Anthropic Messages API requires a token limit");
+        };
+        if payload.response_format.is_some() {
+            bail!("Structured output with a JSON schema is not supported by the Anthropic Messages API");
+        }
+        if payload.tools.is_some() {
+            bail!("Tool calling is not supported by the Anthropic Messages API");
+        }
+        if payload.tool_choice.is_some() {
+            bail!("The tool_choice parameter is not supported by the Anthropic Messages API");
+        }
+        let sampling = &payload.sampling;
+        let unsupported = [
+            ("seed", sampling.seed.is_some()),
+            ("presence_penalty", sampling.presence_penalty.is_some()),
+            ("frequency_penalty", sampling.frequency_penalty.is_some()),
+            ("n", sampling.n.is_some_and(|n| n != 1)),
+            ("logit_bias", sampling.logit_bias.is_some()),
+            ("reasoning_effort", sampling.reasoning_effort.is_some()),
+        ];
+        if let Some((name, _)) = unsupported.iter().find(|(_, set)| *set) {
+            bail!("The {name} parameter is not supported by the Anthropic Messages API");
+        }
+
+        let mut system = Vec::new();
+        let mut messages = Vec::new();
+        for message in &payload.messages {
+            let is_prompt = message.role == "assistant" && messages.is_empty();
+            if message.role == "system" || is_prompt {
+                system.push(message.content.as_text().into_owned());
+            } else {
+                messages.push(MessagesMessage {
+                    role: message.role.clone(),
+                    content: content_blocks(&message.content)?,
+                });
+            }
+        }
+
+        Ok(Self {
+            model: payload.model.to_owned(),
+            system: (!system.is_empty()).then(|| system.join("\n\n")),
+            messages,
+            max_tokens,
+            stream: payload.stream,
+            temperature: sampling.temperature,
+            top_p: sampling.top_p,
+            stop_sequences: sampling.stop.clone(),
+        })
+    }
+}
+
+/// Translates a message content into the Messages API form.
+///
+/// Images given as `data:` URIs become `base64` image sources, other images
+/// `url` sources. Audio parts are rejected.
+fn content_blocks(content: &MessageContent) -> Result<Value> {
+    let parts = match content {
+        MessageContent::Text(text) => return Ok(Value::String(text.clone())),
+        MessageContent::Parts(parts) => parts,
+    };
+
+    let blocks = parts
+        .iter()
+        .map(|part| {
+            Ok(match part {
+                ContentPart::Text { text } => serde_json::json!({"type": "text", "text": text}),
+                ContentPart::InputAudio { .. } => bail!("Audio input is not supported by the Anthropic Messages API"),
+                ContentPart::ImageUrl { image_url } => {
+                    let source = match image_url
+                        .url
+                        .strip_prefix("data:")
+                        .and_then(|uri| uri.split_once(";base64,"))
+                    {
+                        Some((media_type, data)) => {
+                            serde_json::json!({"type": "base64", "media_type": media_type, "data": data})
+                        },
+                        None => serde_json::json!({"type": "url", "url": image_url.url}),
+                    };
+                    serde_json::json!({"type": "image", "source": source})
+                },
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Value::Array(blocks))
+}
+
+/// A content block of a Messages API response.
+///
+/// Only `text` blocks carry generated text; other block types (such as
+/// `thinking` or `tool_use`) are kept but ignored when building the output.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ContentBlock {
+    pub r#type: String,
+    pub text: Option<String>,
+}
+
+/// Token usage statistics reported by the Messages API.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct MessagesUsage {
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_creation_input_tokens: Option<i64>,
+    pub cache_read_input_tokens: Option<i64>,
+}
+
+impl MessagesUsage {
+    /// Converts the usage into the chat completion [`Usage`] shape.
+    ///
+    /// Cached and cache-creation input tokens are counted as prompt tokens,
+    /// with the cache reads also reported as `cached_tokens`.
+    pub fn to_usage(&self) -> Usage {
+        let cache_read = self.cache_read_input_tokens.unwrap_or_default();
+        let prompt_tokens = self.input_tokens + cache_read + self.cache_creation_input_tokens.unwrap_or_default();
+
+        Usage {
+            prompt_tokens,
+            completion_tokens: self.output_tokens,
+            total_tokens: prompt_tokens + self.output_tokens,
+            prompt_tokens_details: self.cache_read_input_tokens.map(|cached_tokens| {
+                PromptTokensDetails {
+                    cached_tokens: Some(cached_tokens),
+                    audio_tokens: None,
+                }
+            }),
+            completion_tokens_details: None,
+        }
+    }
+}
+
+/// Response structure from the Anthropic Messages API.
+///
+/// # Fields
+/// * `id` - Unique identifier for the message
+/// * `type` - Type of object returned (always "message")
+/// * `role` - Role of the generated message (always "assistant")
+/// * `model` - Identifier of the model that generated the response
+/// * `content` - Generated content blocks
+/// * `stop_reason` - Reason why the generation stopped
+/// * `usage` - Token usage statistics for the request
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct MessagesResponse {
+    pub id: String,
+    pub r#type: String,
+    pub role: String,
+    pub model: String,
+    pub content: Vec<ContentBlock>,
+    pub stop_reason: Option<String>,
+    pub usage: MessagesUsage,
+}
+
+impl MessagesResponse {
+    /// Converts the response into an [`ApiResponse`] with a single choice.
+    ///
+    /// The text of all `text` content blocks is concatenated into the message
+    /// content, and `stop_reason` is mapped to the equivalent chat completion
+    /// `finish_reason`.
+    pub fn into_api_response(self) -> ApiResponse {
+        let content = self
+            .content
+            .iter()
+            .filter(|block| block.r#type == "text")
+            .filter_map(|block| block.text.as_deref())
+            .collect::<String>();
+
+        ApiResponse {
+            id: self.id,
+            object: "chat.completion".to_owned(),
+            created: unix_timestamp(),
+            model: self.model,
+            choices: vec![Choice {
+                index: 0,
+                message: Message {
+                    role: if self.role.is_empty() {
+                        "assistant".to_owned()
+                    } else {
+                        self.role
+                    },
+                    content,
+                    ..Default::default()
+                },
+                finish_reason: self.stop_reason.as_deref().map(finish_reason).unwrap_or_default(),
+                ..Default::default()
+            }],
+            usage: self.usage.to_usage(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Maps a Messages API `stop_reason` to a chat completion `finish_reason`.
+///
+/// # Arguments
+/// * `stop_reason` - The stop reason reported by the Messages API
+///
+/// # Returns
+/// * The equivalent finish reason ("stop", "length", "tool_calls"), or the stop
+///   reason itself when there is no equivalent
+pub fn finish_reason(stop_reason: &str) -> String {
+    match stop_reason {
+        "end_turn" | "stop_sequence" => "stop",
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        "refusal" => "content_filter",
+        other => other,
+    }
+    .to_owned()
+}
+
+/// A single event of a streaming Messages API response.
+///
+/// Only the fields needed to rebuild the response are kept; events of other
+/// types (`ping`, `content_block_start`, ...) deserialize with empty fields.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct StreamEvent {
+    r#type: String,
+    message: Option<MessagesResponse>,
+    delta: Option<StreamDelta>,
+    usage: Option<MessagesUsage>,
+}
+
+/// The `delta` object of `content_block_delta` and `message_delta` events.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct StreamDelta {
+    text: Option<String>,
+    stop_reason: Option<String>,
+}
+
+/// Translates streaming Messages API events into chat completion chunks.
+///
+/// The Messages API reports input tokens in `message_start` and the final
+/// output tokens in `message_delta`, so the translator keeps the former to
+/// report the complete usage with the latter.
+#[derive(Debug, Default)]
+pub struct StreamTranslator {
+    id: String,
+    model: String,
+    usage: MessagesUsage,
+}
+
+impl StreamTranslator {
+    /// Creates a translator for a new stream.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Translates the data payload of a single SSE event.
+    ///
+    /// # Arguments
+    /// * `data` - The payload returned by [`crate::stream::SseParser::feed`]
+    ///
+    /// # Returns
+    /// * `Ok(Some(chunk))` - The equivalent chat completion chunk
+    /// * `Ok(None)` - The event carries nothing to report (`ping`,
+    ///   `message_stop`, ...)
+    /// * `Err` - The payload is not valid JSON or reports an error
+    pub fn translate(&mut self, data: &str) -> Result<Option<ChatCompletionChunk>> {
+        let event: StreamEvent = serde_json::from_str(data)?;
+
+        let chunk = match event.r#type.as_str() {
+            "message_start" => {
+                let message = event.message.unwrap_or_default();
+                self.id = message.id;
+                self.model = message.model;
+                self.usage = message.usage;
+                self.chunk(Delta {
+                    role: Some("assistant".to_owned()),
+                    content: None,
+                })
+            },
+            "content_block_delta" => {
+                let text = event.delta.and_then(|delta| delta.text);
+                if text.is_none() {
+                    return Ok(None);
+                }
+                self.chunk(Delta {
+                    role: None,
+                    content: text,
+                })
+            },
+            "message_delta" => {
+                if let Some(usage) = event.usage {
+                    self.usage.output_tokens = usage.output_tokens;
+                }
+                let mut chunk = self.chunk(Delta::default());
+                chunk.choices[0].finish_reason = event
+                    .delta
+                    .and_then(|delta| delta.stop_reason)
+                    .as_deref()
+                    .map(finish_reason);
+                chunk.usage = Some(self.usage.to_usage());
+                chunk
+            },
+            "error" => bail!("Anthropic stream reported an error: {data}"),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(chunk))
+    }
+
+    fn chunk(&self, delta: Delta) -> ChatCompletionChunk {
+        ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_owned(),
+            created: unix_timestamp(),
+            model: self.model.clone(),
+            choices: vec![ChunkChoice {
+                index: 0,
+                delta,
+                finish_reason: None,
+            }],
+            ..Default::default()
+        }
+    }
+}
+
+impl StreamDecoder for StreamTranslator {
+    fn decode(&mut self, data: &str) -> Result<Option<ChatCompletionChunk>> {
+        self.translate(data)
+    }
+}
+
+/// Returns the current Unix timestamp in seconds.
+fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| i64::try_from(elapsed.as_secs()).unwrap_or_default())
+        .unwrap_or_default()
+}
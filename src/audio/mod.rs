@@ -0,0 +1,336 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::Client;
+use reqwest::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::provider::Provider;
+use crate::retry::{RetryPolicy, is_retryable_error, is_retryable_status};
+use crate::schema::{PromptTokensDetails, Usage};
+use crate::{ContentPart, MessageContent};
+
+/// Maximum size of an audio file, as accepted by the `OpenAI` transcription
+/// endpoint.
+pub const MAX_AUDIO_SIZE: u64 = 25 * 1024 * 1024;
+
+/// Path of the transcription endpoint, replacing the chat completions one.
+pub const TRANSCRIPTIONS_PATH: &str = "/audio/transcriptions";
+
+/// Path of the chat completions endpoint.
+const CHAT_COMPLETIONS_PATH: &str = "/chat/completions";
+
+/// Response formats of the transcription endpoint.
+pub const RESPONSE_FORMATS: &[&str] = &["json", "text", "srt", "verbose_json", "vtt"];
+
+/// Timestamp granularities of the transcription endpoint.
+pub const TIMESTAMP_GRANULARITIES: &[&str] = &["word", "segment"];
+
+/// Reads an audio file, checking its size.
+fn read_audio(path: &Path) -> Result<Vec<u8>> {
+    let size = fs::metadata(path)
+        .with_context(|| format!("Failed to read audio file {}", path.display()))?
+        .len();
+    if size > MAX_AUDIO_SIZE {
+        bail!(
+            "Audio file {} is too large ({size} bytes, the limit is {MAX_AUDIO_SIZE} bytes)",
+            path.display()
+        );
+    }
+
+    fs::read(path).with_context(|| format!("Failed to read audio file {}", path.display()))
+}
+
+/// Detects the format of an audio file from its first bytes.
+///
+/// # Arguments
+/// * `bytes` - The content of the audio file
+///
+/// # Returns
+/// * `Some(&str)` - `"wav"` or `"mp3"`, the formats accepted in `input_audio`
+///   content parts
+/// * `None` - If the format is not one of these
+pub fn detect_audio_format(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("wav"),
+        [b'I', b'D', b'3', ..] => Some("mp3"),
+        [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some("mp3"),
+        _ => None,
+    }
+}
+
+/// Creates the `input_audio` part for an audio file.
+///
+/// # Arguments
+/// * `path` - Path of a WAV or MP3 file
+///
+/// # Returns
+/// * `Ok(ContentPart)` - The part holding the base64-encoded audio
+/// * `Err` - An error if the file could not be read, is larger than
+///   [`MAX_AUDIO_SIZE`] or is neither WAV nor MP3
+pub fn audio_part(path: &Path) -> Result<ContentPart> {
+    let bytes = read_audio(path)?;
+    let Some(format) = detect_audio_format(&bytes) else {
+        bail!(
+            "Unsupported audio format for {}: WAV and MP3 are supported",
+            path.display()
+        );
+    };
+
+    Ok(ContentPart::input_audio(STANDARD.encode(&bytes), format))
+}
+
+/// Appends audio files to the content of a user input.
+///
+/// # Arguments
+/// * `content` - The user input
+/// * `paths` - Paths of WAV or MP3 files
+///
+/// # Returns
+/// * `Ok(MessageContent)` - The content unchanged without audio, parts with the
+///   audio appended otherwise
+/// * `Err` - An error if an audio file could not be embedded
+pub fn append_audio(content: MessageContent, paths: &[impl AsRef<Path>]) -> Result<MessageContent> {
+    if paths.is_empty() {
+        return Ok(content);
+    }
+
+    let mut parts = content.into_parts();
+    for path in paths {
+        parts.push(audio_part(path.as_ref())?);
+    }
+
+    Ok(MessageContent::Parts(parts))
+}
+
+/// Returns the transcription URL of an endpoint.
+///
+/// # Arguments
+/// * `url` - The chat completions URL of the endpoint, or a transcription URL
+///
+/// # Returns
+/// * `Ok(String)` - The URL ending with [`TRANSCRIPTIONS_PATH`]
+/// * `Err` - An error if the URL is neither
+pub fn transcription_url(url: &str) -> Result<String> {
+    if url.ends_with(TRANSCRIPTIONS_PATH) {
+        return Ok(url.to_owned());
+    }
+    match url.strip_suffix(CHAT_COMPLETIONS_PATH) {
+        Some(base) => Ok(format!("{base}{TRANSCRIPTIONS_PATH}")),
+        None => {
+            bail!(
+                "Cannot derive the transcription URL from {url}: pass a URL ending with {TRANSCRIPTIONS_PATH} as the \
+                 endpoint"
+            )
+        },
+    }
+}
+
+/// The settings of a transcription request.
+///
+/// # Fields
+/// * `model` - The transcription model (e.g. "whisper-1", "gpt-4o-transcribe")
+/// * `language` - Optional ISO-639-1 language of the audio
+/// * `prompt` - Optional text guiding the style or vocabulary
+/// * `response_format` - Optional format of the result, one of
+///   [`RESPONSE_FORMATS`] (`json` by default)
+/// * `timestamp_granularities` - Timestamps to include, from
+///   [`TIMESTAMP_GRANULARITIES`] (requires `verbose_json`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TranscriptionRequest {
+    pub model: String,
+    pub language: Option<String>,
+    pub prompt: Option<String>,
+    pub response_format: Option<String>,
+    pub timestamp_granularities: Vec<String>,
+}
+
+impl TranscriptionRequest {
+    /// Builds the multipart form of the request.
+    fn form(&self, file_name: &str, bytes: Vec<u8>) -> Form {
+        let mut form = Form::new()
+            .part("file", Part::bytes(bytes).file_name(file_name.to_owned()))
+            .text("model", self.model.clone());
+        for (name, value) in [
+            ("language", &self.language),
+            ("prompt", &self.prompt),
+            ("response_format", &self.response_format),
+        ] {
+            if let Some(value) = value {
+                form = form.text(name, value.clone());
+            }
+        }
+        for granularity in &self.timestamp_granularities {
+            form = form.text("timestamp_granularities[]", granularity.clone());
+        }
+
+        form
+    }
+}
+
+/// Token usage reported by token-billed transcription models.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct TranscriptionUsage {
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub total_tokens: i64,
+    pub input_token_details: Option<InputTokenDetails>,
+}
+
+/// Breakdown of the input tokens of a transcription.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct InputTokenDetails {
+    pub text_tokens: Option<i64>,
+    pub audio_tokens: Option<i64>,
+}
+
+impl TranscriptionUsage {
+    /// Converts the usage into the chat completion [`Usage`] shape, with the
+    /// audio input tokens in the prompt token details.
+    pub fn to_usage(&self) -> Usage {
+        let audio_tokens = self
+            .input_token_details
+            .as_ref()
+            .and_then(|details| details.audio_tokens);
+
+        Usage {
+            prompt_tokens: self.input_tokens,
+            completion_tokens: self.output_tokens,
+            total_tokens: self.total_tokens,
+            prompt_tokens_details: audio_tokens.map(|audio_tokens| {
+                PromptTokensDetails {
+                    audio_tokens: Some(audio_tokens),
+                    ..Default::default()
+                }
+            }),
+            completion_tokens_details: None,
+        }
+    }
+}
+
+/// A `json` or `verbose_json` transcription result.
+///
+/// # Fields
+/// * `text` - The transcribed text
+/// * `usage` - Token usage, for token-billed models (duration-billed models
+///   report a duration instead, which is ignored)
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Transcription {
+    pub text: String,
+    #[serde(default, deserialize_with = "token_usage")]
+    pub usage: Option<TranscriptionUsage>,
+}
+
+/// Deserializes the usage of a transcription, keeping only token counts.
+fn token_usage<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<TranscriptionUsage>, D::Error> {
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+
+    Ok(value
+        .filter(|usage| usage["type"] == "tokens")
+        .and_then(|usage| serde_json::from_value(usage).ok()))
+}
+
+/// A client of the transcription endpoint.
+pub struct Transcriber {
+    http: Client,
+    url: String,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+}
+
+impl Transcriber {
+    /// Creates a client for the transcription endpoint of a provider.
+    ///
+    /// # Arguments
+    /// * `provider` - The provider, whose URL and authentication are used
+    /// * `api_token` - The API token
+    /// * `timeout` - Time limit for a request
+    /// * `retry_policy` - Policy for retrying transient failures
+    ///
+    /// # Returns
+    /// * `Ok(Transcriber)` - The client
+    /// * `Err` - An error if the transcription URL could not be derived
+    pub fn new(provider: &dyn Provider, api_token: &str, timeout: Duration, retry_policy: RetryPolicy) -> Result<Self> {
+        Ok(Self {
+            http: Client::new(),
+            url: transcription_url(provider.url())?,
+            headers: provider.headers(api_token),
+            timeout,
+            retry_policy,
+        })
+    }
+
+    /// Returns the URL requests are sent to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Uploads an audio file and returns the raw transcription.
+    ///
+    /// Transient failures are retried according to the retry policy.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the audio file
+    /// * `request` - The transcription settings
+    ///
+    /// # Returns
+    /// * `Ok(String)` - The response body, in the requested format
+    /// * `Err` - An error if the file could not be read or the request failed
+    pub async fn transcribe(&self, path: &Path, request: &TranscriptionRequest) -> Result<String> {
+        let bytes = read_audio(path)?;
+        let file_name = path
+            .file_name()
+            .map_or_else(|| "audio".to_owned(), |name| name.to_string_lossy().into_owned());
+        info!("Transcribing {} with model '{}'", path.display(), request.model);
+        info!("With URL: '{}'", self.url);
+
+        let mut retry = 0;
+        loop {
+            let mut http_request = self
+                .http
+                .post(&self.url)
+                .timeout(self.timeout)
+                .multipart(request.form(&file_name, bytes.clone()));
+            for (name, value) in &self.headers {
+                http_request = http_request.header(name, value);
+            }
+
+            let retries_left = retry < self.retry_policy.max_retries;
+            let (error, delay) = match http_request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(response.text().await?),
+                Ok(response) => {
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    let body = response.text().await?;
+                    if !retries_left || !is_retryable_status(status) {
+                        bail!("Transcription request failed with status {status}: {body}");
+                    }
+                    (
+                        format!("status {status}"),
+                        self.retry_policy.delay(retry, Some(&headers)),
+                    )
+                },
+                Err(error) => {
+                    if !retries_left || !is_retryable_error(&error) {
+                        return Err(error).context("Failed to send transcription request");
+                    }
+                    (error.to_string(), self.retry_policy.delay(retry, None))
+                },
+            };
+
+            retry += 1;
+            warn!(
+                "Attempt {retry} of {} failed ({error}), retrying in {delay:.2?}",
+                self.retry_policy.max_retries + 1
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
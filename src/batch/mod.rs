@@ -0,0 +1,241 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::pricing::Cost;
+use crate::schema::Usage;
+use crate::{InvokeClient, RequestMessage, RequestPayload, ResponseFormat, TokenLimitField};
+
+/// A single line of a batch input file.
+///
+/// Each line carries its own conversation and may override the model, the
+/// token limit and the structured output schema of the client.
+///
+/// # Fields
+/// * `custom_id` - Identifier copied to the result (defaults to `line-N`)
+/// * `messages` - The message history to send
+/// * `model` - Optional model overriding the client's model
+/// * `max_tokens` - Optional token limit sent as `max_tokens`
+/// * `max_completion_tokens` - Optional token limit sent as
+///   `max_completion_tokens`
+/// * `schema` - Optional JSON schema for structured output
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct BatchRequest {
+    #[serde(default)]
+    pub custom_id: Option<String>,
+    pub messages: Vec<RequestMessage>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(default)]
+    pub schema: Option<Value>,
+}
+
+/// Outcome of a batch request.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchStatus {
+    Ok,
+    Error,
+}
+
+/// A single line of a batch output file.
+///
+/// # Fields
+/// * `custom_id` - Identifier of the request this result belongs to
+/// * `status` - Whether the request succeeded
+/// * `content` - Content of the first choice (on success)
+/// * `usage` - Token usage statistics (on success)
+/// * `cost` - Cost of the request (on success, when the model has a known
+///   price)
+/// * `error` - Error message (on failure)
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub status: BatchStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Cost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchResult {
+    /// Creates a failed result.
+    pub fn error(custom_id: impl Into<String>, error: impl ToString) -> Self {
+        Self {
+            custom_id: custom_id.into(),
+            status: BatchStatus::Error,
+            content: None,
+            usage: None,
+            cost: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// A batch line that was read, successfully or not.
+///
+/// Malformed lines are kept so that they are reported in the output instead
+/// of aborting the whole batch.
+pub type BatchLine = (String, Result<BatchRequest>);
+
+/// Parses the content of a batch input file.
+///
+/// Empty lines are skipped. Lines without a `custom_id` are identified by
+/// their one-based line number (`line-N`).
+///
+/// # Arguments
+/// * `content` - The JSONL content, one request per line
+///
+/// # Returns
+/// * The custom id and parsed request (or parse error) of every line
+pub fn parse_batch(content: &str) -> Vec<BatchLine> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let fallback_id = format!("line-{}", index + 1);
+            match serde_json::from_str::<BatchRequest>(line) {
+                Ok(request) => (request.custom_id.clone().unwrap_or(fallback_id), Ok(request)),
+                Err(error) => (fallback_id, Err(error).context("Failed to parse batch line")),
+            }
+        })
+        .collect()
+}
+
+/// Builds the payload of a batch request with the client's defaults.
+///
+/// # Arguments
+/// * `client` - The client to query
+/// * `request` - The batch request
+fn payload<'a>(client: &'a InvokeClient, request: &'a BatchRequest) -> RequestPayload<'a> {
+    let mut payload = client.payload(request.messages.clone(), false);
+    if let Some(model) = &request.model {
+        payload.model = model;
+    }
+    if let Some(tokens) = request.max_completion_tokens {
+        payload.set_token_limit(TokenLimitField::MaxCompletionTokens, tokens);
+    } else if let Some(tokens) = request.max_tokens {
+        payload.set_token_limit(TokenLimitField::MaxTokens, tokens);
+    }
+    if let Some(json_schema) = &request.schema {
+        payload.response_format = Some(ResponseFormat::json_schema(json_schema.clone()));
+    }
+
+    payload
+}
+
+/// Estimates the cost of the valid requests of a batch, see
+/// [`InvokeClient::estimate_cost`].
+///
+/// # Arguments
+/// * `client` - The client to query
+/// * `lines` - The parsed batch lines
+///
+/// # Returns
+/// * The estimated cost in US dollars, without the requests whose model has no
+///   known price
+pub fn estimate_batch_cost(client: &InvokeClient, lines: &[BatchLine]) -> f64 {
+    lines
+        .iter()
+        .filter_map(|(_, request)| request.as_ref().ok())
+        .filter_map(|request| client.estimate_cost(&payload(client, request)))
+        .map(|cost| cost.total)
+        .fold(0.0, |estimate, cost| estimate + cost)
+}
+
+/// Sends a single batch request with the client's defaults.
+///
+/// # Arguments
+/// * `client` - The client to query
+/// * `request` - The batch request
+///
+/// # Returns
+/// * `Ok((content, usage, cost))` - Content of the first choice, usage and cost
+///   of the request
+/// * `Err` - An error if the request is invalid or failed
+async fn send_request(client: &InvokeClient, request: BatchRequest) -> Result<(String, Usage, Option<Cost>)> {
+    if request.messages.is_empty() {
+        bail!("Batch request has no messages");
+    }
+
+    let payload = payload(client, &request);
+    let response = client.send_payload(&payload).await?;
+    let Some(choice) = response.choices.into_iter().next() else {
+        bail!("API returned a response, but it contained no choices.");
+    };
+    let cost = client.cost(payload.model, &response.usage);
+
+    Ok((choice.message.content, response.usage, cost))
+}
+
+/// Runs batch requests with bounded concurrency.
+///
+/// At most `parallel` requests are in flight at any time. `on_result` is
+/// called with each result as soon as its request completes, so results
+/// arrive in completion order rather than input order.
+///
+/// # Arguments
+/// * `client` - The client to query
+/// * `lines` - The parsed batch lines
+/// * `parallel` - Maximum number of concurrent requests (at least 1)
+/// * `on_result` - Callback receiving every result
+///
+/// # Returns
+/// * `Ok(())` once every request has completed
+/// * `Err` - An error if `on_result` failed
+pub async fn run_batch(
+    client: Arc<InvokeClient>,
+    lines: Vec<BatchLine>,
+    parallel: usize,
+    mut on_result: impl FnMut(BatchResult) -> Result<()>,
+) -> Result<()> {
+    let semaphore = Arc::new(Semaphore::new(parallel.max(1)));
+    let mut tasks = JoinSet::new();
+
+    for (custom_id, request) in lines {
+        let request = match request {
+            Ok(request) => request,
+            Err(error) => {
+                on_result(BatchResult::error(custom_id, format!("{error:#}")))?;
+                continue;
+            },
+        };
+
+        let client = Arc::clone(&client);
+        let semaphore = Arc::clone(&semaphore);
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            match send_request(&client, request).await {
+                Ok((content, usage, cost)) => {
+                    BatchResult {
+                        custom_id,
+                        status: BatchStatus::Ok,
+                        content: Some(content),
+                        usage: Some(usage),
+                        cost,
+                        error: None,
+                    }
+                },
+                Err(error) => BatchResult::error(custom_id, format!("{error:#}")),
+            }
+        });
+    }
+
+    while let Some(result) = tasks.join_next().await {
+        on_result(result.context("Batch request task failed")?)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,552 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::RequestPayload;
+use crate::pricing::Cost;
+use crate::schema::Usage;
+use crate::tokenizer::Tokenizer;
+
+/// Name of the data directory inside the user's data directory.
+const DATA_DIR_NAME: &str = "invoke-llm";
+
+/// Name of the ledger file.
+const LEDGER_FILE_NAME: &str = "ledger.jsonl";
+
+/// Number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Spending limits over a day and a month, in US dollars.
+///
+/// # Fields
+/// * `daily` - Maximum spending of the current day
+/// * `monthly` - Maximum spending of the current month
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetLimits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub monthly: Option<f64>,
+}
+
+/// Settings of the spending ledger and budgets.
+///
+/// Declared in the `[budget]` table of the configuration files, for example:
+///
+/// ```toml
+/// [budget]
+/// daily = 5.0
+/// monthly = 100.0
+///
+/// [budget.profiles.code-review]
+/// daily = 1.0
+/// ```
+///
+/// # Fields
+/// * `ledger` - Path of the ledger file, relative to the configuration file
+///   that sets it (defaults to [`default_ledger_path`])
+/// * `daily` - Maximum spending of the current day, over all requests
+/// * `monthly` - Maximum spending of the current month, over all requests
+/// * `profiles` - Limits of the requests sent with a given profile
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ledger: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly: Option<f64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, BudgetLimits>,
+}
+
+impl BudgetConfig {
+    /// Overrides the settings with the ones set in `other`.
+    ///
+    /// # Arguments
+    /// * `other` - The settings taking precedence
+    pub fn merge(&mut self, other: BudgetConfig) {
+        if other.ledger.is_some() {
+            self.ledger = other.ledger;
+        }
+        if other.daily.is_some() {
+            self.daily = other.daily;
+        }
+        if other.monthly.is_some() {
+            self.monthly = other.monthly;
+        }
+        for (name, limits) in other.profiles {
+            let merged = self.profiles.entry(name).or_default();
+            merged.daily = limits.daily.or(merged.daily);
+            merged.monthly = limits.monthly.or(merged.monthly);
+        }
+    }
+
+    /// Tightens the limits with the ones set in `other`.
+    ///
+    /// Each limit becomes the lower of the two, and limits only set in `other`
+    /// are added. The ledger path of `other` is ignored.
+    ///
+    /// # Arguments
+    /// * `other` - The settings that may only lower the limits
+    pub fn tighten(&mut self, other: BudgetConfig) {
+        self.daily = lowest(self.daily, other.daily);
+        self.monthly = lowest(self.monthly, other.monthly);
+        for (name, limits) in other.profiles {
+            let merged = self.profiles.entry(name).or_default();
+            merged.daily = lowest(merged.daily, limits.daily);
+            merged.monthly = lowest(merged.monthly, limits.monthly);
+        }
+    }
+
+    /// Resolves a relative ledger path against a base directory.
+    pub(crate) fn resolve_paths(&mut self, base_dir: &Path) {
+        if let Some(ledger) = &self.ledger
+            && ledger.is_relative()
+        {
+            self.ledger = Some(base_dir.join(ledger));
+        }
+    }
+
+    /// Returns the path of the ledger file, if it can be determined.
+    pub fn ledger_path(&self) -> Option<PathBuf> {
+        self.ledger.clone().or_else(default_ledger_path)
+    }
+}
+
+/// Returns the lower of two optional limits, where `None` means no limit.
+fn lowest(limit: Option<f64>, other: Option<f64>) -> Option<f64> {
+    match (limit, other) {
+        (Some(limit), Some(other)) => Some(limit.min(other)),
+        _ => limit.or(other),
+    }
+}
+
+/// A request recorded in the ledger.
+///
+/// # Fields
+/// * `timestamp` - Unix timestamp (in seconds) of the request
+/// * `profile` - The configuration profile the request was sent with
+/// * `endpoint` - The endpoint name the request was sent to
+/// * `model` - The requested model
+/// * `usage` - The token usage reported by the provider
+/// * `cost` - The cost of the usage, if the model has a known price
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LedgerEntry {
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub endpoint: String,
+    pub model: String,
+    pub usage: Usage,
+    #[serde(default)]
+    pub cost: Option<Cost>,
+}
+
+impl LedgerEntry {
+    /// Creates an entry for a request sent now.
+    ///
+    /// # Arguments
+    /// * `profile` - The configuration profile, if any
+    /// * `endpoint` - The endpoint name
+    /// * `model` - The requested model
+    /// * `usage` - The token usage of the request
+    /// * `cost` - The cost of the request, if known
+    pub fn new(profile: Option<&str>, endpoint: &str, model: &str, usage: Usage, cost: Option<Cost>) -> Self {
+        Self {
+            timestamp: now(),
+            profile: profile.map(str::to_owned),
+            endpoint: endpoint.to_owned(),
+            model: model.to_owned(),
+            usage,
+            cost,
+        }
+    }
+
+    /// Returns the total cost of the entry, zero when it is unknown.
+    pub fn total_cost(&self) -> f64 {
+        self.cost.map_or(0.0, |cost| cost.total)
+    }
+}
+
+/// Append-only record of the requests sent, one JSON line per request.
+#[derive(Debug, Clone)]
+pub struct Ledger {
+    path: PathBuf,
+}
+
+impl Ledger {
+    /// Creates a ledger stored in a file.
+    ///
+    /// The file and its directory are created when the first entry is
+    /// recorded.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the ledger file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Returns the path of the ledger file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends an entry to the ledger.
+    ///
+    /// Every entry is written with a single append, so that concurrent runs
+    /// sharing the ledger do not interleave their lines.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to record
+    pub fn record(&self, entry: &LedgerEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent).context("Failed to create ledger directory")?;
+        }
+
+        let line = serde_json::to_string(entry)? + "\n";
+        let mut file = File::options()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open ledger file {}", self.path.display()))?;
+        file.write_all(line.as_bytes()).context("Failed to write ledger file")?;
+
+        Ok(())
+    }
+
+    /// Reads every entry of the ledger.
+    ///
+    /// Unreadable lines, such as a line cut short by an interrupted run, are
+    /// skipped.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<LedgerEntry>)` - The entries, in recording order (empty if the
+    ///   file does not exist)
+    /// * `Err` - An error if the file exists but could not be read
+    pub fn entries(&self) -> Result<Vec<LedgerEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read ledger file {}", self.path.display()))?;
+
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+}
+
+/// The time span a budget applies to, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// The current day.
+    Day,
+    /// The current month.
+    Month,
+}
+
+impl Period {
+    /// Returns the Unix timestamp of the start of the period containing a
+    /// timestamp.
+    ///
+    /// # Arguments
+    /// * `timestamp` - A Unix timestamp, in seconds
+    pub fn start(self, timestamp: u64) -> u64 {
+        let days = timestamp / SECONDS_PER_DAY;
+        match self {
+            Self::Day => days * SECONDS_PER_DAY,
+            Self::Month => {
+                let (year, month, _) = civil_from_days(days);
+                days_from_civil(year, month, 1) * SECONDS_PER_DAY
+            },
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Day => f.write_str("daily"),
+            Self::Month => f.write_str("monthly"),
+        }
+    }
+}
+
+/// The spending of a budget over its period.
+///
+/// # Fields
+/// * `profile` - The profile the budget is restricted to (all requests if not
+///   set)
+/// * `period` - The period of the budget
+/// * `limit` - The maximum spending
+/// * `spent` - The spending recorded since the start of the period
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetStatus {
+    pub profile: Option<String>,
+    pub period: Period,
+    pub limit: f64,
+    pub spent: f64,
+}
+
+impl BudgetStatus {
+    /// Returns the amount left to spend, zero once the limit is reached.
+    pub fn remaining(&self) -> f64 {
+        (self.limit - self.spent).max(0.0)
+    }
+
+    /// Returns a name for the budget (e.g. "daily" or "profile 'review'
+    /// monthly").
+    pub fn name(&self) -> String {
+        match &self.profile {
+            Some(profile) => format!("profile '{profile}' {}", self.period),
+            None => self.period.to_string(),
+        }
+    }
+}
+
+/// Error returned when a request would exceed a budget.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error(
+    "The {} budget of ${:.2} would be exceeded: ${:.6} spent, ${estimate:.6} estimated for this request",
+    .status.name(),
+    .status.limit,
+    .status.spent
+)]
+pub struct BudgetExceeded {
+    pub status: BudgetStatus,
+    pub estimate: f64,
+}
+
+/// Error returned when a budget applies to a request whose model has no known
+/// price, so that its cost cannot be checked.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "The price of '{model}' on '{endpoint}' is unknown, so the request cannot be checked against the budgets: set it \
+     in [pricing.{endpoint}]"
+)]
+pub struct UnpricedModel {
+    pub endpoint: String,
+    pub model: String,
+}
+
+/// The budgets of a run and the ledger their spending is read from.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    config: BudgetConfig,
+    ledger: Option<Ledger>,
+    profile: Option<String>,
+}
+
+impl Budget {
+    /// Creates the budgets of a run.
+    ///
+    /// # Arguments
+    /// * `config` - The budget settings
+    /// * `profile` - The profile the requests are sent with, whose own limits
+    ///   apply besides the global ones
+    pub fn new(config: BudgetConfig, profile: Option<&str>) -> Self {
+        let ledger = config.ledger_path().map(Ledger::new);
+        Self {
+            config,
+            ledger,
+            profile: profile.map(str::to_owned),
+        }
+    }
+
+    /// Returns the ledger, unless its path could not be determined.
+    pub fn ledger(&self) -> Option<&Ledger> {
+        self.ledger.as_ref()
+    }
+
+    /// Returns whether any budget applies to the run.
+    pub fn is_limited(&self) -> bool {
+        !self.limits().is_empty()
+    }
+
+    /// Returns the spending of every budget that applies to the run.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<BudgetStatus>)` - The global budgets, then the ones of the
+    ///   profile
+    /// * `Err` - An error if the ledger could not be read
+    pub fn status(&self) -> Result<Vec<BudgetStatus>> {
+        let limits = self.limits();
+        if limits.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let entries = match &self.ledger {
+            Some(ledger) => ledger.entries()?,
+            None => Vec::new(),
+        };
+        let now = now();
+
+        Ok(limits
+            .into_iter()
+            .map(|(profile, period, limit)| {
+                let start = period.start(now);
+                let spent = entries
+                    .iter()
+                    .filter(|entry| entry.timestamp >= start)
+                    .filter(|entry| profile.is_none() || entry.profile.as_ref() == profile)
+                    .map(LedgerEntry::total_cost)
+                    .fold(0.0, |spent, cost| spent + cost);
+                BudgetStatus {
+                    profile: profile.cloned(),
+                    period,
+                    limit,
+                    spent,
+                }
+            })
+            .collect())
+    }
+
+    /// Returns the limits that apply to the run: the global ones, then the
+    /// ones of the profile.
+    fn limits(&self) -> Vec<(Option<&String>, Period, f64)> {
+        let mut limits = vec![
+            (None, Period::Day, self.config.daily),
+            (None, Period::Month, self.config.monthly),
+        ];
+        if let Some(profile) = &self.profile
+            && let Some(profile_limits) = self.config.profiles.get(profile)
+        {
+            limits.push((Some(profile), Period::Day, profile_limits.daily));
+            limits.push((Some(profile), Period::Month, profile_limits.monthly));
+        }
+        limits
+            .into_iter()
+            .filter_map(|(profile, period, limit)| Some((profile, period, limit?)))
+            .collect()
+    }
+
+    /// Checks that a request fits in every budget.
+    ///
+    /// # Arguments
+    /// * `estimate` - The estimated cost of the request
+    ///
+    /// # Returns
+    /// * `Ok(())` - If every budget has room for the request
+    /// * `Err` - A [`BudgetExceeded`] error for the first budget the request
+    ///   would exceed, or an error if the ledger could not be read
+    pub fn check(&self, estimate: f64) -> Result<()> {
+        for status in self.status()? {
+            if status.spent + estimate > status.limit {
+                return Err(BudgetExceeded { status, estimate }.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records a request in the ledger, tagged with the profile of the run.
+    ///
+    /// # Arguments
+    /// * `endpoint` - The endpoint name
+    /// * `model` - The requested model
+    /// * `usage` - The token usage of the request
+    /// * `cost` - The cost of the request, if known
+    pub fn record(&self, endpoint: &str, model: &str, usage: &Usage, cost: Option<Cost>) -> Result<()> {
+        match &self.ledger {
+            Some(ledger) => {
+                ledger.record(&LedgerEntry::new(
+                    self.profile.as_deref(),
+                    endpoint,
+                    model,
+                    usage.clone(),
+                    cost,
+                ))
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+/// Estimates the usage of a request before it is sent.
+///
+/// The prompt is counted with the tokenizer of the model (see
+/// [`Tokenizer::count_payload`]), and the completion is assumed to use the
+/// whole token limit for every choice, so that the estimate is an upper bound
+/// of the usual cost.
+///
+/// # Arguments
+/// * `payload` - The payload to send
+pub fn estimate_usage(payload: &RequestPayload<'_>) -> Usage {
+    let prompt_tokens = Tokenizer::for_model(payload.model).count_payload(payload);
+    let prompt_tokens = i64::try_from(prompt_tokens).unwrap_or(i64::MAX);
+    let max_tokens = payload.max_completion_tokens.or(payload.max_tokens).unwrap_or_default();
+    let completion_tokens = i64::from(max_tokens) * i64::from(payload.sampling.n.unwrap_or(1).max(1));
+
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens.saturating_add(completion_tokens),
+        ..Default::default()
+    }
+}
+
+/// Returns the default path of the ledger file.
+///
+/// This is `$XDG_DATA_HOME/invoke-llm/ledger.jsonl`, falling back to
+/// `~/.local/share/invoke-llm/ledger.jsonl`.
+pub fn default_ledger_path() -> Option<PathBuf> {
+    let data_dir = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")),
+    };
+
+    data_dir.map(|dir| dir.join(DATA_DIR_NAME).join(LEDGER_FILE_NAME))
+}
+
+/// Converts a number of days since the Unix epoch into a (year, month, day)
+/// date of the proleptic Gregorian calendar.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Shifts the epoch to 0000-03-01, so that leap days end the years
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Converts a date of the proleptic Gregorian calendar into a number of days
+/// since the Unix epoch, the inverse of [`civil_from_days`].
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the current Unix timestamp, in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::schema::ApiResponse;
+
+/// Extension of the cache entry files.
+const ENTRY_EXTENSION: &str = "json";
+
+/// A cached response, stored as one JSON file per request.
+///
+/// # Fields
+/// * `created_at` - Unix timestamp (in seconds) of when the entry was stored
+/// * `url` - The endpoint URL the request was sent to
+/// * `response` - The full response returned by the endpoint
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub created_at: u64,
+    pub url: String,
+    pub response: ApiResponse,
+}
+
+/// Summary of the content of a cache directory.
+///
+/// # Fields
+/// * `entries` - Number of cache entries
+/// * `expired` - Number of entries older than the time-to-live, or unreadable
+/// * `bytes` - Total size of the entries on disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub expired: usize,
+    pub bytes: u64,
+}
+
+/// On-disk cache of responses, keyed by a hash of the request.
+///
+/// Every response is stored as `<key>.json` in the cache directory, where the
+/// key is computed by [`cache_key`] from the endpoint URL and the serialized
+/// request body. Entries older than the optional time-to-live are ignored.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+}
+
+impl ResponseCache {
+    /// Creates a cache stored in a directory.
+    ///
+    /// The directory is created when the first entry is stored.
+    ///
+    /// # Arguments
+    /// * `dir` - The cache directory
+    /// * `ttl` - Optional maximum age of the entries (entries never expire when
+    ///   not provided)
+    pub fn new(dir: impl Into<PathBuf>, ttl: Option<Duration>) -> Self {
+        Self { dir: dir.into(), ttl }
+    }
+
+    /// Returns the cache directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Looks up the response cached for a key.
+    ///
+    /// # Arguments
+    /// * `key` - The cache key, see [`cache_key`]
+    ///
+    /// # Returns
+    /// * `Ok(Some(ApiResponse))` - The cached response
+    /// * `Ok(None)` - If there is no entry for the key or it has expired
+    /// * `Err` - An error if the entry could not be read or parsed
+    pub fn get(&self, key: &str) -> Result<Option<ApiResponse>> {
+        let path = self.entry_path(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let entry = read_entry(&path)?;
+        if self.is_expired(&entry) {
+            return Ok(None);
+        }
+
+        Ok(Some(entry.response))
+    }
+
+    /// Stores the response for a key, replacing any previous entry.
+    ///
+    /// # Arguments
+    /// * `key` - The cache key, see [`cache_key`]
+    /// * `url` - The endpoint URL the request was sent to
+    /// * `response` - The response to store
+    pub fn put(&self, key: &str, url: &str, response: &ApiResponse) -> Result<()> {
+        fs::create_dir_all(&self.dir).context("Failed to create cache directory")?;
+
+        let entry = CacheEntry {
+            created_at: now(),
+            url: url.to_owned(),
+            response: response.clone(),
+        };
+        // Write to a temporary file first so that readers never see a partial
+        // entry
+        let path = self.entry_path(key);
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_vec(&entry)?).context("Failed to write cache entry")?;
+        fs::rename(&temporary, &path).context("Failed to write cache entry")?;
+
+        Ok(())
+    }
+
+    /// Summarizes the content of the cache directory.
+    ///
+    /// # Returns
+    /// * `Ok(CacheStats)` - The summary (empty if the directory does not exist)
+    /// * `Err` - An error if the directory could not be listed
+    pub fn stats(&self) -> Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        for path in self.entry_paths()? {
+            stats.entries += 1;
+            stats.bytes += fs::metadata(&path).map_or(0, |metadata| metadata.len());
+            if read_entry(&path).map_or(true, |entry| self.is_expired(&entry)) {
+                stats.expired += 1;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Removes the expired and unreadable entries.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - The number of removed entries
+    /// * `Err` - An error if the directory could not be listed or an entry
+    ///   could not be removed
+    pub fn prune(&self) -> Result<usize> {
+        let mut removed = 0;
+        for path in self.entry_paths()? {
+            if read_entry(&path).map_or(true, |entry| self.is_expired(&entry)) {
+                fs::remove_file(&path).context("Failed to remove cache entry")?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Removes every entry.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - The number of removed entries
+    /// * `Err` - An error if the directory could not be listed or an entry
+    ///   could not be removed
+    pub fn clear(&self) -> Result<usize> {
+        let paths = self.entry_paths()?;
+        for path in &paths {
+            fs::remove_file(path).context("Failed to remove cache entry")?;
+        }
+
+        Ok(paths.len())
+    }
+
+    /// Returns whether an entry is older than the time-to-live.
+    fn is_expired(&self, entry: &CacheEntry) -> bool {
+        self.ttl
+            .is_some_and(|ttl| now().saturating_sub(entry.created_at) >= ttl.as_secs())
+    }
+
+    /// Returns the path of the entry file for a key.
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.{ENTRY_EXTENSION}"))
+    }
+
+    /// Lists the entry files of the cache directory.
+    fn entry_paths(&self) -> Result<Vec<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir).context("Failed to read cache directory")? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|extension| extension == ENTRY_EXTENSION) {
+                paths.push(path);
+            }
+        }
+
+        Ok(paths)
+    }
+}
+
+/// Computes the cache key of a request.
+///
+/// The key is the hex-encoded SHA-256 hash of the endpoint URL and the
+/// serialized request body. Object keys are serialized in sorted order, so
+/// the key is stable across runs.
+///
+/// # Arguments
+/// * `url` - The endpoint URL
+/// * `body` - The request body, as sent to the endpoint
+pub fn cache_key(url: &str, body: &Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(url.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body.to_string().as_bytes());
+
+    hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Reads and parses a cache entry file.
+fn read_entry(path: &Path) -> Result<CacheEntry> {
+    let content = fs::read(path).context("Failed to read cache entry")?;
+    serde_json::from_slice(&content).context("Failed to parse cache entry")
+}
+
+/// Returns the current Unix timestamp in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
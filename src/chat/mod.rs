@@ -0,0 +1,225 @@
+use anyhow::{Result, anyhow, bail};
+use std::path::PathBuf;
+
+use crate::schema::Usage;
+use crate::session::Session;
+use crate::{ASSISTANT_ROLE, RequestMessage, SYSTEM_ROLE, USER_ROLE};
+
+/// Help shown by the `/help` command.
+pub const HELP: &str = "\
+/system [prompt]  show or set the system prompt
+/model [name]     show or change the model
+/tokens [count]   show or change the maximum number of tokens
+/save <file>      save the conversation as a session file
+/load <file>      load a conversation from a session file
+/retry            send the last input again, replacing its reply
+/undo             remove the last input and its reply
+/help             show this help
+/exit             leave the chat (or Ctrl-D)
+Lines starting with '//' are sent with a single leading '/'.";
+
+/// A line entered in the chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatInput {
+    /// A message to send to the model.
+    Message(String),
+    /// A slash command.
+    Command(ChatCommand),
+}
+
+/// A slash command of the chat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatCommand {
+    /// `/system [prompt]`: shows or sets the system prompt.
+    System(Option<String>),
+    /// `/model [name]`: shows or changes the model.
+    Model(Option<String>),
+    /// `/tokens [count]`: shows or changes the maximum number of tokens.
+    Tokens(Option<u32>),
+    /// `/save <file>`: saves the conversation.
+    Save(PathBuf),
+    /// `/load <file>`: loads a conversation.
+    Load(PathBuf),
+    /// `/retry`: sends the last input again.
+    Retry,
+    /// `/undo`: removes the last turn.
+    Undo,
+    /// `/help`: shows the commands.
+    Help,
+    /// `/exit` or `/quit`: leaves the chat.
+    Exit,
+}
+
+/// Parses a line entered in the chat.
+///
+/// # Arguments
+/// * `line` - The line, without its trailing newline
+///
+/// # Returns
+/// * `Ok(Some(ChatInput))` - The message or command
+/// * `Ok(None)` - If the line is blank
+/// * `Err` - An error if the line is an unknown or malformed command
+pub fn parse_input(line: &str) -> Result<Option<ChatInput>> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    if let Some(message) = trimmed.strip_prefix("//") {
+        return Ok(Some(ChatInput::Message(format!("/{message}"))));
+    }
+    let Some(command) = trimmed.strip_prefix('/') else {
+        return Ok(Some(ChatInput::Message(line.to_owned())));
+    };
+
+    let (name, argument) = match command.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, Some(argument.trim()).filter(|argument| !argument.is_empty())),
+        None => (command, None),
+    };
+    let path = |argument: Option<&str>| {
+        argument
+            .map(PathBuf::from)
+            .ok_or_else(|| anyhow!("/{name} needs a file path"))
+    };
+
+    let command = match name {
+        "system" => ChatCommand::System(argument.map(str::to_owned)),
+        "model" => ChatCommand::Model(argument.map(str::to_owned)),
+        "tokens" => ChatCommand::Tokens(argument.map(parse_tokens).transpose()?),
+        "save" => ChatCommand::Save(path(argument)?),
+        "load" => ChatCommand::Load(path(argument)?),
+        "retry" => ChatCommand::Retry,
+        "undo" => ChatCommand::Undo,
+        "help" => ChatCommand::Help,
+        "exit" | "quit" => ChatCommand::Exit,
+        _ => bail!("Unknown command '/{name}', type /help for the commands"),
+    };
+
+    Ok(Some(ChatInput::Command(command)))
+}
+
+/// Parses the argument of the `/tokens` command.
+fn parse_tokens(count: &str) -> Result<u32> {
+    count
+        .parse()
+        .map_err(|_| anyhow!("'{count}' is not a number of tokens"))
+}
+
+/// The state of an interactive chat.
+///
+/// The system prompt is kept apart from the turns, so that it can be changed
+/// at any time and is always sent first.
+///
+/// # Fields
+/// * `system` - The system prompt, if any
+/// * `messages` - The user inputs and assistant replies, in order
+/// * `usage` - Token usage summed over every reply of the chat
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conversation {
+    pub system: Option<String>,
+    pub messages: Vec<RequestMessage>,
+    pub usage: Usage,
+}
+
+impl Conversation {
+    /// Creates an empty conversation.
+    ///
+    /// # Arguments
+    /// * `system` - The system prompt, if any
+    pub fn new(system: Option<String>) -> Self {
+        Self {
+            system,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the messages to send for a new user input.
+    ///
+    /// # Arguments
+    /// * `input` - The new user input
+    /// * `system_role` - Whether to use "system" role instead of "assistant"
+    ///   role for the system prompt
+    pub fn request(&self, input: &str, system_role: bool) -> Vec<RequestMessage> {
+        let mut messages = self.prompt_message(system_role).into_iter().collect::<Vec<_>>();
+        messages.extend(self.messages.iter().cloned());
+        messages.push(RequestMessage::new(USER_ROLE, input));
+
+        messages
+    }
+
+    /// Records a completed turn.
+    ///
+    /// # Arguments
+    /// * `input` - The user input
+    /// * `reply` - The content of the assistant reply
+    /// * `usage` - The token usage of the reply, added to the totals
+    pub fn push_turn(&mut self, input: &str, reply: &str, usage: &Usage) {
+        self.messages.push(RequestMessage::new(USER_ROLE, input));
+        self.messages.push(RequestMessage::new(ASSISTANT_ROLE, reply));
+
+        self.usage.add(usage);
+    }
+
+    /// Removes the last turn.
+    ///
+    /// # Returns
+    /// * `Some(String)` - The user input of the removed turn
+    /// * `None` - If the conversation has no turn
+    pub fn undo(&mut self) -> Option<String> {
+        let index = self.messages.iter().rposition(|message| message.role == USER_ROLE)?;
+
+        self.messages
+            .drain(index..)
+            .next()
+            .map(|message| message.content.to_string())
+    }
+
+    /// Returns the number of user turns.
+    pub fn turns(&self) -> usize {
+        self.messages.iter().filter(|message| message.role == USER_ROLE).count()
+    }
+
+    /// Converts the conversation into a session, with the system prompt first.
+    ///
+    /// # Arguments
+    /// * `system_role` - Whether to use "system" role instead of "assistant"
+    ///   role for the system prompt
+    pub fn to_session(&self, system_role: bool) -> Session {
+        let mut session = Session::new();
+        session.messages = self.prompt_message(system_role).into_iter().collect();
+        session.messages.extend(self.messages.iter().cloned());
+
+        session
+    }
+
+    /// Creates a conversation from a session.
+    ///
+    /// A leading "system" message, or an "assistant" message sent before any
+    /// user input, is taken as the system prompt.
+    ///
+    /// # Arguments
+    /// * `session` - The session to continue
+    pub fn from_session(session: Session) -> Self {
+        let mut messages = session.messages;
+        let system = match messages.first() {
+            Some(first) if first.role == SYSTEM_ROLE || first.role == ASSISTANT_ROLE => {
+                Some(messages.remove(0).content.to_string())
+            },
+            _ => None,
+        };
+
+        Self {
+            system,
+            messages,
+            usage: Usage::default(),
+        }
+    }
+
+    /// Returns the message carrying the system prompt, if any.
+    fn prompt_message(&self, system_role: bool) -> Option<RequestMessage> {
+        let role = if system_role { SYSTEM_ROLE } else { ASSISTANT_ROLE };
+
+        self.system
+            .as_ref()
+            .map(|system| RequestMessage::new(role, system.clone()))
+    }
+}
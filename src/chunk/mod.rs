@@ -0,0 +1,392 @@
+use anyhow::{Context, Result, bail};
+use std::ops::Range;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::info;
+
+use crate::budget::estimate_usage;
+use crate::limits::ContextOverflow;
+use crate::schema::{ApiResponse, Usage};
+use crate::tokenizer::Tokenizer;
+use crate::{InvokeClient, RequestMessage};
+
+/// Separator placed between the partial answers when they are joined.
+pub const ANSWER_SEPARATOR: &str = "\n\n";
+
+/// A piece of the text to split, with its token count.
+///
+/// # Fields
+/// * `text` - The text of the piece, a line or a part of a long line
+/// * `tokens` - Number of tokens of the text
+/// * `section` - Whether a section starts with the piece (a markdown heading or
+///   a code fence), where cutting the text is preferred
+struct Piece<'a> {
+    text: &'a str,
+    tokens: usize,
+    section: bool,
+}
+
+/// Splits a text into chunks of at most about `size` tokens.
+///
+/// The text is cut between lines, preferably before a markdown heading or
+/// around a fenced code block, as long as the chunk is at least half full.
+/// Lines longer than a chunk are cut on token boundaries. Every chunk but the
+/// first starts with the last `overlap` tokens of the previous one, so that
+/// the context around the cuts is kept.
+///
+/// Chunk sizes are computed by summing the token counts of the lines, which
+/// is close to the count of the whole chunk.
+///
+/// # Arguments
+/// * `text` - The text to split
+/// * `tokenizer` - The tokenizer of the model the chunks are sent to
+/// * `size` - Maximum number of tokens of a chunk
+/// * `overlap` - Number of tokens repeated from the previous chunk (lower than
+///   `size`)
+///
+/// # Returns
+/// * The chunks, in order (a single chunk if the text fits, none if it is
+///   empty)
+pub fn split_chunks(text: &str, tokenizer: Tokenizer, size: usize, overlap: usize) -> Vec<String> {
+    let budget = size.saturating_sub(overlap).max(1);
+
+    let mut groups: Vec<Vec<Piece<'_>>> = Vec::new();
+    let mut current: Vec<Piece<'_>> = Vec::new();
+    let mut current_tokens = 0;
+    for piece in pieces(text, tokenizer, budget) {
+        while current_tokens + piece.tokens > budget && !current.is_empty() {
+            // Cuts at the last section start, unless it leaves a small chunk
+            let mut filled = 0;
+            let mut cut = current.len();
+            for (index, candidate) in current.iter().enumerate() {
+                if index > 0 && candidate.section && filled * 2 >= budget {
+                    cut = index;
+                }
+                filled += candidate.tokens;
+            }
+
+            let rest = current.split_off(cut);
+            groups.push(current);
+            current = rest;
+            current_tokens = current.iter().map(|piece| piece.tokens).sum();
+        }
+        current_tokens += piece.tokens;
+        current.push(piece);
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+
+    let mut chunks: Vec<String> = Vec::with_capacity(groups.len());
+    for group in groups {
+        let content: String = group.iter().map(|piece| piece.text).collect();
+        let chunk = match chunks.last() {
+            Some(previous) if overlap > 0 => format!("{}{content}", tokenizer.tail(previous, overlap)),
+            _ => content,
+        };
+        chunks.push(chunk);
+    }
+
+    chunks
+}
+
+/// Splits a text into lines, flagging the ones starting a section, and cuts
+/// the lines longer than `budget` tokens.
+fn pieces(text: &str, tokenizer: Tokenizer, budget: usize) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut after_fence = false;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"].into_iter().find(|marker| trimmed.starts_with(marker));
+        let section = match (fence, marker) {
+            // A code block starts
+            (None, Some(marker)) => {
+                fence = Some(marker);
+                true
+            },
+            // The code block ends, and a section starts after it
+            (Some(open), Some(marker)) if open == marker && trimmed.trim_end() == marker => {
+                fence = None;
+                after_fence = true;
+                false
+            },
+            (Some(_), _) => false,
+            (None, None) => std::mem::take(&mut after_fence) || is_heading(trimmed),
+        };
+
+        let tokens = tokenizer.count(line);
+        if tokens <= budget {
+            pieces.push(Piece {
+                text: line,
+                tokens,
+                section,
+            });
+            continue;
+        }
+        for (index, part) in tokenizer.split(line, budget).into_iter().enumerate() {
+            pieces.push(Piece {
+                text: part,
+                tokens: tokenizer.count(part),
+                section: section && index == 0,
+            });
+        }
+    }
+
+    pieces
+}
+
+/// Returns whether a line is a markdown ATX heading (`#` to `######`).
+fn is_heading(line: &str) -> bool {
+    let level = line.bytes().take_while(|byte| *byte == b'#').count();
+    (1..=6).contains(&level) && line[level..].starts_with([' ', '\t', '\n', '\r'])
+}
+
+/// Joins the partial answers of the chunks.
+///
+/// # Arguments
+/// * `answers` - The answers, in chunk order
+pub fn join_answers(answers: &[String]) -> String {
+    answers.join(ANSWER_SEPARATOR)
+}
+
+/// The result of [`map_reduce`].
+///
+/// # Fields
+/// * `response` - The final response, with the usage summed over every request:
+///   the reply to the reduce prompt, or the partial answers joined into the
+///   first choice when there is no reduce prompt
+/// * `partials` - The responses to every chunk, in chunk order
+#[derive(Debug, Clone)]
+pub struct MapReduceOutcome {
+    pub response: ApiResponse,
+    pub partials: Vec<ApiResponse>,
+}
+
+/// Estimates the cost of a map-reduce run before it is sent.
+///
+/// Every chunk is estimated like a single request (see [`estimate_usage`]).
+/// The reduce request is estimated with partial answers using the whole token
+/// limit. The extra requests of a reduce in groups are left out: [`map_reduce`]
+/// checks every level of groups against the budgets before sending it.
+///
+/// # Arguments
+/// * `client` - The client the requests are sent with
+/// * `prompt` - The prompt sent with every chunk
+/// * `chunks` - The chunks of the input
+/// * `reduce_prompt` - Optional prompt sent with the joined partial answers
+///
+/// # Returns
+/// The estimated cost in US dollars, leaving out requests to models without a
+/// known price
+pub fn estimate_map_reduce_cost(
+    client: &InvokeClient,
+    prompt: &str,
+    chunks: &[String],
+    reduce_prompt: Option<&str>,
+) -> f64 {
+    let map = chunks
+        .iter()
+        .filter_map(|chunk| client.estimate_cost(&client.payload(client.messages(prompt, chunk.as_str()), false)));
+    let reduce = reduce_prompt.and_then(|reduce_prompt| {
+        let mut usage = estimate_usage(&client.payload(client.messages(reduce_prompt, ""), false));
+        let answers = i64::from(client.tokens()).saturating_mul(i64::try_from(chunks.len()).unwrap_or(i64::MAX));
+        usage.prompt_tokens = usage.prompt_tokens.saturating_add(answers);
+        usage.total_tokens = usage.total_tokens.saturating_add(answers);
+        client.cost(client.model(), &usage)
+    });
+
+    map.chain(reduce)
+        .map(|cost| cost.total)
+        .fold(0.0, |estimate, cost| estimate + cost)
+}
+
+/// Runs a prompt on every chunk of an input, then an optional reduce prompt
+/// on the joined partial answers.
+///
+/// The whole run is checked against the client's budgets before any chunk is
+/// sent, see [`estimate_map_reduce_cost`], and so is every level of a reduce in
+/// groups. At most `parallel` chunks are in
+/// flight at any time. The first failed chunk fails the whole run, cancelling
+/// the chunks still in flight. The reduce request is checked with
+/// `check_context`: when the joined answers do not fit, they are reduced in
+/// groups first.
+///
+/// # Arguments
+/// * `client` - The client to query
+/// * `prompt` - The prompt sent with every chunk
+/// * `chunks` - The chunks of the input, see [`split_chunks`]
+/// * `reduce_prompt` - Optional prompt sent with the joined partial answers
+/// * `parallel` - Maximum number of concurrent requests (at least 1)
+/// * `check_context` - Checks that the messages of a reduce request fit in the
+///   context window, failing with a [`ContextOverflow`] error otherwise, even
+///   for estimated token counts: only a failure splits the answers in groups
+///
+/// # Returns
+/// * `Ok(MapReduceOutcome)` - The final response and the partial ones
+/// * `Err` - An error if the run exceeds a budget, a request failed, a response
+///   has no choices or the answers cannot be reduced
+pub async fn map_reduce(
+    client: Arc<InvokeClient>,
+    prompt: &str,
+    chunks: Vec<String>,
+    reduce_prompt: Option<&str>,
+    parallel: usize,
+    check_context: impl Fn(&[RequestMessage]) -> Result<()>,
+) -> Result<MapReduceOutcome> {
+    if chunks.is_empty() {
+        bail!("The input has no chunk to send");
+    }
+    client.check_budget(estimate_map_reduce_cost(&client, prompt, &chunks, reduce_prompt))?;
+
+    let count = chunks.len();
+    let semaphore = Arc::new(Semaphore::new(parallel.max(1)));
+    let mut tasks = JoinSet::new();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let client = Arc::clone(&client);
+        let semaphore = Arc::clone(&semaphore);
+        let prompt = prompt.to_owned();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let response = client
+                .complete(prompt, chunk)
+                .await
+                .with_context(|| format!("Chunk {} of {count} failed", index + 1))?;
+            if response.choices.is_empty() {
+                bail!(
+                    "API returned a response to chunk {} of {count}, but it contained no choices.",
+                    index + 1
+                );
+            }
+            Ok((index, response))
+        });
+    }
+
+    let mut partials: Vec<Option<ApiResponse>> = vec![None; count];
+    while let Some(result) = tasks.join_next().await {
+        let (index, response) = result.context("Chunk task failed")??;
+        info!("Chunk {} of {count} answered", index + 1);
+        partials[index] = Some(response);
+    }
+    let partials: Vec<ApiResponse> = partials.into_iter().flatten().collect();
+
+    let mut usage = Usage::default();
+    for partial in &partials {
+        usage.add(&partial.usage);
+    }
+    let answers: Vec<String> = partials
+        .iter()
+        .map(|partial| partial.choices[0].message.content.clone())
+        .collect();
+
+    let mut response = match reduce_prompt {
+        Some(reduce_prompt) => reduce(&client, reduce_prompt, answers, &check_context, &mut usage).await?,
+        None => {
+            let mut response = partials[0].clone();
+            response.choices.truncate(1);
+            response.choices[0].message.content = join_answers(&answers);
+            response
+        },
+    };
+    response.usage = usage;
+
+    Ok(MapReduceOutcome { response, partials })
+}
+
+/// Reduces the partial answers into a single response.
+///
+/// When the joined answers do not fit in the context window, consecutive
+/// answers are grouped into requests that fit, each group is reduced into a
+/// single answer, and the reduced answers are reduced in turn, until a single
+/// request holds them all. The requests of every level are checked against the
+/// client's budgets before the first one is sent.
+///
+/// # Arguments
+/// * `client` - The client to query
+/// * `reduce_prompt` - The prompt sent with the joined answers
+/// * `answers` - The partial answers, in chunk order
+/// * `check_context` - Checks that the messages of a request fit in the context
+///   window
+/// * `usage` - The usage of the run, which the reduce requests are added to
+///
+/// # Returns
+/// * `Ok(ApiResponse)` - The response to the last reduce request
+/// * `Err` - An error if a level exceeds a budget, a request failed or a
+///   response has no choices, or if the answers cannot be grouped into fewer
+///   requests
+async fn reduce(
+    client: &InvokeClient,
+    reduce_prompt: &str,
+    mut answers: Vec<String>,
+    check_context: &impl Fn(&[RequestMessage]) -> Result<()>,
+    usage: &mut Usage,
+) -> Result<ApiResponse> {
+    loop {
+        let groups = group_answers(client, reduce_prompt, &answers, check_context)?;
+        if groups.len() == 1 {
+            let response = client.complete(reduce_prompt, join_answers(&answers)).await?;
+            usage.add(&response.usage);
+            return Ok(response);
+        }
+        if groups.len() == answers.len() {
+            bail!("The partial answers cannot be reduced: no two of them fit in the context window together");
+        }
+
+        info!(
+            "The partial answers do not fit in the context window, reducing them in {} groups",
+            groups.len()
+        );
+        let estimate = groups
+            .iter()
+            .filter_map(|group| {
+                let messages = client.messages(reduce_prompt, join_answers(&answers[group.clone()]));
+                client.estimate_cost(&client.payload(messages, false))
+            })
+            .fold(0.0, |estimate, cost| estimate + cost.total);
+        client.check_budget(estimate)?;
+
+        let mut reduced = Vec::with_capacity(groups.len());
+        for group in groups {
+            let response = client.complete(reduce_prompt, join_answers(&answers[group])).await?;
+            usage.add(&response.usage);
+            let Some(choice) = response.choices.into_iter().next() else {
+                bail!("API returned a response to a reduce request, but it contained no choices.");
+            };
+            reduced.push(choice.message.content);
+        }
+        answers = reduced;
+    }
+}
+
+/// Groups consecutive answers into reduce requests that fit in the context
+/// window.
+///
+/// # Returns
+/// * `Ok(Vec<Range<usize>>)` - The ranges of the answers of every group
+/// * `Err` - An error if a single answer does not fit, or if the check failed
+fn group_answers(
+    client: &InvokeClient,
+    reduce_prompt: &str,
+    answers: &[String],
+    check_context: &impl Fn(&[RequestMessage]) -> Result<()>,
+) -> Result<Vec<Range<usize>>> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    let mut end = start + 1;
+    while end <= answers.len() {
+        let messages = client.messages(reduce_prompt, join_answers(&answers[start..end]));
+        match check_context(&messages) {
+            Ok(()) => end += 1,
+            Err(error) if error.is::<ContextOverflow>() && end - start > 1 => {
+                groups.push(start..end - 1);
+                start = end - 1;
+            },
+            Err(error) => return Err(error),
+        }
+    }
+    groups.push(start..answers.len());
+
+    Ok(groups)
+}
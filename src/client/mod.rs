@@ -0,0 +1,754 @@
+use reqwest::{Client, Response};
+use serde_json::Value;
+use std::env;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::budget::{Budget, UnpricedModel, estimate_usage};
+use crate::cache::{ResponseCache, cache_key};
+use crate::error::{Error, Result};
+use crate::params::SamplingParams;
+use crate::pricing::{Cost, PricingTable};
+use crate::provider::{AuthScheme, Provider};
+use crate::registry::EndpointRegistry;
+use crate::retry::{RetryPolicy, is_retryable_error, is_retryable_status};
+use crate::schema::{ApiResponse, Usage};
+use crate::stream::{SseParser, StreamAccumulator};
+use crate::tools::Tool;
+use crate::{MessageContent, RequestMessage, RequestPayload, ResponseFormat, StreamOptions};
+
+/// Default time limit for a request (or, when streaming, for the gap between
+/// two received chunks).
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Asynchronous client for chat completion endpoints.
+///
+/// The client holds everything that stays the same between requests: the
+/// provider serving the endpoint, the API token, the model, the token limit,
+/// the prompt role, the optional structured output schema, the optional
+/// response cache, the prices costs are computed with and the optional
+/// spending budgets. It is created with
+/// [`InvokeClient::builder`].
+///
+/// # Examples
+/// ```no_run
+/// # async fn example() -> invoke_llm::error::Result<()> {
+/// use invoke_llm::InvokeClient;
+///
+/// let client = InvokeClient::builder()
+///     .endpoint("hf")
+///     .model("Qwen/Qwen3-Coder-480B-A35B-Instruct:novita")
+///     .tokens(2000)
+///     .build()?;
+///
+/// let response = client.complete("Review the code.", "fn main() {}").await?;
+/// println!("{}", response.choices[0].message.content);
+/// # Ok(())
+/// # }
+/// ```
+pub struct InvokeClient {
+    http: Client,
+    provider: Box<dyn Provider>,
+    api_token: String,
+    model: String,
+    tokens: u32,
+    reasoning: bool,
+    system_role: bool,
+    schema: Option<Value>,
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+    cache: Option<ResponseCache>,
+    tools: Option<Vec<Tool>>,
+    tool_choice: Option<Value>,
+    sampling: SamplingParams,
+    extra_body: Option<Value>,
+    pricing: PricingTable,
+    budget: Option<Budget>,
+}
+
+impl InvokeClient {
+    /// Creates a builder for a new client.
+    pub fn builder() -> InvokeClientBuilder {
+        InvokeClientBuilder::default()
+    }
+
+    /// Returns the provider serving the endpoint.
+    pub fn provider(&self) -> &dyn Provider {
+        self.provider.as_ref()
+    }
+
+    /// Returns the model identifier used for completions.
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Returns the maximum number of tokens to generate.
+    pub fn tokens(&self) -> u32 {
+        self.tokens
+    }
+
+    /// Returns whether the token limit is sent as reasoning tokens.
+    pub fn reasoning(&self) -> bool {
+        self.reasoning
+    }
+
+    /// Returns whether the prompt is sent with the "system" role.
+    pub fn system_role(&self) -> bool {
+        self.system_role
+    }
+
+    /// Returns the structured output schema, as loaded from the schema file.
+    pub fn schema(&self) -> Option<&Value> {
+        self.schema.as_ref()
+    }
+
+    /// Returns the functions the model may call.
+    pub fn tools(&self) -> &[Tool] {
+        self.tools.as_deref().unwrap_or_default()
+    }
+
+    /// Returns the sampling parameters sent with every request.
+    pub fn sampling(&self) -> &SamplingParams {
+        &self.sampling
+    }
+
+    /// Returns the cost of a request sent by the client.
+    ///
+    /// # Arguments
+    /// * `model` - The model the request was sent to
+    /// * `usage` - The token usage of the request
+    ///
+    /// # Returns
+    /// * `Some(Cost)` - The cost, priced for the client's endpoint
+    /// * `None` - If the model has no known price
+    pub fn cost(&self, model: &str, usage: &Usage) -> Option<Cost> {
+        self.pricing.cost(self.provider.name(), model, usage)
+    }
+
+    /// Returns the cost of a response to the client's model.
+    ///
+    /// The requested model is priced first, then the model reported by the
+    /// response, which may be more specific.
+    ///
+    /// # Arguments
+    /// * `response` - The response, with the usage to price
+    pub fn response_cost(&self, response: &ApiResponse) -> Option<Cost> {
+        self.cost(&self.model, &response.usage)
+            .or_else(|| self.cost(&response.model, &response.usage))
+    }
+
+    /// Estimates the cost of a payload before it is sent, see
+    /// [`estimate_usage`].
+    ///
+    /// # Arguments
+    /// * `payload` - The payload to send
+    ///
+    /// # Returns
+    /// * `Some(Cost)` - The estimated cost of the request
+    /// * `None` - If the model has no known price
+    pub fn estimate_cost(&self, payload: &RequestPayload<'_>) -> Option<Cost> {
+        self.cost(payload.model, &estimate_usage(payload))
+    }
+
+    /// Checks that a request of the given estimated cost fits in the
+    /// client's budgets.
+    ///
+    /// Requests are always allowed when the client has no budget.
+    ///
+    /// # Arguments
+    /// * `estimate` - The estimated cost of the request, in US dollars
+    ///
+    /// # Returns
+    /// * `Ok(())` - If every budget has room for the request
+    /// * `Err` - An [`Error::Budget`] error if a budget would be exceeded or
+    ///   the ledger could not be read
+    pub fn check_budget(&self, estimate: f64) -> Result<()> {
+        match &self.budget {
+            Some(budget) => budget.check(estimate).map_err(Error::Budget),
+            None => Ok(()),
+        }
+    }
+
+    /// Changes the model used for the next completions.
+    ///
+    /// # Arguments
+    /// * `model` - The model identifier
+    pub fn set_model(&mut self, model: impl Into<String>) {
+        self.model = model.into();
+    }
+
+    /// Changes the maximum number of tokens of the next completions.
+    ///
+    /// # Arguments
+    /// * `tokens` - Maximum number of tokens to generate
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the limit was changed
+    /// * `Err(Error::Config)` - If the limit is zero
+    pub fn set_tokens(&mut self, tokens: u32) -> Result<()> {
+        if tokens == 0 {
+            return Err(Error::Config("Token count must be greater than 0".to_owned()));
+        }
+        self.tokens = tokens;
+
+        Ok(())
+    }
+
+    /// Builds the message history for a prompt and a user input.
+    ///
+    /// The prompt is sent with the "system" role when the client was built
+    /// with `system_role(true)`, and with the "assistant" role otherwise.
+    ///
+    /// # Arguments
+    /// * `prompt` - The system prompt
+    /// * `input` - The user input, text or parts
+    pub fn messages(&self, prompt: impl Into<String>, input: impl Into<MessageContent>) -> Vec<RequestMessage> {
+        RequestMessage::prompt_and_input(prompt, input, self.system_role)
+    }
+
+    /// Builds the request payload for a message history.
+    ///
+    /// The token limit is placed in the field chosen by the provider, the
+    /// schema, if any, is sent as a `json_schema` response format, and the
+    /// tools, if any, are sent with their `tool_choice`. The sampling
+    /// parameters and the extra body fields are carried along.
+    ///
+    /// # Arguments
+    /// * `messages` - The message history to send
+    /// * `stream` - Whether to request a streaming response
+    pub fn payload(&self, messages: Vec<RequestMessage>, stream: bool) -> RequestPayload<'_> {
+        let mut payload = RequestPayload {
+            messages,
+            model: &self.model,
+            response_format: self.schema.clone().map(ResponseFormat::json_schema),
+            stream: stream.then_some(true),
+            stream_options: stream.then_some(StreamOptions { include_usage: true }),
+            tools: self.tools.clone(),
+            tool_choice: self.tool_choice.clone(),
+            sampling: self.sampling.clone(),
+            extra_body: self.extra_body.clone(),
+            ..Default::default()
+        };
+        payload.set_token_limit(self.provider.token_limit_field(self.reasoning), self.tokens);
+
+        payload
+    }
+
+    /// Queries the endpoint with a prompt and a user input.
+    ///
+    /// # Arguments
+    /// * `prompt` - The system prompt
+    /// * `input` - The user input, text or parts
+    ///
+    /// # Returns
+    /// * `Ok(ApiResponse)` - The parsed response
+    /// * `Err` - An error if the request failed
+    pub async fn complete(&self, prompt: impl Into<String>, input: impl Into<MessageContent>) -> Result<ApiResponse> {
+        self.send(self.messages(prompt, input)).await
+    }
+
+    /// Queries the endpoint with a message history.
+    ///
+    /// # Arguments
+    /// * `messages` - The message history to send
+    ///
+    /// # Returns
+    /// * `Ok(ApiResponse)` - The parsed response
+    /// * `Err` - An error if the request failed
+    pub async fn send(&self, messages: Vec<RequestMessage>) -> Result<ApiResponse> {
+        self.send_payload(&self.payload(messages, false)).await
+    }
+
+    /// Sends a prepared payload and waits for the whole response.
+    ///
+    /// When the client has a response cache, a cached response for the same
+    /// payload and endpoint is returned without querying the endpoint, and
+    /// fresh responses are stored in the cache.
+    ///
+    /// # Arguments
+    /// * `payload` - The payload to send
+    ///
+    /// # Returns
+    /// * `Ok(ApiResponse)` - The parsed response
+    /// * `Err` - An error if the request failed
+    pub async fn send_payload(&self, payload: &RequestPayload<'_>) -> Result<ApiResponse> {
+        let body = self.serialize(payload)?;
+        let key = self.cached(&body);
+        if let Some((_, Some(api_response))) = key {
+            return Ok(api_response);
+        }
+        self.preflight(payload)?;
+
+        let response = self.post(&body, true).await?;
+        let text = response.text().await?;
+        let api_response = self
+            .provider
+            .deserialize_response(&text)
+            .map_err(Error::InvalidResponse)?;
+
+        if let Some((key, _)) = key {
+            self.store(&key, &api_response);
+        }
+        self.record(payload, &api_response);
+
+        Ok(api_response)
+    }
+
+    /// Queries the endpoint with a message history, streaming the reply.
+    ///
+    /// The response is parsed as Server-Sent Events, and `on_delta` is called
+    /// with every content delta of the first choice as soon as it arrives.
+    ///
+    /// # Arguments
+    /// * `messages` - The message history to send
+    /// * `on_delta` - Callback receiving the content deltas
+    ///
+    /// # Returns
+    /// * `Ok(ApiResponse)` - The response rebuilt from all received chunks,
+    ///   with usage when the provider reported it
+    /// * `Err` - An error if the request failed or `on_delta` failed
+    pub async fn stream(
+        &self,
+        messages: Vec<RequestMessage>,
+        on_delta: impl FnMut(&str) -> std::io::Result<()>,
+    ) -> Result<ApiResponse> {
+        self.stream_payload(&self.payload(messages, true), on_delta).await
+    }
+
+    /// Sends a prepared streaming payload, see [`InvokeClient::stream`].
+    ///
+    /// On a cache hit, the whole cached content is passed to `on_delta` at
+    /// once.
+    ///
+    /// # Arguments
+    /// * `payload` - The payload to send, with `stream` set
+    /// * `on_delta` - Callback receiving the content deltas
+    ///
+    /// # Returns
+    /// * `Ok(ApiResponse)` - The response rebuilt from all received chunks
+    /// * `Err` - An error if the request failed or `on_delta` failed
+    pub async fn stream_payload(
+        &self,
+        payload: &RequestPayload<'_>,
+        mut on_delta: impl FnMut(&str) -> std::io::Result<()>,
+    ) -> Result<ApiResponse> {
+        let body = self.serialize(payload)?;
+        let key = self.cached(&body);
+        if let Some((_, Some(api_response))) = key {
+            if let Some(choice) = api_response.choices.first() {
+                on_delta(&choice.message.content)?;
+            }
+            return Ok(api_response);
+        }
+        self.preflight(payload)?;
+
+        let mut response = self.post(&body, false).await?;
+
+        let mut decoder = self.provider.stream_decoder();
+        let mut parser = SseParser::new();
+        let mut accumulator = StreamAccumulator::new();
+
+        let mut push = |data: String| -> Result<()> {
+            if let Some(chunk) = decoder.decode(&data).map_err(Error::InvalidResponse)?
+                && let Some(delta) = accumulator.push(chunk)
+            {
+                on_delta(&delta)?;
+            }
+            Ok(())
+        };
+
+        while let Some(bytes) = response.chunk().await? {
+            for data in parser.feed(&bytes) {
+                push(data)?;
+            }
+        }
+        if let Some(data) = parser.finish() {
+            push(data)?;
+        }
+
+        let api_response = accumulator.finish();
+        if let Some((key, _)) = key {
+            self.store(&key, &api_response);
+        }
+        self.record(payload, &api_response);
+
+        Ok(api_response)
+    }
+
+    /// Serializes a payload into the request body expected by the provider,
+    /// with the extra body fields merged in.
+    fn serialize(&self, payload: &RequestPayload<'_>) -> Result<Value> {
+        info!("Querying model '{}' model", payload.model);
+        info!("With URL: '{}'", self.provider.url());
+
+        let mut body = self
+            .provider
+            .serialize_request(payload)
+            .map_err(Error::InvalidRequest)?;
+        payload.merge_extra_body(&mut body);
+
+        Ok(body)
+    }
+
+    /// Looks up the response cached for a request body.
+    ///
+    /// Cache failures never fail the request: an unreadable entry is logged
+    /// and treated as a miss.
+    ///
+    /// # Returns
+    /// * `Some((key, cached))` - The cache key of the body and the cached
+    ///   response, if any
+    /// * `None` - If the client has no response cache
+    fn cached(&self, body: &Value) -> Option<(String, Option<ApiResponse>)> {
+        let cache = self.cache.as_ref()?;
+        let key = cache_key(self.provider.url(), body);
+
+        let cached = cache.get(&key).unwrap_or_else(|error| {
+            warn!("Ignoring cache entry {key}: {error:#}");
+            None
+        });
+        if cached.is_some() {
+            info!("Using cached response {key}");
+        }
+
+        Some((key, cached))
+    }
+
+    /// Stores a response in the cache, logging failures.
+    fn store(&self, key: &str, api_response: &ApiResponse) {
+        if let Some(cache) = &self.cache
+            && let Err(error) = cache.put(key, self.provider.url(), api_response)
+        {
+            warn!("Failed to cache response {key}: {error:#}");
+        }
+    }
+
+    /// Refuses a payload whose estimated cost does not fit in the budgets.
+    ///
+    /// Models without a known price are refused whenever a budget applies,
+    /// since their spending could not be counted.
+    fn preflight(&self, payload: &RequestPayload<'_>) -> Result<()> {
+        let Some(budget) = &self.budget else {
+            return Ok(());
+        };
+
+        match self.estimate_cost(payload) {
+            Some(estimate) => self.check_budget(estimate.total),
+            None if budget.is_limited() => {
+                Err(Error::Budget(
+                    UnpricedModel {
+                        endpoint: self.provider.name().to_owned(),
+                        model: payload.model.to_owned(),
+                    }
+                    .into(),
+                ))
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Records the usage of a fresh response in the ledger, logging failures.
+    ///
+    /// Cached responses are not recorded, since they cost nothing.
+    fn record(&self, payload: &RequestPayload<'_>, api_response: &ApiResponse) {
+        let Some(budget) = &self.budget else {
+            return;
+        };
+        let usage = &api_response.usage;
+        let cost = self
+            .cost(payload.model, usage)
+            .or_else(|| self.cost(&api_response.model, usage));
+
+        if let Err(error) = budget.record(self.provider.name(), payload.model, usage, cost) {
+            warn!("Failed to record the request in the ledger: {error:#}");
+        }
+    }
+
+    /// Sends a payload and checks that the API answered with a 2xx status.
+    ///
+    /// Transient failures (connection errors, timeouts, 408, 429 and 5xx
+    /// statuses) are retried according to the client's [`RetryPolicy`].
+    ///
+    /// # Arguments
+    /// * `body` - The serialized request body to send
+    /// * `limit_total_time` - Whether the timeout applies to the whole request
+    ///   rather than to the gaps between received chunks
+    async fn post(&self, body: &Value, limit_total_time: bool) -> Result<Response> {
+        let mut retry = 0;
+        loop {
+            let mut request = self.http.post(self.provider.url()).json(body);
+            for (name, value) in self.provider.headers(&self.api_token) {
+                request = request.header(name, value);
+            }
+            if limit_total_time {
+                request = request.timeout(self.timeout);
+            }
+
+            let retries_left = retry < self.retry_policy.max_retries;
+            let (error, delay) = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    let body = response.text().await?;
+                    if !retries_left || !is_retryable_status(status) {
+                        return Err(Error::Api { status, body });
+                    }
+                    (
+                        format!("status {status}"),
+                        self.retry_policy.delay(retry, Some(&headers)),
+                    )
+                },
+                Err(error) => {
+                    if !retries_left || !is_retryable_error(&error) {
+                        return Err(error.into());
+                    }
+                    (error.to_string(), self.retry_policy.delay(retry, None))
+                },
+            };
+
+            retry += 1;
+            warn!(
+                "Attempt {retry} of {} failed ({error}), retrying in {delay:.2?}",
+                self.retry_policy.max_retries + 1
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Builder for [`InvokeClient`].
+///
+/// `endpoint` (or `provider`), `model` and `tokens` are required. The API
+/// token is read from the provider's environment variable unless it is set
+/// explicitly or the provider does not authenticate.
+#[derive(Default)]
+pub struct InvokeClientBuilder {
+    endpoint: Option<String>,
+    registry: Option<EndpointRegistry>,
+    provider: Option<Box<dyn Provider>>,
+    api_token: Option<String>,
+    api_key_env: Option<String>,
+    model: Option<String>,
+    tokens: Option<u32>,
+    reasoning: bool,
+    system_role: Option<bool>,
+    schema: Option<Value>,
+    timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    cache: Option<ResponseCache>,
+    tools: Option<Vec<Tool>>,
+    tool_choice: Option<Value>,
+    sampling: SamplingParams,
+    extra_body: Option<Value>,
+    pricing: Option<PricingTable>,
+    budget: Option<Budget>,
+}
+
+impl InvokeClientBuilder {
+    /// Sets the endpoint name (e.g. "openai", "hf") or a custom URL.
+    #[must_use]
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Sets the registry the endpoint name is looked up in (defaults to
+    /// [`EndpointRegistry::builtin`]).
+    #[must_use]
+    pub fn registry(mut self, registry: EndpointRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Sets the provider directly, taking precedence over `endpoint`.
+    #[must_use]
+    pub fn provider(mut self, provider: Box<dyn Provider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Sets the API token instead of reading it from the environment.
+    #[must_use]
+    pub fn api_token(mut self, api_token: impl Into<String>) -> Self {
+        self.api_token = Some(api_token.into());
+        self
+    }
+
+    /// Sets the environment variable the API token is read from, instead of
+    /// the provider's default one.
+    #[must_use]
+    pub fn api_key_env(mut self, api_key_env: impl Into<String>) -> Self {
+        self.api_key_env = Some(api_key_env.into());
+        self
+    }
+
+    /// Sets the model identifier to use for completions.
+    #[must_use]
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Sets the maximum number of tokens to generate.
+    #[must_use]
+    pub fn tokens(mut self, tokens: u32) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+
+    /// Sets whether a reasoning model is queried, which changes the field
+    /// carrying the token limit.
+    #[must_use]
+    pub fn reasoning(mut self, reasoning: bool) -> Self {
+        self.reasoning = reasoning;
+        self
+    }
+
+    /// Sets whether the prompt is sent with the "system" role instead of the
+    /// "assistant" role.
+    ///
+    /// When not set, the role required by the provider is used, falling back
+    /// to the "assistant" role.
+    #[must_use]
+    pub fn system_role(mut self, system_role: bool) -> Self {
+        self.system_role = Some(system_role);
+        self
+    }
+
+    /// Sets the JSON schema for structured output.
+    #[must_use]
+    pub fn schema(mut self, schema: Value) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Sets the request timeout (defaults to [`DEFAULT_TIMEOUT`]).
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the policy for retrying transient failures (defaults to
+    /// [`RetryPolicy::default`]).
+    #[must_use]
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Sets the cache storing responses on disk (no caching by default).
+    #[must_use]
+    pub fn cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Sets the functions the model may call (no tools by default).
+    ///
+    /// Running the calls is left to the caller, see
+    /// [`crate::tools::run_tool_loop`].
+    #[must_use]
+    pub fn tools(mut self, tools: Vec<Tool>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Sets the `tool_choice` sent with the tools, see
+    /// [`crate::tools::tool_choice`].
+    #[must_use]
+    pub fn tool_choice(mut self, tool_choice: Value) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+
+    /// Sets the sampling parameters sent with every request (provider
+    /// defaults by default).
+    #[must_use]
+    pub fn sampling(mut self, sampling: SamplingParams) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Sets fields deep-merged into every serialized request body, see
+    /// [`crate::params::merge_json`].
+    #[must_use]
+    pub fn extra_body(mut self, extra_body: Value) -> Self {
+        self.extra_body = Some(extra_body);
+        self
+    }
+
+    /// Sets the prices the costs are computed with (defaults to
+    /// [`PricingTable::builtin`]).
+    #[must_use]
+    pub fn pricing(mut self, pricing: PricingTable) -> Self {
+        self.pricing = Some(pricing);
+        self
+    }
+
+    /// Sets the budgets every request is checked against and recorded in (no
+    /// budget by default).
+    ///
+    /// Requests that would exceed a budget fail with [`Error::Budget`].
+    #[must_use]
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Validates the configuration and creates the client.
+    ///
+    /// # Returns
+    /// * `Ok(InvokeClient)` - The configured client
+    /// * `Err` - An error if a required setting is missing, the token count is
+    ///   zero or no API token is available
+    pub fn build(self) -> Result<InvokeClient> {
+        let provider = match (self.provider, self.endpoint) {
+            (Some(provider), _) => provider,
+            (None, Some(endpoint)) => self.registry.unwrap_or_default().resolve(&endpoint),
+            (None, None) => return Err(Error::Config("An endpoint is required".to_owned())),
+        };
+        let Some(model) = self.model else {
+            return Err(Error::Config("A model is required".to_owned()));
+        };
+        let tokens = match self.tokens {
+            Some(0) => return Err(Error::Config("Token count must be greater than 0".to_owned())),
+            Some(tokens) => tokens,
+            None => return Err(Error::Config("A token count is required".to_owned())),
+        };
+
+        let api_token = match self.api_token {
+            Some(api_token) => api_token,
+            None if provider.auth_scheme() == AuthScheme::None => String::new(),
+            None => {
+                let api_key_name = self.api_key_env.as_deref().unwrap_or(provider.api_key_env());
+                env::var(api_key_name).map_err(|_| Error::MissingApiKey(api_key_name.to_owned()))?
+            },
+        };
+
+        let system_role = self.system_role.or_else(|| provider.system_role()).unwrap_or_default();
+        let timeout = self.timeout.unwrap_or(DEFAULT_TIMEOUT);
+        let http = Client::builder().read_timeout(timeout).build()?;
+
+        Ok(InvokeClient {
+            http,
+            provider,
+            api_token,
+            model,
+            tokens,
+            reasoning: self.reasoning,
+            system_role,
+            schema: self.schema,
+            timeout,
+            retry_policy: self.retry_policy.unwrap_or_default(),
+            cache: self.cache,
+            tools: self.tools,
+            tool_choice: self.tool_choice,
+            sampling: self.sampling,
+            extra_body: self.extra_body,
+            pricing: self.pricing.unwrap_or_default(),
+            budget: self.budget,
+        })
+    }
+}
@@ -0,0 +1,474 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::budget::{Budget, BudgetConfig};
+use crate::limits::{LimitsTable, ModelLimits};
+use crate::mcp::McpServerConfig;
+use crate::pricing::{ModelPrice, PricingTable};
+use crate::provider::AuthScheme;
+use crate::registry::{EndpointConfig, EndpointRegistry};
+use crate::schema_lint::load_schema;
+use crate::{InvokeClient, InvokeClientBuilder};
+
+/// Name of the configuration directory inside the user's configuration
+/// directory.
+const CONFIG_DIR_NAME: &str = "invoke-llm";
+
+/// Name of the user configuration file.
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Name of the project-local configuration file, looked up in the current
+/// directory.
+pub const LOCAL_CONFIG_FILE: &str = ".invoke-llm.toml";
+
+/// Named set of request settings.
+///
+/// Every field is optional: unset fields fall back to the command-line
+/// arguments or to the defaults.
+///
+/// # Fields
+/// * `endpoint` - The endpoint name (e.g. "openai", "hf") or a custom URL
+/// * `model` - The model identifier to use for completions
+/// * `tokens` - Maximum number of tokens to generate
+/// * `reasoning` - Whether to use reasoning tokens instead of regular max
+///   tokens
+/// * `system_role` - Whether to use "system" role instead of "assistant" role
+/// * `schema` - Path to a JSON schema file for structured output, relative to
+///   the configuration file that sets it
+/// * `schema_wrap` - Whether the schema file holds a bare JSON Schema, wrapped
+///   into the `json_schema` response format
+/// * `api_key_env` - Environment variable holding the API key
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_role: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_wrap: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+}
+
+impl Profile {
+    /// Overrides the settings of the profile with the ones set in `other`.
+    ///
+    /// # Arguments
+    /// * `other` - The settings taking precedence
+    ///
+    /// # Returns
+    /// The merged profile
+    #[must_use]
+    pub fn merge(self, other: Profile) -> Profile {
+        Profile {
+            endpoint: other.endpoint.or(self.endpoint),
+            model: other.model.or(self.model),
+            tokens: other.tokens.or(self.tokens),
+            reasoning: other.reasoning.or(self.reasoning),
+            system_role: other.system_role.or(self.system_role),
+            schema: other.schema.or(self.schema),
+            schema_wrap: other.schema_wrap.or(self.schema_wrap),
+            api_key_env: other.api_key_env.or(self.api_key_env),
+        }
+    }
+
+    /// Creates a client builder configured with the settings of the profile.
+    ///
+    /// The schema file, if any, is read, wrapped if requested and checked
+    /// against the structured output rules. Settings that are not set
+    /// are left to the builder, which reports missing required ones.
+    ///
+    /// # Arguments
+    /// * `registry` - The registry the endpoint name is looked up in
+    ///
+    /// # Returns
+    /// * `Ok(InvokeClientBuilder)` - The configured builder
+    /// * `Err` - An error if the schema file could not be read or parsed, or
+    ///   would be rejected by the API
+    pub fn builder(&self, registry: EndpointRegistry) -> Result<InvokeClientBuilder> {
+        let mut builder = InvokeClient::builder()
+            .registry(registry)
+            .reasoning(self.reasoning.unwrap_or_default());
+        if let Some(system_role) = self.system_role {
+            builder = builder.system_role(system_role);
+        }
+        if let Some(endpoint) = &self.endpoint {
+            builder = builder.endpoint(endpoint);
+        }
+        if let Some(model) = &self.model {
+            builder = builder.model(model);
+        }
+        if let Some(tokens) = self.tokens {
+            builder = builder.tokens(tokens);
+        }
+        if let Some(schema) = &self.schema {
+            builder = builder.schema(load_schema(schema, self.schema_wrap.unwrap_or_default())?);
+        }
+        if let Some(api_key_env) = &self.api_key_env {
+            builder = builder.api_key_env(api_key_env);
+        }
+
+        Ok(builder)
+    }
+
+    /// Resolves a relative schema path against a base directory.
+    fn resolve_paths(&mut self, base_dir: &Path) {
+        if let Some(schema) = &self.schema
+            && schema.is_relative()
+        {
+            self.schema = Some(base_dir.join(schema));
+        }
+    }
+}
+
+/// Configuration loaded from the user and project configuration files.
+///
+/// # Fields
+/// * `default_profile` - Profile used when none is selected explicitly
+/// * `profiles` - The named profiles
+/// * `endpoints` - User-defined endpoints, extending or replacing the built-in
+///   ones
+/// * `mcp_servers` - MCP servers whose tools can be offered to the model
+/// * `pricing` - Prices of models by endpoint name, extending or replacing the
+///   built-in ones
+/// * `limits` - Token limits of models, extending or replacing the built-in
+///   ones
+/// * `budget` - Spending ledger and budgets
+/// * `sources` - The configuration files the configuration was loaded from
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    #[serde(default)]
+    pub endpoints: BTreeMap<String, EndpointConfig>,
+    #[serde(default)]
+    pub mcp_servers: BTreeMap<String, McpServerConfig>,
+    #[serde(default)]
+    pub pricing: BTreeMap<String, BTreeMap<String, ModelPrice>>,
+    #[serde(default)]
+    pub limits: BTreeMap<String, ModelLimits>,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Loads the user configuration file, then the project-local one.
+    ///
+    /// Missing files are skipped. Settings of the project-local file take
+    /// precedence over the ones of the user file, within the limits set by
+    /// [`Config::merge_local`].
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - The merged configuration (empty if no file exists)
+    /// * `Err` - An error if a configuration file could not be read or parsed,
+    ///   or if the project-local file overrides settings it may not change
+    pub fn load() -> Result<Config> {
+        let mut config = Config::default();
+        for path in config_paths() {
+            if !path.is_file() {
+                continue;
+            }
+            let file = Config::from_file(&path)?;
+            if path == Path::new(LOCAL_CONFIG_FILE) {
+                config.merge_local(file)?;
+            } else {
+                config.merge(file);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Loads a configuration file.
+    ///
+    /// Relative schema and ledger paths are resolved against the directory of
+    /// the file.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the TOML configuration file
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - The parsed configuration
+    /// * `Err` - An error if the file could not be read or parsed
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read configuration file {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut config = Config::parse(&content, base_dir)
+            .with_context(|| format!("Failed to parse configuration file {}", path.display()))?;
+        config.sources.push(path.to_path_buf());
+
+        Ok(config)
+    }
+
+    /// Parses the content of a configuration file.
+    ///
+    /// # Arguments
+    /// * `content` - The TOML content
+    /// * `base_dir` - Directory relative schema and ledger paths are resolved
+    ///   against
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - The parsed configuration
+    /// * `Err` - An error if the content is not a valid configuration
+    pub fn parse(content: &str, base_dir: &Path) -> Result<Config> {
+        let mut config: Config = toml::from_str(content)?;
+        for profile in config.profiles.values_mut() {
+            profile.resolve_paths(base_dir);
+        }
+        config.budget.resolve_paths(base_dir);
+
+        Ok(config)
+    }
+
+    /// Merges another configuration into this one.
+    ///
+    /// Profiles defined in both are merged setting by setting, with the
+    /// settings of `other` taking precedence. Endpoints, MCP servers, model
+    /// prices and limits defined in both are replaced by the ones of `other`,
+    /// and budget settings are overridden by the ones set in `other`.
+    ///
+    /// # Arguments
+    /// * `other` - The configuration taking precedence
+    pub fn merge(&mut self, other: Config) {
+        if other.default_profile.is_some() {
+            self.default_profile = other.default_profile;
+        }
+        for (name, profile) in other.profiles {
+            let merged = match self.profiles.remove(&name) {
+                Some(existing) => existing.merge(profile),
+                None => profile,
+            };
+            self.profiles.insert(name, merged);
+        }
+        self.endpoints.extend(other.endpoints);
+        self.mcp_servers.extend(other.mcp_servers);
+        for (endpoint, prices) in other.pricing {
+            self.pricing.entry(endpoint).or_default().extend(prices);
+        }
+        self.limits.extend(other.limits);
+        self.budget.merge(other.budget);
+        self.sources.extend(other.sources);
+    }
+
+    /// Merges a project-local configuration into this one.
+    ///
+    /// The project-local file comes with the project rather than from the
+    /// user, so it may not send an API key to a host the user did not
+    /// configure: its endpoints must either use the URL and API-key variable
+    /// of an endpoint built in or declared in the user file, or send no key
+    /// (`auth = "none"`), and its profiles may only select known endpoints.
+    /// The `api_key_env` of its profiles and its `default_profile` are ignored,
+    /// and its overrides of existing endpoints are logged. Nor may it replace
+    /// an MCP server declared in the user file, which would run another
+    /// command when the server is selected. Its budgets may only lower the
+    /// other ones, its ledger path is ignored, and so are its prices of models
+    /// already priced, unless they are higher.
+    ///
+    /// # Arguments
+    /// * `other` - The project-local configuration, taking precedence
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the configuration was merged
+    /// * `Err` - An error if it declares an endpoint or a profile that would
+    ///   send an API key to an unknown host, or an existing MCP server
+    pub fn merge_local(&mut self, mut other: Config) -> Result<()> {
+        let source = other
+            .sources
+            .first()
+            .map_or_else(|| LOCAL_CONFIG_FILE.to_owned(), |path| path.display().to_string());
+
+        let registry = self.registry();
+        for (name, endpoint) in &other.endpoints {
+            let known = registry
+                .iter()
+                .any(|(_, known)| known.url == endpoint.url && known.api_key_env == endpoint.api_key_env);
+            if !known && endpoint.auth != Some(AuthScheme::None) {
+                bail!(
+                    "{source} may not send the {} API key to {}: the endpoint '{name}' must use the URL and API-key \
+                     variable of a built-in or user-defined endpoint, or set auth = \"none\"",
+                    endpoint.api_key_env,
+                    endpoint.url
+                );
+            }
+            if registry.get(name).is_some() {
+                warn!("{source} overrides the endpoint '{name}'");
+            }
+        }
+        if other.default_profile.take().is_some() {
+            warn!("Ignoring the default profile of {source}, select its profiles with --profile");
+        }
+        for (name, profile) in &mut other.profiles {
+            if profile.api_key_env.take().is_some() {
+                warn!("Ignoring the API-key variable of the profile '{name}' of {source}");
+            }
+            if let Some(endpoint) = &profile.endpoint
+                && registry.get(endpoint).is_none()
+                && !other.endpoints.contains_key(endpoint)
+                && !registry.iter().any(|(_, known)| known.url == *endpoint)
+            {
+                bail!(
+                    "{source} may not send the API key to {endpoint}: the profile '{name}' must use a built-in or \
+                     user-defined endpoint"
+                );
+            }
+        }
+        if let Some(name) = other
+            .mcp_servers
+            .keys()
+            .find(|name| self.mcp_servers.contains_key(*name))
+        {
+            bail!("{source} may not replace the MCP server '{name}' of the user configuration");
+        }
+        if other.budget.ledger.is_some() {
+            warn!("Ignoring the ledger path of {source}");
+        }
+        let pricing = self.pricing();
+        for (endpoint, prices) in &mut other.pricing {
+            prices.retain(|model, price| {
+                match pricing.get(endpoint, model) {
+                    Some(known) if !price.is_at_least(known) => {
+                        warn!(
+                            "Ignoring the price of '{model}' on '{endpoint}' in {source}, lower than the configured \
+                             one"
+                        );
+                        false
+                    },
+                    _ => true,
+                }
+            });
+        }
+
+        let budget = std::mem::take(&mut other.budget);
+        self.merge(other);
+        self.budget.tighten(budget);
+        Ok(())
+    }
+
+    /// Returns the built-in endpoints extended with the user-defined ones.
+    pub fn registry(&self) -> EndpointRegistry {
+        let mut registry = EndpointRegistry::builtin();
+        for (name, endpoint) in &self.endpoints {
+            registry.insert(name, endpoint.clone());
+        }
+
+        registry
+    }
+
+    /// Returns the built-in model prices extended with the user-defined ones.
+    pub fn pricing(&self) -> PricingTable {
+        let mut pricing = PricingTable::builtin();
+        for (endpoint, prices) in &self.pricing {
+            for (model, price) in prices {
+                pricing.insert(endpoint, model, *price);
+            }
+        }
+
+        pricing
+    }
+
+    /// Returns the built-in model limits extended with the user-defined ones.
+    pub fn limits(&self) -> LimitsTable {
+        let mut limits = LimitsTable::builtin();
+        for (model, model_limits) in &self.limits {
+            limits.insert(model, *model_limits);
+        }
+
+        limits
+    }
+
+    /// Returns the budgets of the requests sent with a profile.
+    ///
+    /// # Arguments
+    /// * `profile` - The name of the selected profile, if any
+    pub fn budget(&self, profile: Option<&str>) -> Budget {
+        Budget::new(self.budget.clone(), profile)
+    }
+
+    /// Returns the settings of an MCP server.
+    ///
+    /// # Arguments
+    /// * `name` - The server name
+    ///
+    /// # Returns
+    /// * `Ok(&McpServerConfig)` - The server settings
+    /// * `Err` - An error if the server is not declared
+    pub fn mcp_server(&self, name: &str) -> Result<&McpServerConfig> {
+        match self.mcp_servers.get(name) {
+            Some(server) => Ok(server),
+            None if self.mcp_servers.is_empty() => {
+                bail!("MCP server '{name}' is not declared: no MCP server is configured")
+            },
+            None => {
+                bail!(
+                    "MCP server '{name}' is not declared (available: {})",
+                    self.mcp_servers.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            },
+        }
+    }
+
+    /// Returns the selected profile.
+    ///
+    /// # Arguments
+    /// * `name` - The explicitly selected profile, falling back to
+    ///   `default_profile` when not provided
+    ///
+    /// # Returns
+    /// * `Ok(Some((name, Profile)))` - The selected profile and its name
+    /// * `Ok(None)` - If no profile is selected
+    /// * `Err` - An error if the selected profile is not defined
+    pub fn profile(&self, name: Option<&str>) -> Result<Option<(&str, &Profile)>> {
+        let Some(name) = name.or(self.default_profile.as_deref()) else {
+            return Ok(None);
+        };
+
+        match self.profiles.get_key_value(name) {
+            Some((name, profile)) => Ok(Some((name, profile))),
+            None if self.profiles.is_empty() => bail!("Profile '{name}' is not defined: no profile is configured"),
+            None => {
+                bail!(
+                    "Profile '{name}' is not defined (available: {})",
+                    self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+            },
+        }
+    }
+}
+
+/// Returns the configuration files, in increasing order of precedence.
+///
+/// These are `$XDG_CONFIG_HOME/invoke-llm/config.toml` (falling back to
+/// `~/.config/invoke-llm/config.toml`) and `.invoke-llm.toml` in the current
+/// directory.
+pub fn config_paths() -> Vec<PathBuf> {
+    let user_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    };
+
+    user_dir
+        .map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+        .into_iter()
+        .chain([PathBuf::from(LOCAL_CONFIG_FILE)])
+        .collect()
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::InvokeClient;
+use crate::pricing::Cost;
+use crate::schema::{ApiResponse, Choice, ContentFilterResults, Usage};
+
+/// Version of the [`Envelope`] layout, increased on incompatible changes.
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// Machine-readable record of a completion, written by `--format json` and
+/// `--format jsonl`.
+///
+/// The envelope keeps the metadata of the response that the plain text output
+/// drops, along with the timing and the parameters of the request. Its fields
+/// are always present (`null` when unknown), so that pipelines can rely on
+/// them.
+///
+/// # Fields
+/// * `version` - The layout version, [`ENVELOPE_VERSION`]
+/// * `id` - Identifier of the response
+/// * `model` - Model that generated the response, as reported by the API
+/// * `created` - Unix timestamp of the response
+/// * `content` - Content of the first choice (empty without choices)
+/// * `finish_reason` - Finish reason of the first choice
+/// * `choices` - All the choices of the response
+/// * `usage` - Token usage, summed over the requests of the run
+/// * `cost` - Cost of the usage, `null` when the model has no known price
+/// * `system_fingerprint` - Backend configuration fingerprint
+/// * `service_tier` - Service tier that processed the request
+/// * `content_filter_results` - Content filtering of the first choice (Azure)
+/// * `latency_ms` - Time spent waiting for the response, in milliseconds
+/// * `endpoint` - The provider the request was sent to
+/// * `params` - The parameters of the request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope {
+    pub version: u32,
+    pub id: String,
+    pub model: String,
+    pub created: i64,
+    pub content: String,
+    pub finish_reason: Option<String>,
+    pub choices: Vec<Choice>,
+    pub usage: Usage,
+    pub cost: Option<Cost>,
+    pub system_fingerprint: Option<String>,
+    pub service_tier: Option<String>,
+    pub content_filter_results: Option<ContentFilterResults>,
+    pub latency_ms: u64,
+    pub endpoint: EndpointInfo,
+    pub params: RequestParams,
+}
+
+/// The provider a request was sent to.
+///
+/// # Fields
+/// * `name` - The endpoint name (e.g. "openai"), the URL itself for a custom
+///   endpoint
+/// * `url` - The chat completions URL
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EndpointInfo {
+    pub name: String,
+    pub url: String,
+}
+
+/// The parameters a request was sent with.
+///
+/// # Fields
+/// * `model` - The requested model
+/// * `tokens` - Maximum number of tokens to generate
+/// * `reasoning` - Whether the limit was sent as reasoning tokens
+/// * `system_role` - Whether the prompt was sent with the "system" role
+/// * `schema` - Name of the structured output schema, if any
+/// * `tools` - Names of the functions the model could call
+/// * `stream` - Whether the response was streamed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestParams {
+    pub model: String,
+    pub tokens: u32,
+    pub reasoning: bool,
+    pub system_role: bool,
+    pub schema: Option<String>,
+    pub tools: Vec<String>,
+    pub stream: bool,
+}
+
+impl Envelope {
+    /// Builds the envelope of a response.
+    ///
+    /// # Arguments
+    /// * `response` - The response, with the usage of the whole run
+    /// * `client` - The client the request was sent with
+    /// * `latency` - Time spent waiting for the response
+    /// * `stream` - Whether the response was streamed
+    pub fn new(response: ApiResponse, client: &InvokeClient, latency: Duration, stream: bool) -> Self {
+        let first_choice = response.choices.first();
+        let content = first_choice
+            .map(|choice| choice.message.content.clone())
+            .unwrap_or_default();
+        let finish_reason = first_choice
+            .map(|choice| choice.finish_reason.clone())
+            .filter(|reason| !reason.is_empty());
+        let content_filter_results = first_choice.and_then(|choice| choice.content_filter_results.clone());
+        let cost = client.response_cost(&response);
+
+        let provider = client.provider();
+        Self {
+            version: ENVELOPE_VERSION,
+            id: response.id,
+            model: response.model,
+            created: response.created,
+            content,
+            finish_reason,
+            choices: response.choices,
+            usage: response.usage,
+            cost,
+            system_fingerprint: response.system_fingerprint,
+            service_tier: response.service_tier,
+            content_filter_results,
+            latency_ms: u64::try_from(latency.as_millis()).unwrap_or(u64::MAX),
+            endpoint: EndpointInfo {
+                name: provider.name().to_owned(),
+                url: provider.url().to_owned(),
+            },
+            params: RequestParams {
+                model: client.model().to_owned(),
+                tokens: client.tokens(),
+                reasoning: client.reasoning(),
+                system_role: client.system_role(),
+                schema: client
+                    .schema()
+                    .and_then(|schema| schema["name"].as_str())
+                    .map(str::to_owned),
+                tools: client.tools().iter().map(|tool| tool.function.name.clone()).collect(),
+                stream,
+            },
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use reqwest::StatusCode;
+
+/// Result type returned by the [`crate::InvokeClient`] API.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors returned by the [`crate::InvokeClient`] API.
+///
+/// Each variant describes one way a completion request can fail, so that
+/// callers can react to specific failures (e.g. a missing API token or a
+/// non-2xx status) without parsing error messages.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The client configuration is incomplete or invalid.
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+
+    /// No API token was given and the provider's variable is not set.
+    #[error("{0} variable not set. Please provide your API token.")]
+    MissingApiKey(String),
+
+    /// The payload could not be translated into the provider's format.
+    #[error("Failed to build request: {0:#}")]
+    InvalidRequest(anyhow::Error),
+
+    /// The request could not be sent or the response could not be read.
+    #[error("Failed to send request to the API: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The API answered with a non-2xx status.
+    #[error("API request failed with status {status}: {body}")]
+    Api { status: StatusCode, body: String },
+
+    /// The API answered with a body that is not a valid response.
+    #[error("{0:#}")]
+    InvalidResponse(anyhow::Error),
+
+    /// The request would exceed a spending budget (see
+    /// [`crate::budget::BudgetExceeded`]), or the ledger could not be read.
+    #[error("{0:#}")]
+    Budget(anyhow::Error),
+
+    /// Streamed content could not be written out.
+    #[error("Failed to write streamed content: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,171 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+use crate::{ContentPart, MessageContent};
+
+/// Maximum size of an image file, as accepted by the `OpenAI` API.
+pub const MAX_IMAGE_SIZE: u64 = 20 * 1024 * 1024;
+
+/// Detects the MIME type of an image from its first bytes.
+///
+/// # Arguments
+/// * `bytes` - The content of the image file
+///
+/// # Returns
+/// * `Some(&str)` - The MIME type of a PNG, JPEG, GIF or WebP image
+/// * `None` - If the format is not one of these
+pub fn detect_mime(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Reads an image file into a base64 `data:` URI.
+///
+/// # Arguments
+/// * `path` - Path of the image file
+///
+/// # Returns
+/// * `Ok(String)` - The `data:<mime>;base64,<data>` URI
+/// * `Err` - An error if the file could not be read, is larger than
+///   [`MAX_IMAGE_SIZE`] or is not a PNG, JPEG, GIF or WebP image
+pub fn data_uri(path: &Path) -> Result<String> {
+    let size = fs::metadata(path)
+        .with_context(|| format!("Failed to read image {}", path.display()))?
+        .len();
+    if size > MAX_IMAGE_SIZE {
+        bail!(
+            "Image {} is too large ({size} bytes, the limit is {MAX_IMAGE_SIZE} bytes)",
+            path.display()
+        );
+    }
+
+    let bytes = fs::read(path).with_context(|| format!("Failed to read image {}", path.display()))?;
+    let Some(mime) = detect_mime(&bytes) else {
+        bail!(
+            "Unsupported image format for {}: PNG, JPEG, GIF and WebP are supported",
+            path.display()
+        );
+    };
+
+    Ok(format!("data:{mime};base64,{}", STANDARD.encode(&bytes)))
+}
+
+/// Returns whether an image reference is a URL sent as is rather than a
+/// local file.
+fn is_url(source: &str) -> bool {
+    source.starts_with("data:") || source.contains("://")
+}
+
+/// Creates the image part for a `--image` argument.
+///
+/// # Arguments
+/// * `source` - An `http(s)` URL or `data:` URI, sent as is, or the path of an
+///   image file, embedded as a `data:` URI
+///
+/// # Returns
+/// * `Ok(ContentPart)` - The `image_url` part
+/// * `Err` - An error if the image file could not be embedded
+pub fn image_part(source: &str) -> Result<ContentPart> {
+    if is_url(source) {
+        return Ok(ContentPart::image_url(source));
+    }
+
+    Ok(ContentPart::image_url(data_uri(Path::new(source))?))
+}
+
+/// Builds the content of a user input, embedding the images it references.
+///
+/// Markdown image references to local files, such as `![diagram](arch.png)`,
+/// are replaced by the image, resolved relative to `base_dir`. References to
+/// URLs, and to files that cannot be embedded (missing, too large or in an
+/// unsupported format), are left in the text, the latter with a warning. The
+/// `images` are appended after the text.
+///
+/// # Arguments
+/// * `text` - The user input
+/// * `base_dir` - Directory the referenced files are resolved against
+/// * `images` - Additional images, as accepted by [`image_part`]
+///
+/// # Returns
+/// * `Ok(MessageContent)` - Plain text when there is no image, parts otherwise
+/// * `Err` - An error if one of the `images` could not be embedded
+pub fn embed_images(text: String, base_dir: &Path, images: &[String]) -> Result<MessageContent> {
+    let mut parts = Vec::new();
+    let mut rest = text.as_str();
+    let mut pending = String::new();
+    while let Some((before, target, after)) = next_reference(rest) {
+        pending.push_str(before);
+        let reference = &rest[before.len()..rest.len() - after.len()];
+        if is_url(target) {
+            pending.push_str(reference);
+        } else {
+            match data_uri(&base_dir.join(target)) {
+                Ok(uri) => {
+                    push_text(&mut parts, &mut pending);
+                    parts.push(ContentPart::image_url(uri));
+                },
+                Err(error) => {
+                    warn!("Leaving the image reference {reference} as text: {error:#}");
+                    pending.push_str(reference);
+                },
+            }
+        }
+        rest = after;
+    }
+    if parts.is_empty() && images.is_empty() {
+        return Ok(MessageContent::Text(text));
+    }
+
+    pending.push_str(rest);
+    push_text(&mut parts, &mut pending);
+    for image in images {
+        parts.push(image_part(image)?);
+    }
+
+    Ok(MessageContent::Parts(parts))
+}
+
+/// Moves the pending text into a text part, unless it is blank.
+fn push_text(parts: &mut Vec<ContentPart>, pending: &mut String) {
+    let text = std::mem::take(pending);
+    if !text.trim().is_empty() {
+        parts.push(ContentPart::text(text.trim()));
+    }
+}
+
+/// Finds the next Markdown image reference.
+///
+/// # Returns
+/// * `Some((before, target, after))` - The text before the reference, its
+///   target (without the optional title) and the text after it
+/// * `None` - If there is no further reference
+fn next_reference(text: &str) -> Option<(&str, &str, &str)> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find("![").map(|index| offset + index) {
+        let label_end = start + 2 + text[start + 2..].find("](")?;
+        let target_start = label_end + 2;
+        let target_end = target_start + text[target_start..].find(')')?;
+        // A label spanning several lines is not a reference
+        if !text[start + 2..label_end].contains('\n') {
+            let target = text[target_start..target_end]
+                .split_whitespace()
+                .next()
+                .unwrap_or_default();
+            if !target.is_empty() {
+                return Some((&text[..start], target, &text[target_end + 1..]));
+            }
+        }
+        offset = start + 2;
+    }
+
+    None
+}
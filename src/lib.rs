@@ -0,0 +1,478 @@
+pub mod anthropic;
+pub mod audio;
+pub mod batch;
+pub mod budget;
+pub mod cache;
+pub mod chat;
+pub mod chunk;
+pub mod client;
+pub mod config;
+pub mod envelope;
+pub mod error;
+pub mod image;
+pub mod limits;
+pub mod mcp;
+pub mod openai_batch;
+pub mod params;
+pub mod pricing;
+pub mod provider;
+pub mod registry;
+pub mod retry;
+pub mod schema;
+pub mod schema_lint;
+pub mod session;
+pub mod stream;
+pub mod template;
+#[cfg(test)]
+mod tests;
+pub mod tokenizer;
+pub mod tools;
+pub mod validation;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::params::{SamplingParams, merge_json};
+use crate::schema::{Message, ToolCall};
+use crate::tools::Tool;
+
+pub use crate::client::{InvokeClient, InvokeClientBuilder};
+pub use crate::error::Error;
+pub use crate::provider::{
+    AuthScheme, OpenAiCompatible, Provider, StreamDecoder, TokenLimitField, env_api_key, known_endpoints,
+    resolve_provider,
+};
+pub use crate::retry::RetryPolicy;
+
+/// Role identifier for assistant messages in the chat completion API.
+/// Used to distinguish AI-generated responses from user inputs in the message
+/// history.
+pub const ASSISTANT_ROLE: &str = "assistant";
+
+/// Role identifier for system messages in the chat completion API.
+pub const SYSTEM_ROLE: &str = "system";
+
+/// Role identifier for user messages in the chat completion API.
+/// Used to distinguish user inputs from AI-generated responses in the message
+/// history.
+pub const USER_ROLE: &str = "user";
+
+/// Role identifier for messages carrying the result of a tool call.
+pub const TOOL_ROLE: &str = "tool";
+
+/// The content of a request message: plain text, or an array of parts mixing
+/// text, images and audio.
+///
+/// Both forms are accepted by the chat completion API; plain text is
+/// serialized as a JSON string and parts as an array of typed objects.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+    /// Returns the text of the content, with the text parts joined by
+    /// newlines and the other parts left out.
+    pub fn as_text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Text(text) => Cow::Borrowed(text),
+            Self::Parts(parts) => {
+                let texts: Vec<_> = parts
+                    .iter()
+                    .filter_map(|part| {
+                        match part {
+                            ContentPart::Text { text } => Some(text.as_str()),
+                            ContentPart::ImageUrl { .. } | ContentPart::InputAudio { .. } => None,
+                        }
+                    })
+                    .collect();
+                Cow::Owned(texts.join("\n"))
+            },
+        }
+    }
+
+    /// Converts the content into parts, a text becoming a single text part
+    /// unless it is empty.
+    pub fn into_parts(self) -> Vec<ContentPart> {
+        match self {
+            Self::Text(text) if text.is_empty() => Vec::new(),
+            Self::Text(text) => vec![ContentPart::Text { text }],
+            Self::Parts(parts) => parts,
+        }
+    }
+
+    /// Returns whether the content holds neither text nor any other part.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Text(text) => text.is_empty(),
+            Self::Parts(parts) => parts.is_empty(),
+        }
+    }
+}
+
+impl Default for MessageContent {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_owned())
+    }
+}
+
+impl From<Vec<ContentPart>> for MessageContent {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        Self::Parts(parts)
+    }
+}
+
+impl PartialEq<&str> for MessageContent {
+    fn eq(&self, other: &&str) -> bool {
+        matches!(self, Self::Text(text) if text == other)
+    }
+}
+
+impl fmt::Display for MessageContent {
+    /// Writes the text of the content, with a placeholder for every image and
+    /// audio part.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.write_str(text),
+            Self::Parts(parts) => {
+                for (index, part) in parts.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str("\n")?;
+                    }
+                    match part {
+                        ContentPart::Text { text } => f.write_str(text)?,
+                        ContentPart::ImageUrl { .. } => f.write_str("[image]")?,
+                        ContentPart::InputAudio { .. } => f.write_str("[audio]")?,
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// A part of a multi-part message content.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// A piece of text.
+    Text { text: String },
+    /// An image, given by URL or as a base64 `data:` URI.
+    ImageUrl { image_url: ImageUrl },
+    /// A base64-encoded audio clip, for models accepting audio input.
+    InputAudio { input_audio: InputAudio },
+}
+
+impl ContentPart {
+    /// Creates a text part.
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    /// Creates an image part.
+    ///
+    /// # Arguments
+    /// * `url` - An `http(s)` URL or a `data:` URI
+    pub fn image_url(url: impl Into<String>) -> Self {
+        Self::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail: None,
+            },
+        }
+    }
+
+    /// Creates an audio part.
+    ///
+    /// # Arguments
+    /// * `data` - The base64-encoded audio
+    /// * `format` - The audio format (`"wav"` or `"mp3"`)
+    pub fn input_audio(data: impl Into<String>, format: impl Into<String>) -> Self {
+        Self::InputAudio {
+            input_audio: InputAudio {
+                data: data.into(),
+                format: format.into(),
+            },
+        }
+    }
+}
+
+/// The audio of an `input_audio` content part.
+///
+/// # Fields
+/// * `data` - The base64-encoded audio
+/// * `format` - The audio format (`"wav"` or `"mp3"`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InputAudio {
+    pub data: String,
+    pub format: String,
+}
+
+/// The image of an `image_url` content part.
+///
+/// # Fields
+/// * `url` - An `http(s)` URL or a `data:<mime>;base64,<data>` URI
+/// * `detail` - Optional resolution hint (`"low"`, `"high"` or `"auto"`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImageUrl {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Represents a single message in the chat completion request.
+///
+/// Each message consists of a role (either "user" or "assistant") and content.
+/// Messages are used to provide context and instructions to the AI model.
+/// The content is either text or, for user messages with images, an array of
+/// parts. Assistant messages may also carry the tool calls the model
+/// requested, and "tool" messages the identifier of the call whose result
+/// they carry.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RequestMessage {
+    pub role: String,
+    pub content: MessageContent,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl RequestMessage {
+    /// Creates a message.
+    ///
+    /// # Arguments
+    /// * `role` - The role of the message sender ("system", "user" or
+    ///   "assistant")
+    /// * `content` - The content of the message, text or parts
+    pub fn new(role: impl Into<String>, content: impl Into<MessageContent>) -> Self {
+        Self {
+            role: role.into(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a "tool" message carrying the result of a tool call.
+    ///
+    /// # Arguments
+    /// * `tool_call_id` - Identifier of the call, as sent by the model
+    /// * `content` - The result of the call
+    pub fn tool_result(tool_call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id.into()),
+            ..Self::new(TOOL_ROLE, content.into())
+        }
+    }
+
+    /// Builds the message history for a prompt and a user input.
+    ///
+    /// The prompt is sent with the "system" role when `system_role` is set,
+    /// and with the "assistant" role otherwise.
+    ///
+    /// # Arguments
+    /// * `prompt` - The system prompt
+    /// * `input` - The user input, text or parts
+    /// * `system_role` - Whether to use "system" role instead of "assistant"
+    ///   role for the prompt
+    pub fn prompt_and_input(
+        prompt: impl Into<String>,
+        input: impl Into<MessageContent>,
+        system_role: bool,
+    ) -> Vec<Self> {
+        let prompt_role = if system_role { SYSTEM_ROLE } else { ASSISTANT_ROLE };
+
+        vec![Self::new(prompt_role, prompt.into()), Self::new(USER_ROLE, input)]
+    }
+}
+
+impl From<&Message> for RequestMessage {
+    /// Converts a reply into a message of the history, keeping its tool calls.
+    ///
+    /// A reply without a role is taken as an "assistant" message.
+    fn from(message: &Message) -> Self {
+        let role = if message.role.is_empty() {
+            ASSISTANT_ROLE
+        } else {
+            &message.role
+        };
+
+        Self {
+            tool_calls: message.tool_calls.clone(),
+            ..Self::new(role, message.content.clone())
+        }
+    }
+}
+
+/// Represents the response format configuration for structured output.
+///
+/// This structure is used to specify the JSON schema for the expected response
+/// format.
+#[derive(Serialize, Debug, Clone)]
+pub struct ResponseFormat {
+    pub r#type: String,
+    pub json_schema: serde_json::Value,
+}
+
+impl ResponseFormat {
+    /// Creates a `json_schema` response format for the given schema.
+    ///
+    /// # Arguments
+    /// * `json_schema` - The schema as loaded by [`read_schema_file`]
+    pub fn json_schema(json_schema: serde_json::Value) -> Self {
+        Self {
+            r#type: "json_schema".to_owned(),
+            json_schema,
+        }
+    }
+}
+
+/// Options that control a streaming response.
+///
+/// `include_usage` asks the provider to send a final chunk with the token
+/// usage of the whole request.
+#[derive(Serialize, Debug, Clone)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+/// The complete request payload sent to the chat completion API.
+///
+/// This structure contains all the necessary parameters for making a chat
+/// completion request, including the message history, model identifier, and
+/// token limits.
+///
+/// # Fields
+/// * `messages` - Vector of messages providing context for the completion
+/// * `model` - Identifier of the model to use for generation
+/// * `max_tokens` - Maximum number of tokens to generate (used for regular
+///   models)
+/// * `max_completion_tokens` - Maximum number of completion tokens (used for
+///   reasoning models)
+/// * `response_format` - Optional structured output schema
+/// * `stream` - Whether to stream the response as Server-Sent Events
+/// * `stream_options` - Streaming options (only sent together with `stream`)
+/// * `tools` - Optional functions the model may call
+/// * `tool_choice` - Optional constraint on which function the model calls
+///   (`"auto"`, `"none"`, `"required"` or a specific function)
+/// * `sampling` - Sampling parameters, sent as top-level fields
+/// * `extra_body` - Optional fields deep-merged into the serialized request
+///   body, see [`RequestPayload::merge_extra_body`]
+#[derive(Serialize, Debug, Default)]
+pub struct RequestPayload<'a> {
+    pub messages: Vec<RequestMessage>,
+    pub model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub sampling: SamplingParams,
+    #[serde(skip)]
+    pub extra_body: Option<serde_json::Value>,
+}
+
+impl RequestPayload<'_> {
+    /// Sets the token limit in the given payload field.
+    ///
+    /// The other token limit field is cleared, so that exactly one of
+    /// `max_tokens` and `max_completion_tokens` is sent.
+    ///
+    /// # Arguments
+    /// * `field` - The field carrying the limit, as chosen by the provider
+    /// * `tokens` - Maximum number of tokens to generate
+    pub fn set_token_limit(&mut self, field: TokenLimitField, tokens: u32) {
+        let (max_tokens, max_completion_tokens) = match field {
+            TokenLimitField::MaxTokens => (Some(tokens), None),
+            TokenLimitField::MaxCompletionTokens => (None, Some(tokens)),
+        };
+        self.max_tokens = max_tokens;
+        self.max_completion_tokens = max_completion_tokens;
+    }
+
+    /// Merges the extra body fields into a serialized request body.
+    ///
+    /// The merge happens after the provider translated the payload, so that
+    /// provider-specific fields can be set without code changes.
+    ///
+    /// # Arguments
+    /// * `body` - The request body, as returned by
+    ///   [`Provider::serialize_request`]
+    pub fn merge_extra_body(&self, body: &mut serde_json::Value) {
+        if let Some(extra_body) = &self.extra_body {
+            merge_json(body, extra_body);
+        }
+    }
+}
+
+/// Reads the entire contents of a file into a string.
+///
+/// This function attempts to read all text content from the specified file path
+/// and returns it as a String. If the file cannot be read, an error is returned
+/// with context about what went wrong.
+///
+/// # Arguments
+/// * `file_path` - Path to the file to read (can be any type that implements
+///   `AsRef<Path>`)
+///
+/// # Returns
+/// * `Ok(String)` - The file contents as a string
+/// * `Err` - An error if the file could not be read
+///
+/// # Examples
+/// ```no_run
+/// # use invoke_llm::read_file_content;
+/// let content = read_file_content("example.txt")?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn read_file_content(file_path: impl AsRef<Path>) -> Result<String> {
+    fs::read_to_string(file_path).context("Failed to read file content")
+}
+
+/// Reads and parses a JSON schema file.
+///
+/// This function reads a JSON file containing a schema definition and parses it
+/// into a serde_json::Value. The schema is validated to ensure it's valid JSON.
+///
+/// # Arguments
+/// * `schema_path` - Path to the JSON schema file
+///
+/// # Returns
+/// * `Ok(serde_json::Value)` - The parsed JSON schema
+/// * `Err` - An error if the file could not be read or parsed as JSON
+pub fn read_schema_file(schema_path: impl AsRef<Path>) -> Result<serde_json::Value> {
+    let schema_content = fs::read_to_string(schema_path).context("Failed to read schema file")?;
+
+    let schema: serde_json::Value =
+        serde_json::from_str(&schema_content).context("Failed to parse JSON schema file")?;
+
+    Ok(schema)
+}
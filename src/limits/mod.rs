@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::pricing::strip_date_suffix;
+use crate::tokenizer::base_model;
+
+/// Token limits of a model.
+///
+/// Entries are declared in the `[limits]` table of the configuration files,
+/// for example:
+///
+/// ```toml
+/// [limits]
+/// "qwen3-coder" = { context_window = 262144, max_output = 65536 }
+/// ```
+///
+/// # Fields
+/// * `context_window` - Maximum number of tokens of the prompt and the
+///   completion together
+/// * `max_output` - Maximum number of tokens of the completion, if lower than
+///   the context window
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ModelLimits {
+    pub context_window: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output: Option<u32>,
+}
+
+impl ModelLimits {
+    /// Checks that a request fits in the context window.
+    ///
+    /// # Arguments
+    /// * `model` - The model identifier, for the error message
+    /// * `prompt_tokens` - The number of tokens of the prompt
+    /// * `max_tokens` - The token limit of the completion
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the prompt and the whole completion fit
+    /// * `Err(ContextOverflow)` - If they do not
+    pub fn check(&self, model: &str, prompt_tokens: usize, max_tokens: u32) -> Result<(), ContextOverflow> {
+        let needed = prompt_tokens.saturating_add(max_tokens as usize);
+        if needed > self.context_window as usize {
+            return Err(ContextOverflow {
+                model: model.to_owned(),
+                prompt_tokens,
+                max_tokens,
+                context_window: self.context_window,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Error returned when a request does not fit in the context window of its
+/// model.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "The request needs {} tokens ({prompt_tokens} in the prompt and {max_tokens} to generate), more than the context \
+     window of '{model}' ({context_window} tokens)",
+    .prompt_tokens.saturating_add(*.max_tokens as usize)
+)]
+pub struct ContextOverflow {
+    pub model: String,
+    pub prompt_tokens: usize,
+    pub max_tokens: u32,
+    pub context_window: u32,
+}
+
+/// Limits shipped with the tool.
+///
+/// # Fields
+/// * `model` - The model identifier
+/// * `context_window`, `max_output` - The limits, see [`ModelLimits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuiltinLimits {
+    pub model: &'static str,
+    pub context_window: u32,
+    pub max_output: u32,
+}
+
+/// Limits known without any configuration, as published by the providers.
+///
+/// The `[limits]` table of the configuration files takes precedence.
+pub const BUILTIN_LIMITS: &[BuiltinLimits] = &[
+    limits("gpt-5", 400_000, 128_000),
+    limits("gpt-5-mini", 400_000, 128_000),
+    limits("gpt-5-nano", 400_000, 128_000),
+    limits("gpt-4.1", 1_047_576, 32_768),
+    limits("gpt-4.1-mini", 1_047_576, 32_768),
+    limits("gpt-4.1-nano", 1_047_576, 32_768),
+    limits("gpt-4o", 128_000, 16_384),
+    limits("gpt-4o-mini", 128_000, 16_384),
+    limits("gpt-4-turbo", 128_000, 4_096),
+    limits("gpt-4", 8_192, 8_192),
+    limits("gpt-3.5-turbo", 16_385, 4_096),
+    limits("o3", 200_000, 100_000),
+    limits("o4-mini", 200_000, 100_000),
+    limits("gpt-oss-120b", 131_072, 131_072),
+    limits("gpt-oss-20b", 131_072, 131_072),
+    limits("claude-opus-4-1", 200_000, 32_000),
+    limits("claude-sonnet-4-5", 200_000, 64_000),
+    limits("claude-haiku-4-5", 200_000, 64_000),
+    limits("gemini-2.5-pro", 1_048_576, 65_536),
+    limits("gemini-2.5-flash", 1_048_576, 65_536),
+    limits("gemini-2.5-flash-lite", 1_048_576, 65_536),
+    limits("deepseek-chat", 128_000, 8_192),
+    limits("deepseek-reasoner", 128_000, 65_536),
+];
+
+/// Creates a [`BuiltinLimits`].
+const fn limits(model: &'static str, context_window: u32, max_output: u32) -> BuiltinLimits {
+    BuiltinLimits {
+        model,
+        context_window,
+        max_output,
+    }
+}
+
+impl From<&BuiltinLimits> for ModelLimits {
+    fn from(limits: &BuiltinLimits) -> Self {
+        Self {
+            context_window: limits.context_window,
+            max_output: Some(limits.max_output),
+        }
+    }
+}
+
+/// Token limits of models, keyed by model identifier.
+///
+/// The table starts with the [`BUILTIN_LIMITS`], which user-defined entries
+/// extend or replace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitsTable {
+    limits: BTreeMap<String, ModelLimits>,
+}
+
+impl Default for LimitsTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl LimitsTable {
+    /// Creates a table holding the built-in limits.
+    pub fn builtin() -> Self {
+        let mut table = Self {
+            limits: BTreeMap::new(),
+        };
+        for limits in BUILTIN_LIMITS {
+            table.insert(limits.model, ModelLimits::from(limits));
+        }
+
+        table
+    }
+
+    /// Adds the limits of a model, replacing any limits of the same model.
+    ///
+    /// # Arguments
+    /// * `model` - The model identifier
+    /// * `limits` - The limits of the model
+    pub fn insert(&mut self, model: impl Into<String>, limits: ModelLimits) {
+        self.limits.insert(model.into(), limits);
+    }
+
+    /// Returns the limits of a model.
+    ///
+    /// Models with a date suffix (e.g. `gpt-4.1-mini-2025-04-14`) fall back to
+    /// the limits of the undated model, and models with an organization
+    /// prefix or a provider suffix (e.g. `openai/gpt-oss-120b:cerebras`) to
+    /// the limits of the bare model.
+    ///
+    /// # Arguments
+    /// * `model` - The model identifier
+    pub fn get(&self, model: &str) -> Option<&ModelLimits> {
+        let lookup = |model: &str| {
+            self.limits
+                .get(model)
+                .or_else(|| strip_date_suffix(model).and_then(|model| self.limits.get(model)))
+        };
+
+        lookup(model).or_else(|| lookup(base_model(model)))
+    }
+
+    /// Iterates over the limits, sorted by model.
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &ModelLimits)> {
+        self.limits.iter().map(|(model, limits)| (model.as_str(), limits))
+    }
+}
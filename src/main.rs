@@ -1,415 +1,2355 @@
-mod schema;
-#[cfg(test)]
-mod tests;
-
 use anyhow::{Context, Result, bail};
-use clap::Parser;
-use reqwest::Client;
-use serde::Serialize;
+use clap::builder::RangedU64ValueParser;
+use clap::error::ErrorKind;
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
+use invoke_llm::audio::{
+    RESPONSE_FORMATS, TIMESTAMP_GRANULARITIES, Transcriber, Transcription, TranscriptionRequest, append_audio,
+};
+use invoke_llm::batch::{estimate_batch_cost, parse_batch, run_batch};
+use invoke_llm::budget::{BudgetExceeded, UnpricedModel};
+use invoke_llm::cache::ResponseCache;
+use invoke_llm::chat::{ChatCommand, ChatInput, Conversation, HELP, parse_input};
+use invoke_llm::chunk::{map_reduce, split_chunks};
+use invoke_llm::client::DEFAULT_TIMEOUT;
+use invoke_llm::config::{Config, Profile};
+use invoke_llm::envelope::Envelope;
+use invoke_llm::error::Error;
+use invoke_llm::image::embed_images;
+use invoke_llm::limits::{ContextOverflow, LimitsTable};
+use invoke_llm::mcp::McpServer;
+use invoke_llm::openai_batch::{BATCH_URL, BatchInputLine, custom_ids_for, file_names_for, parse_batch_output};
+use invoke_llm::params::{REASONING_EFFORTS, SamplingParams, merge_json, parse_logit_bias, parse_set, read_extra_body};
+use invoke_llm::pricing::Cost;
+use invoke_llm::schema::{ApiResponse, Usage};
+use invoke_llm::schema_lint::load_schema;
+use invoke_llm::session::{Session, list_sessions};
+use invoke_llm::template::{parse_var, read_vars_file, render_file};
+use invoke_llm::tokenizer::Tokenizer;
+use invoke_llm::tools::{DEFAULT_MAX_TOOL_ITERATIONS, ToolSet, run_tool_loop, tool_choice};
+use invoke_llm::validation::{SchemaMismatch, send_validated, validate_reply};
+use invoke_llm::{
+    AuthScheme, InvokeClient, InvokeClientBuilder, RequestMessage, RequestPayload, ResponseFormat, RetryPolicy,
+    TokenLimitField, read_file_content,
+};
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use serde_json::{Map, Value};
 use std::env;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use std::time::Instant;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use tracing::{info, warn};
 
-use crate::schema::ApiResponse;
+/// Environment variable that holds the Sentry DSN.
+const SENTRY_DSN_ENV: &str = "SENTRY_DSN";
+
+/// Exit code of a run whose reply does not match the schema.
+const SCHEMA_MISMATCH_EXIT_CODE: i32 = 3;
+
+/// Exit code of a run refused because it would exceed a spending budget.
+const BUDGET_EXCEEDED_EXIT_CODE: i32 = 4;
+
+/// Command-line argument parser for the application.
+///
+/// This structure defines all the required and optional parameters that can be
+/// passed to the application via command line. It uses clap's derive macro to
+/// automatically generate the argument parsing logic. Without a subcommand, a
+/// single prompt and input file are sent (see [`RunArgs`]).
+#[derive(Parser, Debug)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = "Query an OpenAI-compatible endpoint with a prompt and input file, writing the response to an output \
+                  file.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+/// Subcommands of the application.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run every request of a JSONL file, writing one result per line.
+    Batch(BatchArgs),
+
+    /// Create `OpenAI` Batch API input files and ingest their output files.
+    #[command(subcommand)]
+    BatchApi(BatchApiCommand),
+
+    /// Show the spending recorded in the ledger and the budget left.
+    Budget(BudgetArgs),
+
+    /// Chat interactively, streaming every reply.
+    Chat(ChatArgs),
+
+    /// Inspect the configuration files and profiles.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Count the tokens of a prompt and an input file, and check them against
+    /// the context window of the model, without sending anything.
+    Count(CountArgs),
+
+    /// Inspect and clean the on-disk response cache.
+    #[command(subcommand)]
+    Cache(CacheCommand),
+
+    /// Inspect and edit the conversations saved with --session.
+    #[command(subcommand)]
+    Session(SessionCommand),
+
+    /// Transcribe an audio file with the transcription endpoint.
+    Transcribe(TranscribeArgs),
+}
+
+/// Subcommands for managing session files.
+#[derive(Subcommand, Debug)]
+enum SessionCommand {
+    /// List the session files of a directory.
+    List {
+        /// The directory holding the session files.
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    /// Print the messages of a session.
+    Show {
+        /// Path of the session file.
+        file: PathBuf,
+    },
+
+    /// Copy a session into a new one, to continue it in another direction.
+    Fork {
+        /// Path of the session file to copy.
+        source: PathBuf,
+
+        /// Path of the new session file, which must not exist.
+        destination: PathBuf,
+
+        /// Number of user turns to keep in the copy (all if not provided).
+        #[arg(long, required = false)]
+        turns: Option<usize>,
+    },
+
+    /// Drop the latest messages of a session.
+    Truncate {
+        /// Path of the session file.
+        file: PathBuf,
+
+        /// Number of user turns to keep, with their replies.
+        #[arg(long, required_unless_present = "messages", conflicts_with = "messages")]
+        turns: Option<usize>,
+
+        /// Number of messages to keep, including the prompt.
+        #[arg(long, required = false)]
+        messages: Option<usize>,
+    },
+}
+
+/// Subcommands for managing the response cache.
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Show the number, size and expired count of the cached responses.
+    Stats(CacheDirArgs),
+
+    /// Remove the expired and unreadable cached responses.
+    Prune(CacheDirArgs),
+
+    /// Remove every cached response.
+    Clear(CacheDirArgs),
+}
+
+/// Subcommands for working with the `OpenAI` Batch API.
+#[derive(Subcommand, Debug)]
+enum BatchApiCommand {
+    /// Build a batch input file from a prompt and an input file or directory.
+    Create(Box<BatchCreateArgs>),
+
+    /// Split a downloaded batch output file into one response file per item.
+    Ingest(BatchIngestArgs),
+}
+
+/// Subcommands for inspecting the configuration files.
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the configuration resolved from the configuration files, the
+    /// selected profile and the given arguments.
+    Show(ProfileArgs),
+
+    /// List the built-in and user-defined endpoints.
+    Endpoints,
+
+    /// List the built-in and user-defined model prices, in dollars per
+    /// million tokens.
+    Pricing,
+}
+
+/// Arguments for querying the endpoint with a single prompt and input file.
+#[derive(clap::Args, Debug)]
+#[command(group(ArgGroup::new("tool_sources").args(["tools", "mcp_servers"]).multiple(true)))]
+struct RunArgs {
+    #[command(flatten)]
+    settings: ProfileArgs,
+
+    // The prompt and input are checked by `required_arg` rather than by clap,
+    // which cannot make them optional only when a subcommand is given
+    /// Path to the file containing the system prompt (required).
+    #[arg(short, long, value_parser, required = false)]
+    prompt: Option<PathBuf>,
+
+    /// Path to the file containing the user input (required).
+    #[arg(short, long, value_parser, required = false)]
+    input: Option<PathBuf>,
+
+    /// Image file or URL sent after the input, for vision models (can be
+    /// repeated)
+    #[arg(long = "image", value_name = "PATH_OR_URL")]
+    images: Vec<String>,
+
+    /// WAV or MP3 file sent after the input, for models accepting audio
+    /// input (can be repeated)
+    #[arg(long = "audio", value_name = "PATH")]
+    audio: Vec<PathBuf>,
+
+    /// Optional path to save the response (prints to stdout if not provided).
+    #[arg(short, value_parser, required = false)]
+    output: Option<PathBuf>,
+
+    /// Optional `API_TOKEN` to use to access the API endpoint
+    #[arg(short, long, required = false)]
+    api_token: Option<String>,
+
+    /// Whether to stream the response, printing tokens as they arrive
+    #[arg(long, required = false)]
+    stream: bool,
+
+    /// Optional session file holding the conversation, which is sent before
+    /// the input and extended with the input and the reply
+    #[arg(long, value_parser, required = false)]
+    session: Option<PathBuf>,
+
+    /// Optional path to a JSON file declaring functions backed by local
+    /// commands, which the model may call before answering
+    #[arg(long, value_parser, required = false, conflicts_with = "stream")]
+    tools: Option<PathBuf>,
+
+    /// Name of an MCP server of the configuration files whose tools the model
+    /// may call (can be repeated)
+    #[arg(long = "mcp", value_name = "NAME", conflicts_with = "stream")]
+    mcp_servers: Vec<String>,
+
+    /// Which tool the model calls: "auto", "none", "required" or a function
+    /// name
+    #[arg(long, required = false, requires = "tool_sources")]
+    tool_choice: Option<String>,
+
+    /// Maximum number of requests sent while the model calls tools
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_TOOL_ITERATIONS,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_tool_iterations: usize,
+
+    /// How the response is written: the content as "text", or an envelope
+    /// with the response metadata as "json" or "jsonl" (one line, appended to
+    /// the output file)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Number of times the model is asked to correct a reply that does not
+    /// match the schema, with the validation errors
+    #[arg(long, default_value_t = 0, conflicts_with = "stream")]
+    schema_retries: usize,
+
+    /// Whether to send the request without checking that it fits in the
+    /// context window of the model
+    #[arg(long, required = false)]
+    no_context_check: bool,
+
+    /// Maximum number of tokens of an input chunk: the input is split on
+    /// token boundaries, preferably at markdown headings and code fences, and
+    /// the prompt is run on every chunk
+    #[arg(
+        long,
+        value_name = "TOKENS",
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with_all = ["stream", "session", "tool_sources", "schema_retries", "images", "audio"]
+    )]
+    chunk_size: Option<usize>,
+
+    /// Number of tokens of the end of a chunk repeated at the start of the
+    /// next one
+    #[arg(long, value_name = "TOKENS", default_value_t = 0, requires = "chunk_size")]
+    chunk_overlap: usize,
+
+    /// Optional path to a prompt run on the partial answers of the chunks,
+    /// joined, to produce the final answer
+    #[arg(long, value_parser, requires = "chunk_size")]
+    reduce_prompt: Option<PathBuf>,
+
+    /// Maximum number of chunks sent at the same time
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..), requires = "chunk_size")]
+    parallel: u16,
+
+    #[command(flatten)]
+    template: TemplateArgs,
+
+    #[command(flatten)]
+    sampling: SamplingArgs,
+
+    #[command(flatten)]
+    retry: RetryArgs,
+
+    #[command(flatten)]
+    cache: CacheArgs,
+}
+
+/// How the response of a run is written.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// The content of the first choice
+    Text,
+    /// A pretty-printed envelope with the content and the response metadata
+    Json,
+    /// The envelope on a single line
+    Jsonl,
+}
+
+/// Arguments for the interactive chat.
+#[derive(clap::Args, Debug)]
+struct ChatArgs {
+    #[command(flatten)]
+    settings: ProfileArgs,
+
+    /// Optional path to a file containing the initial system prompt.
+    #[arg(short, long, value_parser, required = false)]
+    prompt: Option<PathBuf>,
+
+    /// Optional session file to continue, as with /load.
+    #[arg(long, value_parser, required = false, conflicts_with = "prompt")]
+    session: Option<PathBuf>,
+
+    /// Optional `API_TOKEN` to use to access the API endpoint
+    #[arg(short, long, required = false)]
+    api_token: Option<String>,
+
+    /// Whether to send every turn without checking that the conversation fits
+    /// in the context window of the model
+    #[arg(long, required = false)]
+    no_context_check: bool,
+
+    #[command(flatten)]
+    sampling: SamplingArgs,
+
+    #[command(flatten)]
+    retry: RetryArgs,
+}
+
+/// Arguments for counting the tokens of a prompt and an input file.
+#[derive(clap::Args, Debug)]
+#[command(group(ArgGroup::new("files").args(["prompt", "input"]).multiple(true).required(true)))]
+struct CountArgs {
+    #[command(flatten)]
+    settings: ProfileArgs,
+
+    /// Path to the file containing the system prompt.
+    #[arg(short, long, value_parser, required = false)]
+    prompt: Option<PathBuf>,
+
+    /// Path to the file containing the user input.
+    #[arg(short, long, value_parser, required = false)]
+    input: Option<PathBuf>,
+
+    #[command(flatten)]
+    template: TemplateArgs,
+}
+
+/// Arguments for transcribing an audio file.
+#[derive(clap::Args, Debug)]
+struct TranscribeArgs {
+    /// Path of the audio file to transcribe (mp3, mp4, mpeg, mpga, m4a, wav,
+    /// webm, flac or ogg, at most 25 MiB).
+    file: PathBuf,
+
+    /// Name of the configuration profile providing the endpoint and model.
+    #[arg(long, required = false)]
+    profile: Option<String>,
+
+    /// The API endpoint name (e.g., "openai", "groq"), whose chat completions
+    /// URL is turned into the transcription one, or a transcription URL.
+    #[arg(short, long, required = false)]
+    endpoint: Option<String>,
+
+    /// The transcription model (e.g., "whisper-1", "gpt-4o-transcribe").
+    #[arg(short, long, required = false)]
+    model: Option<String>,
+
+    /// ISO-639-1 language of the audio (e.g., "en"), detected if not set.
+    #[arg(short, long, required = false)]
+    language: Option<String>,
+
+    /// Text guiding the style or vocabulary of the transcription.
+    #[arg(short, long, required = false)]
+    prompt: Option<String>,
+
+    /// Format of the transcription; with "json" (the default), only the text
+    /// is written.
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(RESPONSE_FORMATS))]
+    response_format: Option<String>,
+
+    /// Timestamps to include, requires "verbose_json" (comma-separated).
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = clap::builder::PossibleValuesParser::new(TIMESTAMP_GRANULARITIES)
+    )]
+    timestamp_granularities: Vec<String>,
+
+    /// Optional path to save the transcription (prints to stdout if not
+    /// provided).
+    #[arg(short, value_parser, required = false)]
+    output: Option<PathBuf>,
+
+    /// Optional `API_TOKEN` to use to access the API endpoint
+    #[arg(short, long, required = false)]
+    api_token: Option<String>,
+
+    #[command(flatten)]
+    retry: RetryArgs,
+}
+
+/// Request settings that may come from a profile of the configuration files.
+///
+/// Settings given on the command line override the ones of the profile.
+#[derive(clap::Args, Debug)]
+struct ProfileArgs {
+    /// Name of the configuration profile providing default settings.
+    #[arg(long, required = false)]
+    profile: Option<String>,
+
+    /// The API endpoint name (e.g., "openai", "google") or a custom URL to
+    /// query.
+    #[arg(short, long, required = false)]
+    endpoint: Option<String>,
+
+    /// The model identifier to use for the completion.
+    #[arg(short, long, required = false)]
+    model: Option<String>,
+
+    /// Whether to use reasoning tokens instead of regular max tokens.
+    #[arg(short, long, required = false, overrides_with = "no_reasoning")]
+    reasoning: bool,
+
+    /// Whether to use regular max tokens, even if the profile sets
+    /// `reasoning`.
+    #[arg(long, required = false)]
+    no_reasoning: bool,
+
+    /// Whether to use "system" role instead of "assistant" role
+    #[arg(short, long, required = false, overrides_with = "no_system_role")]
+    system_role: bool,
+
+    /// Whether to use "assistant" role, even if the profile or the endpoint
+    /// sets `system_role`.
+    #[arg(long, required = false)]
+    no_system_role: bool,
+
+    /// Maximum number of tokens to generate.
+    #[arg(short, long, required = false)]
+    tokens: Option<u32>,
+
+    /// Optional path to a JSON schema file for structured output
+    #[arg(long, value_parser, required = false)]
+    schema: Option<PathBuf>,
+
+    /// Whether the schema file holds a bare JSON Schema, to wrap into the
+    /// `json_schema` response format (with the file name as its name)
+    #[arg(long, required = false)]
+    schema_wrap: bool,
+}
+
+impl ProfileArgs {
+    /// Resolves the settings from the configuration and the arguments.
+    ///
+    /// # Arguments
+    /// * `config` - The loaded configuration files
+    ///
+    /// # Returns
+    /// * `Ok(Profile)` - The selected profile overridden by the arguments
+    /// * `Err` - An error if the selected profile is not defined
+    fn resolve(&self, config: &Config) -> Result<Profile> {
+        let profile = config
+            .profile(self.profile.as_deref())?
+            .map(|(_, profile)| profile.clone())
+            .unwrap_or_default();
+
+        Ok(profile.merge(Profile {
+            endpoint: self.endpoint.clone(),
+            model: self.model.clone(),
+            tokens: self.tokens,
+            reasoning: switch(self.reasoning, self.no_reasoning),
+            system_role: switch(self.system_role, self.no_system_role),
+            schema: self.schema.clone(),
+            schema_wrap: self.schema_wrap.then_some(true),
+            api_key_env: None,
+        }))
+    }
+}
+
+/// Returns the value of a flag given with a negated form, if either was given.
+///
+/// # Arguments
+/// * `on` - Whether the flag was given (e.g. `--reasoning`)
+/// * `off` - Whether its negation was given (e.g. `--no-reasoning`)
+fn switch(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Arguments for running a JSONL file of requests.
+#[derive(clap::Args, Debug)]
+struct BatchArgs {
+    /// The API endpoint name (e.g., "openai", "google") or a custom URL to
+    /// query.
+    #[arg(short, long, required = true)]
+    endpoint: String,
+
+    /// The model used for lines that do not set one.
+    #[arg(short, long, required = true)]
+    model: String,
+
+    /// Whether to use reasoning tokens for lines that only set a default.
+    #[arg(short, long, required = false)]
+    reasoning: bool,
+
+    /// Maximum number of tokens for lines that do not set one.
+    #[arg(short, long, required = true)]
+    tokens: u32,
+
+    /// Path to the JSONL file with one request per line.
+    #[arg(short, long, value_parser, required = true)]
+    file: PathBuf,
+
+    /// Optional path to save the results (prints to stdout if not provided).
+    #[arg(short, value_parser, required = false)]
+    output: Option<PathBuf>,
+
+    /// Maximum number of requests in flight at the same time.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    parallel: u16,
+
+    /// Optional `API_TOKEN` to use to access the API endpoint
+    #[arg(short, long, required = false)]
+    api_token: Option<String>,
+
+    #[command(flatten)]
+    sampling: SamplingArgs,
+
+    #[command(flatten)]
+    retry: RetryArgs,
+
+    #[command(flatten)]
+    cache: CacheArgs,
+}
+
+/// Arguments for building an `OpenAI` Batch API input file.
+#[derive(clap::Args, Debug)]
+struct BatchCreateArgs {
+    /// The model identifier to use for the completions.
+    #[arg(short, long, required = true)]
+    model: String,
+
+    /// Whether to use reasoning tokens instead of regular max tokens.
+    #[arg(short, long, required = false)]
+    reasoning: bool,
+
+    /// Whether to use "system" role instead of "assistant" role
+    #[arg(short, long, required = false)]
+    system_role: bool,
+
+    /// Maximum number of tokens to generate.
+    #[arg(short, long, required = true)]
+    tokens: u32,
+
+    /// Path to the file containing the system prompt.
+    #[arg(short, long, value_parser, required = true)]
+    prompt: PathBuf,
+
+    /// Path to the file containing the user input, or to a directory whose
+    /// files are each sent as one request (the file stem is the `custom_id`).
+    #[arg(short, long, value_parser, required = true)]
+    input: PathBuf,
+
+    /// Optional path to a JSON schema file for structured output
+    #[arg(long, value_parser, required = false)]
+    schema: Option<PathBuf>,
+
+    /// Whether the schema file holds a bare JSON Schema, to wrap into the
+    /// `json_schema` response format (with the file name as its name)
+    #[arg(long, required = false)]
+    schema_wrap: bool,
+
+    /// Optional path to save the batch file (prints to stdout if not provided).
+    #[arg(short, value_parser, required = false)]
+    output: Option<PathBuf>,
+
+    /// Relative URL of the endpoint the requests are sent to.
+    #[arg(long, default_value = BATCH_URL)]
+    url: String,
+
+    #[command(flatten)]
+    template: TemplateArgs,
+
+    #[command(flatten)]
+    sampling: SamplingArgs,
+}
+
+/// Arguments for ingesting an `OpenAI` Batch API output file.
+#[derive(clap::Args, Debug)]
+struct BatchIngestArgs {
+    /// Path to the downloaded batch output file.
+    #[arg(short, long, value_parser, required = true)]
+    file: PathBuf,
+
+    /// Directory where one `<custom_id>.json` response is written per item.
+    #[arg(short, long, value_parser, required = true)]
+    dir: PathBuf,
+}
+
+/// Arguments setting the sampling parameters and extra request body fields.
+#[derive(clap::Args, Debug)]
+struct SamplingArgs {
+    /// Sampling temperature (usually between 0 and 2)
+    #[arg(long, required = false)]
+    temperature: Option<f64>,
+
+    /// Nucleus sampling probability mass (between 0 and 1)
+    #[arg(long, required = false)]
+    top_p: Option<f64>,
+
+    /// Seed for best-effort deterministic sampling
+    #[arg(long, required = false)]
+    seed: Option<i64>,
+
+    /// Sequence ending the generation (can be repeated)
+    #[arg(long = "stop", value_name = "SEQUENCE")]
+    stop: Vec<String>,
+
+    /// Penalty for tokens already present in the text (between -2 and 2)
+    #[arg(long, required = false, allow_negative_numbers = true)]
+    presence_penalty: Option<f64>,
+
+    /// Penalty proportional to the frequency of tokens (between -2 and 2)
+    #[arg(long, required = false, allow_negative_numbers = true)]
+    frequency_penalty: Option<f64>,
+
+    /// Number of choices to generate, sent as `n`
+    #[arg(long, required = false, value_parser = RangedU64ValueParser::<u32>::new().range(1..))]
+    choices: Option<u32>,
+
+    /// Bias added to the logits of a token identifier as TOKEN=BIAS, with a
+    /// bias between -100 and 100 (can be repeated)
+    #[arg(long = "logit-bias", value_name = "TOKEN=BIAS", value_parser = parse_logit_bias)]
+    logit_bias: Vec<(String, i32)>,
+
+    /// Reasoning effort of reasoning models
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(REASONING_EFFORTS))]
+    reasoning_effort: Option<String>,
+
+    /// Path to a JSON file whose fields are deep-merged into the request body
+    #[arg(long, value_parser, required = false)]
+    extra_body: Option<PathBuf>,
+
+    /// Request body field as key=value, with dots addressing nested fields
+    /// and the value parsed as JSON if possible (can be repeated, applied
+    /// after --extra-body, `null` removes the field)
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_set)]
+    set: Vec<Value>,
+}
+
+impl SamplingArgs {
+    /// Returns the sampling parameters described by the arguments.
+    fn params(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            seed: self.seed,
+            stop: (!self.stop.is_empty()).then(|| self.stop.clone()),
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            n: self.choices,
+            logit_bias: (!self.logit_bias.is_empty()).then(|| self.logit_bias.iter().cloned().collect()),
+            reasoning_effort: self.reasoning_effort.clone(),
+        }
+    }
+
+    /// Returns the fields merged into the request body: the ones of the
+    /// --extra-body file overridden by the --set ones.
+    fn extra_body(&self) -> Result<Option<Value>> {
+        let mut extra_body = self.extra_body.as_deref().map(read_extra_body).transpose()?;
+        for patch in &self.set {
+            merge_json(extra_body.get_or_insert_with(|| Value::Object(Map::new())), patch);
+        }
+
+        Ok(extra_body)
+    }
+
+    /// Configures a client builder with the sampling parameters and extra
+    /// body fields.
+    fn apply(&self, builder: InvokeClientBuilder) -> Result<InvokeClientBuilder> {
+        let builder = builder.sampling(self.params());
+        Ok(match self.extra_body()? {
+            Some(extra_body) => builder.extra_body(extra_body),
+            None => builder,
+        })
+    }
+}
+
+/// Arguments controlling how transient failures are retried.
+#[derive(clap::Args, Debug)]
+struct RetryArgs {
+    /// Number of retries on connection errors, 408, 429 and 5xx statuses
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+
+    /// Delay in seconds before the first retry, doubled on every retry
+    #[arg(long, value_parser = parse_seconds, default_value = "1")]
+    backoff_base: Duration,
+
+    /// Upper bound in seconds of the delay between retries
+    #[arg(long, value_parser = parse_seconds, default_value = "60")]
+    max_backoff: Duration,
+}
+
+impl RetryArgs {
+    /// Returns the retry policy described by the arguments.
+    fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.retries,
+            backoff_base: self.backoff_base,
+            max_backoff: self.max_backoff,
+        }
+    }
+}
+
+/// Arguments controlling the templating of the prompt and input files.
+#[derive(clap::Args, Debug)]
+struct TemplateArgs {
+    /// Whether to render the prompt files as templates, which is implied by
+    /// --var, --vars and --template-input
+    #[arg(long, required = false)]
+    template: bool,
+
+    /// Whether to also render the input files as templates
+    #[arg(long, required = false)]
+    template_input: bool,
+
+    /// Template variable as key=value (may be repeated, overrides --vars)
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var)]
+    vars: Vec<(String, String)>,
+
+    /// Path to a JSON or TOML file holding template variables
+    #[arg(long = "vars", value_name = "FILE", value_parser, required = false)]
+    vars_file: Option<PathBuf>,
+}
+
+impl TemplateArgs {
+    /// Returns the template variables, or `None` if templating is disabled.
+    fn variables(&self) -> Result<Option<Value>> {
+        if !self.template && !self.template_input && self.vars.is_empty() && self.vars_file.is_none() {
+            return Ok(None);
+        }
+
+        let mut vars = match &self.vars_file {
+            Some(path) => read_vars_file(path)?,
+            None => Map::new(),
+        };
+        for (key, value) in &self.vars {
+            vars.insert(key.clone(), Value::String(value.clone()));
+        }
+
+        Ok(Some(Value::Object(vars)))
+    }
+
+    /// Returns the variables the input files are rendered with, which are
+    /// only used with --template-input.
+    ///
+    /// # Arguments
+    /// * `vars` - The template variables, if templating is enabled
+    fn input_variables<'a>(&self, vars: Option<&'a Value>) -> Option<&'a Value> {
+        vars.filter(|_| self.template_input)
+    }
+}
+
+/// Reads a prompt or input file, rendering it as a template if variables are
+/// given.
+///
+/// # Arguments
+/// * `path` - Path of the file
+/// * `vars` - The template variables, if templating is enabled
+fn read_input(path: impl AsRef<Path>, vars: Option<&Value>) -> Result<String> {
+    match vars {
+        Some(vars) => render_file(path.as_ref(), vars),
+        None => read_file_content(path),
+    }
+}
+
+/// Arguments controlling the on-disk response cache.
+#[derive(clap::Args, Debug)]
+struct CacheArgs {
+    /// Directory caching responses, returned without querying the endpoint
+    /// when the same request is sent again
+    #[arg(long, value_parser, required = false)]
+    cache_dir: Option<PathBuf>,
+
+    /// Maximum age of a cached response (e.g. "3600", "30m", "12h" or "7d")
+    #[arg(long, value_parser = parse_duration, required = false)]
+    cache_ttl: Option<Duration>,
+
+    /// Whether to bypass the response cache even if a directory is set
+    #[arg(long, required = false)]
+    no_cache: bool,
+}
+
+impl CacheArgs {
+    /// Returns the response cache described by the arguments, if enabled.
+    fn cache(&self) -> Option<ResponseCache> {
+        if self.no_cache {
+            return None;
+        }
+        let dir = self.cache_dir.as_ref()?;
+
+        Some(ResponseCache::new(dir, self.cache_ttl))
+    }
+}
+
+/// Arguments for the cache management subcommands.
+#[derive(clap::Args, Debug)]
+struct CacheDirArgs {
+    /// The cache directory to manage.
+    #[arg(long, value_parser, required = true)]
+    cache_dir: PathBuf,
+
+    /// Maximum age of a cached response, older responses being expired.
+    #[arg(long, value_parser = parse_duration, required = false)]
+    cache_ttl: Option<Duration>,
+}
+
+/// Arguments for showing the budgets.
+#[derive(clap::Args, Debug)]
+struct BudgetArgs {
+    /// Name of the configuration profile whose budgets are shown besides the
+    /// global ones (defaults to the default profile).
+    #[arg(long, required = false)]
+    profile: Option<String>,
+}
+
+/// Parses a duration given in seconds or with a unit suffix.
+///
+/// # Arguments
+/// * `value` - The command-line value (e.g. "90", "30s", "15m", "12h" or "7d")
+///
+/// # Returns
+/// * `Ok(Duration)` - The parsed duration
+/// * `Err` - A message if the value is not a non-negative duration
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.char_indices().last() {
+        Some((index, 's')) => (&value[..index], 1.0),
+        Some((index, 'm')) => (&value[..index], 60.0),
+        Some((index, 'h')) => (&value[..index], 3600.0),
+        Some((index, 'd')) => (&value[..index], 86400.0),
+        _ => (value, 1.0),
+    };
+    let amount: f64 = number
+        .parse()
+        .map_err(|_| format!("'{value}' is not a duration (e.g. \"90\", \"15m\", \"12h\" or \"7d\")"))?;
+    Duration::try_from_secs_f64(amount * unit).map_err(|_| format!("'{value}' is not a valid duration"))
+}
+
+/// Parses a non-negative number of seconds into a [`Duration`].
+///
+/// # Arguments
+/// * `value` - The command-line value (e.g. "1" or "0.5")
+///
+/// # Returns
+/// * `Ok(Duration)` - The parsed duration
+/// * `Err` - A message if the value is not a non-negative number
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|_| format!("'{value}' is not a number of seconds"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("'{value}' is not a valid number of seconds"))
+}
+
+/// Initialize Sentry reporting when the corresponding DSN is provided.
+fn init_sentry() -> Option<sentry::ClientInitGuard> {
+    let dsn = match env::var(SENTRY_DSN_ENV) {
+        Ok(value) if !value.trim().is_empty() => value,
+        _ => return None,
+    };
+
+    info!("Sentry DSN detected. Enabling error monitoring.");
+    let guard = sentry::init((
+        dsn,
+        sentry::ClientOptions {
+            release: sentry::release_name!(),
+            attach_stacktrace: true,
+            ..Default::default()
+        },
+    ));
+
+    Some(guard)
+}
+
+/// Main application entry point.
+///
+/// Initializes logging, optional Sentry monitoring, and delegates the core
+/// business logic to [`run`].
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Logs go to stderr, leaving stdout to the response
+    tracing_subscriber::fmt().with_writer(io::stderr).init();
+
+    let sentry_guard = init_sentry();
+    let sentry_enabled = sentry_guard.is_some();
+
+    let result = run().await;
+
+    if let Err(ref error) = result
+        && sentry_enabled
+    {
+        sentry_anyhow::capture_anyhow(error);
+    }
+
+    // Replies not matching the schema exit with their own code
+    if let Err(ref error) = result
+        && error.downcast_ref::<SchemaMismatch>().is_some()
+    {
+        eprintln!("Error: {error:?}");
+        drop(sentry_guard);
+        process::exit(SCHEMA_MISMATCH_EXIT_CODE);
+    }
+
+    // Runs refused by a budget exit with their own code
+    if let Err(ref error) = result
+        && is_budget_exceeded(error)
+    {
+        eprintln!("Error: {error:?}");
+        drop(sentry_guard);
+        process::exit(BUDGET_EXCEEDED_EXIT_CODE);
+    }
+
+    result
+}
+
+/// Returns an argument required when no subcommand is given.
+///
+/// Exits with a usage error, like clap does for its own required arguments,
+/// when the argument is missing.
+///
+/// # Arguments
+/// * `value` - The parsed argument
+/// * `name` - The argument as shown in the error (e.g. "--prompt <PROMPT>")
+fn required_arg<'a, T>(value: Option<&'a T>, name: &str) -> &'a T {
+    value.unwrap_or_else(|| missing_arg(name).exit())
+}
+
+/// Builds the usage error of a missing required argument.
+///
+/// # Arguments
+/// * `name` - The argument as shown in the error (e.g. "--prompt <PROMPT>")
+fn missing_arg(name: &str) -> clap::Error {
+    Args::command().error(
+        ErrorKind::MissingRequiredArgument,
+        format!("the following required argument was not provided: {name}"),
+    )
+}
+
+/// Execute the application's core workflow.
+///
+/// Parses command-line arguments and dispatches to the selected subcommand,
+/// or to [`run_completion`] when none is given.
+///
+/// # Returns
+/// * `Ok(())` on successful completion
+/// * `Err` on any failure during execution
+async fn run() -> Result<()> {
+    let args = Args::parse();
+
+    match args.command {
+        Some(Command::Batch(batch_args)) => run_batch_file(batch_args).await,
+        Some(Command::BatchApi(BatchApiCommand::Create(create_args))) => create_batch_api_file(*create_args),
+        Some(Command::BatchApi(BatchApiCommand::Ingest(ingest_args))) => ingest_batch_api_file(ingest_args),
+        Some(Command::Budget(budget_args)) => show_budget(&budget_args),
+        Some(Command::Cache(cache_command)) => manage_cache(cache_command),
+        Some(Command::Chat(chat_args)) => run_chat(chat_args).await,
+        Some(Command::Config(ConfigCommand::Show(profile_args))) => show_config(&profile_args),
+        Some(Command::Config(ConfigCommand::Endpoints)) => list_endpoints(),
+        Some(Command::Config(ConfigCommand::Pricing)) => list_pricing(),
+        Some(Command::Count(count_args)) => count_tokens(count_args),
+        Some(Command::Session(session_command)) => manage_session(session_command),
+        Some(Command::Transcribe(transcribe_args)) => transcribe(transcribe_args).await,
+        None => run_completion(args.run).await,
+    }
+}
+
+/// Query the endpoint with a single prompt and input file.
+///
+/// This function is a thin wrapper over [`InvokeClient`]:
+/// 1. Builds the client, which validates the parameters and resolves the
+///    provider and API token
+/// 2. Reads prompt and input files
+/// 3. Sends the request, streaming the reply if requested
+/// 4. Outputs the response
+///
+/// # Environment Variables
+/// * `API_TOKEN_OAI` - `OpenAI` API key
+/// * `API_TOKEN_GOOGLE` - `Google` API key
+/// * `API_TOKEN_HF` - `Hugging Face` API key
+/// * `API_TOKEN_ANTHROPIC` - `Anthropic` API key
+/// * `API_TOKEN` - Default API key for custom endpoints
+///
+/// # Returns
+/// * `Ok(())` on successful completion
+/// * `Err` on any failure during execution
+async fn run_completion(args: RunArgs) -> Result<()> {
+    let start_time = Instant::now();
+
+    let config = Config::load()?;
+    let settings = args.settings.resolve(&config)?;
+    let profile = config.profile(args.settings.profile.as_deref())?.map(|(name, _)| name);
+
+    // Configures the client from the resolved settings (including the schema),
+    // the pricing, the budgets and the retry policy
+    let builder = settings
+        .builder(config.registry())?
+        .pricing(config.pricing())
+        .budget(config.budget(profile))
+        .retry_policy(args.retry.policy());
+    let mut builder = args.sampling.apply(builder)?;
+    if let Some(api_token) = args.api_token {
+        builder = builder.api_token(api_token);
+    }
+    if let Some(cache) = args.cache.cache() {
+        builder = builder.cache(cache);
+    }
+    let mut tools = args.tools.as_deref().map(ToolSet::from_file).transpose()?;
+    for name in &args.mcp_servers {
+        let server = McpServer::spawn(name, config.mcp_server(name)?).await?;
+        let count = tools.get_or_insert_default().add_mcp_server(server).await?;
+        info!("Loaded {count} tools from MCP server '{name}'");
+    }
+    if let Some(tools) = &tools {
+        builder = builder.tools(tools.definitions());
+    }
+    if let Some(choice) = &args.tool_choice {
+        builder = builder.tool_choice(tool_choice(choice));
+    }
+    let client = Arc::new(builder.build()?);
+
+    // The prompt is only sent when starting a conversation
+    let mut session = args.session.as_deref().map(Session::load_or_new).transpose()?;
+    let history = session.as_ref().filter(|session| !session.is_empty());
+    if history.is_some() && args.prompt.is_some() {
+        warn!("Ignoring the prompt file: the session already holds the conversation");
+    }
+
+    // Renders the templates before the messages are built
+    let vars = args.template.variables()?;
+    let prompt_content = match history {
+        Some(_) => None,
+        None => {
+            Some(read_input(
+                required_arg(args.prompt.as_ref(), "--prompt <PROMPT>"),
+                vars.as_ref(),
+            )?)
+        },
+    };
+    if prompt_content.as_ref().is_some_and(String::is_empty) {
+        bail!("Prompt content from prompt file is empty.");
+    }
+
+    let input_path = required_arg(args.input.as_ref(), "--input <INPUT>");
+    let input_content = read_input(input_path, args.template.input_variables(vars.as_ref()))?;
+    if input_content.is_empty() {
+        bail!("Input content from input file is empty.");
+    }
+
+    // Splits the input before its images are embedded, the chunks being sent
+    // as text
+    let chunked = match (args.chunk_size, &prompt_content) {
+        (Some(size), Some(prompt_content)) => {
+            if args.chunk_overlap >= size {
+                bail!("--chunk-overlap must be lower than --chunk-size");
+            }
+            let tokenizer = Tokenizer::for_model(client.model());
+            let chunks = split_chunks(&input_content, tokenizer, size, args.chunk_overlap);
+            info!("Input split into {} chunks of at most {size} tokens", chunks.len());
+            Some((prompt_content.clone(), chunks))
+        },
+        _ => None,
+    };
+    let reduce_prompt = args
+        .reduce_prompt
+        .as_deref()
+        .map(|path| read_input(path, vars.as_ref()))
+        .transpose()?;
+
+    // Embeds the images referenced by the input, the ones of --image and the
+    // audio files
+    let base_dir = input_path.parent().unwrap_or_else(|| Path::new(""));
+    let input_content = append_audio(embed_images(input_content, base_dir, &args.images)?, &args.audio)?;
+
+    let messages = match (history, prompt_content) {
+        (_, Some(prompt_content)) => client.messages(prompt_content, input_content),
+        (Some(history), None) => history.messages_with(input_content),
+        (None, None) => unreachable!("the prompt is read when there is no history"),
+    };
+
+    if !args.no_context_check {
+        let limits = config.limits();
+        match &chunked {
+            Some((prompt_content, chunks)) => {
+                for chunk in chunks {
+                    check_context(&client, &limits, &client.messages(prompt_content, chunk.as_str()))?;
+                }
+            },
+            None => check_context(&client, &limits, &messages)?,
+        }
+    }
+
+    if args.schema_retries > 0 && client.schema().is_none() {
+        warn!("Ignoring --schema-retries: no schema is set");
+    }
+
+    let text = args.format == OutputFormat::Text;
+    let request_time = Instant::now();
+    let (api_response, messages, errors) = if args.stream {
+        let api_response = stream_response(&client, messages.clone(), args.output.as_deref(), text).await?;
+
+        if api_response.choices.is_empty() {
+            warn!("API returned a stream, but it contained no choices.");
+        } else if text && args.output.is_some() {
+            info!("API response successfully saved to output file");
+        }
+
+        // The streamed reply is already written, so it is only validated
+        let errors = match (client.schema(), api_response.choices.first()) {
+            (Some(schema), Some(first_choice)) => validate_reply(schema, &first_choice.message.content),
+            _ => Vec::new(),
+        };
+
+        (api_response, messages, errors)
+    } else {
+        // The tool calls and their results become part of the conversation
+        let send = async |messages: Vec<RequestMessage>| {
+            match &tools {
+                Some(tools) => {
+                    let outcome = run_tool_loop(&client, messages, tools, args.max_tool_iterations).await?;
+                    Ok((outcome.response, outcome.messages))
+                },
+                None => Ok((client.send(messages.clone()).await?, messages)),
+            }
+        };
+        // Replies not matching the schema are corrected by the model
+        let (api_response, messages, errors) = match (chunked, client.schema()) {
+            (Some((prompt_content, chunks)), schema) => {
+                let parallel = usize::from(args.parallel);
+                let limits = config.limits();
+                let check_reduce = |messages: &[RequestMessage]| {
+                    if args.no_context_check {
+                        return Ok(());
+                    }
+                    check_reduce_context(&client, &limits, messages)
+                };
+                let outcome = map_reduce(
+                    Arc::clone(&client),
+                    &prompt_content,
+                    chunks,
+                    reduce_prompt.as_deref(),
+                    parallel,
+                    check_reduce,
+                )
+                .await?;
+                for (index, partial) in outcome.partials.iter().enumerate() {
+                    info!("Chunk {} of {}", index + 1, outcome.partials.len());
+                    log_usage(&partial.usage);
+                }
+
+                // Only the reduced reply is a single answer to validate
+                let errors = match (schema, &reduce_prompt, outcome.response.choices.first()) {
+                    (Some(schema), Some(_), Some(first_choice)) => {
+                        validate_reply(schema, &first_choice.message.content)
+                    },
+                    (Some(_), None, _) => {
+                        warn!("The partial answers are not validated against the schema without --reduce-prompt");
+                        Vec::new()
+                    },
+                    _ => Vec::new(),
+                };
+                (outcome.response, messages, errors)
+            },
+            (None, Some(schema)) => {
+                let validated = send_validated(schema, messages, args.schema_retries, send).await?;
+                (validated.response, validated.messages, validated.errors)
+            },
+            (None, None) => {
+                let (api_response, messages) = send(messages).await?;
+                (api_response, messages, Vec::new())
+            },
+        };
+
+        match api_response.choices.first() {
+            Some(first_choice) if text => {
+                let content = &first_choice.message.content;
+                match &args.output {
+                    Some(path) => {
+                        fs::write(path, content)?;
+                        info!("API response successfully saved to output file");
+                    },
+                    None => println!("{content}"), // Consistent output
+                }
+            },
+            Some(_) => {},
+            None => warn!("API returned a response, but it contained no choices."),
+        }
+        log_usage(&api_response.usage);
+
+        (api_response, messages, errors)
+    };
+    let latency = request_time.elapsed();
+    if let Some(cost) = client.response_cost(&api_response) {
+        info!("Cost: {cost}");
+    }
+
+    if !text {
+        let envelope = Envelope::new(api_response.clone(), &client, latency, args.stream);
+        write_envelope(&envelope, args.format, args.output.as_deref())?;
+    }
+
+    if let (Some(session), Some(path), Some(first_choice)) = (&mut session, &args.session, api_response.choices.first())
+    {
+        session.record(messages, &first_choice.message, client.model());
+        session.save(path)?;
+        info!("Session saved with {} turns", session.turns());
+    }
+
+    info!("Time elapsed: {:.2?}", start_time.elapsed());
+
+    if !errors.is_empty() {
+        return Err(SchemaMismatch { errors }.into());
+    }
+
+    Ok(())
+}
+
+/// Transcribe an audio file.
+///
+/// The endpoint and model may come from a profile. The audio is posted as a
+/// multipart form to the transcription URL of the endpoint, and the
+/// transcription is written in the requested format, or as plain text for the
+/// default `json` format.
+///
+/// # Arguments
+/// * `args` - The transcribe subcommand arguments
+///
+/// # Returns
+/// * `Ok(())` on successful completion
+/// * `Err` - An error if the settings are incomplete, the file is invalid or
+///   the request failed
+async fn transcribe(args: TranscribeArgs) -> Result<()> {
+    let start_time = Instant::now();
+
+    let config = Config::load()?;
+    let profile = config
+        .profile(args.profile.as_deref())?
+        .map(|(_, profile)| profile.clone())
+        .unwrap_or_default();
+    let Some(endpoint) = args.endpoint.or(profile.endpoint) else {
+        bail!("An endpoint is required: pass --endpoint or select a profile setting one");
+    };
+    let Some(model) = args.model.or(profile.model) else {
+        bail!("A model is required: pass --model or select a profile setting one");
+    };
+    if !args.timestamp_granularities.is_empty() && args.response_format.as_deref() != Some("verbose_json") {
+        bail!("--timestamp-granularities requires --response-format verbose_json");
+    }
+
+    let provider = config.registry().resolve(&endpoint);
+    let api_token = match args.api_token {
+        Some(api_token) => api_token,
+        None if provider.auth_scheme() == AuthScheme::None => String::new(),
+        None => {
+            let api_key_env = profile.api_key_env.as_deref().unwrap_or(provider.api_key_env());
+            env::var(api_key_env).with_context(|| format!("{api_key_env} variable not set"))?
+        },
+    };
+    let transcriber = Transcriber::new(provider.as_ref(), &api_token, DEFAULT_TIMEOUT, args.retry.policy())?;
+
+    let request = TranscriptionRequest {
+        model,
+        language: args.language,
+        prompt: args.prompt,
+        response_format: args.response_format,
+        timestamp_granularities: args.timestamp_granularities,
+    };
+    let body = transcriber.transcribe(&args.file, &request).await?;
+
+    // Only JSON transcriptions carry the text apart and the usage
+    let content = match request.response_format.as_deref() {
+        None | Some("json" | "verbose_json") => {
+            let transcription: Transcription =
+                serde_json::from_str(&body).context("Failed to parse the transcription")?;
+            if let Some(usage) = &transcription.usage {
+                log_usage(&usage.to_usage());
+            }
+            if request.response_format.is_some_and(|format| format == "verbose_json") {
+                body
+            } else {
+                transcription.text
+            }
+        },
+        _ => body,
+    };
+
+    match &args.output {
+        Some(path) => {
+            fs::write(path, content)?;
+            info!("Transcription successfully saved to output file");
+        },
+        None => println!("{}", content.trim_end()),
+    }
+
+    info!("Time elapsed: {:.2?}", start_time.elapsed());
+
+    Ok(())
+}
+
+/// Chat interactively with the endpoint.
+///
+/// Every line entered is sent with the whole conversation and the reply is
+/// streamed to stdout, followed by the token usage of the turn. Lines
+/// starting with `/` are commands (see [`HELP`]). Failed requests are
+/// reported without leaving the chat.
+///
+/// # Arguments
+/// * `args` - The chat subcommand arguments
+///
+/// # Returns
+/// * `Ok(())` once the user leaves the chat
+/// * `Err` - An error if the client could not be built or the terminal could
+///   not be read
+async fn run_chat(args: ChatArgs) -> Result<()> {
+    let config = Config::load()?;
+    let settings = args.settings.resolve(&config)?;
+    let profile = config.profile(args.settings.profile.as_deref())?.map(|(name, _)| name);
+
+    let builder = settings
+        .builder(config.registry())?
+        .pricing(config.pricing())
+        .budget(config.budget(profile))
+        .retry_policy(args.retry.policy());
+    let mut builder = args.sampling.apply(builder)?;
+    if let Some(api_token) = args.api_token {
+        builder = builder.api_token(api_token);
+    }
+    let mut client = builder.build()?;
+    let mut total_cost: Option<Cost> = None;
+    let limits = config.limits();
+
+    let mut conversation = match (&args.session, &args.prompt) {
+        (Some(path), _) => Conversation::from_session(Session::load(path)?),
+        (None, Some(path)) => Conversation::new(Some(read_file_content(path)?)),
+        (None, None) => Conversation::new(None),
+    };
+
+    let mut editor = DefaultEditor::new()?;
+    eprintln!("Chatting with '{}', type /help for the commands.", client.model());
+
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error.into()),
+        };
+        let input = match parse_input(&line) {
+            Ok(Some(input)) => input,
+            Ok(None) => continue,
+            Err(error) => {
+                eprintln!("{error}");
+                continue;
+            },
+        };
+        editor.add_history_entry(line.as_str())?;
+
+        // A retried turn is restored if its new request fails
+        let (message, previous) = match input {
+            ChatInput::Message(message) => (message, None),
+            ChatInput::Command(ChatCommand::Exit) => break,
+            ChatInput::Command(ChatCommand::Retry) => {
+                let previous = conversation.clone();
+                match conversation.undo() {
+                    Some(message) => (message, Some(previous)),
+                    None => {
+                        eprintln!("Nothing to retry");
+                        continue;
+                    },
+                }
+            },
+            ChatInput::Command(command) => {
+                if let Err(error) = run_chat_command(command, &mut conversation, &mut client) {
+                    eprintln!("{error:#}");
+                }
+                continue;
+            },
+        };
+
+        let messages = conversation.request(&message, client.system_role());
+        if !args.no_context_check
+            && let Err(error) = check_context(&client, &limits, &messages)
+        {
+            eprintln!("{error:#}");
+            if let Some(previous) = previous {
+                conversation = previous;
+            }
+            continue;
+        }
+
+        let mut stdout = io::stdout();
+        let result = client
+            .stream(messages, |delta| {
+                stdout.write_all(delta.as_bytes())?;
+                stdout.flush()
+            })
+            .await;
+        println!();
+
+        match result {
+            Ok(api_response) => {
+                let reply = api_response
+                    .choices
+                    .first()
+                    .map_or("", |choice| choice.message.content.as_str());
+                conversation.push_turn(&message, reply, &api_response.usage);
+
+                // The model may change between turns, so the cost is summed
+                // turn by turn
+                let cost = client.response_cost(&api_response);
+                if let Some(cost) = &cost {
+                    total_cost.get_or_insert_default().add(cost);
+                }
+                let costs = match (cost, total_cost) {
+                    (Some(cost), Some(total_cost)) => format!(", {cost} ({total_cost} in total)"),
+                    _ => String::new(),
+                };
+
+                let (usage, total) = (&api_response.usage, &conversation.usage);
+                if usage.total_tokens > 0 {
+                    eprintln!(
+                        "[{} prompt + {} completion = {} tokens, {} tokens in total{costs}]",
+                        usage.prompt_tokens, usage.completion_tokens, usage.total_tokens, total.total_tokens
+                    );
+                }
+            },
+            Err(error) => {
+                eprintln!("{error}");
+                if let Some(previous) = previous {
+                    conversation = previous;
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a chat command other than `/retry` and `/exit`.
+///
+/// # Arguments
+/// * `command` - The command to run
+/// * `conversation` - The conversation of the chat
+/// * `client` - The client of the chat, whose model and token limit may change
+///
+/// # Returns
+/// * `Ok(())` on successful completion
+/// * `Err` - An error if the command failed, which does not end the chat
+fn run_chat_command(command: ChatCommand, conversation: &mut Conversation, client: &mut InvokeClient) -> Result<()> {
+    match command {
+        ChatCommand::System(None) => println!("{}", conversation.system.as_deref().unwrap_or("(no system prompt)")),
+        ChatCommand::System(Some(system)) => conversation.system = Some(system),
+        ChatCommand::Model(None) => println!("{}", client.model()),
+        ChatCommand::Model(Some(model)) => client.set_model(model),
+        ChatCommand::Tokens(None) => println!("{}", client.tokens()),
+        ChatCommand::Tokens(Some(tokens)) => client.set_tokens(tokens)?,
+        ChatCommand::Save(path) => {
+            let mut session = conversation.to_session(client.system_role());
+            session.model = Some(client.model().to_owned());
+            session.save(&path)?;
+            eprintln!("Saved {} turns to {}", conversation.turns(), path.display());
+        },
+        ChatCommand::Load(path) => {
+            *conversation = Conversation::from_session(Session::load(&path)?);
+            eprintln!("Loaded {} turns from {}", conversation.turns(), path.display());
+        },
+        ChatCommand::Undo => {
+            match conversation.undo() {
+                Some(_) => eprintln!("Removed the last turn"),
+                None => eprintln!("Nothing to undo"),
+            }
+        },
+        ChatCommand::Help => println!("{HELP}"),
+        ChatCommand::Retry | ChatCommand::Exit => {},
+    }
+
+    Ok(())
+}
+
+/// Run every request of a batch file.
+///
+/// Requests are sent with at most `--parallel` in flight, and each result is
+/// written as one JSON line as soon as its request completes. Failed requests
+/// are reported in the output rather than aborting the batch.
+///
+/// # Arguments
+/// * `args` - The batch subcommand arguments
+///
+/// # Returns
+/// * `Ok(())` once every request has completed, even if some failed
+/// * `Err` - An error if the client could not be built or the files could not
+///   be read or written
+async fn run_batch_file(args: BatchArgs) -> Result<()> {
+    let start_time = Instant::now();
+
+    let config = Config::load()?;
+    let mut builder = InvokeClient::builder()
+        .registry(config.registry())
+        .pricing(config.pricing())
+        .budget(config.budget(None))
+        .endpoint(&args.endpoint)
+        .model(&args.model)
+        .tokens(args.tokens)
+        .reasoning(args.reasoning)
+        .retry_policy(args.retry.policy());
+    builder = args.sampling.apply(builder)?;
+    if let Some(api_token) = args.api_token {
+        builder = builder.api_token(api_token);
+    }
+    if let Some(cache) = args.cache.cache() {
+        builder = builder.cache(cache);
+    }
+    let client = Arc::new(builder.build()?);
+
+    let lines = parse_batch(&read_file_content(&args.file)?);
+    info!("Running {} batch requests", lines.len());
+
+    // The whole batch is refused when it would not fit in the budgets
+    client.check_budget(estimate_batch_cost(&client, &lines))?;
+
+    let mut writer: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(File::create(path).context("Failed to create output file")?),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let (mut succeeded, mut failed) = (0, 0);
+    let mut total_cost: Option<Cost> = None;
+    run_batch(client, lines, usize::from(args.parallel), |result| {
+        if result.error.is_some() {
+            failed += 1;
+        } else {
+            succeeded += 1;
+        }
+        if let Some(cost) = &result.cost {
+            total_cost.get_or_insert_default().add(cost);
+        }
+        writeln!(writer, "{}", serde_json::to_string(&result)?)?;
+        writer.flush()?;
+        Ok(())
+    })
+    .await?;
+
+    info!("Batch finished: {succeeded} succeeded, {failed} failed");
+    if let Some(total_cost) = total_cost {
+        info!("Cost: {total_cost}");
+    }
+    info!("Time elapsed: {:.2?}", start_time.elapsed());
+
+    Ok(())
+}
+
+/// Build an `OpenAI` Batch API input file.
+///
+/// The input may be a single file, sent as one request, or a directory whose
+/// files are sent as one request each, in file name order. Every line carries
+/// the same prompt, model, token limit and optional schema as a regular run.
+///
+/// # Arguments
+/// * `args` - The batch-api create subcommand arguments
+///
+/// # Returns
+/// * `Ok(())` once the batch file has been written
+/// * `Err` - An error if the files could not be read or written, or no input is
+///   left to send
+fn create_batch_api_file(args: BatchCreateArgs) -> Result<()> {
+    if args.tokens == 0 {
+        bail!("Token count must be greater than 0");
+    }
+
+    let vars = args.template.variables()?;
+    let prompt_content = read_input(&args.prompt, vars.as_ref())?;
+    if prompt_content.is_empty() {
+        bail!("Prompt content from prompt file is empty.");
+    }
+    let schema = args
+        .schema
+        .as_deref()
+        .map(|schema| load_schema(schema, args.schema_wrap))
+        .transpose()?;
 
-/// Default endpoint value used when no known endpoint name is provided.
-/// This constant serves as a fallback to indicate that a custom endpoint URL
-/// should be used.
-const DEFAULT_ENDPOINT: &str = "none";
+    let inputs = if args.input.is_dir() {
+        let mut paths = fs::read_dir(&args.input)
+            .context("Failed to read input directory")?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.is_file());
+        paths.sort();
+        paths
+    } else {
+        vec![args.input.clone()]
+    };
+    let custom_ids = custom_ids_for(&inputs)?;
 
-/// Role identifier for assistant messages in the chat completion API.
-/// Used to distinguish AI-generated responses from user inputs in the message
-/// history.
-const ASSISTANT_ROLE: &str = "assistant";
+    let sampling = args.sampling.params();
+    let extra_body = args.sampling.extra_body()?;
 
-/// Role identifier for system messages in the chat completion API.
-const SYSTEM_ROLE: &str = "system";
+    let token_limit_field = if args.reasoning {
+        TokenLimitField::MaxCompletionTokens
+    } else {
+        TokenLimitField::MaxTokens
+    };
 
-/// Role identifier for user messages in the chat completion API.
-/// Used to distinguish user inputs from AI-generated responses in the message
-/// history.
-const USER_ROLE: &str = "user";
+    let mut lines = Vec::new();
+    for (path, custom_id) in inputs.iter().zip(custom_ids) {
+        let input_content = read_input(path, args.template.input_variables(vars.as_ref()))?;
+        if input_content.is_empty() {
+            warn!("Skipping empty input file {}", path.display());
+            continue;
+        }
 
-/// Environment variable that holds the Sentry DSN.
-const SENTRY_DSN_ENV: &str = "SENTRY_DSN";
+        let mut payload = RequestPayload {
+            messages: RequestMessage::prompt_and_input(prompt_content.clone(), input_content, args.system_role),
+            model: &args.model,
+            response_format: schema.clone().map(ResponseFormat::json_schema),
+            sampling: sampling.clone(),
+            extra_body: extra_body.clone(),
+            ..Default::default()
+        };
+        payload.set_token_limit(token_limit_field, args.tokens);
 
-/// Command-line argument parser for the application.
-///
-/// This structure defines all the required and optional parameters that can be
-/// passed to the application via command line. It uses clap's derive macro to
-/// automatically generate the argument parsing logic.
-#[derive(Parser, Debug)]
-#[command(
-    author,
-    version,
-    about,
-    long_about = "Query an OpenAI-compatible endpoint with a prompt and input file, writing the response to an output \
-                  file."
-)]
-struct Args {
-    /// The API endpoint name (e.g., "openai", "google") or a custom URL to
-    /// query.
-    #[arg(short, long, required = true)]
-    endpoint: String,
+        let mut line = BatchInputLine::new(custom_id, &payload)?;
+        line.url.clone_from(&args.url);
+        lines.push(serde_json::to_string(&line)?);
+    }
+    if lines.is_empty() {
+        bail!("No non-empty input file to add to the batch.");
+    }
 
-    /// The model identifier to use for the completion.
-    #[arg(short, long, required = true)]
-    model: String,
+    let content = lines.join("\n") + "\n";
+    match &args.output {
+        Some(path) => {
+            fs::write(path, content)?;
+            info!("Batch file with {} requests saved to output file", lines.len());
+        },
+        None => print!("{content}"),
+    }
 
-    /// Whether to use reasoning tokens instead of regular max tokens.
-    #[arg(short, long, required = false)]
-    reasoning: bool,
+    Ok(())
+}
 
-    /// Whether to use "system" role instead of "assistant" role
-    #[arg(short, long, required = false)]
-    system_role: bool,
+/// Split an `OpenAI` Batch API output file into one response file per item.
+///
+/// Every successful item is written as a pretty-printed [`ApiResponse`] to
+/// `<custom_id>.json` in the output directory. Failed items are logged and
+/// counted rather than aborting the ingestion.
+///
+/// # Arguments
+/// * `args` - The batch-api ingest subcommand arguments
+///
+/// # Returns
+/// * `Ok(())` once every item has been processed, even if some failed
+/// * `Err` - An error if the files could not be read, parsed or written
+fn ingest_batch_api_file(args: BatchIngestArgs) -> Result<()> {
+    let lines = parse_batch_output(&read_file_content(&args.file)?)?;
+    // Custom ids mapped to the same file would overwrite each other's results
+    let file_names = file_names_for(lines.iter().map(|line| line.custom_id.as_str()))?;
+    fs::create_dir_all(&args.dir).context("Failed to create output directory")?;
 
-    /// Maximum number of tokens to generate.
-    #[arg(short, long, required = true)]
-    tokens: u32,
+    let (mut succeeded, mut failed) = (0, 0);
+    for (line, file_name) in lines.into_iter().zip(file_names) {
+        let custom_id = line.custom_id.clone();
+        match line.into_api_response() {
+            Ok(api_response) => {
+                let path = args.dir.join(format!("{file_name}.json"));
+                fs::write(&path, serde_json::to_string_pretty(&api_response)?)?;
+                succeeded += 1;
+            },
+            Err(error) => {
+                warn!("Batch item {custom_id} failed: {error:#}");
+                failed += 1;
+            },
+        }
+    }
 
-    /// Path to the file containing the system prompt.
-    #[arg(short, long, value_parser, required = true)]
-    prompt: PathBuf,
+    info!("Batch output ingested: {succeeded} succeeded, {failed} failed");
 
-    /// Path to the file containing the user input.
-    #[arg(short, long, value_parser, required = true)]
-    input: PathBuf,
+    Ok(())
+}
 
-    /// Optional path to save the response (prints to stdout if not provided).
-    #[arg(short, value_parser, required = false)]
-    output: Option<PathBuf>,
+/// Print the resolved configuration.
+///
+/// The loaded configuration files and the selected profile are printed as
+/// comments, followed by the resolved settings in TOML.
+///
+/// # Arguments
+/// * `args` - The profile and the settings overriding it
+///
+/// # Returns
+/// * `Ok(())` on successful completion
+/// * `Err` - An error if a configuration file is invalid or the selected
+///   profile is not defined
+fn show_config(args: &ProfileArgs) -> Result<()> {
+    let config = Config::load()?;
+    let settings = args.resolve(&config)?;
 
-    /// Optional `API_TOKEN` to use to access the API endpoint
-    #[arg(short, long, required = false)]
-    api_token: Option<String>,
+    if config.sources.is_empty() {
+        println!("# No configuration file found");
+    }
+    for source in &config.sources {
+        println!("# Loaded {}", source.display());
+    }
+    match config.profile(args.profile.as_deref())? {
+        Some((name, _)) => println!("# Profile: {name}"),
+        None => println!("# Profile: none"),
+    }
+    print!("{}", toml::to_string(&settings)?);
 
-    /// Optional path to a JSON schema file for structured output
-    #[arg(long, value_parser, required = false)]
-    schema: Option<PathBuf>,
+    Ok(())
 }
 
-/// Represents a single message in the chat completion request.
+/// Print the endpoints that `--endpoint` can refer to.
 ///
-/// Each message consists of a role (either "user" or "assistant") and content.
-/// Messages are used to provide context and instructions to the AI model.
-#[derive(Serialize, Debug, Clone)]
-struct RequestMessage {
-    role: String,
-    content: String,
+/// # Returns
+/// * `Ok(())` on successful completion
+/// * `Err` - An error if a configuration file is invalid
+fn list_endpoints() -> Result<()> {
+    let config = Config::load()?;
+    for (name, endpoint) in config.registry().iter() {
+        let origin = if config.endpoints.contains_key(name) {
+            "user"
+        } else {
+            "built-in"
+        };
+        println!("{name}\t{}\t{}\t{origin}", endpoint.url, endpoint.api_key_env);
+    }
+
+    Ok(())
 }
 
-/// Represents the response format configuration for structured output.
+/// Print the model prices used to compute the cost of requests.
 ///
-/// This structure is used to specify the JSON schema for the expected response format.
-#[derive(Serialize, Debug, Clone)]
-struct ResponseFormat {
-    r#type: String,
-    json_schema: serde_json::Value,
+/// # Returns
+/// * `Ok(())` on successful completion
+/// * `Err` - An error if a configuration file is invalid
+fn list_pricing() -> Result<()> {
+    let config = Config::load()?;
+    for (endpoint, model, price) in config.pricing().iter() {
+        let origin = if config
+            .pricing
+            .get(endpoint)
+            .is_some_and(|prices| prices.contains_key(model))
+        {
+            "user"
+        } else {
+            "built-in"
+        };
+        let optional = |price: Option<f64>| price.map_or_else(|| "-".to_owned(), |price| price.to_string());
+        println!(
+            "{endpoint}\t{model}\t{}\t{}\t{}\t{}\t{origin}",
+            price.input,
+            optional(price.cached_input),
+            price.output,
+            optional(price.reasoning)
+        );
+    }
+
+    Ok(())
 }
 
-/// The complete request payload sent to the chat completion API.
+/// Print the token counts of a prompt and an input file.
 ///
-/// This structure contains all the necessary parameters for making a chat
-/// completion request, including the message history, model identifier, and
-/// token limits.
+/// The files are counted apart and as the messages of a request, then checked
+/// against the context window of the model like a run does (see
+/// [`check_context`]).
 ///
-/// # Fields
-/// * `messages` - Vector of messages providing context for the completion
-/// * `model` - Identifier of the model to use for generation
-/// * `max_tokens` - Maximum number of tokens to generate (used for regular
-///   models)
-/// * `max_completion_tokens` - Maximum number of completion tokens (used for
-///   reasoning models)
-/// * `response_format` - Optional structured output schema
-#[derive(Serialize, Debug)]
-struct RequestPayload<'a> {
-    messages: Vec<RequestMessage>,
-    model: &'a str,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    max_tokens: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    max_completion_tokens: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    response_format: Option<ResponseFormat>,
+/// # Arguments
+/// * `args` - The files, and the settings giving the model and token limit
+///
+/// # Returns
+/// * `Ok(())` - If the request fits or may fit in the context window
+/// * `Err` - An error if a file could not be read, no model is set or the
+///   request does not fit
+fn count_tokens(args: CountArgs) -> Result<()> {
+    let config = Config::load()?;
+    let settings = args.settings.resolve(&config)?;
+    let Some(model) = settings.model.as_deref() else {
+        bail!("A model is required, from --model or a profile");
+    };
+    let tokenizer = Tokenizer::for_model(model);
+
+    let vars = args.template.variables()?;
+    let prompt = args.prompt.map(|path| read_input(path, vars.as_ref())).transpose()?;
+    let input = args
+        .input
+        .map(|path| read_input(path, args.template.input_variables(vars.as_ref())))
+        .transpose()?;
+
+    println!("# Model: {model}");
+    println!("# Tokenizer: {tokenizer}");
+    if let Some(prompt) = &prompt {
+        println!("prompt\t{}", tokenizer.count(prompt));
+    }
+    if let Some(input) = &input {
+        println!("input\t{}", tokenizer.count(input));
+    }
+
+    // The request is counted as a run would send it
+    let system_role = settings.system_role.unwrap_or_default();
+    let messages = RequestMessage::prompt_and_input(prompt.unwrap_or_default(), input.unwrap_or_default(), system_role);
+    let schema = settings
+        .schema
+        .as_deref()
+        .map(|path| load_schema(path, settings.schema_wrap.unwrap_or_default()))
+        .transpose()?;
+    let payload = RequestPayload {
+        messages,
+        model,
+        response_format: schema.map(ResponseFormat::json_schema),
+        ..Default::default()
+    };
+    let request_tokens = tokenizer.count_payload(&payload);
+    println!("request\t{request_tokens}");
+    let tokens = settings.tokens.unwrap_or_default();
+    if tokens > 0 {
+        println!("tokens\t{tokens}");
+        println!("total\t{}", request_tokens.saturating_add(tokens as usize));
+    }
+
+    let limits = config.limits();
+    let Some(model_limits) = limits.get(model) else {
+        println!("# No known context window for '{model}'");
+        return Ok(());
+    };
+    println!("context_window\t{}", model_limits.context_window);
+    if let Some(max_output) = model_limits.max_output {
+        println!("max_output\t{max_output}");
+    }
+    let total = i64::try_from(request_tokens.saturating_add(tokens as usize)).unwrap_or(i64::MAX);
+    println!("remaining\t{}", i64::from(model_limits.context_window) - total);
+
+    match model_limits.check(model, request_tokens, tokens) {
+        Err(overflow) if tokenizer.is_exact() => Err(overflow.into()),
+        Err(overflow) => {
+            warn!("{overflow}, as estimated");
+            Ok(())
+        },
+        Ok(()) => Ok(()),
+    }
 }
 
-/// Reads the entire contents of a file into a string.
-///
-/// This function attempts to read all text content from the specified file path
-/// and returns it as a String. If the file cannot be read, an error is returned
-/// with context about what went wrong.
+/// Print the spending of every configured budget and the amount left.
 ///
 /// # Arguments
-/// * `file_path` - Path to the file to read (can be any type that implements
-///   `AsRef<Path>`)
+/// * `args` - The profile whose own budgets are shown besides the global ones
 ///
 /// # Returns
-/// * `Ok(String)` - The file contents as a string
-/// * `Err` - An error if the file could not be read
-///
-/// # Examples
-/// ```
-/// let content = read_file_content("example.txt")?;
-fn read_file_content(file_path: impl AsRef<Path>) -> Result<String> {
-    fs::read_to_string(file_path).context("Failed to read file content")
+/// * `Ok(())` on successful completion
+/// * `Err` - An error if a configuration file is invalid, the selected profile
+///   is not defined or the ledger could not be read
+fn show_budget(args: &BudgetArgs) -> Result<()> {
+    let config = Config::load()?;
+    let profile = config.profile(args.profile.as_deref())?.map(|(name, _)| name);
+    let budget = config.budget(profile);
+
+    match budget.ledger() {
+        Some(ledger) => println!("# Ledger: {}", ledger.path().display()),
+        None => println!("# Ledger: none (no data directory)"),
+    }
+    let statuses = budget.status()?;
+    if statuses.is_empty() {
+        println!("# No budget is configured");
+    }
+    for status in statuses {
+        println!(
+            "{}\t${:.6} spent\t${:.2} limit\t${:.6} remaining",
+            status.name(),
+            status.spent,
+            status.limit,
+            status.remaining()
+        );
+    }
+
+    Ok(())
 }
 
-/// Reads and parses a JSON schema file.
-///
-/// This function reads a JSON file containing a schema definition and parses it
-/// into a serde_json::Value. The schema is validated to ensure it's valid JSON.
+/// Inspect or clean a response cache directory.
 ///
 /// # Arguments
-/// * `schema_path` - Path to the JSON schema file
+/// * `command` - The cache subcommand to run
 ///
 /// # Returns
-/// * `Ok(serde_json::Value)` - The parsed JSON schema
-/// * `Err` - An error if the file could not be read or parsed as JSON
-fn read_schema_file(schema_path: impl AsRef<Path>) -> Result<serde_json::Value> {
-    let schema_content = fs::read_to_string(schema_path).context("Failed to read schema file")?;
+/// * `Ok(())` on successful completion
+/// * `Err` - An error if the cache directory could not be read or cleaned
+fn manage_cache(command: CacheCommand) -> Result<()> {
+    match command {
+        CacheCommand::Stats(args) => {
+            let stats = ResponseCache::new(&args.cache_dir, args.cache_ttl).stats()?;
+            println!("Directory: {}", args.cache_dir.display());
+            println!("Entries: {}", stats.entries);
+            println!("Expired: {}", stats.expired);
+            println!("Size: {} bytes", stats.bytes);
+        },
+        CacheCommand::Prune(args) => {
+            let removed = ResponseCache::new(&args.cache_dir, args.cache_ttl).prune()?;
+            println!("Removed {removed} expired cache entries");
+        },
+        CacheCommand::Clear(args) => {
+            let removed = ResponseCache::new(&args.cache_dir, args.cache_ttl).clear()?;
+            println!("Removed {removed} cache entries");
+        },
+    }
 
-    let schema: serde_json::Value =
-        serde_json::from_str(&schema_content).context("Failed to parse JSON schema file")?;
+    Ok(())
+}
+
+/// Inspect or edit session files.
+///
+/// # Arguments
+/// * `command` - The session subcommand to run
+///
+/// # Returns
+/// * `Ok(())` on successful completion
+/// * `Err` - An error if a session file could not be read or written
+fn manage_session(command: SessionCommand) -> Result<()> {
+    match command {
+        SessionCommand::List { dir } => {
+            for (path, session) in list_sessions(&dir)? {
+                let updated = UNIX_EPOCH + Duration::from_secs(session.updated_at);
+                println!(
+                    "{}\t{} turns\t{} messages\t{}\t{}",
+                    path.display(),
+                    session.turns(),
+                    session.messages.len(),
+                    session.model.as_deref().unwrap_or("-"),
+                    httpdate::fmt_http_date(updated)
+                );
+            }
+        },
+        SessionCommand::Show { file } => {
+            let session = Session::load(&file)?;
+            for (index, message) in session.messages.iter().enumerate() {
+                println!("--- [{index}] {} ---", message.role);
+                println!("{}", message.content);
+            }
+        },
+        SessionCommand::Fork {
+            source,
+            destination,
+            turns,
+        } => {
+            let session = Session::fork(&source, &destination, turns)?;
+            println!(
+                "Forked {} into {} ({} turns)",
+                source.display(),
+                destination.display(),
+                session.turns()
+            );
+        },
+        SessionCommand::Truncate { file, turns, messages } => {
+            let mut session = Session::load(&file)?;
+            let removed = match (turns, messages) {
+                (Some(turns), _) => session.truncate_turns(turns),
+                (None, Some(messages)) => session.truncate(messages),
+                (None, None) => 0,
+            };
+            session.save(&file)?;
+            println!("Removed {removed} messages ({} turns left)", session.turns());
+        },
+    }
 
-    Ok(schema)
+    Ok(())
 }
 
-/// Maps known endpoint names to their corresponding API URLs.
+/// Margin added to estimated token counts when grouping the answers of a
+/// reduce, in percent of the estimate.
+const REDUCE_ESTIMATE_MARGIN: usize = 20;
+
+/// Check that a request fits in the context window of its model.
 ///
-/// This function takes a string identifier for a known service and returns
-/// the appropriate API endpoint URL. If the name is not recognized, it returns
-/// the `DEFAULT_ENDPOINT` constant.
+/// The prompt is counted with the tokenizer of the model, along with the tool
+/// and schema definitions, and the whole `--tokens` limit is added to it.
+/// Exact counts that do not fit fail the run, while estimated counts only
+/// warn, since they may be off. Models without known limits are not checked.
 ///
 /// # Arguments
-/// * `name` - The endpoint name to look up ("openai", "google", "hf", etc.)
+/// * `client` - The client the request is sent with
+/// * `limits` - The token limits of the models
+/// * `messages` - The message history to send
 ///
 /// # Returns
-/// * The corresponding API URL, or `DEFAULT_ENDPOINT` if name is not recognized
-///
-/// # Supported Endpoints
-/// * "openai" - `OpenAI` API endpoint
-/// * "google" - Google Generative Language API endpoint
-/// * "hf" - Hugging Face API endpoint
-fn known_endpoints(name: &str) -> &str {
-    match name {
-        "openai" => "https://api.openai.com/v1/chat/completions",
-        "google" => "https://generativelanguage.googleapis.com/v1beta/chat/completions",
-        "hf" => "https://router.huggingface.co/v1/chat/completions",
-        _ => DEFAULT_ENDPOINT,
+/// * `Ok(())` - If the request fits or may fit
+/// * `Err` - A [`invoke_llm::limits::ContextOverflow`] error if the request
+///   does not fit
+fn check_context(client: &InvokeClient, limits: &LimitsTable, messages: &[RequestMessage]) -> Result<()> {
+    match fit_context(client, limits, messages, 0) {
+        Some((tokenizer, Err(overflow))) if tokenizer.is_exact() => Err(overflow.into()),
+        Some((_, Err(overflow))) => {
+            warn!("{overflow}, as estimated");
+            Ok(())
+        },
+        Some((_, Ok(()))) | None => Ok(()),
     }
 }
 
-/// Maps endpoint names to their corresponding environment variable names for
-/// API keys.
+/// Check that a reduce request fits in the context window of its model.
 ///
-/// This function determines which environment variable should be checked for
-/// the API key based on the endpoint being used. This allows different services
-/// to use different environment variables for their authentication tokens.
+/// Unlike [`check_context`], estimated counts that do not fit fail too, so
+/// that the answers are reduced in groups rather than sent in a request that
+/// may overflow. Since they may be off, they are raised by
+/// [`REDUCE_ESTIMATE_MARGIN`] percent first.
 ///
 /// # Arguments
-/// * `name` - The endpoint name to look up ("openai", "google", "hf", etc.)
+/// * `client` - The client the request is sent with
+/// * `limits` - The token limits of the models
+/// * `messages` - The messages of the reduce request
 ///
 /// # Returns
-/// * The corresponding environment variable name for the API key
-///
-/// # Environment Variables
-/// * "openai" - Uses `API_TOKEN_OAI`
-/// * "google" - Uses `API_TOKEN_GOOGLE`
-/// * "hf" - Uses `API_TOKEN_HF`
-/// * All others - Uses `API_TOKEN` (default)
-fn env_api_key(name: &str) -> &str {
-    match name {
-        "openai" => "API_TOKEN_OAI",
-        "google" => "API_TOKEN_GOOGLE",
-        "hf" => "API_TOKEN_HF",
-        _ => "API_TOKEN",
+/// * `Ok(())` - If the request fits, or the limits of the model are unknown
+/// * `Err` - A [`invoke_llm::limits::ContextOverflow`] error otherwise
+fn check_reduce_context(client: &InvokeClient, limits: &LimitsTable, messages: &[RequestMessage]) -> Result<()> {
+    match fit_context(client, limits, messages, REDUCE_ESTIMATE_MARGIN) {
+        Some((_, Err(overflow))) => Err(overflow.into()),
+        Some((_, Ok(()))) | None => Ok(()),
     }
 }
 
-/// Initialize Sentry reporting when the corresponding DSN is provided.
-fn init_sentry() -> Option<sentry::ClientInitGuard> {
-    let dsn = match env::var(SENTRY_DSN_ENV) {
-        Ok(value) if !value.trim().is_empty() => value,
-        _ => return None,
+/// Counts the prompt of a request and checks it against the context window of
+/// its model, warning when the whole `--tokens` limit exceeds its maximum
+/// output.
+///
+/// # Arguments
+/// * `client` - The client the request is sent with
+/// * `limits` - The token limits of the models
+/// * `messages` - The message history to send
+/// * `margin` - Percentage added to estimated counts
+///
+/// # Returns
+/// The tokenizer the prompt was counted with and the outcome of the check, or
+/// `None` if the limits of the model are unknown
+fn fit_context(
+    client: &InvokeClient,
+    limits: &LimitsTable,
+    messages: &[RequestMessage],
+    margin: usize,
+) -> Option<(Tokenizer, Result<(), ContextOverflow>)> {
+    let model = client.model();
+    let Some(model_limits) = limits.get(model) else {
+        info!("Skipping the context check: the limits of '{model}' are unknown");
+        return None;
     };
+    if let Some(max_output) = model_limits.max_output
+        && client.tokens() > max_output
+    {
+        warn!(
+            "{} tokens requested, more than the maximum output of '{model}' ({max_output} tokens)",
+            client.tokens()
+        );
+    }
 
-    info!("Sentry DSN detected. Enabling error monitoring.");
-    let guard = sentry::init((
-        dsn,
-        sentry::ClientOptions {
-            release: sentry::release_name!(),
-            attach_stacktrace: true,
-            ..Default::default()
-        },
-    ));
+    let tokenizer = Tokenizer::for_model(model);
+    let mut prompt_tokens = tokenizer.count_payload(&client.payload(messages.to_vec(), false));
+    info!("Prompt size: {prompt_tokens} tokens ({tokenizer})");
+    if !tokenizer.is_exact() {
+        prompt_tokens = prompt_tokens.saturating_add(prompt_tokens.saturating_mul(margin) / 100);
+    }
 
-    Some(guard)
+    Some((tokenizer, model_limits.check(model, prompt_tokens, client.tokens())))
 }
 
-/// Main application entry point.
-///
-/// Initializes logging, optional Sentry monitoring, and delegates the core
-/// business logic to [`run`].
-#[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+/// Returns whether an error is a run refused by a budget.
+fn is_budget_exceeded(error: &anyhow::Error) -> bool {
+    let error = match error.downcast_ref::<Error>() {
+        Some(Error::Budget(error)) => error,
+        _ => error,
+    };
 
-    let sentry_guard = init_sentry();
-    let sentry_enabled = sentry_guard.is_some();
+    error.is::<BudgetExceeded>() || error.is::<UnpricedModel>()
+}
 
-    let result = run().await;
+/// Stream a response, writing content deltas as they arrive.
+///
+/// Every content delta of the first choice is written immediately, either to
+/// stdout or appended to the output file, which is truncated beforehand.
+///
+/// # Arguments
+/// * `client` - The client to query
+/// * `messages` - The message history to send
+/// * `output` - Optional path of the output file (stdout if not provided)
+/// * `display` - Whether the deltas are written, rather than only collected for
+///   an envelope written afterwards
+///
+/// # Returns
+/// * `Ok(ApiResponse)` - The response rebuilt from all received chunks
+/// * `Err` - An error if the stream could not be read, parsed or written
+async fn stream_response(
+    client: &InvokeClient,
+    messages: Vec<RequestMessage>,
+    output: Option<&Path>,
+    display: bool,
+) -> Result<ApiResponse> {
+    let mut writer: Box<dyn Write> = match output {
+        _ if !display => Box::new(io::sink()),
+        Some(path) => Box::new(File::create(path).context("Failed to create output file")?),
+        None => Box::new(io::stdout().lock()),
+    };
 
-    if let Err(ref error) = result {
-        if sentry_enabled {
-            sentry_anyhow::capture_anyhow(error);
-        }
+    let api_response = client
+        .stream(messages, |delta| {
+            writer.write_all(delta.as_bytes())?;
+            writer.flush()
+        })
+        .await?;
+
+    if display && output.is_none() {
+        writeln!(writer)?; // Consistent output
     }
+    writer.flush()?;
 
-    result
+    log_usage(&api_response.usage);
+
+    Ok(api_response)
 }
 
-/// Execute the application's core workflow.
+/// Write the envelope of a response.
 ///
-/// This function orchestrates the entire application workflow:
-/// 1. Parses command-line arguments
-/// 2. Validates input parameters
-/// 3. Reads prompt and input files
-/// 4. Constructs the API request payload
-/// 5. Sends the request to the specified endpoint
-/// 6. Processes and outputs the response
-///
-/// # Workflow
-/// 1. Initialize timing
-/// 2. Parse command-line arguments using Args struct
-/// 3. Validate token count is greater than 0
-/// 4. Retrieve API key from environment variables
-/// 5. Read prompt and input file contents
-/// 6. Construct message history with assistant prompt and user input
-/// 7. Build request payload with appropriate token limits
-/// 8. Determine API endpoint URL
-/// 9. Send HTTP POST request to API
-/// 10. Handle API response and output results
+/// A `json` envelope is pretty-printed and replaces the output file, while a
+/// `jsonl` envelope is written on a single line and appended to it, so that
+/// the output file collects one line per run.
 ///
-/// # Environment Variables
-/// * `API_TOKEN_OAI` - `OpenAI` API key
-/// * `API_TOKEN_GOOGLE` - `Google` API key
-/// * `API_TOKEN_HF` - `Hugging Face` API key
-/// * `API_TOKEN` - Default API key for custom endpoints
+/// # Arguments
+/// * `envelope` - The envelope to write
+/// * `format` - [`OutputFormat::Json`] or [`OutputFormat::Jsonl`]
+/// * `output` - Optional path of the output file (stdout if not provided)
 ///
 /// # Returns
-/// * `Ok(())` on successful completion
-/// * `Err` on any failure during execution
-async fn run() -> Result<()> {
-    let start_time = Instant::now();
-    let args = Args::parse();
+/// * `Ok(())` on success
+/// * `Err` - An error if the output file could not be written
+fn write_envelope(envelope: &Envelope, format: OutputFormat, output: Option<&Path>) -> Result<()> {
+    let line = match format {
+        OutputFormat::Jsonl => serde_json::to_string(envelope)?,
+        _ => serde_json::to_string_pretty(envelope)?,
+    };
 
-    if args.tokens == 0 {
-        bail!("Token count must be greater than 0");
+    match output {
+        Some(path) if format == OutputFormat::Jsonl => {
+            let mut file = File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .context("Failed to open output file")?;
+            writeln!(file, "{line}")?;
+            info!("API response envelope appended to output file");
+        },
+        Some(path) => {
+            fs::write(path, format!("{line}\n"))?;
+            info!("API response envelope successfully saved to output file");
+        },
+        None => println!("{line}"),
     }
 
-    let api_token = if let Some(value) = args.api_token {
-        value
-    } else {
-        let api_key_name = env_api_key(&args.endpoint);
+    Ok(())
+}
+
+/// Logs the token usage of a request, if the provider reported it.
+fn log_usage(usage: &Usage) {
+    if usage.total_tokens == 0 {
+        return;
+    }
 
-        env::var(api_key_name)
-            .with_context(|| format!("{api_key_name} variable not set. Please provide your API token."))?
+    let audio_tokens = usage.audio_tokens();
+    let audio = if audio_tokens > 0 {
+        format!(" ({audio_tokens} audio)")
+    } else {
+        String::new()
     };
+    info!(
+        "Usage: {} prompt tokens, {} completion tokens, {} total tokens{audio}",
+        usage.prompt_tokens, usage.completion_tokens, usage.total_tokens
+    );
+}
 
-    let prompt_content = read_file_content(args.prompt)?;
-    if prompt_content.is_empty() {
-        bail!("Prompt content from prompt file is empty.");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use invoke_llm::limits::ModelLimits;
+
+    /// Parses a command line given without the program name.
+    fn parse(command_line: &str) -> Result<Args, clap::Error> {
+        Args::try_parse_from(std::iter::once("invoke-llm").chain(command_line.split_whitespace()))
     }
 
-    let input_content = read_file_content(args.input)?;
-    if input_content.is_empty() {
-        bail!("Input content from input file is empty.");
+    #[test]
+    fn test_parse_plain_run() -> Result<()> {
+        let args = parse("-e openai -m gpt-4.1-mini -t 500 -p prompt.txt -i input.txt")?;
+        assert!(args.command.is_none());
+        assert_eq!(args.run.settings.endpoint.as_deref(), Some("openai"));
+        assert_eq!(args.run.settings.tokens, Some(500));
+        assert_eq!(args.run.prompt, Some(PathBuf::from("prompt.txt")));
+        assert_eq!(args.run.input, Some(PathBuf::from("input.txt")));
+        assert_eq!(
+            switch(args.run.settings.reasoning, args.run.settings.no_reasoning),
+            None
+        );
+
+        // Run arguments cannot be mixed with a subcommand
+        assert!(parse("-p prompt.txt config endpoints").is_err());
+
+        Ok(())
     }
 
-    let mut messages = vec![RequestMessage {
-        role: if args.system_role {
-            SYSTEM_ROLE.to_owned()
-        } else {
-            ASSISTANT_ROLE.to_owned()
-        },
-        content: prompt_content,
-    }];
-
-    messages.push(RequestMessage {
-        role: USER_ROLE.to_owned(),
-        content: input_content,
-    });
-
-    // Read and parse the schema file if provided
-    let response_format = if let Some(schema_path) = args.schema {
-        let schema_json = read_schema_file(&schema_path)?;
-        Some(ResponseFormat {
-            r#type: "json_schema".to_owned(),
-            json_schema: schema_json,
-        })
-    } else {
-        None
-    };
+    #[test]
+    fn test_parse_template_input() -> Result<()> {
+        let vars = Value::Object(Map::new());
 
-    let payload = RequestPayload {
-        messages,
-        model: &args.model,
-        max_tokens: if args.reasoning { None } else { Some(args.tokens) },
-        max_completion_tokens: if args.reasoning { Some(args.tokens) } else { None },
-        response_format,
-    };
+        // Variables render the prompt, but not the input
+        let args = parse("-p prompt.txt -i input.txt --var tone=formal")?;
+        assert!(args.run.template.variables()?.is_some());
+        assert!(args.run.template.input_variables(Some(&vars)).is_none());
 
-    let client = Client::builder()
-        .timeout(Duration::from_secs(120))
-        .build()
-        .context("Failed to create HTTP client")?;
-    let auth_header_value = format!("Bearer {api_token}");
-
-    let mut api_url = known_endpoints(&args.endpoint);
-    if api_url == "none" {
-        api_url = &args.endpoint;
-    }
-
-    info!("Querying model '{}' model", args.model);
-    info!("With URL: '{api_url}'");
-
-    let response = client
-        .post(api_url)
-        .header("Authorization", &auth_header_value)
-        .json(&payload)
-        .send()
-        .await
-        .context("Failed to send request to the API.")?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_body = response.text().await.context("Could not read error body")?;
-        bail!("API request failed with status {}: {}", status, error_body);
-    }
-
-    let api_response: ApiResponse = response
-        .json()
-        .await
-        .context("Failed to parse JSON response from the API.")?;
-
-    if let Some(first_choice) = api_response.choices.first() {
-        let content = &first_choice.message.content;
-        match &args.output {
-            Some(path) => {
-                fs::write(path, content)?;
-                info!("API response successfully saved to output file");
-            },
-            None => println!("{content}"), // Consistent output
+        let args = parse("-p prompt.txt -i input.txt --template-input")?;
+        assert!(args.run.template.variables()?.is_some());
+        assert!(args.run.template.input_variables(Some(&vars)).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_negated_flags() -> Result<()> {
+        let settings = parse("-r --no-system-role -p prompt.txt -i input.txt")?.run.settings;
+        assert_eq!(switch(settings.reasoning, settings.no_reasoning), Some(true));
+        assert_eq!(switch(settings.system_role, settings.no_system_role), Some(false));
+
+        // The last of a flag and its negation wins
+        let settings = parse("--reasoning --no-reasoning -s --no-system-role -s")?.run.settings;
+        assert_eq!(switch(settings.reasoning, settings.no_reasoning), Some(false));
+        assert_eq!(switch(settings.system_role, settings.no_system_role), Some(true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_missing_prompt_or_input() -> Result<()> {
+        // Left to `required_arg`, since a session may stand in for the prompt
+        let args = parse("-e openai -m gpt-4.1-mini -t 500 -i input.txt")?;
+        assert!(args.run.prompt.is_none());
+        let args = parse("-e openai -m gpt-4.1-mini -t 500 -p prompt.txt")?;
+        assert!(args.run.input.is_none());
+
+        let error = missing_arg("--input <INPUT>");
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+        assert!(error.to_string().contains("--input <INPUT>"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_subcommands() -> Result<()> {
+        let command_lines = [
+            "batch -e openai -m gpt-4.1-mini -t 500 -f requests.jsonl",
+            "batch-api create -m gpt-4.1-mini -t 500 -p prompt.txt -i inputs",
+            "batch-api ingest -f output.jsonl -d results",
+            "budget",
+            "cache stats --cache-dir cache",
+            "cache prune --cache-dir cache",
+            "cache clear --cache-dir cache",
+            "chat -e openai -m gpt-4.1-mini -t 500",
+            "config show",
+            "config endpoints",
+            "config pricing",
+            "count -m gpt-4.1-mini -i input.txt",
+            "session list",
+            "session show session.json",
+            "session fork session.json copy.json",
+            "session truncate session.json --turns 2",
+            "transcribe -e openai -m whisper-1 audio.wav",
+        ];
+        for command_line in command_lines {
+            let args = parse(command_line).map_err(|error| anyhow::anyhow!("{command_line}: {error}"))?;
+            assert!(args.command.is_some(), "{command_line}");
         }
-    } else {
-        warn!("API returned a response, but it contained no choices.");
+
+        // Subcommands keep their own required arguments
+        assert!(parse("batch -e openai -m gpt-4.1-mini -t 500").is_err());
+        assert!(parse("count -m gpt-4.1-mini").is_err());
+
+        Ok(())
     }
 
-    info!("Time elapsed: {:.2?}", start_time.elapsed());
+    #[test]
+    fn test_check_reduce_context_estimates() -> Result<()> {
+        let client = InvokeClient::builder()
+            .endpoint("http://localhost:1")
+            .api_token("secret")
+            .model("local-model")
+            .tokens(100)
+            .build()?;
+        let messages = client.messages("Merge", "Some partial answer. ".repeat(100));
+        let prompt_tokens = Tokenizer::Heuristic.count_payload(&client.payload(messages.clone(), false));
+        let limits = |context_window: usize| {
+            let mut limits = LimitsTable::builtin();
+            limits.insert("local-model", ModelLimits {
+                context_window: u32::try_from(context_window).unwrap_or(u32::MAX),
+                max_output: None,
+            });
+            limits
+        };
 
-    Ok(())
+        // An estimate that fits, but not with the margin, only warns before a
+        // request, while a reduce splits it
+        let tight = limits(prompt_tokens + prompt_tokens / 10 + 100);
+        assert!(check_context(&client, &tight, &messages).is_ok());
+        let error = check_reduce_context(&client, &tight, &messages).unwrap_err();
+        assert!(error.is::<ContextOverflow>());
+
+        let roomy = limits(prompt_tokens * 2 + 100);
+        assert!(check_reduce_context(&client, &roomy, &messages).is_ok());
+
+        // Models without known limits are not checked
+        assert!(check_reduce_context(&client, &LimitsTable::builtin(), &messages).is_ok());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,459 @@
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::tools::{FunctionDefinition, Tool};
+
+/// Version of the Model Context Protocol requested by the client.
+pub const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Default time limit for a request to an MCP server.
+pub const DEFAULT_MCP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// JSON-RPC error code for a method the client does not implement.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// An MCP server launched over stdio, declared in the configuration files:
+///
+/// ```toml
+/// [mcp_servers.files]
+/// command = ["npx", "-y", "@modelcontextprotocol/server-filesystem", "."]
+/// env = { LOG_LEVEL = "error" }
+/// timeout = 30
+/// ```
+///
+/// # Fields
+/// * `command` - The program and its arguments, run without a shell
+/// * `env` - Environment variables set for the server, in addition to the
+///   inherited ones
+/// * `timeout` - Time limit in seconds for each request (60 if not provided)
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct McpServerConfig {
+    pub command: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+/// A tool advertised by an MCP server in its `tools/list` result.
+///
+/// # Fields
+/// * `name` - Name of the tool
+/// * `description` - What the tool does
+/// * `input_schema` - JSON schema of the arguments object
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct McpTool {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "inputSchema")]
+    pub input_schema: Value,
+}
+
+impl McpTool {
+    /// Returns the function definition sent to the model.
+    pub fn definition(&self) -> Tool {
+        Tool {
+            r#type: "function".to_owned(),
+            function: FunctionDefinition {
+                name: self.name.clone(),
+                description: self.description.clone(),
+                parameters: self.input_schema.clone(),
+                strict: None,
+            },
+        }
+    }
+}
+
+/// A page of the `tools/list` result.
+#[derive(Deserialize, Debug)]
+struct ToolsPage {
+    tools: Vec<McpTool>,
+    #[serde(default, rename = "nextCursor")]
+    next_cursor: Option<String>,
+}
+
+/// The `tools/call` result.
+#[derive(Deserialize, Debug)]
+struct CallResult {
+    #[serde(default)]
+    content: Vec<Value>,
+    #[serde(default, rename = "isError")]
+    is_error: bool,
+}
+
+/// A JSON-RPC client speaking the Model Context Protocol over a pair of
+/// streams carrying newline-delimited messages.
+///
+/// The client is generic over the streams so that it can talk to a spawned
+/// server through its stdio as well as to an in-memory stub.
+///
+/// The message being read or written is kept in a buffer of the client, so
+/// that a request cancelled by a timeout leaves no partial message behind: the
+/// next request finishes it first.
+pub struct McpClient<R, W> {
+    name: String,
+    reader: BufReader<R>,
+    writer: W,
+    next_id: u64,
+    /// The bytes read of the next incoming message
+    incoming: Vec<u8>,
+    /// The bytes of the outgoing messages not written yet
+    outgoing: Vec<u8>,
+}
+
+impl<R: AsyncRead+Unpin, W: AsyncWrite+Unpin> McpClient<R, W> {
+    /// Creates a client, without sending any message.
+    ///
+    /// # Arguments
+    /// * `name` - The server name, used in error messages
+    /// * `reader` - The stream the server writes its messages to
+    /// * `writer` - The stream the server reads its messages from
+    pub fn new(name: impl Into<String>, reader: R, writer: W) -> Self {
+        Self {
+            name: name.into(),
+            reader: BufReader::new(reader),
+            writer,
+            next_id: 1,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// Performs the initialization handshake.
+    ///
+    /// # Returns
+    /// * `Ok(Value)` - The `initialize` result sent by the server
+    /// * `Err` - An error if the server rejected or did not answer the request
+    pub async fn initialize(&mut self) -> Result<Value> {
+        let params = serde_json::json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": {"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")}
+        });
+        let result = self.request("initialize", params).await?;
+        self.send(&serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Lists the tools of the server, following the pagination cursors.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<McpTool>)` - The tools of every page
+    /// * `Err` - An error if a request failed or a page could not be parsed
+    pub async fn list_tools(&mut self) -> Result<Vec<McpTool>> {
+        let mut tools = Vec::new();
+        let mut cursor = None;
+        loop {
+            let params = match cursor {
+                Some(cursor) => serde_json::json!({"cursor": cursor}),
+                None => serde_json::json!({}),
+            };
+            let result = self.request("tools/list", params).await?;
+            let page: ToolsPage = serde_json::from_value(result)
+                .with_context(|| format!("Invalid tools/list result from MCP server '{}'", self.name))?;
+            tools.extend(page.tools);
+
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(tools),
+            }
+        }
+    }
+
+    /// Calls a tool of the server.
+    ///
+    /// Text items of the result are joined by newlines; other items (images,
+    /// resources) are kept as JSON.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the tool
+    /// * `arguments` - The arguments object
+    ///
+    /// # Returns
+    /// * `Ok(String)` - The content of the result
+    /// * `Err` - An error if the request failed or the server reported the call
+    ///   as failed
+    pub async fn call_tool(&mut self, name: &str, arguments: Value) -> Result<String> {
+        let result = self
+            .request("tools/call", serde_json::json!({"name": name, "arguments": arguments}))
+            .await?;
+        let result: CallResult = serde_json::from_value(result)
+            .with_context(|| format!("Invalid tools/call result from MCP server '{}'", self.name))?;
+
+        let content = result
+            .content
+            .iter()
+            .map(|item| {
+                match (item["type"].as_str(), item["text"].as_str()) {
+                    (Some("text"), Some(text)) => text.to_owned(),
+                    _ => item.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if result.is_error {
+            bail!("{content}");
+        }
+
+        Ok(content)
+    }
+
+    /// Sends a request and waits for its response.
+    ///
+    /// Notifications received meanwhile are logged and skipped, requests of the
+    /// server are answered (only `ping` is supported) and responses to other
+    /// requests, such as ones that timed out earlier, are dropped.
+    ///
+    /// # Arguments
+    /// * `method` - The method name
+    /// * `params` - The method parameters
+    ///
+    /// # Returns
+    /// * `Ok(Value)` - The result of the request
+    /// * `Err` - An error if the connection failed or the server returned an
+    ///   error
+    pub async fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&serde_json::json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+            .await?;
+
+        loop {
+            let mut message = self.receive().await?;
+            match (
+                message.get("id").cloned(),
+                message.get("method").and_then(Value::as_str),
+            ) {
+                (Some(request_id), Some(server_method)) => {
+                    let reply = match server_method {
+                        "ping" => serde_json::json!({"jsonrpc": "2.0", "id": request_id, "result": {}}),
+                        _ => {
+                            let error = format!("Method not found: {server_method}");
+                            serde_json::json!({
+                                "jsonrpc": "2.0",
+                                "id": request_id,
+                                "error": {"code": METHOD_NOT_FOUND, "message": error}
+                            })
+                        },
+                    };
+                    self.send(&reply).await?;
+                },
+                (None, Some(notification)) => debug!("MCP server '{}' sent {notification}", self.name),
+                (Some(response_id), None) if response_id == id => {
+                    if let Some(error) = message.get("error") {
+                        bail!(
+                            "MCP server '{}' failed {method}: {} (code {})",
+                            self.name,
+                            error["message"].as_str().unwrap_or("unknown error"),
+                            error["code"]
+                        );
+                    }
+                    return Ok(message.get_mut("result").map(Value::take).unwrap_or_default());
+                },
+                _ => debug!("Dropping unexpected message from MCP server '{}': {message}", self.name),
+            }
+        }
+    }
+
+    /// Writes a message followed by a newline, after the rest of any message
+    /// whose writing was cancelled.
+    async fn send(&mut self, message: &Value) -> Result<()> {
+        self.outgoing.extend_from_slice(message.to_string().as_bytes());
+        self.outgoing.push(b'\n');
+        while !self.outgoing.is_empty() {
+            let written = self
+                .writer
+                .write(&self.outgoing)
+                .await
+                .with_context(|| format!("Failed to write to MCP server '{}'", self.name))?;
+            if written == 0 {
+                bail!("MCP server '{}' closed the connection", self.name);
+            }
+            self.outgoing.drain(..written);
+        }
+        self.writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Reads the next non-empty line as a JSON message.
+    ///
+    /// The line is read into a buffer of the client, where a read cancelled
+    /// midway leaves the bytes read so far.
+    async fn receive(&mut self) -> Result<Value> {
+        loop {
+            let read = self
+                .reader
+                .read_until(b'\n', &mut self.incoming)
+                .await
+                .with_context(|| format!("Failed to read from MCP server '{}'", self.name))?;
+            if read == 0 {
+                bail!("MCP server '{}' closed the connection", self.name);
+            }
+            let line = std::mem::take(&mut self.incoming);
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                return serde_json::from_str(&line)
+                    .with_context(|| format!("Invalid message from MCP server '{}': {}", self.name, line.trim()));
+            }
+        }
+    }
+}
+
+/// Boxed stream the server writes to.
+type ServerOutput = Box<dyn AsyncRead+Send+Unpin>;
+
+/// Boxed stream the server reads from.
+type ServerInput = Box<dyn AsyncWrite+Send+Unpin>;
+
+/// An initialized connection to an MCP server.
+///
+/// Requests are serialized through a lock, so that the server may be shared
+/// by the tools it provides.
+pub struct McpServer {
+    name: String,
+    client: Mutex<McpClient<ServerOutput, ServerInput>>,
+    timeout: Duration,
+    /// The server process, killed when the connection is dropped
+    _child: Option<Child>,
+}
+
+impl McpServer {
+    /// Launches a server declared in the configuration and initializes it.
+    ///
+    /// The standard error of the server is inherited, so that its logs are
+    /// shown along the ones of the tool.
+    ///
+    /// # Arguments
+    /// * `name` - The server name
+    /// * `config` - The server settings
+    ///
+    /// # Returns
+    /// * `Ok(McpServer)` - The initialized server
+    /// * `Err` - An error if the server could not be launched or initialized
+    pub async fn spawn(name: &str, config: &McpServerConfig) -> Result<Self> {
+        let Some((program, args)) = config.command.split_first() else {
+            bail!("MCP server '{name}' has no command");
+        };
+
+        let mut child = Command::new(program)
+            .args(args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to launch MCP server '{name}' ({program})"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("No stdout for MCP server '{name}'"))?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("No stdin for MCP server '{name}'"))?;
+
+        let timeout = config.timeout.map_or(DEFAULT_MCP_TIMEOUT, Duration::from_secs);
+        let mut server = Self::connect(name, stdout, stdin, timeout).await?;
+        server._child = Some(child);
+
+        Ok(server)
+    }
+
+    /// Initializes a server reachable through a pair of streams.
+    ///
+    /// # Arguments
+    /// * `name` - The server name
+    /// * `reader` - The stream the server writes its messages to
+    /// * `writer` - The stream the server reads its messages from
+    /// * `timeout` - Time limit for each request
+    ///
+    /// # Returns
+    /// * `Ok(McpServer)` - The initialized server
+    /// * `Err` - An error if the initialization failed
+    pub async fn connect(
+        name: &str,
+        reader: impl AsyncRead+Send+Unpin+'static,
+        writer: impl AsyncWrite+Send+Unpin+'static,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let server = Self {
+            name: name.to_owned(),
+            client: Mutex::new(McpClient::new(name, Box::new(reader), Box::new(writer))),
+            timeout,
+            _child: None,
+        };
+        let result = server
+            .with_timeout("initialize", async |client| client.initialize().await)
+            .await?;
+        info!(
+            "Connected to MCP server '{name}' ({})",
+            result["serverInfo"]["name"].as_str().unwrap_or("unknown")
+        );
+
+        Ok(server)
+    }
+
+    /// Returns the server name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Lists the tools of the server.
+    pub async fn list_tools(&self) -> Result<Vec<McpTool>> {
+        self.with_timeout("tools/list", async |client| client.list_tools().await)
+            .await
+    }
+
+    /// Calls a tool of the server.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the tool
+    /// * `arguments` - The arguments object
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<String> {
+        self.with_timeout("tools/call", async |client| client.call_tool(name, arguments).await)
+            .await
+    }
+
+    /// Runs an operation on the client within the request time limit.
+    async fn with_timeout<T>(
+        &self,
+        method: &str,
+        operation: impl AsyncFnOnce(&mut McpClient<ServerOutput, ServerInput>) -> Result<T>,
+    ) -> Result<T> {
+        let mut client = self.client.lock().await;
+
+        tokio::time::timeout(self.timeout, operation(&mut client))
+            .await
+            .with_context(|| {
+                format!(
+                    "MCP server '{}' did not answer {method} within {}s",
+                    self.name,
+                    self.timeout.as_secs()
+                )
+            })?
+    }
+}
+
+impl fmt::Debug for McpServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("McpServer")
+            .field("name", &self.name)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::RequestPayload;
+use crate::schema::ApiResponse;
+
+/// Relative URL of the chat completions endpoint in batch input files.
+pub const BATCH_URL: &str = "/v1/chat/completions";
+
+/// HTTP method of every request in a batch input file.
+const BATCH_METHOD: &str = "POST";
+
+/// A single line of an `OpenAI` Batch API input file.
+///
+/// # Fields
+/// * `custom_id` - Identifier used to match the line with its output
+/// * `method` - HTTP method of the request (always "POST")
+/// * `url` - Relative URL of the endpoint (e.g. `/v1/chat/completions`)
+/// * `body` - The serialized [`RequestPayload`], with its extra body fields
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchInputLine {
+    pub custom_id: String,
+    pub method: String,
+    pub url: String,
+    pub body: Value,
+}
+
+impl BatchInputLine {
+    /// Creates a batch input line for a chat completion payload.
+    ///
+    /// # Arguments
+    /// * `custom_id` - Identifier used to match the line with its output
+    /// * `payload` - The chat completion payload to send
+    ///
+    /// # Returns
+    /// * `Ok(BatchInputLine)` - The batch input line
+    /// * `Err` - An error if the payload could not be serialized
+    pub fn new(custom_id: impl Into<String>, payload: &RequestPayload) -> Result<Self> {
+        let mut body = serde_json::to_value(payload).context("Failed to serialize request payload")?;
+        payload.merge_extra_body(&mut body);
+
+        Ok(Self {
+            custom_id: custom_id.into(),
+            method: BATCH_METHOD.to_owned(),
+            url: BATCH_URL.to_owned(),
+            body,
+        })
+    }
+}
+
+/// The response of a request in an `OpenAI` Batch API output file.
+///
+/// # Fields
+/// * `status_code` - HTTP status of the request
+/// * `request_id` - Identifier of the request
+/// * `body` - The response body (an [`ApiResponse`] on success)
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct BatchOutputResponse {
+    pub status_code: u16,
+    pub request_id: String,
+    pub body: Value,
+}
+
+/// The error of a request in an `OpenAI` Batch API output file.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct BatchOutputError {
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// A single line of an `OpenAI` Batch API output file.
+///
+/// # Fields
+/// * `id` - Identifier of the batch request
+/// * `custom_id` - Identifier of the matching input line
+/// * `response` - The response, when the request was sent
+/// * `error` - The error, when the request could not be sent
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct BatchOutputLine {
+    pub id: String,
+    pub custom_id: String,
+    pub response: Option<BatchOutputResponse>,
+    pub error: Option<BatchOutputError>,
+}
+
+impl BatchOutputLine {
+    /// Extracts the chat completion response of the line.
+    ///
+    /// # Returns
+    /// * `Ok(ApiResponse)` - The parsed response
+    /// * `Err` - An error if the request failed, returned a non-2xx status or
+    ///   its body is not a chat completion
+    pub fn into_api_response(self) -> Result<ApiResponse> {
+        if let Some(error) = self.error {
+            anyhow::bail!(
+                "Batch request failed ({}): {}",
+                error.code.unwrap_or_default(),
+                error.message
+            );
+        }
+        let Some(response) = self.response else {
+            anyhow::bail!("Batch output line has neither a response nor an error");
+        };
+        if !(200..300).contains(&response.status_code) {
+            anyhow::bail!(
+                "Batch request failed with status {}: {}",
+                response.status_code,
+                response.body
+            );
+        }
+
+        serde_json::from_value(response.body).context("Failed to parse chat completion in batch output")
+    }
+}
+
+/// Parses the content of an `OpenAI` Batch API output file.
+///
+/// # Arguments
+/// * `content` - The JSONL content, one output line per request
+///
+/// # Returns
+/// * `Ok(Vec<BatchOutputLine>)` - The parsed lines (empty lines are skipped)
+/// * `Err` - An error naming the first line that is not valid JSON
+pub fn parse_batch_output(content: &str) -> Result<Vec<BatchOutputLine>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| format!("Failed to parse batch output line {}", index + 1))
+        })
+        .collect()
+}
+
+/// Derives a custom id from an input file path.
+///
+/// The file stem is used, so `inputs/review.md` becomes `review`.
+///
+/// # Arguments
+/// * `path` - Path of the input file
+pub fn custom_id_for(path: &Path) -> String {
+    path.file_stem().or_else(|| path.file_name()).map_or_else(
+        || path.display().to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
+/// Derives the custom ids of input files, which must be unique in a batch.
+///
+/// # Arguments
+/// * `paths` - Paths of the input files
+///
+/// # Returns
+/// * `Ok(Vec<String>)` - The custom ids, in the order of the paths
+/// * `Err` - An error naming the first two files sharing a custom id (e.g.
+///   `review.md` and `review.txt`)
+pub fn custom_ids_for(paths: &[PathBuf]) -> Result<Vec<String>> {
+    let mut seen: HashMap<String, &Path> = HashMap::new();
+    let mut custom_ids = Vec::with_capacity(paths.len());
+    for path in paths {
+        let custom_id = custom_id_for(path);
+        if let Some(previous) = seen.insert(custom_id.clone(), path) {
+            bail!(
+                "Input files {} and {} share the custom id '{custom_id}', rename one of them",
+                previous.display(),
+                path.display()
+            );
+        }
+        custom_ids.push(custom_id);
+    }
+
+    Ok(custom_ids)
+}
+
+/// Turns a custom id into a safe file name.
+///
+/// Path separators and other characters that are not portable in file names
+/// are replaced by underscores.
+///
+/// # Arguments
+/// * `custom_id` - The custom id of a batch line
+pub fn file_name_for(custom_id: &str) -> String {
+    let name: String = custom_id
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if name.is_empty() || name.chars().all(|c| c == '.') {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+/// Turns the custom ids of a batch output into file names, which must not
+/// collide.
+///
+/// # Arguments
+/// * `custom_ids` - The custom ids of the batch lines
+///
+/// # Returns
+/// * `Ok(Vec<String>)` - The file names, see [`file_name_for`]
+/// * `Err` - An error naming the first two custom ids mapped to the same file
+///   (e.g. `a/b` and `a_b`)
+pub fn file_names_for<'a>(custom_ids: impl IntoIterator<Item=&'a str>) -> Result<Vec<String>> {
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    let mut file_names = Vec::new();
+    for custom_id in custom_ids {
+        let file_name = file_name_for(custom_id);
+        if let Some(previous) = seen.insert(file_name.clone(), custom_id) {
+            bail!("Custom ids '{previous}' and '{custom_id}' would both be written to {file_name}.json");
+        }
+        file_names.push(file_name);
+    }
+
+    Ok(file_names)
+}
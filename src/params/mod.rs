@@ -0,0 +1,155 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Accepted values of the `reasoning_effort` request field.
+pub const REASONING_EFFORTS: &[&str] = &["minimal", "low", "medium", "high"];
+
+/// Sampling parameters sent with every request of a client.
+///
+/// Unset parameters are left out of the payload, so that the provider's
+/// defaults apply.
+///
+/// # Fields
+/// * `temperature` - Sampling temperature (usually 0 to 2)
+/// * `top_p` - Nucleus sampling probability mass
+/// * `seed` - Seed for best-effort deterministic sampling
+/// * `stop` - Sequences that end the generation
+/// * `presence_penalty` - Penalty for tokens already present (-2 to 2)
+/// * `frequency_penalty` - Penalty proportional to token frequency (-2 to 2)
+/// * `n` - Number of choices to generate
+/// * `logit_bias` - Bias added to the logits of token identifiers (-100 to 100)
+/// * `reasoning_effort` - Effort of reasoning models, one of
+///   [`REASONING_EFFORTS`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SamplingParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<BTreeMap<String, i32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+}
+
+impl SamplingParams {
+    /// Returns whether no parameter is set.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Deep-merges a JSON patch into a value.
+///
+/// Objects are merged key by key, recursively. A `null` in the patch removes
+/// the key, and any other value replaces the one of the target, as in a JSON
+/// merge patch (RFC 7386).
+///
+/// # Arguments
+/// * `target` - The value to update, usually the serialized request body
+/// * `patch` - The value merged into it
+pub fn merge_json(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!("the target was just made an object");
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_json(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Parses a `key=value` assignment into a patch of the request body.
+///
+/// Dots in the key address nested objects, and the value is parsed as JSON
+/// when possible, falling back to a string.
+///
+/// # Arguments
+/// * `assignment` - The assignment (e.g. "top_k=40",
+///   "provider.order=[\"novita\"]" or "user=alice")
+///
+/// # Returns
+/// * `Ok(Value)` - An object to merge with [`merge_json`]
+/// * `Err` - A message if there is no `=` or a key segment is empty
+pub fn parse_set(assignment: &str) -> Result<Value, String> {
+    let Some((key, value)) = assignment.split_once('=') else {
+        return Err(format!("'{assignment}' is not a key=value assignment"));
+    };
+    let segments: Vec<_> = key.trim().split('.').collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(format!("'{key}' is not a valid key: use names separated by dots"));
+    }
+
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()));
+    let patch = segments.iter().rev().fold(value, |value, segment| {
+        Value::Object(Map::from_iter([((*segment).to_owned(), value)]))
+    });
+
+    Ok(patch)
+}
+
+/// Parses a `TOKEN=BIAS` logit bias entry.
+///
+/// # Arguments
+/// * `entry` - The entry (e.g. "50256=-100")
+///
+/// # Returns
+/// * `Ok((token, bias))` - The token identifier and its bias
+/// * `Err` - A message if the entry is malformed or the bias is out of range
+pub fn parse_logit_bias(entry: &str) -> Result<(String, i32), String> {
+    let Some((token, bias)) = entry.split_once('=') else {
+        return Err(format!("'{entry}' is not a TOKEN=BIAS entry"));
+    };
+    if token.trim().parse::<u32>().is_err() {
+        return Err(format!("'{token}' is not a token identifier"));
+    }
+    match bias.trim().parse::<i32>() {
+        Ok(bias) if (-100..=100).contains(&bias) => Ok((token.trim().to_owned(), bias)),
+        _ => Err(format!("'{bias}' is not a bias between -100 and 100")),
+    }
+}
+
+/// Reads a JSON file holding fields to merge into the request body.
+///
+/// # Arguments
+/// * `path` - Path of the JSON file
+///
+/// # Returns
+/// * `Ok(Value)` - The object held by the file
+/// * `Err` - An error if the file could not be read or parsed, or does not hold
+///   an object
+pub fn read_extra_body(path: &Path) -> Result<Value> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read extra body file {}", path.display()))?;
+    let value: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse extra body file {}", path.display()))?;
+    if !value.is_object() {
+        bail!("Extra body file {} must hold an object", path.display());
+    }
+
+    Ok(value)
+}
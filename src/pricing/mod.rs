@@ -0,0 +1,285 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::LazyLock;
+
+use crate::schema::Usage;
+
+/// Number of tokens the prices are given for.
+const TOKENS_PER_PRICE: f64 = 1_000_000.0;
+
+/// Prices of a model, in US dollars per million tokens.
+///
+/// Entries are declared in the `[pricing.<endpoint>]` tables of the
+/// configuration files, for example:
+///
+/// ```toml
+/// [pricing.openai]
+/// "gpt-4.1-mini" = { input = 0.4, cached_input = 0.1, output = 1.6 }
+/// ```
+///
+/// # Fields
+/// * `input` - Price of the prompt tokens
+/// * `cached_input` - Price of the prompt tokens read from the provider's
+///   prompt cache (defaults to `input`)
+/// * `output` - Price of the completion tokens
+/// * `reasoning` - Price of the reasoning tokens (defaults to `output`)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    pub input: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_input: Option<f64>,
+    pub output: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<f64>,
+}
+
+impl ModelPrice {
+    /// Returns whether no token is priced lower than with another price.
+    ///
+    /// # Arguments
+    /// * `other` - The price to compare with
+    pub fn is_at_least(&self, other: &ModelPrice) -> bool {
+        let cached_input = |price: &ModelPrice| price.cached_input.unwrap_or(price.input);
+        let reasoning = |price: &ModelPrice| price.reasoning.unwrap_or(price.output);
+
+        self.input >= other.input
+            && self.output >= other.output
+            && cached_input(self) >= cached_input(other)
+            && reasoning(self) >= reasoning(other)
+    }
+
+    /// Computes the cost of a request from its token usage.
+    ///
+    /// Cached prompt tokens are taken out of the prompt tokens, and reasoning
+    /// tokens out of the completion tokens, since providers count them in
+    /// both.
+    ///
+    /// # Arguments
+    /// * `usage` - The token usage reported by the provider
+    pub fn cost(&self, usage: &Usage) -> Cost {
+        let cached_tokens = usage
+            .prompt_tokens_details
+            .as_ref()
+            .and_then(|details| details.cached_tokens)
+            .unwrap_or_default()
+            .clamp(0, usage.prompt_tokens.max(0));
+        let reasoning_tokens = usage
+            .completion_tokens_details
+            .as_ref()
+            .and_then(|details| details.reasoning_tokens)
+            .unwrap_or_default()
+            .clamp(0, usage.completion_tokens.max(0));
+
+        let price = |tokens: i64, price: f64| tokens as f64 * price / TOKENS_PER_PRICE;
+        let input = price(usage.prompt_tokens - cached_tokens, self.input);
+        let cached_input = price(cached_tokens, self.cached_input.unwrap_or(self.input));
+        let output = price(usage.completion_tokens - reasoning_tokens, self.output);
+        let reasoning = price(reasoning_tokens, self.reasoning.unwrap_or(self.output));
+
+        Cost {
+            input,
+            cached_input,
+            output,
+            reasoning,
+            total: input + cached_input + output + reasoning,
+        }
+    }
+}
+
+/// The cost of one or more requests, in US dollars.
+///
+/// # Fields
+/// * `input` - Cost of the uncached prompt tokens
+/// * `cached_input` - Cost of the cached prompt tokens
+/// * `output` - Cost of the completion tokens, reasoning tokens aside
+/// * `reasoning` - Cost of the reasoning tokens
+/// * `total` - Sum of the other costs
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cost {
+    pub input: f64,
+    pub cached_input: f64,
+    pub output: f64,
+    pub reasoning: f64,
+    pub total: f64,
+}
+
+impl Cost {
+    /// Adds the cost of another request to this cost.
+    ///
+    /// # Arguments
+    /// * `other` - The cost to add
+    pub fn add(&mut self, other: &Cost) {
+        self.input += other.input;
+        self.cached_input += other.cached_input;
+        self.output += other.output;
+        self.reasoning += other.reasoning;
+        self.total += other.total;
+    }
+}
+
+impl fmt::Display for Cost {
+    /// Writes the total cost in dollars (e.g. `$0.001234`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${:.6}", self.total)
+    }
+}
+
+/// A price shipped with the tool.
+///
+/// # Fields
+/// * `endpoint` - The built-in endpoint name serving the model
+/// * `model` - The model identifier
+/// * `input`, `cached_input`, `output` - The prices, see [`ModelPrice`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuiltinPrice {
+    pub endpoint: &'static str,
+    pub model: &'static str,
+    pub input: f64,
+    pub cached_input: f64,
+    pub output: f64,
+}
+
+/// Prices known without any configuration, as published by the providers.
+///
+/// They may lag behind price changes; the `[pricing]` tables of the
+/// configuration files take precedence.
+pub const BUILTIN_PRICES: &[BuiltinPrice] = &[
+    price("openai", "gpt-5", 1.25, 0.125, 10.0),
+    price("openai", "gpt-5-mini", 0.25, 0.025, 2.0),
+    price("openai", "gpt-5-nano", 0.05, 0.005, 0.4),
+    price("openai", "gpt-4.1", 2.0, 0.5, 8.0),
+    price("openai", "gpt-4.1-mini", 0.4, 0.1, 1.6),
+    price("openai", "gpt-4.1-nano", 0.1, 0.025, 0.4),
+    price("openai", "gpt-4o", 2.5, 1.25, 10.0),
+    price("openai", "gpt-4o-mini", 0.15, 0.075, 0.6),
+    price("openai", "o3", 2.0, 0.5, 8.0),
+    price("openai", "o4-mini", 1.1, 0.275, 4.4),
+    price("anthropic", "claude-opus-4-1", 15.0, 1.5, 75.0),
+    price("anthropic", "claude-sonnet-4-5", 3.0, 0.3, 15.0),
+    price("anthropic", "claude-haiku-4-5", 1.0, 0.1, 5.0),
+    price("google", "gemini-2.5-pro", 1.25, 0.31, 10.0),
+    price("google", "gemini-2.5-flash", 0.3, 0.075, 2.5),
+    price("google", "gemini-2.5-flash-lite", 0.1, 0.025, 0.4),
+    price("deepseek", "deepseek-chat", 0.28, 0.028, 0.42),
+    price("deepseek", "deepseek-reasoner", 0.28, 0.028, 0.42),
+];
+
+/// Creates a [`BuiltinPrice`].
+const fn price(
+    endpoint: &'static str,
+    model: &'static str,
+    input: f64,
+    cached_input: f64,
+    output: f64,
+) -> BuiltinPrice {
+    BuiltinPrice {
+        endpoint,
+        model,
+        input,
+        cached_input,
+        output,
+    }
+}
+
+impl From<&BuiltinPrice> for ModelPrice {
+    fn from(price: &BuiltinPrice) -> Self {
+        Self {
+            input: price.input,
+            cached_input: Some(price.cached_input),
+            output: price.output,
+            reasoning: None,
+        }
+    }
+}
+
+/// Prices of models, keyed by endpoint name and model identifier.
+///
+/// The table starts with the [`BUILTIN_PRICES`], which user-defined entries
+/// extend or replace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricingTable {
+    prices: BTreeMap<String, BTreeMap<String, ModelPrice>>,
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl PricingTable {
+    /// Creates a table holding the built-in prices.
+    pub fn builtin() -> Self {
+        let mut table = Self {
+            prices: BTreeMap::new(),
+        };
+        for price in BUILTIN_PRICES {
+            table.insert(price.endpoint, price.model, ModelPrice::from(price));
+        }
+
+        table
+    }
+
+    /// Adds the price of a model, replacing any price of the same model.
+    ///
+    /// # Arguments
+    /// * `endpoint` - The endpoint name serving the model
+    /// * `model` - The model identifier
+    /// * `price` - The prices of the model
+    pub fn insert(&mut self, endpoint: impl Into<String>, model: impl Into<String>, price: ModelPrice) {
+        self.prices
+            .entry(endpoint.into())
+            .or_default()
+            .insert(model.into(), price);
+    }
+
+    /// Returns the price of a model.
+    ///
+    /// Models with a date suffix (e.g. `gpt-4.1-mini-2025-04-14` or
+    /// `claude-sonnet-4-5-20250929`) fall back to the price of the undated
+    /// model.
+    ///
+    /// # Arguments
+    /// * `endpoint` - The endpoint name serving the model
+    /// * `model` - The model identifier
+    pub fn get(&self, endpoint: &str, model: &str) -> Option<&ModelPrice> {
+        let prices = self.prices.get(endpoint)?;
+        prices
+            .get(model)
+            .or_else(|| strip_date_suffix(model).and_then(|model| prices.get(model)))
+    }
+
+    /// Iterates over the prices, sorted by endpoint name and model.
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &str, &ModelPrice)> {
+        self.prices.iter().flat_map(|(endpoint, prices)| {
+            prices
+                .iter()
+                .map(move |(model, price)| (endpoint.as_str(), model.as_str(), price))
+        })
+    }
+
+    /// Computes the cost of a request.
+    ///
+    /// # Arguments
+    /// * `endpoint` - The endpoint name the request was sent to
+    /// * `model` - The model identifier
+    /// * `usage` - The token usage reported by the provider
+    ///
+    /// # Returns
+    /// * `Some(Cost)` - The cost of the request
+    /// * `None` - If the model has no known price
+    pub fn cost(&self, endpoint: &str, model: &str, usage: &Usage) -> Option<Cost> {
+        self.get(endpoint, model).map(|price| price.cost(usage))
+    }
+}
+
+/// Strips a `-YYYY-MM-DD` or `-YYYYMMDD` date suffix from a model identifier.
+pub(crate) fn strip_date_suffix(model: &str) -> Option<&str> {
+    static DATE_SUFFIX: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"-(\d{4}-\d{2}-\d{2}|\d{8})$").expect("valid date suffix pattern"));
+
+    DATE_SUFFIX.find(model).map(|suffix| &model[..suffix.start()])
+}
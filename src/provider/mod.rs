@@ -0,0 +1,347 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::RequestPayload;
+use crate::registry::{EndpointRegistry, builtin_endpoint};
+use crate::schema::{ApiResponse, ChatCompletionChunk};
+use crate::stream::parse_chunk;
+
+/// Default endpoint value used when no known endpoint name is provided.
+/// This constant serves as a fallback to indicate that a custom endpoint URL
+/// should be used.
+pub const DEFAULT_ENDPOINT: &str = "none";
+
+/// Maps known endpoint names to their corresponding API URLs.
+///
+/// This function takes a string identifier for a built-in endpoint and returns
+/// the appropriate API endpoint URL. If the name is not recognized, it returns
+/// the `DEFAULT_ENDPOINT` constant. User-defined endpoints are resolved by the
+/// [`EndpointRegistry`] instead.
+///
+/// # Arguments
+/// * `name` - The endpoint name to look up ("openai", "google", "hf", etc.)
+///
+/// # Returns
+/// * The corresponding API URL, or `DEFAULT_ENDPOINT` if name is not recognized
+///
+/// # Supported Endpoints
+/// Every entry of [`crate::registry::BUILTIN_ENDPOINTS`], including:
+/// * "openai" - `OpenAI` API endpoint
+/// * "google" - Google Generative Language API endpoint
+/// * "hf" - Hugging Face API endpoint
+/// * "anthropic" - Anthropic Messages API endpoint
+/// * "groq", "together", "mistral", "deepseek", "openrouter", "fireworks" and
+///   "cerebras" - OpenAI-compatible provider endpoints
+pub fn known_endpoints(name: &str) -> &'static str {
+    builtin_endpoint(name).map_or(DEFAULT_ENDPOINT, |endpoint| endpoint.url)
+}
+
+/// Maps endpoint names to their corresponding environment variable names for
+/// API keys.
+///
+/// This function determines which environment variable should be checked for
+/// the API key based on the endpoint being used. This allows different services
+/// to use different environment variables for their authentication tokens.
+///
+/// # Arguments
+/// * `name` - The endpoint name to look up ("openai", "google", "hf", etc.)
+///
+/// # Returns
+/// * The corresponding environment variable name for the API key
+///
+/// # Environment Variables
+/// * "openai" - Uses `API_TOKEN_OAI`
+/// * "google" - Uses `API_TOKEN_GOOGLE`
+/// * "hf" - Uses `API_TOKEN_HF`
+/// * "anthropic" - Uses `API_TOKEN_ANTHROPIC`
+/// * Other built-in endpoints - Use `API_TOKEN_<NAME>` (e.g. `API_TOKEN_GROQ`)
+/// * All others - Uses `API_TOKEN` (default)
+pub fn env_api_key(name: &str) -> &'static str {
+    builtin_endpoint(name).map_or("API_TOKEN", |endpoint| endpoint.api_key_env)
+}
+
+/// How the API token is attached to a request.
+///
+/// In configuration files, this is written `"bearer"`, `"none"` or
+/// `{ header = "<name>" }`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthScheme {
+    /// `Authorization: Bearer <token>`, used by OpenAI-compatible APIs.
+    Bearer,
+    /// The raw token in the named header (e.g. `x-api-key`).
+    Header(String),
+    /// No authentication, for local servers. No API token is required.
+    None,
+}
+
+impl AuthScheme {
+    /// Builds the header that carries the API token.
+    ///
+    /// # Arguments
+    /// * `api_token` - The API token to send
+    ///
+    /// # Returns
+    /// * `Some((name, value))` - The header name and value
+    /// * `None` - If the token is not sent
+    pub fn header(&self, api_token: &str) -> Option<(String, String)> {
+        match self {
+            Self::Bearer => Some(("Authorization".to_owned(), format!("Bearer {api_token}"))),
+            Self::Header(name) => Some((name.clone(), api_token.to_owned())),
+            Self::None => None,
+        }
+    }
+}
+
+/// The payload field that carries the token limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenLimitField {
+    /// `max_tokens`, used by regular models.
+    MaxTokens,
+    /// `max_completion_tokens`, used by reasoning models.
+    MaxCompletionTokens,
+}
+
+impl TokenLimitField {
+    /// Returns the name of the field in the serialized payload.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::MaxTokens => "max_tokens",
+            Self::MaxCompletionTokens => "max_completion_tokens",
+        }
+    }
+}
+
+/// Decodes the data payloads of a streaming response into chunks.
+///
+/// Implementations may keep state between events, for APIs that spread the
+/// information of a chunk over several events.
+pub trait StreamDecoder: Send {
+    /// Decodes the data payload of a single SSE event.
+    ///
+    /// # Arguments
+    /// * `data` - The payload returned by [`crate::stream::SseParser::feed`]
+    ///
+    /// # Returns
+    /// * `Ok(Some(chunk))` - The equivalent chat completion chunk
+    /// * `Ok(None)` - The event carries nothing to report
+    /// * `Err` - The payload could not be decoded
+    fn decode(&mut self, data: &str) -> Result<Option<ChatCompletionChunk>>;
+}
+
+/// A backend that can serve chat completions.
+///
+/// A provider knows where to send requests, how to authenticate, how to
+/// translate a [`RequestPayload`] into its wire format and how to translate
+/// its responses back into [`ApiResponse`]. New backends are added by
+/// implementing this trait and returning them from [`resolve_provider`].
+pub trait Provider: Send+Sync {
+    /// Returns the endpoint name of the provider (e.g. "openai").
+    fn name(&self) -> &str;
+
+    /// Returns the URL requests are sent to.
+    fn url(&self) -> &str;
+
+    /// Returns the environment variable holding the API token.
+    fn api_key_env(&self) -> &str;
+
+    /// Returns how the API token is attached to requests.
+    fn auth_scheme(&self) -> AuthScheme {
+        AuthScheme::Bearer
+    }
+
+    /// Returns headers sent with every request, besides authentication.
+    fn default_headers(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Returns whether the endpoint requires the prompt to be sent with the
+    /// "system" role (`Some(true)`) or the "assistant" role (`Some(false)`).
+    ///
+    /// An explicit choice made when building the client takes precedence.
+    fn system_role(&self) -> Option<bool> {
+        None
+    }
+
+    /// Returns the payload field that carries the token limit.
+    ///
+    /// # Arguments
+    /// * `reasoning` - Whether a reasoning model is queried
+    fn token_limit_field(&self, reasoning: bool) -> TokenLimitField {
+        if reasoning {
+            TokenLimitField::MaxCompletionTokens
+        } else {
+            TokenLimitField::MaxTokens
+        }
+    }
+
+    /// Serializes the payload into the request body.
+    ///
+    /// # Arguments
+    /// * `payload` - The chat completion payload to send
+    ///
+    /// # Returns
+    /// * `Ok(Value)` - The JSON request body
+    /// * `Err` - An error if the payload cannot be expressed for this provider
+    fn serialize_request(&self, payload: &RequestPayload) -> Result<Value> {
+        serde_json::to_value(payload).context("Failed to serialize request payload")
+    }
+
+    /// Deserializes a successful response body.
+    ///
+    /// # Arguments
+    /// * `body` - The raw response body
+    ///
+    /// # Returns
+    /// * `Ok(ApiResponse)` - The parsed response
+    /// * `Err` - An error if the body is not a valid response
+    fn deserialize_response(&self, body: &str) -> Result<ApiResponse> {
+        serde_json::from_str(body).context("Failed to parse JSON response from the API.")
+    }
+
+    /// Creates a decoder for a streaming response.
+    fn stream_decoder(&self) -> Box<dyn StreamDecoder> {
+        Box::new(ChunkDecoder)
+    }
+
+    /// Returns every header of a request, including authentication.
+    ///
+    /// # Arguments
+    /// * `api_token` - The API token to send
+    fn headers(&self, api_token: &str) -> Vec<(String, String)> {
+        let mut headers: Vec<_> = self.auth_scheme().header(api_token).into_iter().collect();
+        headers.extend(self.default_headers());
+        headers
+    }
+}
+
+/// Decoder for OpenAI-compatible `chat.completion.chunk` streams.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChunkDecoder;
+
+impl StreamDecoder for ChunkDecoder {
+    fn decode(&mut self, data: &str) -> Result<Option<ChatCompletionChunk>> {
+        parse_chunk(data)
+    }
+}
+
+/// A provider speaking the OpenAI chat completion API.
+///
+/// Used for the built-in "openai", "google" and "hf" endpoints, the other
+/// OpenAI-compatible entries of the [`EndpointRegistry`] and any custom URL,
+/// which all share the same request and response shapes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenAiCompatible {
+    name: String,
+    url: String,
+    api_key_env: String,
+    auth_scheme: AuthScheme,
+    headers: Vec<(String, String)>,
+    system_role: Option<bool>,
+}
+
+impl OpenAiCompatible {
+    /// Creates a provider for an arbitrary OpenAI-compatible endpoint.
+    ///
+    /// # Arguments
+    /// * `name` - The endpoint name
+    /// * `url` - The chat completions URL
+    /// * `api_key_env` - The environment variable holding the API token
+    pub fn new(name: impl Into<String>, url: impl Into<String>, api_key_env: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            api_key_env: api_key_env.into(),
+            auth_scheme: AuthScheme::Bearer,
+            headers: Vec::new(),
+            system_role: None,
+        }
+    }
+
+    /// Sets how the API token is attached to requests (defaults to
+    /// [`AuthScheme::Bearer`]).
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Sets the headers sent with every request, besides authentication.
+    #[must_use]
+    pub fn with_headers(mut self, headers: Vec<(String, String)>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets the prompt role required by the endpoint, see
+    /// [`Provider::system_role`].
+    #[must_use]
+    pub fn with_system_role(mut self, system_role: Option<bool>) -> Self {
+        self.system_role = system_role;
+        self
+    }
+
+    /// Creates the built-in `OpenAI` provider.
+    pub fn openai() -> Self {
+        Self::new("openai", known_endpoints("openai"), env_api_key("openai"))
+    }
+
+    /// Creates the built-in Google Generative Language provider.
+    pub fn google() -> Self {
+        Self::new("google", known_endpoints("google"), env_api_key("google"))
+    }
+
+    /// Creates the built-in Hugging Face router provider.
+    pub fn hf() -> Self {
+        Self::new("hf", known_endpoints("hf"), env_api_key("hf"))
+    }
+
+    /// Creates a provider for a custom URL, authenticated with `API_TOKEN`.
+    pub fn custom(url: impl Into<String>) -> Self {
+        let url = url.into();
+        Self::new(url.clone(), url, env_api_key(""))
+    }
+}
+
+impl Provider for OpenAiCompatible {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn api_key_env(&self) -> &str {
+        &self.api_key_env
+    }
+
+    fn auth_scheme(&self) -> AuthScheme {
+        self.auth_scheme.clone()
+    }
+
+    fn default_headers(&self) -> Vec<(String, String)> {
+        self.headers.clone()
+    }
+
+    fn system_role(&self) -> Option<bool> {
+        self.system_role
+    }
+}
+
+/// Resolves an endpoint name or URL into a provider.
+///
+/// Only the built-in endpoints are known; use [`EndpointRegistry::resolve`]
+/// to also resolve user-defined ones.
+///
+/// # Arguments
+/// * `endpoint` - A built-in endpoint name ("openai", "google", "hf",
+///   "anthropic", "groq", etc.) or a custom URL
+///
+/// # Returns
+/// * The matching built-in provider, or an OpenAI-compatible provider for the
+///   custom URL
+pub fn resolve_provider(endpoint: &str) -> Box<dyn Provider> {
+    EndpointRegistry::builtin().resolve(endpoint)
+}
@@ -0,0 +1,282 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::anthropic::Anthropic;
+use crate::provider::{AuthScheme, OpenAiCompatible, Provider, env_api_key};
+
+/// The wire format spoken by an endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKind {
+    /// The `OpenAI` chat completion API, also served by most other providers.
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// The Anthropic Messages API.
+    Anthropic,
+}
+
+/// An endpoint shipped with the tool.
+///
+/// # Fields
+/// * `name` - The endpoint name used on the command line
+/// * `url` - The chat completions URL
+/// * `api_key_env` - Environment variable holding the API key
+/// * `api` - The wire format spoken by the endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuiltinEndpoint {
+    pub name: &'static str,
+    pub url: &'static str,
+    pub api_key_env: &'static str,
+    pub api: ApiKind,
+}
+
+/// Endpoints available without any configuration.
+pub const BUILTIN_ENDPOINTS: &[BuiltinEndpoint] = &[
+    BuiltinEndpoint {
+        name: "openai",
+        url: "https://api.openai.com/v1/chat/completions",
+        api_key_env: "API_TOKEN_OAI",
+        api: ApiKind::OpenAi,
+    },
+    BuiltinEndpoint {
+        name: "google",
+        url: "https://generativelanguage.googleapis.com/v1beta/chat/completions",
+        api_key_env: "API_TOKEN_GOOGLE",
+        api: ApiKind::OpenAi,
+    },
+    BuiltinEndpoint {
+        name: "hf",
+        url: "https://router.huggingface.co/v1/chat/completions",
+        api_key_env: "API_TOKEN_HF",
+        api: ApiKind::OpenAi,
+    },
+    BuiltinEndpoint {
+        name: "anthropic",
+        url: "https://api.anthropic.com/v1/messages",
+        api_key_env: "API_TOKEN_ANTHROPIC",
+        api: ApiKind::Anthropic,
+    },
+    BuiltinEndpoint {
+        name: "groq",
+        url: "https://api.groq.com/openai/v1/chat/completions",
+        api_key_env: "API_TOKEN_GROQ",
+        api: ApiKind::OpenAi,
+    },
+    BuiltinEndpoint {
+        name: "together",
+        url: "https://api.together.xyz/v1/chat/completions",
+        api_key_env: "API_TOKEN_TOGETHER",
+        api: ApiKind::OpenAi,
+    },
+    BuiltinEndpoint {
+        name: "mistral",
+        url: "https://api.mistral.ai/v1/chat/completions",
+        api_key_env: "API_TOKEN_MISTRAL",
+        api: ApiKind::OpenAi,
+    },
+    BuiltinEndpoint {
+        name: "deepseek",
+        url: "https://api.deepseek.com/chat/completions",
+        api_key_env: "API_TOKEN_DEEPSEEK",
+        api: ApiKind::OpenAi,
+    },
+    BuiltinEndpoint {
+        name: "openrouter",
+        url: "https://openrouter.ai/api/v1/chat/completions",
+        api_key_env: "API_TOKEN_OPENROUTER",
+        api: ApiKind::OpenAi,
+    },
+    BuiltinEndpoint {
+        name: "fireworks",
+        url: "https://api.fireworks.ai/inference/v1/chat/completions",
+        api_key_env: "API_TOKEN_FIREWORKS",
+        api: ApiKind::OpenAi,
+    },
+    BuiltinEndpoint {
+        name: "cerebras",
+        url: "https://api.cerebras.ai/v1/chat/completions",
+        api_key_env: "API_TOKEN_CEREBRAS",
+        api: ApiKind::OpenAi,
+    },
+];
+
+/// Looks up a built-in endpoint by name.
+///
+/// # Arguments
+/// * `name` - The endpoint name (e.g. "openai", "groq")
+///
+/// # Returns
+/// * `Some(&BuiltinEndpoint)` - The built-in endpoint
+/// * `None` - If no built-in endpoint has that name
+pub fn builtin_endpoint(name: &str) -> Option<&'static BuiltinEndpoint> {
+    BUILTIN_ENDPOINTS.iter().find(|endpoint| endpoint.name == name)
+}
+
+/// Settings of a named endpoint.
+///
+/// Entries are declared in the `[endpoints.<name>]` tables of the
+/// configuration files, for example:
+///
+/// ```toml
+/// [endpoints.gateway]
+/// url = "https://llm.internal.example.com/v1/chat/completions"
+/// api_key_env = "API_TOKEN_GATEWAY"
+/// auth = { header = "api-key" }
+/// headers = { "X-Team" = "search" }
+/// system_role = true
+/// ```
+///
+/// # Fields
+/// * `url` - The chat completions URL
+/// * `api_key_env` - Environment variable holding the API key (defaults to
+///   `API_TOKEN`)
+/// * `auth` - How the API key is sent (`"bearer"`, `{ header = "<name>" }` or
+///   `"none"`), defaulting to the one of the API
+/// * `headers` - Headers sent with every request
+/// * `system_role` - Whether the prompt must be sent with the "system" role
+///   (`true`) or the "assistant" role (`false`)
+/// * `api` - The wire format spoken by the endpoint (`"openai"` or
+///   `"anthropic"`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointConfig {
+    pub url: String,
+    #[serde(default = "default_api_key_env")]
+    pub api_key_env: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthScheme>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_role: Option<bool>,
+    #[serde(default)]
+    pub api: ApiKind,
+}
+
+impl EndpointConfig {
+    /// Creates the settings of an OpenAI-compatible endpoint.
+    ///
+    /// # Arguments
+    /// * `url` - The chat completions URL
+    /// * `api_key_env` - Environment variable holding the API key
+    pub fn new(url: impl Into<String>, api_key_env: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            api_key_env: api_key_env.into(),
+            auth: None,
+            headers: BTreeMap::new(),
+            system_role: None,
+            api: ApiKind::OpenAi,
+        }
+    }
+
+    /// Creates the provider serving the endpoint.
+    ///
+    /// # Arguments
+    /// * `name` - The endpoint name
+    pub fn provider(&self, name: &str) -> Box<dyn Provider> {
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        match self.api {
+            ApiKind::OpenAi => {
+                let mut provider = OpenAiCompatible::new(name, &self.url, &self.api_key_env)
+                    .with_headers(headers)
+                    .with_system_role(self.system_role);
+                if let Some(auth) = &self.auth {
+                    provider = provider.with_auth_scheme(auth.clone());
+                }
+                Box::new(provider)
+            },
+            ApiKind::Anthropic => {
+                let mut provider = Anthropic::with_endpoint(name, &self.url, &self.api_key_env).with_headers(headers);
+                if let Some(auth) = &self.auth {
+                    provider = provider.with_auth_scheme(auth.clone());
+                }
+                Box::new(provider)
+            },
+        }
+    }
+}
+
+impl From<&BuiltinEndpoint> for EndpointConfig {
+    fn from(endpoint: &BuiltinEndpoint) -> Self {
+        Self {
+            api: endpoint.api,
+            ..Self::new(endpoint.url, endpoint.api_key_env)
+        }
+    }
+}
+
+/// Returns the environment variable used by endpoints that do not set one.
+fn default_api_key_env() -> String {
+    env_api_key("").to_owned()
+}
+
+/// Named endpoints that `--endpoint` can refer to.
+///
+/// The registry starts with the [`BUILTIN_ENDPOINTS`], which user-defined
+/// entries extend or replace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointRegistry {
+    endpoints: BTreeMap<String, EndpointConfig>,
+}
+
+impl Default for EndpointRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl EndpointRegistry {
+    /// Creates a registry holding the built-in endpoints.
+    pub fn builtin() -> Self {
+        Self {
+            endpoints: BUILTIN_ENDPOINTS
+                .iter()
+                .map(|endpoint| (endpoint.name.to_owned(), EndpointConfig::from(endpoint)))
+                .collect(),
+        }
+    }
+
+    /// Adds an endpoint, replacing any endpoint with the same name.
+    ///
+    /// # Arguments
+    /// * `name` - The endpoint name
+    /// * `config` - The endpoint settings
+    pub fn insert(&mut self, name: impl Into<String>, config: EndpointConfig) {
+        self.endpoints.insert(name.into(), config);
+    }
+
+    /// Returns the settings of a named endpoint.
+    ///
+    /// # Arguments
+    /// * `name` - The endpoint name
+    pub fn get(&self, name: &str) -> Option<&EndpointConfig> {
+        self.endpoints.get(name)
+    }
+
+    /// Iterates over the endpoints, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &EndpointConfig)> {
+        self.endpoints.iter().map(|(name, config)| (name.as_str(), config))
+    }
+
+    /// Resolves an endpoint name or URL into a provider.
+    ///
+    /// # Arguments
+    /// * `endpoint` - A registered endpoint name or a custom URL
+    ///
+    /// # Returns
+    /// * The provider of the registered endpoint, or an OpenAI-compatible
+    ///   provider for the custom URL, authenticated with `API_TOKEN`
+    pub fn resolve(&self, endpoint: &str) -> Box<dyn Provider> {
+        match self.endpoints.get(endpoint) {
+            Some(config) => config.provider(endpoint),
+            None => Box::new(OpenAiCompatible::custom(endpoint)),
+        }
+    }
+}
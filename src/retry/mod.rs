@@ -0,0 +1,171 @@
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::time::{Duration, SystemTime};
+
+/// Prefix of the rate limit headers announcing when a limit resets (e.g.
+/// `x-ratelimit-reset-requests`, `x-ratelimit-reset-tokens`).
+const RATELIMIT_RESET_PREFIX: &str = "x-ratelimit-reset";
+
+/// Policy deciding whether and when a failed request is retried.
+///
+/// Retries use exponential backoff: the n-th retry waits `backoff_base * 2^n`,
+/// capped at `max_backoff`. When the server announces when to come back with
+/// `Retry-After` or `x-ratelimit-reset-*` headers, that delay is used instead
+/// (still capped at `max_backoff`).
+///
+/// # Fields
+/// * `max_retries` - Number of retries after the first attempt (0 disables
+///   retries)
+/// * `backoff_base` - Delay before the first retry
+/// * `max_backoff` - Upper bound of any delay between attempts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub backoff_base: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff_base: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the exponential backoff delay before a retry.
+    ///
+    /// # Arguments
+    /// * `retry` - Zero-based index of the retry
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(retry);
+        self.backoff_base.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    /// Returns the delay before a retry, honouring server hints.
+    ///
+    /// # Arguments
+    /// * `retry` - Zero-based index of the retry
+    /// * `headers` - Headers of the failed response, if there was one
+    pub fn delay(&self, retry: u32, headers: Option<&HeaderMap>) -> Duration {
+        headers
+            .and_then(server_delay)
+            .map_or_else(|| self.backoff(retry), |delay| delay.min(self.max_backoff))
+    }
+}
+
+/// Returns whether a response status is worth retrying.
+///
+/// Request timeouts (408), rate limits (429) and server errors (5xx) are
+/// transient; any other status is final.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Returns whether a transport error is worth retrying.
+///
+/// Connection failures, timeouts and requests that broke off while being sent
+/// are transient; errors such as an invalid URL are final.
+pub fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+/// Extracts the delay requested by the server from response headers.
+///
+/// `Retry-After` takes precedence and may be given in seconds or as an HTTP
+/// date. Otherwise, or when it is unusable (e.g. negative or malformed), the
+/// longest `x-ratelimit-reset-*` delay is used, which providers send either in
+/// seconds or as a duration like `6m0s` or `20ms`.
+///
+/// # Arguments
+/// * `headers` - Headers of the failed response
+///
+/// # Returns
+/// * The requested delay, or `None` if the headers carry no hint
+pub fn server_delay(headers: &HeaderMap) -> Option<Duration> {
+    let retry_after = headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    if retry_after.is_some() {
+        return retry_after;
+    }
+
+    headers
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with(RATELIMIT_RESET_PREFIX))
+        .filter_map(|(_, value)| value.to_str().ok())
+        .filter_map(parse_reset_duration)
+        .max()
+}
+
+/// Parses a `Retry-After` value, given in seconds or as an HTTP date.
+///
+/// # Arguments
+/// * `value` - The header value
+///
+/// # Returns
+/// * The parsed delay (zero for a date in the past), or `None` if the value is
+///   malformed or negative
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Parses a rate limit reset value such as `1`, `1.5`, `20ms` or `1h2m3.5s`.
+///
+/// # Arguments
+/// * `value` - The header value
+///
+/// # Returns
+/// * The parsed duration, or `None` if the value is empty or not a duration
+pub fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let (number, after_number) = rest.split_at(number_end);
+        let number: f64 = number.parse().ok()?;
+
+        let unit_end = after_number
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(after_number.len());
+        let (unit, after_unit) = after_number.split_at(unit_end);
+        let seconds = match unit {
+            "h" => number * 3600.0,
+            "m" => number * 60.0,
+            "s" => number,
+            "ms" => number / 1000.0,
+            _ => return None,
+        };
+
+        total += Duration::try_from_secs_f64(seconds).ok()?;
+        rest = after_unit;
+    }
+
+    Some(total)
+}
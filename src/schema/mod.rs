@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
 /// Response structure from the chat completion API.
@@ -54,20 +54,59 @@ pub struct Choice {
 
 /// Represents a message in the API response.
 ///
-/// Contains the role and content of a message, along with optional refusal
-/// and annotation information.
+/// Contains the role and content of a message, along with optional refusal,
+/// annotation and tool call information.
 ///
 /// # Fields
 /// * `role` - Role of the message sender ("assistant", "user", etc.)
-/// * `content` - The actual content of the message
+/// * `content` - The actual content of the message (empty when the API sends
+///   `null`, as it does alongside tool calls)
 /// * `refusal` - Reason for refusal if the request was refused
 /// * `annotations` - Additional annotations (if any)
+/// * `tool_calls` - Functions the model asks to call (if any)
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub content: String,
     pub refusal: Option<Value>,
     pub annotations: Option<Vec<Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+/// A function call requested by the model.
+///
+/// # Fields
+/// * `id` - Identifier of the call, echoed by the `tool` message carrying its
+///   result
+/// * `type` - Type of the call (always "function")
+/// * `function` - The function to call and its arguments
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub r#type: String,
+    pub function: FunctionCall,
+}
+
+/// The function and arguments of a [`ToolCall`].
+///
+/// # Fields
+/// * `name` - Name of the function to call
+/// * `arguments` - The arguments, as a JSON-encoded object
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+/// Deserializes a `null` value as the default value of the type.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default+Deserialize<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -132,6 +171,54 @@ pub struct Usage {
     pub completion_tokens_details: Option<CompletionTokensDetails>,
 }
 
+impl Usage {
+    /// Adds the token counts of another request to this usage.
+    ///
+    /// The prompt, completion and total counts are summed, as well as the
+    /// cached, audio and reasoning token details reported by either usage.
+    ///
+    /// # Arguments
+    /// * `other` - The usage to add
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+
+        if let Some(other) = &other.prompt_tokens_details {
+            let details = self.prompt_tokens_details.get_or_insert_default();
+            add_count(&mut details.cached_tokens, other.cached_tokens);
+            add_count(&mut details.audio_tokens, other.audio_tokens);
+        }
+        if let Some(other) = &other.completion_tokens_details {
+            let details = self.completion_tokens_details.get_or_insert_default();
+            add_count(&mut details.reasoning_tokens, other.reasoning_tokens);
+            add_count(&mut details.audio_tokens, other.audio_tokens);
+        }
+    }
+
+    /// Returns the number of audio tokens of the prompt and the completion.
+    pub fn audio_tokens(&self) -> i64 {
+        let prompt = self
+            .prompt_tokens_details
+            .as_ref()
+            .and_then(|details| details.audio_tokens);
+        let completion = self
+            .completion_tokens_details
+            .as_ref()
+            .and_then(|details| details.audio_tokens);
+
+        prompt.unwrap_or_default() + completion.unwrap_or_default()
+    }
+}
+
+/// Adds an optional token count to another, keeping `None` only when both
+/// are unknown.
+fn add_count(total: &mut Option<i64>, count: Option<i64>) {
+    if let Some(count) = count {
+        *total = Some(total.unwrap_or_default() + count);
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PromptTokensDetails {
     pub cached_tokens: Option<i64>,
@@ -146,3 +233,56 @@ pub struct CompletionTokensDetails {
     pub accepted_prediction_tokens: Option<i64>,
     pub rejected_prediction_tokens: Option<i64>,
 }
+
+/// A single `chat.completion.chunk` event from a streaming response.
+///
+/// When `stream: true` is requested, the API sends a sequence of these chunks
+/// as Server-Sent Events instead of one [`ApiResponse`]. Each chunk carries a
+/// content delta; the final chunk may carry token usage when the provider
+/// honours `stream_options.include_usage`.
+///
+/// # Fields
+/// * `id` - Identifier shared by all chunks of the same completion
+/// * `object` - Type of object returned (typically "chat.completion.chunk")
+/// * `created` - Unix timestamp of when the completion was created
+/// * `model` - Identifier of the model that generated the chunk
+/// * `choices` - Deltas for each choice (empty on the trailing usage chunk)
+/// * `usage` - Token usage statistics (only on the last chunk, if at all)
+/// * `system_fingerprint` - System fingerprint for the response (if available)
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<ChunkChoice>,
+    pub usage: Option<Usage>,
+    pub system_fingerprint: Option<String>,
+}
+
+/// Represents the delta for a single choice inside a streaming chunk.
+///
+/// # Fields
+/// * `index` - Index of the choice this delta belongs to
+/// * `delta` - The incremental message content
+/// * `finish_reason` - Set on the last delta of the choice
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChunkChoice {
+    pub index: i64,
+    pub delta: Delta,
+    pub finish_reason: Option<String>,
+}
+
+/// Incremental message content carried by a [`ChunkChoice`].
+///
+/// # Fields
+/// * `role` - Role of the message sender (usually only on the first delta)
+/// * `content` - The next piece of generated text
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Delta {
+    pub role: Option<String>,
+    pub content: Option<String>,
+}
@@ -0,0 +1,371 @@
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::read_schema_file;
+use crate::validation::escape_pointer;
+
+/// Fields of the `json_schema` response format.
+const WRAPPER_FIELDS: &[&str] = &["name", "description", "schema", "strict"];
+
+/// Keywords showing that an object is a JSON Schema rather than the
+/// `json_schema` wrapper.
+const SCHEMA_KEYWORDS: &[&str] = &["$schema", "type", "properties", "anyOf", "$defs", "$ref"];
+
+/// Keywords rejected by the `OpenAI` API in strict mode.
+const UNSUPPORTED_STRICT_KEYWORDS: &[&str] = &[
+    "allOf",
+    "oneOf",
+    "not",
+    "if",
+    "then",
+    "else",
+    "dependentRequired",
+    "dependentSchemas",
+    "patternProperties",
+    "unevaluatedProperties",
+    "propertyNames",
+    "minProperties",
+    "maxProperties",
+    "unevaluatedItems",
+    "contains",
+    "minContains",
+    "maxContains",
+    "uniqueItems",
+];
+
+/// Maximum length of the schema name.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The API would reject the schema.
+    Error,
+    /// The schema is accepted but likely not what was meant.
+    Warning,
+}
+
+/// A problem found in a schema file.
+///
+/// # Fields
+/// * `severity` - Whether the API would reject the schema
+/// * `pointer` - JSON pointer (RFC 6901) to the offending value in the file,
+///   empty for the whole file
+/// * `message` - What is wrong and how to fix it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pointer = if self.pointer.is_empty() {
+            "(root)"
+        } else {
+            &self.pointer
+        };
+        write!(f, "{pointer}: {}", self.message)
+    }
+}
+
+/// Checks a schema file against the rules of the `OpenAI` structured output
+/// API.
+///
+/// The file must hold the `json_schema` response format: a `name`, an
+/// optional `description` and `strict` flag, and the JSON Schema under
+/// `schema`. When `strict` is `true`, the schema must also follow the strict
+/// mode rules: the root is an object, every object sets
+/// `"additionalProperties": false` and lists all its properties in `required`,
+/// `$ref`s resolve, and unsupported keywords such as `allOf` are not used.
+///
+/// # Arguments
+/// * `json_schema` - The schema file as loaded by [`read_schema_file`]
+///
+/// # Returns
+/// * `Vec<Diagnostic>` - The problems found, empty if there is none
+pub fn lint_schema(json_schema: &Value) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        root: json_schema.get("schema").unwrap_or(&Value::Null),
+        diagnostics: Vec::new(),
+    };
+    let Some(wrapper) = json_schema.as_object() else {
+        linter.error("", "the schema file must hold a JSON object");
+        return linter.diagnostics;
+    };
+
+    if !wrapper.contains_key("schema") {
+        if SCHEMA_KEYWORDS.iter().any(|keyword| wrapper.contains_key(*keyword)) {
+            linter.error(
+                "",
+                "the file holds a bare JSON Schema without the `name`/`schema` wrapper: pass --schema-wrap to wrap it",
+            );
+            return linter.diagnostics;
+        }
+        linter.error("", "missing \"schema\", the JSON Schema of the reply");
+    }
+    for key in wrapper.keys().filter(|key| !WRAPPER_FIELDS.contains(&key.as_str())) {
+        linter.error(
+            &format!("/{}", escape_pointer(key)),
+            format!("unknown field \"{key}\" (expected {})", WRAPPER_FIELDS.join(", ")),
+        );
+    }
+    match wrapper.get("name") {
+        Some(Value::String(name)) if is_valid_name(name) => {},
+        Some(_) => {
+            linter.error(
+                "/name",
+                format!("the name must be 1 to {MAX_NAME_LENGTH} letters, digits, underscores or dashes"),
+            );
+        },
+        None => linter.error("", "missing \"name\", which identifies the schema (e.g. \"answer\")"),
+    }
+    if wrapper
+        .get("description")
+        .is_some_and(|description| !description.is_string())
+    {
+        linter.error("/description", "the description must be a string");
+    }
+    let strict = match wrapper.get("strict") {
+        Some(Value::Bool(strict)) => *strict,
+        Some(_) => {
+            linter.error("/strict", "strict must be true or false");
+            false
+        },
+        None => false,
+    };
+
+    match wrapper.get("schema") {
+        Some(Value::Object(schema)) if strict => {
+            if schema.get("type").and_then(Value::as_str) != Some("object") || schema.contains_key("anyOf") {
+                linter.error("/schema", "the root schema must be of type \"object\" in strict mode");
+            }
+            linter.check(linter.root, "/schema");
+        },
+        Some(Value::Object(_)) => {},
+        Some(_) => linter.error("/schema", "the schema must be a JSON object"),
+        None => {},
+    }
+
+    linter.diagnostics
+}
+
+/// Wraps a bare JSON Schema into the `json_schema` response format, in strict
+/// mode.
+///
+/// # Arguments
+/// * `schema` - The JSON Schema of the reply
+/// * `name` - The name of the schema
+pub fn wrap_schema(schema: Value, name: &str) -> Value {
+    serde_json::json!({"name": name, "strict": true, "schema": schema})
+}
+
+/// Derives a schema name from the name of a schema file.
+///
+/// Characters not allowed in names are replaced by underscores.
+///
+/// # Arguments
+/// * `path` - Path of the schema file (e.g. `schemas/review.v2.json` gives
+///   `review_v2`)
+pub fn schema_name(path: &Path) -> String {
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default()
+        .chars()
+        .map(|char| {
+            if char.is_ascii_alphanumeric() || char == '-' {
+                char
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_NAME_LENGTH)
+        .collect::<String>();
+
+    if name.is_empty() { "response".to_owned() } else { name }
+}
+
+/// Reads a schema file and checks it before it is sent.
+///
+/// Warnings are logged, while errors are all reported at once.
+///
+/// # Arguments
+/// * `path` - Path to the schema file
+/// * `wrap` - Whether a bare JSON Schema is wrapped with [`wrap_schema`], files
+///   already holding the wrapper being kept as they are
+///
+/// # Returns
+/// * `Ok(Value)` - The schema file, wrapped if requested
+/// * `Err` - An error if the file could not be read or parsed, or if the schema
+///   would be rejected by the API
+pub fn load_schema(path: &Path, wrap: bool) -> Result<Value> {
+    let mut json_schema = read_schema_file(path)?;
+    if wrap && json_schema.is_object() && json_schema.get("schema").is_none() {
+        let name = schema_name(path);
+        info!("Wrapping the schema of {} as '{name}'", path.display());
+        json_schema = wrap_schema(json_schema, &name);
+    }
+
+    let (errors, warnings): (Vec<_>, Vec<_>) = lint_schema(&json_schema)
+        .into_iter()
+        .partition(|diagnostic| diagnostic.severity == Severity::Error);
+    for warning in warnings {
+        warn!("Schema {}: {warning}", path.display());
+    }
+    if !errors.is_empty() {
+        let errors = errors
+            .iter()
+            .map(|error| format!("  {error}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        bail!("Invalid schema file {}:\n{errors}", path.display());
+    }
+
+    Ok(json_schema)
+}
+
+/// Returns whether a schema name is accepted by the API.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-')
+}
+
+/// Walks a schema in strict mode, collecting the diagnostics.
+struct Linter<'a> {
+    root: &'a Value,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    /// Records an error.
+    fn error(&mut self, pointer: &str, message: impl Into<String>) {
+        self.push(Severity::Error, pointer, message);
+    }
+
+    /// Records a diagnostic.
+    fn push(&mut self, severity: Severity, pointer: &str, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            pointer: pointer.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    /// Checks a sub-schema and the sub-schemas it holds.
+    fn check(&mut self, schema: &'a Value, pointer: &str) {
+        let Some(schema) = schema.as_object() else {
+            return;
+        };
+
+        for keyword in UNSUPPORTED_STRICT_KEYWORDS {
+            if schema.contains_key(*keyword) {
+                self.error(
+                    &format!("{pointer}/{keyword}"),
+                    format!("\"{keyword}\" is not supported in strict mode"),
+                );
+            }
+        }
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str)
+            && self.resolve(reference).is_none()
+        {
+            self.error(
+                &format!("{pointer}/$ref"),
+                format!("{reference} does not resolve to a definition of the schema"),
+            );
+        }
+
+        let is_object = match schema.get("type") {
+            Some(Value::String(name)) => name == "object",
+            Some(Value::Array(names)) => names.iter().any(|name| name == "object"),
+            _ => schema.contains_key("properties"),
+        };
+        if is_object {
+            self.check_object(schema, pointer);
+        }
+        if !schema.contains_key("type")
+            && !["$ref", "anyOf", "allOf", "oneOf", "enum", "const"]
+                .iter()
+                .any(|keyword| schema.contains_key(*keyword))
+            && pointer != "/schema"
+        {
+            self.push(
+                Severity::Warning,
+                pointer,
+                "no \"type\" is set, the API may reject the schema",
+            );
+        }
+
+        for (keyword, value) in schema {
+            let child = format!("{pointer}/{}", escape_pointer(keyword));
+            match (keyword.as_str(), value) {
+                ("properties" | "$defs" | "definitions", Value::Object(schemas)) => {
+                    for (name, sub_schema) in schemas {
+                        self.check(sub_schema, &format!("{child}/{}", escape_pointer(name)));
+                    }
+                },
+                ("anyOf" | "prefixItems", Value::Array(schemas)) => {
+                    for (index, sub_schema) in schemas.iter().enumerate() {
+                        self.check(sub_schema, &format!("{child}/{index}"));
+                    }
+                },
+                ("items", sub_schema) => self.check(sub_schema, &child),
+                _ => {},
+            }
+        }
+    }
+
+    /// Checks the strict mode rules of an object schema.
+    fn check_object(&mut self, schema: &Map<String, Value>, pointer: &str) {
+        if schema.get("additionalProperties") != Some(&Value::Bool(false)) {
+            self.error(
+                pointer,
+                "objects must set \"additionalProperties\": false in strict mode",
+            );
+        }
+
+        let required = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let optional = schema
+            .get("properties")
+            .and_then(Value::as_object)
+            .map(|properties| {
+                properties
+                    .keys()
+                    .filter(|name| !required.iter().any(|required| required == *name))
+                    .map(|name| format!("\"{name}\""))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if !optional.is_empty() {
+            self.error(
+                pointer,
+                format!(
+                    "all properties must be required in strict mode, add {} to \"required\" (allow null for optional \
+                     values)",
+                    optional.join(", ")
+                ),
+            );
+        }
+    }
+
+    /// Resolves a local reference (`#` or `#/<pointer>`) against the schema.
+    fn resolve(&self, reference: &str) -> Option<&'a Value> {
+        let pointer = reference.strip_prefix('#')?;
+        if pointer.is_empty() {
+            return Some(self.root);
+        }
+
+        self.root.pointer(pointer)
+    }
+}
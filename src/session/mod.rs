@@ -0,0 +1,229 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::schema::Message;
+use crate::{MessageContent, RequestMessage, USER_ROLE};
+
+/// Extension of the session files.
+pub const SESSION_EXTENSION: &str = "json";
+
+/// A conversation persisted between invocations.
+///
+/// A session file holds the whole message history: the prompt, then every
+/// user input followed by the assistant reply it received. Each run with
+/// `--session` sends the history with the new input, and appends both the
+/// input and the reply to the file.
+///
+/// # Fields
+/// * `created_at` - Unix timestamp (in seconds) of when the session was created
+/// * `updated_at` - Unix timestamp (in seconds) of the last saved turn
+/// * `model` - The model that produced the last reply
+/// * `messages` - The message history, in order
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Session {
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub updated_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    pub messages: Vec<RequestMessage>,
+}
+
+impl Session {
+    /// Creates an empty session.
+    pub fn new() -> Self {
+        let now = now();
+        Self {
+            created_at: now,
+            updated_at: now,
+            ..Default::default()
+        }
+    }
+
+    /// Loads a session file.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the session file
+    ///
+    /// # Returns
+    /// * `Ok(Session)` - The loaded session
+    /// * `Err` - An error if the file could not be read or is not a session
+    pub fn load(path: &Path) -> Result<Session> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read session file {}", path.display()))?;
+
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse session file {}", path.display()))
+    }
+
+    /// Loads a session file, or starts a new session if it does not exist.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the session file
+    ///
+    /// # Returns
+    /// * `Ok(Session)` - The loaded or new session
+    /// * `Err` - An error if the file exists but could not be read or parsed
+    pub fn load_or_new(path: &Path) -> Result<Session> {
+        if path.exists() {
+            Session::load(path)
+        } else {
+            Ok(Session::new())
+        }
+    }
+
+    /// Saves the session, updating its modification time.
+    ///
+    /// The file is written to a temporary file first and then renamed, so
+    /// that an interrupted run never leaves a partial session behind.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the session file
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        self.updated_at = now();
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent).context("Failed to create session directory")?;
+        }
+
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_string_pretty(self)?).context("Failed to write session file")?;
+        fs::rename(&temporary, path).context("Failed to write session file")?;
+
+        Ok(())
+    }
+
+    /// Returns whether the session has no message yet.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Returns the number of user turns of the session.
+    pub fn turns(&self) -> usize {
+        self.messages.iter().filter(|message| message.role == USER_ROLE).count()
+    }
+
+    /// Returns the history followed by a new user input, ready to be sent.
+    ///
+    /// # Arguments
+    /// * `input` - The new user input, text or parts
+    pub fn messages_with(&self, input: impl Into<MessageContent>) -> Vec<RequestMessage> {
+        let mut messages = self.messages.clone();
+        messages.push(RequestMessage::new(USER_ROLE, input));
+
+        messages
+    }
+
+    /// Records a completed turn.
+    ///
+    /// # Arguments
+    /// * `messages` - The messages that were sent, which replace the history
+    /// * `reply` - The message of the first choice of the response
+    /// * `model` - The model that produced the reply
+    pub fn record(&mut self, messages: Vec<RequestMessage>, reply: &Message, model: &str) {
+        self.messages = messages;
+        self.messages.push(RequestMessage::from(reply));
+        self.model = Some(model.to_owned());
+    }
+
+    /// Keeps the first `turns` user turns, dropping every later message.
+    ///
+    /// Messages before the first user input (such as the prompt) are always
+    /// kept, as well as the reply of the last kept turn.
+    ///
+    /// # Arguments
+    /// * `turns` - Number of user turns to keep
+    ///
+    /// # Returns
+    /// The number of removed messages
+    pub fn truncate_turns(&mut self, turns: usize) -> usize {
+        let len = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| message.role == USER_ROLE)
+            .nth(turns)
+            .map_or(self.messages.len(), |(index, _)| index);
+
+        self.truncate(len)
+    }
+
+    /// Keeps the first `len` messages.
+    ///
+    /// # Arguments
+    /// * `len` - Number of messages to keep
+    ///
+    /// # Returns
+    /// The number of removed messages
+    pub fn truncate(&mut self, len: usize) -> usize {
+        let removed = self.messages.len().saturating_sub(len);
+        self.messages.truncate(len);
+
+        removed
+    }
+
+    /// Copies the session into a new, independent session.
+    ///
+    /// # Arguments
+    /// * `source` - Path of the session file to copy
+    /// * `destination` - Path of the new session file, which must not exist
+    /// * `turns` - Optional number of user turns to keep in the copy
+    ///
+    /// # Returns
+    /// * `Ok(Session)` - The new session, already saved
+    /// * `Err` - An error if the source could not be loaded or the destination
+    ///   exists or could not be written
+    pub fn fork(source: &Path, destination: &Path, turns: Option<usize>) -> Result<Session> {
+        if destination.exists() {
+            bail!("Session file {} already exists", destination.display());
+        }
+
+        let mut session = Session {
+            created_at: now(),
+            ..Session::load(source)?
+        };
+        if let Some(turns) = turns {
+            session.truncate_turns(turns);
+        }
+        session.save(destination)?;
+
+        Ok(session)
+    }
+}
+
+/// Lists the session files of a directory, sorted by path.
+///
+/// JSON files that are not sessions (such as schemas) are skipped.
+///
+/// # Arguments
+/// * `dir` - The directory to list
+///
+/// # Returns
+/// * `Ok(Vec<(PathBuf, Session)>)` - The session files and their content
+/// * `Err` - An error if the directory could not be listed
+pub fn list_sessions(dir: &Path) -> Result<Vec<(PathBuf, Session)>> {
+    let mut sessions = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read session directory {}", dir.display()))? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().is_none_or(|extension| extension != SESSION_EXTENSION) {
+            continue;
+        }
+        if let Ok(session) = Session::load(&path) {
+            sessions.push((path, session));
+        }
+    }
+    sessions.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+    Ok(sessions)
+}
+
+/// Returns the current Unix timestamp in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
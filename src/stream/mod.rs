@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+
+use crate::schema::{ApiResponse, ChatCompletionChunk, Choice, Message, Usage};
+
+/// Sentinel payload that OpenAI-compatible APIs send to terminate a stream.
+const DONE_SENTINEL: &str = "[DONE]";
+
+/// Incremental parser for a Server-Sent Events byte stream.
+///
+/// Bytes are fed in whatever pieces the network delivers them. Complete
+/// events are returned as soon as their terminating blank line arrives, with
+/// the `data:` lines of each event joined by newlines. Comment lines and
+/// fields other than `data` are ignored.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Creates an empty parser.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a piece of the byte stream into the parser.
+    ///
+    /// # Arguments
+    /// * `bytes` - The next bytes received from the server
+    ///
+    /// # Returns
+    /// * The data payloads of all events completed by these bytes
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        while let Some(position) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=position).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data.push(value.strip_prefix(' ').unwrap_or(value).to_owned());
+            }
+        }
+
+        events
+    }
+
+    /// Flushes an event left unterminated when the stream ended.
+    ///
+    /// # Returns
+    /// * The data payload of the pending event, if any
+    pub fn finish(&mut self) -> Option<String> {
+        let mut events = self.feed(b"\n\n");
+        events.pop()
+    }
+}
+
+/// Parses the data payload of a single SSE event into a chunk.
+///
+/// # Arguments
+/// * `data` - The payload returned by [`SseParser::feed`]
+///
+/// # Returns
+/// * `Ok(Some(chunk))` - The parsed chunk
+/// * `Ok(None)` - The stream terminator (`[DONE]`) was received
+/// * `Err` - The payload is not a valid chunk
+pub fn parse_chunk(data: &str) -> Result<Option<ChatCompletionChunk>> {
+    let data = data.trim();
+    if data == DONE_SENTINEL {
+        return Ok(None);
+    }
+
+    let chunk = serde_json::from_str(data).with_context(|| format!("Failed to parse stream chunk: {data}"))?;
+
+    Ok(Some(chunk))
+}
+
+/// Rebuilds a complete [`ApiResponse`] out of streamed chunks.
+///
+/// The accumulator keeps the metadata of the first chunk, concatenates the
+/// content deltas per choice and records the finish reason and usage when
+/// they arrive, so that the result can be handled exactly like a
+/// non-streaming response.
+#[derive(Debug, Default)]
+pub struct StreamAccumulator {
+    response: ApiResponse,
+    usage_received: bool,
+}
+
+impl StreamAccumulator {
+    /// Creates an empty accumulator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merges a chunk into the response being rebuilt.
+    ///
+    /// # Arguments
+    /// * `chunk` - The next chunk received from the stream
+    ///
+    /// # Returns
+    /// * The content delta of the first choice, if the chunk carried one
+    pub fn push(&mut self, chunk: ChatCompletionChunk) -> Option<String> {
+        if self.response.id.is_empty() {
+            self.response.id = chunk.id;
+            self.response.created = chunk.created;
+            self.response.model = chunk.model;
+            self.response.object = "chat.completion".to_owned();
+        }
+        if chunk.system_fingerprint.is_some() {
+            self.response.system_fingerprint = chunk.system_fingerprint;
+        }
+        if let Some(usage) = chunk.usage {
+            self.response.usage = usage;
+            self.usage_received = true;
+        }
+
+        let mut first_delta = None;
+        for chunk_choice in chunk.choices {
+            let choice = self.choice_mut(chunk_choice.index);
+            if let Some(role) = chunk_choice.delta.role {
+                choice.message.role = role;
+            }
+            if let Some(finish_reason) = chunk_choice.finish_reason {
+                choice.finish_reason = finish_reason;
+            }
+            if let Some(content) = chunk_choice.delta.content {
+                choice.message.content.push_str(&content);
+                if chunk_choice.index == 0 {
+                    first_delta = Some(content);
+                }
+            }
+        }
+
+        first_delta
+    }
+
+    /// Returns the usage reported by the provider, if it sent any.
+    pub fn usage(&self) -> Option<&Usage> {
+        self.usage_received.then_some(&self.response.usage)
+    }
+
+    /// Consumes the accumulator and returns the rebuilt response.
+    pub fn finish(self) -> ApiResponse {
+        self.response
+    }
+
+    fn choice_mut(&mut self, index: i64) -> &mut Choice {
+        let position = match self.response.choices.iter().position(|choice| choice.index == index) {
+            Some(position) => position,
+            None => {
+                self.response.choices.push(Choice {
+                    index,
+                    message: Message {
+                        role: "assistant".to_owned(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+                self.response.choices.len() - 1
+            },
+        };
+
+        &mut self.response.choices[position]
+    }
+}
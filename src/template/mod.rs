@@ -0,0 +1,425 @@
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Maximum nesting of `{{> include}}` partials, which also stops include
+/// cycles.
+pub const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Opening delimiter of a template tag.
+const OPEN: &str = "{{";
+
+/// Closing delimiter of a template tag.
+const CLOSE: &str = "}}";
+
+/// A parsed piece of a template.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    /// Literal text, copied as is.
+    Text(String),
+    /// `{{name}}`: the value of a variable.
+    Var { name: String, line: usize },
+    /// `{{> path}}`: another template file, rendered with the same variables.
+    Include { path: String, line: usize },
+    /// `{{#if name}}...{{else}}...{{/if}}`: a conditional block.
+    If {
+        name: String,
+        line: usize,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    /// `{{#each name}}...{{/each}}`: a block repeated for every item of an
+    /// array (or value of an object).
+    Each { name: String, line: usize, body: Vec<Node> },
+}
+
+/// The tag that ended a block while parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum End {
+    Eof,
+    Else,
+    CloseIf,
+    CloseEach,
+}
+
+/// A template for prompt and input files.
+///
+/// The syntax is a small subset of Handlebars:
+/// * `{{name}}` inserts a variable, `{{user.name}}` a field of an object
+/// * `{{> other.md}}` (or `{{> include other.md}}`) inserts another template
+///   file, resolved relative to the including file
+/// * `{{#if name}}...{{else}}...{{/if}}` keeps the first block when the
+///   variable is truthy (not `false`, `null`, `0`, empty string, array or
+///   object)
+/// * `{{#each items}}...{{/each}}` repeats the block for every item, which is
+///   available as `{{this}}` (fields as `{{this.field}}` or `{{field}}`) along
+///   with its position `{{@index}}`
+/// * `{{! comment }}` is removed, and `\{{` inserts a literal `{{`
+///
+/// Every variable must be defined: rendering fails on the first undefined
+/// one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    name: String,
+    base_dir: PathBuf,
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    /// Parses a template.
+    ///
+    /// # Arguments
+    /// * `source` - The template text
+    /// * `name` - Name of the template used in error messages
+    /// * `base_dir` - Directory included files are resolved against
+    ///
+    /// # Returns
+    /// * `Ok(Template)` - The parsed template
+    /// * `Err` - An error if a tag is unclosed, empty or unbalanced
+    pub fn parse(source: &str, name: impl Into<String>, base_dir: impl Into<PathBuf>) -> Result<Self> {
+        let name = name.into();
+        let mut parser = Parser {
+            source,
+            position: 0,
+            name: &name,
+        };
+
+        let (nodes, end) = parser.parse_block()?;
+        match end {
+            End::Eof => {},
+            End::Else => bail!("{name}:{}: '{{{{else}}}}' outside of '{{{{#if}}}}'", parser.line()),
+            End::CloseIf => bail!("{name}:{}: '{{{{/if}}}}' without '{{{{#if}}}}'", parser.line()),
+            End::CloseEach => bail!("{name}:{}: '{{{{/each}}}}' without '{{{{#each}}}}'", parser.line()),
+        }
+
+        Ok(Self {
+            name,
+            base_dir: base_dir.into(),
+            nodes,
+        })
+    }
+
+    /// Reads and parses a template file.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the template file
+    ///
+    /// # Returns
+    /// * `Ok(Template)` - The parsed template
+    /// * `Err` - An error if the file could not be read or parsed
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).with_context(|| format!("Failed to read template {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Self::parse(&source, path.display().to_string(), base_dir)
+    }
+
+    /// Renders the template.
+    ///
+    /// # Arguments
+    /// * `vars` - The variables, usually a JSON object
+    ///
+    /// # Returns
+    /// * `Ok(String)` - The rendered text
+    /// * `Err` - An error naming the template and line of the first undefined
+    ///   variable or unreadable include
+    pub fn render(&self, vars: &Value) -> Result<String> {
+        let mut output = String::new();
+        let mut scope = Scope {
+            frames: vec![(vars.clone(), None)],
+        };
+        self.render_nodes(&self.nodes, &mut scope, 0, &mut output)?;
+
+        Ok(output)
+    }
+
+    /// Renders a list of nodes into `output`.
+    fn render_nodes(&self, nodes: &[Node], scope: &mut Scope, depth: usize, output: &mut String) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => output.push_str(text),
+                Node::Var { name, line } => output.push_str(&to_text(&self.lookup(scope, name, *line)?)),
+                Node::Include { path, line } => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        bail!(
+                            "{}:{line}: includes are nested more than {MAX_INCLUDE_DEPTH} levels deep",
+                            self.name
+                        );
+                    }
+                    let included = Template::from_file(self.base_dir.join(path))
+                        .with_context(|| format!("{}:{line}: failed to include '{path}'", self.name))?;
+                    included.render_nodes(&included.nodes, scope, depth + 1, output)?;
+                },
+                Node::If {
+                    name,
+                    line,
+                    then,
+                    otherwise,
+                } => {
+                    let branch = if is_truthy(&self.lookup(scope, name, *line)?) {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    self.render_nodes(branch, scope, depth, output)?;
+                },
+                Node::Each { name, line, body } => {
+                    let items = match self.lookup(scope, name, *line)? {
+                        Value::Array(items) => items,
+                        Value::Object(fields) => fields.into_iter().map(|(_, value)| value).collect(),
+                        Value::Null => Vec::new(),
+                        _ => bail!("{}:{line}: '{name}' is not an array or an object", self.name),
+                    };
+                    for (index, item) in items.into_iter().enumerate() {
+                        scope.frames.push((item, Some(index)));
+                        let result = self.render_nodes(body, scope, depth, output);
+                        scope.frames.pop();
+                        result?;
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up a variable, failing if it is undefined.
+    fn lookup(&self, scope: &Scope, name: &str, line: usize) -> Result<Value> {
+        match scope.lookup(name) {
+            Some(value) => Ok(value),
+            None => bail!("{}:{line}: undefined variable '{name}'", self.name),
+        }
+    }
+}
+
+/// Reads and renders a template file.
+///
+/// # Arguments
+/// * `path` - Path of the template file
+/// * `vars` - The variables, usually a JSON object
+///
+/// # Returns
+/// * `Ok(String)` - The rendered text
+/// * `Err` - An error if the file could not be read, parsed or rendered
+pub fn render_file(path: impl AsRef<Path>, vars: &Value) -> Result<String> {
+    Template::from_file(path)?.render(vars)
+}
+
+/// Reads the variables of a JSON or TOML file.
+///
+/// Files ending with `.toml` are parsed as TOML, any other file as JSON.
+///
+/// # Arguments
+/// * `path` - Path of the variables file
+///
+/// # Returns
+/// * `Ok(Map)` - The variables
+/// * `Err` - An error if the file could not be read or does not hold an object
+pub fn read_vars_file(path: impl AsRef<Path>) -> Result<Map<String, Value>> {
+    let path = path.as_ref();
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read variables file {}", path.display()))?;
+
+    let vars: Value = if path.extension().is_some_and(|extension| extension == "toml") {
+        toml::from_str(&content).with_context(|| format!("Failed to parse variables file {}", path.display()))?
+    } else {
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse variables file {}", path.display()))?
+    };
+
+    match vars {
+        Value::Object(vars) => Ok(vars),
+        _ => bail!("Variables file {} must hold an object", path.display()),
+    }
+}
+
+/// Parses a `key=value` variable assignment.
+///
+/// # Arguments
+/// * `assignment` - The assignment (e.g. "lang=Spanish")
+///
+/// # Returns
+/// * `Ok((key, value))` - The variable name and its value
+/// * `Err` - A message if there is no `=` or the name is empty
+pub fn parse_var(assignment: &str) -> Result<(String, String), String> {
+    match assignment.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_owned(), value.to_owned())),
+        _ => Err(format!("'{assignment}' is not a key=value assignment")),
+    }
+}
+
+/// Variables visible while rendering: the root variables and the current
+/// item of every enclosing `{{#each}}` block, with its index.
+struct Scope {
+    frames: Vec<(Value, Option<usize>)>,
+}
+
+impl Scope {
+    /// Looks up a variable, from the innermost block to the root variables.
+    fn lookup(&self, name: &str) -> Option<Value> {
+        let (item, index) = self.frames.last()?;
+        if name == "@index" {
+            return index.map(Value::from);
+        }
+        if name == "this" {
+            return Some(item.clone());
+        }
+        if let Some(path) = name.strip_prefix("this.") {
+            return get_path(item, path).cloned();
+        }
+
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|(value, _)| get_path(value, name))
+            .cloned()
+    }
+}
+
+/// Follows a dotted path (e.g. `user.name`) into a value.
+fn get_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| {
+        match value {
+            Value::Object(fields) => fields.get(key),
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        }
+    })
+}
+
+/// Returns whether a value selects the first block of a conditional.
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64().is_some_and(|number| number != 0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+    }
+}
+
+/// Converts a value into the text inserted in the template.
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Recursive descent parser over the template text.
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+    name: &'a str,
+}
+
+impl Parser<'_> {
+    /// Returns the line number of the current position.
+    fn line(&self) -> usize {
+        self.source[..self.position].matches('\n').count() + 1
+    }
+
+    /// Parses nodes until the end of the text or a block-ending tag.
+    fn parse_block(&mut self) -> Result<(Vec<Node>, End)> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+
+        loop {
+            let rest = &self.source[self.position..];
+            let Some(start) = rest.find(OPEN) else {
+                text.push_str(rest);
+                self.position = self.source.len();
+                push_text(&mut nodes, &mut text);
+                return Ok((nodes, End::Eof));
+            };
+
+            // `\{{` is a literal `{{`
+            if rest[..start].ends_with('\\') {
+                text.push_str(&rest[..start - 1]);
+                text.push_str(OPEN);
+                self.position += start + OPEN.len();
+                continue;
+            }
+
+            text.push_str(&rest[..start]);
+            self.position += start;
+            let line = self.line();
+            let Some(length) = rest[start + OPEN.len()..].find(CLOSE) else {
+                bail!("{}:{line}: unclosed '{OPEN}'", self.name);
+            };
+            let tag = rest[start + OPEN.len()..start + OPEN.len() + length].trim();
+            self.position += OPEN.len() + length + CLOSE.len();
+
+            if tag.starts_with('!') {
+                continue;
+            }
+            push_text(&mut nodes, &mut text);
+
+            match tag {
+                "" => bail!("{}:{line}: empty tag", self.name),
+                "else" => return Ok((nodes, End::Else)),
+                "/if" => return Ok((nodes, End::CloseIf)),
+                "/each" => return Ok((nodes, End::CloseEach)),
+                _ => {},
+            }
+
+            if let Some(path) = tag.strip_prefix('>') {
+                // `{{> include other.md}}` is accepted as a longer form
+                let path = path.trim();
+                let path = path.strip_prefix("include ").map_or(path, str::trim_start);
+                if path.is_empty() {
+                    bail!("{}:{line}: include without a path", self.name);
+                }
+                nodes.push(Node::Include {
+                    path: path.to_owned(),
+                    line,
+                });
+            } else if let Some(name) = tag.strip_prefix("#if ") {
+                let name = name.trim().to_owned();
+                let (then, end) = self.parse_block()?;
+                let otherwise = match end {
+                    End::Else => self.expect_end(End::CloseIf, line, "#if")?,
+                    End::CloseIf => Vec::new(),
+                    _ => bail!("{}:{line}: '{{{{#if {name}}}}}' is not closed", self.name),
+                };
+                nodes.push(Node::If {
+                    name,
+                    line,
+                    then,
+                    otherwise,
+                });
+            } else if let Some(name) = tag.strip_prefix("#each ") {
+                let name = name.trim().to_owned();
+                let body = self.expect_end(End::CloseEach, line, "#each")?;
+                nodes.push(Node::Each { name, line, body });
+            } else if tag.starts_with('#') || tag.starts_with('/') {
+                bail!("{}:{line}: unknown block '{tag}'", self.name);
+            } else {
+                nodes.push(Node::Var {
+                    name: tag.to_owned(),
+                    line,
+                });
+            }
+        }
+    }
+
+    /// Parses a block that must end with the given tag.
+    fn expect_end(&mut self, expected: End, line: usize, block: &str) -> Result<Vec<Node>> {
+        let (nodes, end) = self.parse_block()?;
+        if end != expected {
+            bail!("{}:{line}: '{{{{{block}}}}}' is not closed", self.name);
+        }
+
+        Ok(nodes)
+    }
+}
+
+/// Moves pending literal text into a node.
+fn push_text(nodes: &mut Vec<Node>, text: &mut String) {
+    if !text.is_empty() {
+        nodes.push(Node::Text(std::mem::take(text)));
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::Result;
+
+use crate::{
+    RequestMessage, RequestPayload, ResponseFormat,
+    anthropic::{MessagesRequest, MessagesResponse, StreamTranslator, finish_reason},
+    env_api_key, known_endpoints,
+    params::SamplingParams,
+    stream::StreamAccumulator,
+};
+
+fn payload(messages: Vec<RequestMessage>) -> RequestPayload<'static> {
+    RequestPayload {
+        messages,
+        model: "claude-sonnet-4-5",
+        max_tokens: Some(1000),
+        ..Default::default()
+    }
+}
+
+fn message(role: &str, content: &str) -> RequestMessage {
+    RequestMessage {
+        role: role.to_owned(),
+        content: content.into(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_anthropic_endpoint() {
+    assert_eq!(known_endpoints("anthropic"), "https://api.anthropic.com/v1/messages");
+    assert_eq!(env_api_key("anthropic"), "API_TOKEN_ANTHROPIC");
+}
+
+#[test]
+fn test_messages_request_moves_prompt_to_system() -> Result<()> {
+    let request = MessagesRequest::from_payload(&payload(vec![
+        message("assistant", "You are a reviewer."),
+        message("user", "Review this."),
+    ]))?;
+
+    assert_eq!(request.system.as_deref(), Some("You are a reviewer."));
+    assert_eq!(request.messages.len(), 1);
+    assert_eq!(request.messages[0].role, "user");
+    assert_eq!(request.max_tokens, 1000);
+
+    let json = serde_json::to_string(&request)?;
+    assert_eq!(
+        json,
+        "{\"model\":\"claude-sonnet-4-5\",\"system\":\"You are a \
+         reviewer.\",\"messages\":[{\"role\":\"user\",\"content\":\"Review this.\"}],\"max_tokens\":1000}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_messages_request_keeps_later_assistant_turns() -> Result<()> {
+    let request = MessagesRequest::from_payload(&payload(vec![
+        message("system", "Be brief."),
+        message("user", "Hi!"),
+        message("assistant", "Hello."),
+        message("user", "Bye!"),
+    ]))?;
+
+    assert_eq!(request.system.as_deref(), Some("Be brief."));
+    let roles: Vec<&str> = request.messages.iter().map(|message| message.role.as_str()).collect();
+    assert_eq!(roles, vec!["user", "assistant", "user"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_messages_request_uses_completion_tokens() -> Result<()> {
+    let mut payload = payload(vec![message("user", "Hi!")]);
+    payload.max_tokens = None;
+    payload.max_completion_tokens = Some(64);
+
+    let request = MessagesRequest::from_payload(&payload)?;
+    assert_eq!(request.max_tokens, 64);
+    assert!(request.system.is_none());
+
+    payload.max_completion_tokens = None;
+    assert!(MessagesRequest::from_payload(&payload).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_messages_request_rejects_schema() {
+    let mut payload = payload(vec![message("user", "Hi!")]);
+    payload.response_format = Some(ResponseFormat {
+        r#type: "json_schema".to_owned(),
+        json_schema: serde_json::json!({}),
+    });
+
+    assert!(MessagesRequest::from_payload(&payload).is_err());
+}
+
+#[test]
+fn test_messages_request_rejects_tool_choice() {
+    let mut payload = payload(vec![message("user", "Hi!")]);
+    payload.tool_choice = Some(serde_json::json!("auto"));
+
+    let error = MessagesRequest::from_payload(&payload).expect_err("tool_choice is not supported");
+    assert!(error.to_string().contains("tool_choice"));
+}
+
+#[test]
+fn test_messages_request_sampling() -> Result<()> {
+    let mut payload = payload(vec![message("user", "Hi!")]);
+    payload.sampling = SamplingParams {
+        temperature: Some(0.5),
+        top_p: Some(0.9),
+        stop: Some(vec!["END".to_owned()]),
+        n: Some(1),
+        ..Default::default()
+    };
+
+    let json = serde_json::to_value(MessagesRequest::from_payload(&payload)?)?;
+    assert_eq!(json["temperature"], 0.5);
+    assert_eq!(json["top_p"], 0.9);
+    assert_eq!(json["stop_sequences"], serde_json::json!(["END"]));
+    assert!(json.get("stop").is_none());
+
+    payload.sampling.seed = Some(7);
+    let error = MessagesRequest::from_payload(&payload).expect_err("seed is not supported");
+    assert!(error.to_string().contains("seed"));
+
+    Ok(())
+}
+
+#[test]
+fn test_messages_response_into_api_response() -> Result<()> {
+    let json = serde_json::json!({
+        "id": "msg_1",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude-sonnet-4-5",
+        "content": [
+            {"type": "thinking", "thinking": "..."},
+            {"type": "text", "text": "Hello, "},
+            {"type": "text", "text": "world!"}
+        ],
+        "stop_reason": "max_tokens",
+        "usage": {"input_tokens": 10, "output_tokens": 5, "cache_read_input_tokens": 4}
+    });
+
+    let response: MessagesResponse = serde_json::from_value(json)?;
+    let api_response = response.into_api_response();
+
+    assert_eq!(api_response.id, "msg_1");
+    assert_eq!(api_response.object, "chat.completion");
+    assert_eq!(api_response.choices.len(), 1);
+    assert_eq!(api_response.choices[0].message.role, "assistant");
+    assert_eq!(api_response.choices[0].message.content, "Hello, world!");
+    assert_eq!(api_response.choices[0].finish_reason, "length");
+    assert_eq!(api_response.usage.prompt_tokens, 14);
+    assert_eq!(api_response.usage.completion_tokens, 5);
+    assert_eq!(api_response.usage.total_tokens, 19);
+    assert_eq!(
+        api_response
+            .usage
+            .prompt_tokens_details
+            .and_then(|details| details.cached_tokens),
+        Some(4)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_finish_reason_mapping() {
+    assert_eq!(finish_reason("end_turn"), "stop");
+    assert_eq!(finish_reason("stop_sequence"), "stop");
+    assert_eq!(finish_reason("max_tokens"), "length");
+    assert_eq!(finish_reason("tool_use"), "tool_calls");
+    assert_eq!(finish_reason("pause_turn"), "pause_turn");
+}
+
+#[test]
+fn test_stream_translator_rebuilds_response() -> Result<()> {
+    let message_start = serde_json::json!({
+        "type": "message_start",
+        "message": {
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-sonnet-4-5",
+            "content": [],
+            "usage": {"input_tokens": 12, "output_tokens": 1}
+        }
+    });
+    let text_delta = |text: &str| {
+        serde_json::json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": {"type": "text_delta", "text": text}
+        })
+    };
+    let message_delta = serde_json::json!({
+        "type": "message_delta",
+        "delta": {"stop_reason": "end_turn"},
+        "usage": {"output_tokens": 7}
+    });
+
+    let events = [
+        message_start,
+        serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text"}}),
+        serde_json::json!({"type": "ping"}),
+        text_delta("Hello"),
+        text_delta("!"),
+        serde_json::json!({"type": "content_block_stop", "index": 0}),
+        message_delta,
+        serde_json::json!({"type": "message_stop"}),
+    ];
+
+    let mut translator = StreamTranslator::new();
+    let mut accumulator = StreamAccumulator::new();
+    for event in events {
+        if let Some(chunk) = translator.translate(&event.to_string())? {
+            accumulator.push(chunk);
+        }
+    }
+
+    let response = accumulator.finish();
+    assert_eq!(response.id, "msg_1");
+    assert_eq!(response.model, "claude-sonnet-4-5");
+    assert_eq!(response.choices[0].message.content, "Hello!");
+    assert_eq!(response.choices[0].finish_reason, "stop");
+    assert_eq!(response.usage.prompt_tokens, 12);
+    assert_eq!(response.usage.completion_tokens, 7);
+
+    Ok(())
+}
+
+#[test]
+fn test_stream_translator_error_event() {
+    let mut translator = StreamTranslator::new();
+    let event = serde_json::json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}});
+    assert!(translator.translate(&event.to_string()).is_err());
+}
@@ -0,0 +1,198 @@
+use anyhow::Result;
+use std::fs;
+use tempfile::TempDir;
+
+use super::server::{http_response, mock_server};
+use crate::{
+    ContentPart, MessageContent, OpenAiCompatible, RequestMessage, RequestPayload, RetryPolicy,
+    anthropic::MessagesRequest,
+    audio::{
+        MAX_AUDIO_SIZE, Transcriber, Transcription, TranscriptionRequest, append_audio, audio_part,
+        detect_audio_format, transcription_url,
+    },
+    schema::{CompletionTokensDetails, PromptTokensDetails, Usage},
+};
+
+/// The first bytes of a WAV file.
+const WAV: &[u8] = b"RIFF\x24\0\0\0WAVEfmt ";
+
+#[test]
+fn test_detect_audio_format() {
+    assert_eq!(detect_audio_format(WAV), Some("wav"));
+    assert_eq!(detect_audio_format(b"ID3\x04\0\0\0\0"), Some("mp3"));
+    assert_eq!(detect_audio_format(b"\xff\xfb\x90\x64"), Some("mp3"));
+    assert_eq!(detect_audio_format(b"RIFF\x24\0\0\0WEBPVP8 "), None);
+    assert_eq!(detect_audio_format(b"OggS\0\x02"), None);
+    assert_eq!(detect_audio_format(b""), None);
+}
+
+#[test]
+fn test_audio_parts() -> Result<()> {
+    let dir = TempDir::new()?;
+    let wav = dir.path().join("clip.wav");
+    fs::write(&wav, WAV)?;
+
+    let content = append_audio(MessageContent::from("Summarize this."), &[&wav])?;
+    assert_eq!(content.to_string(), "Summarize this.\n[audio]");
+    assert_eq!(
+        serde_json::to_value(RequestMessage::new("user", content))?,
+        serde_json::json!({
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "Summarize this."},
+                {"type": "input_audio", "input_audio": {"data": "UklGRiQAAABXQVZFZm10IA==", "format": "wav"}}
+            ]
+        })
+    );
+
+    // Without audio files the content is unchanged
+    let paths: [&str; 0] = [];
+    assert_eq!(append_audio("Hi!".into(), &paths)?, MessageContent::from("Hi!"));
+
+    let text = dir.path().join("notes.wav");
+    fs::write(&text, "not audio")?;
+    assert!(audio_part(&text).is_err_and(|error| error.to_string().contains("WAV and MP3")));
+
+    let large = dir.path().join("large.wav");
+    fs::File::create(&large)?.set_len(MAX_AUDIO_SIZE + 1)?;
+    assert!(audio_part(&large).is_err_and(|error| error.to_string().contains("too large")));
+
+    // Audio cannot be sent to the Anthropic Messages API
+    let payload = RequestPayload {
+        messages: vec![RequestMessage::new("user", vec![audio_part(&wav)?])],
+        model: "claude-sonnet-4-5",
+        max_tokens: Some(100),
+        ..Default::default()
+    };
+    assert!(MessagesRequest::from_payload(&payload).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_transcription_url() -> Result<()> {
+    assert_eq!(
+        transcription_url("https://api.openai.com/v1/chat/completions")?,
+        "https://api.openai.com/v1/audio/transcriptions"
+    );
+    assert_eq!(
+        transcription_url("https://api.groq.com/openai/v1/audio/transcriptions")?,
+        "https://api.groq.com/openai/v1/audio/transcriptions"
+    );
+    assert!(transcription_url("https://api.anthropic.com/v1/messages").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_transcription_usage() -> Result<()> {
+    let transcription: Transcription = serde_json::from_str(
+        r#"{"text": "Hello.", "usage": {"type": "tokens", "input_tokens": 14, "output_tokens": 3,
+            "total_tokens": 17, "input_token_details": {"text_tokens": 4, "audio_tokens": 10}}}"#,
+    )?;
+    assert_eq!(transcription.text, "Hello.");
+    let usage = transcription.usage.map(|usage| usage.to_usage()).unwrap_or_default();
+    assert_eq!(usage.prompt_tokens, 14);
+    assert_eq!(usage.total_tokens, 17);
+    assert_eq!(usage.audio_tokens(), 10);
+
+    // Duration-billed models report no token usage
+    let transcription: Transcription =
+        serde_json::from_str(r#"{"text": "Hello.", "usage": {"type": "duration", "seconds": 2}}"#)?;
+    assert_eq!(transcription.usage, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_usage_add_sums_details() {
+    let mut usage = Usage {
+        prompt_tokens: 10,
+        completion_tokens: 5,
+        total_tokens: 15,
+        prompt_tokens_details: Some(PromptTokensDetails {
+            cached_tokens: Some(4),
+            audio_tokens: None,
+        }),
+        completion_tokens_details: None,
+    };
+    usage.add(&Usage {
+        prompt_tokens: 20,
+        completion_tokens: 5,
+        total_tokens: 25,
+        prompt_tokens_details: Some(PromptTokensDetails {
+            cached_tokens: None,
+            audio_tokens: Some(12),
+        }),
+        completion_tokens_details: Some(CompletionTokensDetails {
+            audio_tokens: Some(3),
+            ..Default::default()
+        }),
+    });
+
+    assert_eq!(usage.total_tokens, 40);
+    let details = usage.prompt_tokens_details.clone().unwrap_or_default();
+    assert_eq!(details.cached_tokens, Some(4));
+    assert_eq!(details.audio_tokens, Some(12));
+    assert_eq!(usage.audio_tokens(), 15);
+}
+
+#[tokio::test]
+async fn test_transcriber_posts_multipart() -> Result<()> {
+    let dir = TempDir::new()?;
+    let wav = dir.path().join("meeting.wav");
+    fs::write(&wav, WAV)?;
+
+    let server = mock_server(vec![
+        http_response("503 Service Unavailable", &[], "busy"),
+        http_response("200 OK", &[], "1\n00:00:00,000 --> 00:00:01,000\nHello.\n"),
+    ])
+    .await;
+    let policy = RetryPolicy {
+        backoff_base: std::time::Duration::from_millis(1),
+        ..Default::default()
+    };
+    let transcriber = Transcriber::new(
+        &OpenAiCompatible::custom(&server.url),
+        "secret",
+        std::time::Duration::from_secs(5),
+        policy,
+    )?;
+    assert!(transcriber.url().ends_with("/v1/audio/transcriptions"));
+
+    let request = TranscriptionRequest {
+        model: "whisper-1".to_owned(),
+        language: Some("en".to_owned()),
+        response_format: Some("srt".to_owned()),
+        timestamp_granularities: vec!["segment".to_owned()],
+        ..Default::default()
+    };
+    let body = transcriber.transcribe(&wav, &request).await?;
+    assert!(body.contains("Hello."));
+
+    let requests = server.requests.lock().expect("lock requests").clone();
+    assert_eq!(requests.len(), 2);
+    let form = &requests[1];
+    assert!(form.contains("name=\"file\"; filename=\"meeting.wav\""));
+    assert!(form.contains("WAVEfmt"));
+    for (name, value) in [
+        ("model", "whisper-1"),
+        ("language", "en"),
+        ("response_format", "srt"),
+        ("timestamp_granularities[]", "segment"),
+    ] {
+        assert!(
+            form.contains(&format!("name=\"{name}\"\r\n\r\n{value}\r\n")),
+            "missing {name}"
+        );
+    }
+    assert!(!form.contains("name=\"prompt\""));
+
+    Ok(())
+}
+
+#[test]
+fn test_content_into_parts() {
+    assert!(MessageContent::from("").into_parts().is_empty());
+    assert_eq!(MessageContent::from("Hi!").into_parts(), vec![ContentPart::text("Hi!")]);
+}
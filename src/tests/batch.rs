@@ -0,0 +1,126 @@
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::server::{http_response, mock_server};
+use crate::{
+    InvokeClient, RetryPolicy,
+    batch::{BatchResult, BatchStatus, parse_batch, run_batch},
+};
+
+fn completion_body() -> String {
+    serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "test_model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Done"}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4}
+    })
+    .to_string()
+}
+
+#[test]
+fn test_parse_batch() {
+    let content = [
+        r#"{"custom_id":"first","messages":[{"role":"user","content":"Hi!"}],"model":"m","max_tokens":10}"#,
+        "",
+        r#"{"messages":[{"role":"user","content":"Hello!"}]}"#,
+        "not json",
+    ]
+    .join("\n");
+
+    let lines = parse_batch(&content);
+    assert_eq!(lines.len(), 3);
+
+    assert_eq!(lines[0].0, "first");
+    let first = lines[0].1.as_ref().expect("first line parses");
+    assert_eq!(first.model.as_deref(), Some("m"));
+    assert_eq!(first.max_tokens, Some(10));
+    assert_eq!(first.messages[0].content, "Hi!");
+
+    assert_eq!(lines[1].0, "line-3");
+    assert!(lines[1].1.is_ok());
+
+    assert_eq!(lines[2].0, "line-4");
+    assert!(lines[2].1.is_err());
+}
+
+#[test]
+fn test_batch_result_serialization() -> Result<()> {
+    let result = BatchResult::error("req-1", "boom");
+    assert_eq!(
+        serde_json::to_string(&result)?,
+        "{\"custom_id\":\"req-1\",\"status\":\"error\",\"error\":\"boom\"}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_batch() -> Result<()> {
+    let server = mock_server(vec![
+        http_response("200 OK", &[], &completion_body()),
+        http_response("200 OK", &[], &completion_body()),
+    ])
+    .await;
+    let client = InvokeClient::builder()
+        .endpoint(&server.url)
+        .api_token("secret")
+        .model("default_model")
+        .tokens(100)
+        .retry_policy(RetryPolicy {
+            max_retries: 0,
+            backoff_base: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        })
+        .build()?;
+
+    let content = [
+        r#"{"custom_id":"a","messages":[{"role":"user","content":"One"}],"model":"other","max_completion_tokens":5}"#,
+        r#"{"custom_id":"b","messages":[{"role":"user","content":"Two"}],"schema":{"name":"s"}}"#,
+        r#"{"custom_id":"c","messages":[]}"#,
+        "{broken",
+    ]
+    .join("\n");
+
+    let mut results = Vec::new();
+    run_batch(Arc::new(client), parse_batch(&content), 2, |result| {
+        results.push(result);
+        Ok(())
+    })
+    .await?;
+    results.sort_by(|left, right| left.custom_id.cmp(&right.custom_id));
+
+    let ids: Vec<&str> = results.iter().map(|result| result.custom_id.as_str()).collect();
+    assert_eq!(ids, vec!["a", "b", "c", "line-4"]);
+
+    assert_eq!(results[0].status, BatchStatus::Ok);
+    assert_eq!(results[0].content.as_deref(), Some("Done"));
+    assert_eq!(results[0].usage.as_ref().map(|usage| usage.total_tokens), Some(4));
+    assert_eq!(results[1].status, BatchStatus::Ok);
+    assert_eq!(results[2].status, BatchStatus::Error);
+    assert!(
+        results[2]
+            .error
+            .as_deref()
+            .is_some_and(|error| error.contains("no messages"))
+    );
+    assert_eq!(results[3].status, BatchStatus::Error);
+
+    let requests = server.requests.lock().expect("lock requests");
+    let mut sent: Vec<serde_json::Value> = requests
+        .iter()
+        .map(|body| serde_json::from_str(body))
+        .collect::<Result<_, _>>()?;
+    sent.sort_by_key(|body| body["messages"][0]["content"].to_string());
+
+    assert_eq!(sent[0]["model"], "other");
+    assert_eq!(sent[0]["max_completion_tokens"], 5);
+    assert!(sent[0].get("max_tokens").is_none());
+    assert_eq!(sent[1]["model"], "default_model");
+    assert_eq!(sent[1]["max_tokens"], 100);
+    assert_eq!(sent[1]["response_format"]["json_schema"]["name"], "s");
+
+    Ok(())
+}
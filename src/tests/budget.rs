@@ -0,0 +1,344 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tempfile::TempDir;
+
+use super::server::{http_response, mock_server};
+use crate::{
+    InvokeClient, RequestMessage, RequestPayload,
+    budget::{
+        Budget, BudgetConfig, BudgetExceeded, BudgetLimits, Ledger, LedgerEntry, Period, UnpricedModel, estimate_usage,
+    },
+    config::Config,
+    error::Error,
+    pricing::{Cost, ModelPrice, PricingTable},
+    schema::Usage,
+};
+
+fn cost(total: f64) -> Option<Cost> {
+    Some(Cost {
+        total,
+        ..Default::default()
+    })
+}
+
+fn entry(timestamp: u64, profile: Option<&str>, total: f64) -> LedgerEntry {
+    LedgerEntry {
+        timestamp,
+        profile: profile.map(str::to_owned),
+        endpoint: "openai".to_owned(),
+        model: "gpt-4.1-mini".to_owned(),
+        usage: Usage::default(),
+        cost: cost(total),
+    }
+}
+
+fn now() -> u64 {
+    LedgerEntry::new(None, "", "", Usage::default(), None).timestamp
+}
+
+#[test]
+fn test_period_start() {
+    // 2024-02-29T13:45:00Z, in a leap year
+    let timestamp = 1_709_214_300;
+    assert_eq!(Period::Day.start(timestamp), 1_709_164_800);
+    assert_eq!(Period::Month.start(timestamp), 1_706_745_600);
+
+    // 2025-01-01T00:00:00Z starts both periods
+    assert_eq!(Period::Day.start(1_735_689_600), 1_735_689_600);
+    assert_eq!(Period::Month.start(1_735_689_600), 1_735_689_600);
+
+    // 2025-12-31T23:59:59Z
+    assert_eq!(Period::Month.start(1_767_225_599), 1_764_547_200);
+}
+
+#[test]
+fn test_ledger_record_and_entries() -> Result<()> {
+    let dir = TempDir::new()?;
+    let ledger = Ledger::new(dir.path().join("data").join("ledger.jsonl"));
+    assert!(ledger.entries()?.is_empty());
+
+    ledger.record(&entry(1, Some("review"), 0.5))?;
+    ledger.record(&entry(2, None, 0.25))?;
+    assert_eq!(ledger.entries()?, vec![
+        entry(1, Some("review"), 0.5),
+        entry(2, None, 0.25)
+    ]);
+
+    // A line cut short by an interrupted run is skipped
+    std::fs::write(
+        ledger.path(),
+        std::fs::read_to_string(ledger.path())? + "{\"timestamp\":3,\"end",
+    )?;
+    assert_eq!(ledger.entries()?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_budget_status_and_check() -> Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("ledger.jsonl");
+    let ledger = Ledger::new(&path);
+    let now = now();
+    ledger.record(&entry(now, Some("review"), 1.0))?;
+    ledger.record(&entry(now, None, 0.5))?;
+    // Spent before the current month, so outside every period
+    ledger.record(&entry(Period::Month.start(now) - 1, None, 100.0))?;
+
+    let config = BudgetConfig {
+        ledger: Some(path),
+        daily: Some(2.0),
+        monthly: None,
+        profiles: BTreeMap::from([("review".to_owned(), BudgetLimits {
+            daily: None,
+            monthly: Some(1.2),
+        })]),
+    };
+
+    let budget = Budget::new(config.clone(), None);
+    let statuses = budget.status()?;
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].name(), "daily");
+    assert_eq!(statuses[0].spent, 1.5);
+    assert_eq!(statuses[0].remaining(), 0.5);
+    assert!(budget.check(0.5).is_ok());
+    let error = budget.check(0.6).unwrap_err();
+    let exceeded = error.downcast_ref::<BudgetExceeded>().expect("budget exceeded");
+    assert_eq!(exceeded.status.period, Period::Day);
+    assert_eq!(exceeded.estimate, 0.6);
+
+    // Profiles are held to their own limits, only counting their own spending
+    let budget = Budget::new(config, Some("review"));
+    let statuses = budget.status()?;
+    assert_eq!(statuses.len(), 2);
+    assert_eq!(statuses[1].name(), "profile 'review' monthly");
+    assert_eq!(statuses[1].spent, 1.0);
+    assert!(budget.check(0.2).is_ok());
+    assert!(
+        budget
+            .check(0.3)
+            .unwrap_err()
+            .to_string()
+            .starts_with("The profile 'review' monthly budget of $1.20 would be exceeded")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_budget_record() -> Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("ledger.jsonl");
+    let config = BudgetConfig {
+        ledger: Some(path.clone()),
+        ..Default::default()
+    };
+
+    // Without limits, nothing is refused but requests are still recorded
+    let budget = Budget::new(config, Some("review"));
+    assert!(budget.status()?.is_empty());
+    assert!(budget.check(1000.0).is_ok());
+    budget.record("openai", "gpt-4.1-mini", &Usage::default(), cost(0.1))?;
+
+    let entries = Ledger::new(path).entries()?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].profile.as_deref(), Some("review"));
+    assert_eq!(entries[0].model, "gpt-4.1-mini");
+    assert_eq!(entries[0].total_cost(), 0.1);
+
+    Ok(())
+}
+
+#[test]
+fn test_estimate_usage() {
+    let mut payload = RequestPayload {
+        messages: vec![
+            RequestMessage::new("system", "12345678"),
+            RequestMessage::new("user", "123456789"),
+        ],
+        model: "local-model",
+        max_tokens: Some(100),
+        ..Default::default()
+    };
+    // Estimated at 4 characters per token, with the chat format overhead
+    let usage = estimate_usage(&payload);
+    assert_eq!(usage.prompt_tokens, (3 + 2 + 2) + (3 + 1 + 3) + 3);
+    assert_eq!(usage.completion_tokens, 100);
+    assert_eq!(usage.total_tokens, 117);
+
+    // Every choice may use the whole token limit
+    payload.sampling.n = Some(3);
+    assert_eq!(estimate_usage(&payload).completion_tokens, 300);
+}
+
+#[test]
+fn test_config_budget() -> Result<()> {
+    let mut config = Config::parse(
+        r#"
+[budget]
+ledger = "spending.jsonl"
+daily = 5.0
+monthly = 100.0
+
+[budget.profiles.review]
+daily = 1.0
+"#,
+        Path::new("/etc/invoke-llm"),
+    )?;
+    assert_eq!(
+        config.budget.ledger_path(),
+        Some(Path::new("/etc/invoke-llm/spending.jsonl").to_path_buf())
+    );
+
+    config.merge(Config::parse(
+        r#"
+[budget]
+daily = 2.0
+
+[budget.profiles.review]
+monthly = 10.0
+"#,
+        Path::new("/project"),
+    )?);
+    assert_eq!(config.budget.daily, Some(2.0));
+    assert_eq!(config.budget.monthly, Some(100.0));
+    assert_eq!(config.budget.profiles["review"], BudgetLimits {
+        daily: Some(1.0),
+        monthly: Some(10.0),
+    });
+    assert_eq!(
+        config.budget.ledger,
+        Some(Path::new("/etc/invoke-llm/spending.jsonl").to_path_buf())
+    );
+
+    // The project-local file may only lower the budgets
+    config.merge_local(Config::parse(
+        r#"
+[budget]
+ledger = "elsewhere.jsonl"
+daily = 50.0
+monthly = 80.0
+
+[budget.profiles.review]
+daily = 0.5
+monthly = 20.0
+
+[budget.profiles.draft]
+daily = 0.1
+"#,
+        Path::new("/project"),
+    )?)?;
+    assert_eq!(config.budget.daily, Some(2.0));
+    assert_eq!(config.budget.monthly, Some(80.0));
+    assert_eq!(config.budget.profiles["review"], BudgetLimits {
+        daily: Some(0.5),
+        monthly: Some(10.0),
+    });
+    assert_eq!(config.budget.profiles["draft"].daily, Some(0.1));
+    assert_eq!(
+        config.budget.ledger,
+        Some(Path::new("/etc/invoke-llm/spending.jsonl").to_path_buf())
+    );
+
+    assert!(Config::parse("[budget]\nweekly = 1.0\n", Path::new("")).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_budget() -> Result<()> {
+    let body = serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "local-model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 1000, "completion_tokens": 1000, "total_tokens": 2000}
+    });
+    let server = mock_server(vec![http_response("200 OK", &[], &body.to_string())]).await;
+
+    let dir = TempDir::new()?;
+    let path = dir.path().join("ledger.jsonl");
+    let mut pricing = PricingTable::builtin();
+    pricing.insert(&server.url, "local-model", ModelPrice {
+        input: 1.0,
+        cached_input: None,
+        output: 4.0,
+        reasoning: None,
+    });
+    let client = |tokens: u32| {
+        InvokeClient::builder()
+            .endpoint(&server.url)
+            .api_token("secret")
+            .model("local-model")
+            .tokens(tokens)
+            .pricing(pricing.clone())
+            .budget(Budget::new(
+                BudgetConfig {
+                    ledger: Some(path.clone()),
+                    daily: Some(0.01),
+                    ..Default::default()
+                },
+                None,
+            ))
+            .build()
+    };
+
+    // The request is recorded with its cost
+    client(1000)?.complete("Prompt", "Input").await?;
+    let entries = Ledger::new(&path).entries()?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].endpoint, server.url);
+    assert_eq!(entries[0].total_cost(), 0.001 + 0.004);
+
+    // The token limit alone would exceed the budget left
+    let error = client(2000)?.complete("Prompt", "Input").await.unwrap_err();
+    assert!(matches!(&error, Error::Budget(error) if error.is::<BudgetExceeded>()));
+    assert_eq!(server.requests.lock().unwrap().len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_unpriced_model() -> Result<()> {
+    let body = serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "local-model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 10, "completion_tokens": 10, "total_tokens": 20}
+    });
+    let server = mock_server(vec![http_response("200 OK", &[], &body.to_string())]).await;
+
+    let dir = TempDir::new()?;
+    let client = |daily: Option<f64>| {
+        InvokeClient::builder()
+            .endpoint(&server.url)
+            .api_token("secret")
+            .model("local-model")
+            .tokens(100)
+            .budget(Budget::new(
+                BudgetConfig {
+                    ledger: Some(dir.path().join("ledger.jsonl")),
+                    daily,
+                    ..Default::default()
+                },
+                None,
+            ))
+            .build()
+    };
+
+    // A model without a price cannot be checked against a budget
+    let error = client(Some(100.0))?.complete("Prompt", "Input").await.unwrap_err();
+    assert!(matches!(&error, Error::Budget(error) if error.is::<UnpricedModel>()));
+    assert!(error.to_string().contains("local-model"));
+    assert!(server.requests.lock().unwrap().is_empty());
+
+    // Without any budget, it is only recorded
+    client(None)?.complete("Prompt", "Input").await?;
+    assert_eq!(server.requests.lock().unwrap().len(), 1);
+
+    Ok(())
+}
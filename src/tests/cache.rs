@@ -0,0 +1,120 @@
+use anyhow::Result;
+use std::fs;
+use std::time::Duration;
+use tempfile::TempDir;
+
+use super::server::{http_response, mock_server};
+use crate::{
+    InvokeClient,
+    cache::{CacheStats, ResponseCache, cache_key},
+    schema::{ApiResponse, Choice, Message},
+};
+
+fn response(content: &str) -> ApiResponse {
+    ApiResponse {
+        id: "chatcmpl-1".to_owned(),
+        choices: vec![Choice {
+            message: Message {
+                role: "assistant".to_owned(),
+                content: content.to_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_cache_key() {
+    let body = serde_json::json!({"model": "m", "messages": [{"role": "user", "content": "Hi!"}]});
+    let reordered = serde_json::json!({"messages": [{"content": "Hi!", "role": "user"}], "model": "m"});
+
+    let key = cache_key("https://example.com/v1", &body);
+    assert_eq!(key.len(), 64);
+    assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(key, cache_key("https://example.com/v1", &reordered));
+    assert_ne!(key, cache_key("https://example.org/v1", &body));
+    assert_ne!(
+        key,
+        cache_key("https://example.com/v1", &serde_json::json!({"model": "m"}))
+    );
+}
+
+#[test]
+fn test_cache_put_get() -> Result<()> {
+    let dir = TempDir::new()?;
+    let cache = ResponseCache::new(dir.path().join("cache"), None);
+
+    assert!(cache.get("missing")?.is_none());
+    assert_eq!(cache.stats()?, CacheStats::default());
+
+    cache.put("key", "https://example.com", &response("Hello!"))?;
+    let cached = cache.get("key")?.expect("cached response");
+    assert_eq!(cached.choices[0].message.content, "Hello!");
+
+    let stats = cache.stats()?;
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.expired, 0);
+    assert!(stats.bytes > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_cache_expiry_prune_clear() -> Result<()> {
+    let dir = TempDir::new()?;
+    let cache = ResponseCache::new(dir.path(), None);
+    cache.put("first", "https://example.com", &response("1"))?;
+    cache.put("second", "https://example.com", &response("2"))?;
+    fs::write(dir.path().join("broken.json"), "not json")?;
+
+    assert!(cache.get("broken").is_err());
+    assert_eq!(cache.prune()?, 1);
+    assert_eq!(cache.stats()?.entries, 2);
+
+    let expired = ResponseCache::new(dir.path(), Some(Duration::ZERO));
+    assert!(expired.get("first")?.is_none());
+    assert_eq!(expired.stats()?.expired, 2);
+
+    let fresh = ResponseCache::new(dir.path(), Some(Duration::from_secs(3600)));
+    assert!(fresh.get("first")?.is_some());
+    assert_eq!(fresh.prune()?, 0);
+
+    assert_eq!(cache.clear()?, 2);
+    assert_eq!(cache.stats()?.entries, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_uses_cache() -> Result<()> {
+    let body = serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "test_model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hello!"}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}
+    })
+    .to_string();
+    // The server answers a single request, so a second network call would fail
+    let server = mock_server(vec![http_response("200 OK", &[], &body)]).await;
+    let dir = TempDir::new()?;
+    let client = InvokeClient::builder()
+        .endpoint(&server.url)
+        .api_token("secret")
+        .model("test_model")
+        .tokens(100)
+        .cache(ResponseCache::new(dir.path(), None))
+        .build()?;
+
+    let first = client.complete("Be brief.", "Hi!").await?;
+    let second = client.complete("Be brief.", "Hi!").await?;
+    assert_eq!(first, second);
+    assert_eq!(second.usage.total_tokens, 7);
+
+    assert_eq!(server.requests.lock().expect("lock requests").len(), 1);
+
+    Ok(())
+}
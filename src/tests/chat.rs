@@ -0,0 +1,104 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::{
+    RequestMessage,
+    chat::{ChatCommand, ChatInput, Conversation, parse_input},
+    schema::Usage,
+};
+
+fn usage(prompt_tokens: i64, completion_tokens: i64) -> Usage {
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+        ..Default::default()
+    }
+}
+
+fn command(line: &str) -> Result<ChatCommand> {
+    match parse_input(line)? {
+        Some(ChatInput::Command(command)) => Ok(command),
+        other => anyhow::bail!("'{line}' parsed as {other:?}"),
+    }
+}
+
+#[test]
+fn test_chat_parse_input() -> Result<()> {
+    assert_eq!(parse_input("   ")?, None);
+    assert_eq!(
+        parse_input("Explain this.")?,
+        Some(ChatInput::Message("Explain this.".to_owned()))
+    );
+    assert_eq!(
+        parse_input("//etc/hosts is empty")?,
+        Some(ChatInput::Message("/etc/hosts is empty".to_owned()))
+    );
+
+    assert_eq!(command("/system")?, ChatCommand::System(None));
+    assert_eq!(
+        command("/system  Answer in French. ")?,
+        ChatCommand::System(Some("Answer in French.".to_owned()))
+    );
+    assert_eq!(command("/model gpt-4o")?, ChatCommand::Model(Some("gpt-4o".to_owned())));
+    assert_eq!(command("/tokens 500")?, ChatCommand::Tokens(Some(500)));
+    assert_eq!(
+        command("/save chat.json")?,
+        ChatCommand::Save(PathBuf::from("chat.json"))
+    );
+    assert_eq!(
+        command("/load chat.json")?,
+        ChatCommand::Load(PathBuf::from("chat.json"))
+    );
+    assert_eq!(command("/retry")?, ChatCommand::Retry);
+    assert_eq!(command("/undo")?, ChatCommand::Undo);
+    assert_eq!(command("/quit")?, ChatCommand::Exit);
+
+    assert!(parse_input("/tokens many").is_err());
+    assert!(parse_input("/save").is_err());
+    assert!(parse_input("/unknown").is_err_and(|error| error.to_string().contains("/help")));
+
+    Ok(())
+}
+
+#[test]
+fn test_chat_conversation_turns() {
+    let mut conversation = Conversation::new(Some("Be brief.".to_owned()));
+    assert_eq!(conversation.request("Hi!", true), vec![
+        RequestMessage::new("system", "Be brief."),
+        RequestMessage::new("user", "Hi!"),
+    ]);
+
+    conversation.push_turn("Hi!", "Hello.", &usage(10, 2));
+    conversation.push_turn("How are you?", "Fine.", &usage(20, 1));
+    assert_eq!(conversation.turns(), 2);
+    assert_eq!(conversation.usage.total_tokens, 33);
+
+    let request = conversation.request("Bye!", false);
+    assert_eq!(request.len(), 6);
+    assert_eq!(request[0], RequestMessage::new("assistant", "Be brief."));
+    assert_eq!(request[5], RequestMessage::new("user", "Bye!"));
+
+    assert_eq!(conversation.undo().as_deref(), Some("How are you?"));
+    assert_eq!(conversation.messages.len(), 2);
+    assert_eq!(conversation.undo().as_deref(), Some("Hi!"));
+    assert_eq!(conversation.undo(), None);
+}
+
+#[test]
+fn test_chat_conversation_sessions() {
+    let mut conversation = Conversation::new(Some("Be brief.".to_owned()));
+    conversation.push_turn("Hi!", "Hello.", &usage(10, 2));
+
+    let session = conversation.to_session(true);
+    assert_eq!(session.messages.len(), 3);
+    assert_eq!(session.turns(), 1);
+
+    let restored = Conversation::from_session(session);
+    assert_eq!(restored.system.as_deref(), Some("Be brief."));
+    assert_eq!(restored.messages, conversation.messages);
+
+    let without_prompt = Conversation::new(None).to_session(false);
+    assert!(without_prompt.is_empty());
+    assert_eq!(Conversation::from_session(without_prompt), Conversation::default());
+}
@@ -0,0 +1,310 @@
+use anyhow::Result;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+use super::server::{http_response, mock_server};
+use crate::{
+    Error, InvokeClient, RequestMessage,
+    budget::{Budget, BudgetConfig, BudgetExceeded},
+    chunk::{ANSWER_SEPARATOR, estimate_map_reduce_cost, join_answers, map_reduce, split_chunks},
+    limits::ContextOverflow,
+    pricing::{ModelPrice, PricingTable},
+    tokenizer::Tokenizer,
+};
+
+/// Context check of a client without limits.
+fn no_check(_: &[RequestMessage]) -> Result<()> {
+    Ok(())
+}
+
+/// Error of a request that does not fit in the context window.
+fn overflow() -> anyhow::Error {
+    ContextOverflow {
+        model: "local-model".to_owned(),
+        prompt_tokens: 1000,
+        max_tokens: 100,
+        context_window: 1000,
+    }
+    .into()
+}
+
+/// Checks that a reduce request holds at most two answers.
+fn two_answers(messages: &[RequestMessage]) -> Result<()> {
+    if messages[1].content.to_string().matches(ANSWER_SEPARATOR).count() > 1 {
+        return Err(overflow());
+    }
+    Ok(())
+}
+
+fn completion(content: &str, prompt_tokens: u32) -> String {
+    let body = serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "local-model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": content}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": prompt_tokens, "completion_tokens": 5, "total_tokens": prompt_tokens + 5}
+    });
+    http_response("200 OK", &[], &body.to_string())
+}
+
+#[test]
+fn test_split_chunks_small_input() {
+    let tokenizer = Tokenizer::Heuristic;
+    assert_eq!(split_chunks("Hello, world!\n", tokenizer, 100, 10), vec![
+        "Hello, world!\n"
+    ]);
+    assert!(split_chunks("", tokenizer, 100, 0).is_empty());
+}
+
+#[test]
+fn test_split_chunks_at_headings() {
+    // Every line is 2 tokens, so every section is 8 tokens
+    let section = |title: &str, line: &str| format!("# {title}\n{}", format!("{line}\n").repeat(3));
+    let sections = [
+        section("One", "line aa"),
+        section("Two", "line bb"),
+        section("Six", "line cc"),
+    ];
+    let text = sections.concat();
+
+    // The first chunk could hold the next heading, but is cut before it
+    let chunks = split_chunks(&text, Tokenizer::Heuristic, 10, 0);
+    assert_eq!(chunks, sections);
+
+    // Chunks are cut at the last heading, unless it leaves them half empty
+    let chunks = split_chunks(&text, Tokenizer::Heuristic, 20, 0);
+    assert_eq!(chunks, vec![sections[..2].concat(), sections[2].clone()]);
+}
+
+#[test]
+fn test_split_chunks_around_code_fences() {
+    // A code block starts a section
+    let text = "para aa\npara bb\npara cc\n```\nx = 1;\n```\nafter a\n";
+    let chunks = split_chunks(text, Tokenizer::Heuristic, 10, 0);
+    assert_eq!(chunks, vec![
+        "para aa\npara bb\npara cc\n",
+        "```\nx = 1;\n```\nafter a\n"
+    ]);
+
+    // Comments in a code block are not headings
+    let text = "text aa\n```sh\n# cmd a\nls -la\n```\nafter a\nafter b\n";
+    let chunks = split_chunks(text, Tokenizer::Heuristic, 10, 0);
+    assert_eq!(chunks, vec![
+        "text aa\n```sh\n# cmd a\nls -la\n```\n",
+        "after a\nafter b\n"
+    ]);
+}
+
+#[test]
+fn test_split_chunks_long_lines_and_overlap() {
+    // A line longer than a chunk is cut on token boundaries
+    let text = "x".repeat(40);
+    let chunks = split_chunks(&text, Tokenizer::Heuristic, 3, 0);
+    assert_eq!(chunks.len(), 4);
+    assert_eq!(chunks.concat(), text);
+
+    // Chunks start with the end of the previous one
+    let chunks = split_chunks("abcdefg\nhijklmn\nopqrstu\n", Tokenizer::Heuristic, 3, 1);
+    assert_eq!(chunks, vec!["abcdefg\n", "efg\nhijklmn\n", "lmn\nopqrstu\n"]);
+
+    // Exact counts stay within the chunk size
+    let text = "The quick brown fox jumps over the lazy dog.\n".repeat(50);
+    let tokenizer = Tokenizer::O200kBase;
+    let chunks = split_chunks(&text, tokenizer, 64, 8);
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|chunk| tokenizer.count(chunk) <= 64));
+}
+
+#[tokio::test]
+async fn test_map_reduce() -> Result<()> {
+    let server = mock_server(vec![
+        completion("Answer 1", 100),
+        completion("Answer 2", 200),
+        completion("Summary", 10),
+    ])
+    .await;
+    let client = Arc::new(
+        InvokeClient::builder()
+            .endpoint(&server.url)
+            .api_token("secret")
+            .model("local-model")
+            .tokens(100)
+            .build()?,
+    );
+
+    let chunks = vec!["Part one".to_owned(), "Part two".to_owned()];
+    let outcome = map_reduce(client, "Summarize", chunks, Some("Merge the summaries"), 1, no_check).await?;
+    assert_eq!(outcome.partials.len(), 2);
+    assert_eq!(outcome.partials[1].choices[0].message.content, "Answer 2");
+    assert_eq!(outcome.response.choices[0].message.content, "Summary");
+    assert_eq!(outcome.response.usage.prompt_tokens, 310);
+    assert_eq!(outcome.response.usage.total_tokens, 325);
+
+    // The reduce prompt receives the partial answers in chunk order
+    let requests = server.requests.lock().unwrap();
+    assert!(requests[0].contains("Part one"));
+    let reduce: serde_json::Value = serde_json::from_str(&requests[2])?;
+    assert_eq!(reduce["messages"][0]["content"], "Merge the summaries");
+    assert_eq!(
+        reduce["messages"][1]["content"],
+        join_answers(&["Answer 1".to_owned(), "Answer 2".to_owned()])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_map_without_reduce() -> Result<()> {
+    let server = mock_server(vec![
+        completion("Answer 1", 100),
+        completion("Answer 2", 200),
+        completion("Answer 3", 300),
+    ])
+    .await;
+    let client = Arc::new(
+        InvokeClient::builder()
+            .endpoint(&server.url)
+            .api_token("secret")
+            .model("local-model")
+            .tokens(100)
+            .build()?,
+    );
+
+    let chunks = vec!["One".to_owned(), "Two".to_owned(), "Three".to_owned()];
+    let outcome = map_reduce(client, "Translate", chunks, None, 3, no_check).await?;
+    assert_eq!(server.requests.lock().unwrap().len(), 3);
+
+    // The partial answers are joined in chunk order, whichever came first
+    let mut answers: Vec<_> = outcome
+        .partials
+        .iter()
+        .map(|partial| partial.choices[0].message.content.clone())
+        .collect();
+    assert_eq!(outcome.response.choices[0].message.content, join_answers(&answers));
+    answers.sort();
+    assert_eq!(answers, ["Answer 1", "Answer 2", "Answer 3"]);
+    assert_eq!(outcome.response.usage.prompt_tokens, 600);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_map_reduce_in_groups() -> Result<()> {
+    let server = mock_server(vec![
+        completion("Answer 1", 10),
+        completion("Answer 2", 10),
+        completion("Answer 3", 10),
+        completion("Answer 4", 10),
+        completion("Merged 1", 10),
+        completion("Merged 2", 10),
+        completion("Summary", 10),
+    ])
+    .await;
+    let client = Arc::new(
+        InvokeClient::builder()
+            .endpoint(&server.url)
+            .api_token("secret")
+            .model("local-model")
+            .tokens(100)
+            .build()?,
+    );
+
+    // At most two answers fit in a reduce request
+    let chunks = ["One", "Two", "Three", "Four"].map(str::to_owned).to_vec();
+    let outcome = map_reduce(Arc::clone(&client), "Summarize", chunks, Some("Merge"), 1, two_answers).await?;
+    assert_eq!(outcome.response.choices[0].message.content, "Summary");
+    assert_eq!(outcome.response.usage.prompt_tokens, 70);
+
+    let requests = server.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 7);
+    let reduce: serde_json::Value = serde_json::from_str(&requests[6])?;
+    assert_eq!(
+        reduce["messages"][1]["content"],
+        join_answers(&["Merged 1".to_owned(), "Merged 2".to_owned()])
+    );
+
+    // A single answer that does not fit fails the run
+    let server = mock_server(vec![completion("Answer 1", 10), completion("Answer 2", 10)]).await;
+    let client = Arc::new(
+        InvokeClient::builder()
+            .endpoint(&server.url)
+            .api_token("secret")
+            .model("local-model")
+            .tokens(100)
+            .build()?,
+    );
+    let chunks = vec!["One".to_owned(), "Two".to_owned()];
+    let error = map_reduce(client, "Summarize", chunks, Some("Merge"), 1, |_| Err(overflow()))
+        .await
+        .unwrap_err();
+    assert!(error.is::<ContextOverflow>());
+    assert_eq!(server.requests.lock().unwrap().len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_map_reduce_budget() -> Result<()> {
+    let server = mock_server(vec![completion("Answer 1", 10), completion("Answer 2", 10)]).await;
+    let dir = TempDir::new()?;
+    let budget_client = |url: &str, ledger: &str, daily: f64| {
+        let mut pricing = PricingTable::builtin();
+        pricing.insert(url, "local-model", ModelPrice {
+            input: 1.0,
+            cached_input: None,
+            output: 4.0,
+            reasoning: None,
+        });
+        InvokeClient::builder()
+            .endpoint(url)
+            .api_token("secret")
+            .model("local-model")
+            .tokens(1000)
+            .pricing(pricing)
+            .budget(Budget::new(
+                BudgetConfig {
+                    ledger: Some(dir.path().join(ledger)),
+                    daily: Some(daily),
+                    ..Default::default()
+                },
+                None,
+            ))
+            .build()
+            .map(Arc::new)
+    };
+    let client = budget_client(&server.url, "ledger.jsonl", 0.01)?;
+
+    // Every chunk fits in the budget, but not the reduce step on top of them
+    let chunks = vec!["One".to_owned(), "Two".to_owned()];
+    let map = estimate_map_reduce_cost(&client, "Summarize", &chunks, None);
+    assert!(map > 0.008 && map < 0.01);
+    assert!(estimate_map_reduce_cost(&client, "Summarize", &chunks, Some("Merge")) > map + 0.006);
+
+    let error = map_reduce(Arc::clone(&client), "Summarize", chunks, Some("Merge"), 2, no_check)
+        .await
+        .unwrap_err();
+    assert!(matches!(error.downcast_ref::<Error>(), Some(Error::Budget(error)) if error.is::<BudgetExceeded>()));
+    assert!(server.requests.lock().unwrap().is_empty());
+
+    // The run fits, but the answers cost more than estimated and leave no room
+    // for the level of groups the reduce needs
+    let server = mock_server(vec![
+        completion("Answer 1", 5000),
+        completion("Answer 2", 5000),
+        completion("Answer 3", 5000),
+        completion("Answer 4", 5000),
+    ])
+    .await;
+    let client = budget_client(&server.url, "grouped.jsonl", 0.025)?;
+    let chunks = ["One", "Two", "Three", "Four"].map(str::to_owned).to_vec();
+    assert!(estimate_map_reduce_cost(&client, "Summarize", &chunks, Some("Merge")) < 0.025);
+
+    let error = map_reduce(client, "Summarize", chunks, Some("Merge"), 1, two_answers)
+        .await
+        .unwrap_err();
+    assert!(matches!(error.downcast_ref::<Error>(), Some(Error::Budget(error)) if error.is::<BudgetExceeded>()));
+    assert_eq!(server.requests.lock().unwrap().len(), 4);
+
+    Ok(())
+}
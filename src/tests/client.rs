@@ -0,0 +1,199 @@
+use anyhow::Result;
+
+use super::server::{http_response, mock_server};
+use crate::{Error, InvokeClient, OpenAiCompatible, params::SamplingParams};
+
+fn completion_body(content: &str) -> String {
+    serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "test_model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": content}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}
+    })
+    .to_string()
+}
+
+fn client_for(url: &str) -> Result<InvokeClient> {
+    Ok(InvokeClient::builder()
+        .endpoint(url)
+        .api_token("secret")
+        .model("test_model")
+        .tokens(100)
+        .build()?)
+}
+
+#[test]
+fn test_builder_requires_settings() {
+    let missing_endpoint = InvokeClient::builder().model("m").tokens(1).api_token("t").build();
+    assert!(matches!(missing_endpoint, Err(Error::Config(_))));
+
+    let missing_model = InvokeClient::builder().endpoint("hf").tokens(1).api_token("t").build();
+    assert!(matches!(missing_model, Err(Error::Config(_))));
+
+    let missing_tokens = InvokeClient::builder().endpoint("hf").model("m").api_token("t").build();
+    assert!(matches!(missing_tokens, Err(Error::Config(_))));
+
+    let zero_tokens = InvokeClient::builder()
+        .endpoint("hf")
+        .model("m")
+        .tokens(0)
+        .api_token("t")
+        .build();
+    assert!(matches!(zero_tokens, Err(Error::Config(message)) if message.contains("greater than 0")));
+}
+
+#[test]
+fn test_builder_missing_api_key() {
+    let provider = OpenAiCompatible::new("test", "http://localhost", "INVOKE_LLM_TEST_UNSET_TOKEN");
+    let result = InvokeClient::builder()
+        .provider(Box::new(provider))
+        .model("m")
+        .tokens(1)
+        .build();
+
+    assert!(matches!(result, Err(Error::MissingApiKey(name)) if name == "INVOKE_LLM_TEST_UNSET_TOKEN"));
+}
+
+#[test]
+fn test_client_messages_and_payload() -> Result<()> {
+    let client = InvokeClient::builder()
+        .endpoint("openai")
+        .api_token("secret")
+        .model("o4-mini")
+        .tokens(1000)
+        .reasoning(true)
+        .system_role(true)
+        .schema(serde_json::json!({"name": "test_schema"}))
+        .build()?;
+
+    assert_eq!(client.model(), "o4-mini");
+    assert_eq!(client.provider().name(), "openai");
+
+    let messages = client.messages("Be brief.", "Hi!");
+    assert_eq!(messages[0].role, "system");
+    assert_eq!(messages[1].role, "user");
+
+    let payload = client.payload(messages, false);
+    let json = serde_json::to_value(&payload)?;
+    assert_eq!(json["max_completion_tokens"], 1000);
+    assert!(json.get("max_tokens").is_none());
+    assert_eq!(json["response_format"]["json_schema"]["name"], "test_schema");
+    assert!(json.get("stream").is_none());
+
+    let default_role = client_for("openai")?.messages("Be brief.", "Hi!");
+    assert_eq!(default_role[0].role, "assistant");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_complete() -> Result<()> {
+    let server = mock_server(vec![http_response("200 OK", &[], &completion_body("Hello!"))]).await;
+    let client = client_for(&server.url)?;
+
+    let response = client.complete("Be brief.", "Hi!").await?;
+    assert_eq!(response.choices[0].message.content, "Hello!");
+    assert_eq!(response.usage.total_tokens, 7);
+
+    let requests = server.requests.lock().expect("lock requests");
+    let sent: serde_json::Value = serde_json::from_str(&requests[0])?;
+    assert_eq!(sent["model"], "test_model");
+    assert_eq!(sent["max_tokens"], 100);
+    assert_eq!(sent["messages"][1]["content"], "Hi!");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_sampling_and_extra_body() -> Result<()> {
+    let server = mock_server(vec![http_response("200 OK", &[], &completion_body("Hello!"))]).await;
+    let client = InvokeClient::builder()
+        .endpoint(&server.url)
+        .api_token("secret")
+        .model("test_model")
+        .tokens(100)
+        .sampling(SamplingParams {
+            temperature: Some(0.2),
+            seed: Some(42),
+            ..Default::default()
+        })
+        .extra_body(serde_json::json!({"provider": {"order": ["novita"]}, "max_tokens": null, "seed": 7}))
+        .build()?;
+
+    client.complete("Be brief.", "Hi!").await?;
+
+    let requests = server.requests.lock().expect("lock requests");
+    let sent: serde_json::Value = serde_json::from_str(&requests[0])?;
+    assert_eq!(sent["temperature"], 0.2);
+    assert_eq!(sent["seed"], 7);
+    assert_eq!(sent["provider"]["order"][0], "novita");
+    assert!(sent.get("max_tokens").is_none());
+    assert!(sent.get("top_p").is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_api_error() -> Result<()> {
+    let server = mock_server(vec![http_response("400 Bad Request", &[], "{\"error\":\"bad\"}")]).await;
+    let client = client_for(&server.url)?;
+
+    let result = client.complete("Be brief.", "Hi!").await;
+    assert!(matches!(result, Err(Error::Api { status, body }) if status == 400 && body.contains("bad")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_invalid_response() -> Result<()> {
+    let server = mock_server(vec![http_response("200 OK", &[], "not json")]).await;
+    let client = client_for(&server.url)?;
+
+    let result = client.complete("Be brief.", "Hi!").await;
+    assert!(matches!(result, Err(Error::InvalidResponse(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_stream() -> Result<()> {
+    let event = |chunk: serde_json::Value| format!("data: {chunk}\n\n");
+    let usage = serde_json::json!({"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7});
+    let body = [
+        event(serde_json::json!({"id": "1", "choices": [{"index": 0, "delta": {"content": "Hel"}}]})),
+        event(serde_json::json!({"id": "1", "choices": [{"index": 0, "delta": {"content": "lo"}, "finish_reason": "stop"}]})),
+        event(serde_json::json!({"id": "1", "choices": [], "usage": usage})),
+        "data: [DONE]\n\n".to_owned(),
+    ]
+    .concat();
+    let server = mock_server(vec![http_response(
+        "200 OK",
+        &[("content-type", "text/event-stream")],
+        &body,
+    )])
+    .await;
+    let client = client_for(&server.url)?;
+
+    let mut printed = String::new();
+    let messages = client.messages("Be brief.", "Hi!");
+    let response = client
+        .stream(messages, |delta| {
+            printed.push_str(delta);
+            Ok(())
+        })
+        .await?;
+
+    assert_eq!(printed, "Hello");
+    assert_eq!(response.choices[0].message.content, "Hello");
+    assert_eq!(response.choices[0].finish_reason, "stop");
+    assert_eq!(response.usage.total_tokens, 7);
+
+    let requests = server.requests.lock().expect("lock requests");
+    let sent: serde_json::Value = serde_json::from_str(&requests[0])?;
+    assert_eq!(sent["stream"], true);
+    assert_eq!(sent["stream_options"]["include_usage"], true);
+
+    Ok(())
+}
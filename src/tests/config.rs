@@ -0,0 +1,130 @@
+use anyhow::Result;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+use crate::{
+    Error,
+    config::{Config, Profile},
+    registry::EndpointRegistry,
+};
+
+const CONFIG: &str = r#"
+default_profile = "review"
+
+[profiles.review]
+endpoint = "hf"
+model = "Qwen/Qwen3-Coder-480B-A35B-Instruct:novita"
+tokens = 20000
+schema = "schemas/review.json"
+
+[profiles.reasoning]
+endpoint = "openai"
+model = "o4-mini"
+reasoning = true
+schema = "/abs/schema.json"
+"#;
+
+#[test]
+fn test_parse_config() -> Result<()> {
+    let config = Config::parse(CONFIG, Path::new("/project"))?;
+
+    assert_eq!(config.default_profile.as_deref(), Some("review"));
+    assert_eq!(config.profiles.len(), 2);
+
+    let review = &config.profiles["review"];
+    assert_eq!(review.endpoint.as_deref(), Some("hf"));
+    assert_eq!(review.tokens, Some(20000));
+    assert_eq!(review.reasoning, None);
+    assert_eq!(review.schema, Some(PathBuf::from("/project/schemas/review.json")));
+    assert_eq!(
+        config.profiles["reasoning"].schema,
+        Some(PathBuf::from("/abs/schema.json"))
+    );
+
+    assert!(Config::parse("[profiles.review]\nmodle = \"typo\"\n", Path::new("")).is_err());
+    assert!(Config::parse("unknown = 1\n", Path::new("")).is_err());
+    assert_eq!(Config::parse("", Path::new(""))?, Config::default());
+
+    Ok(())
+}
+
+#[test]
+fn test_config_from_file_and_merge() -> Result<()> {
+    let mut file = NamedTempFile::new()?;
+    write!(file, "[profiles.review]\nmodel = \"local-model\"\nsystem_role = true\n")?;
+
+    let mut config = Config::parse(CONFIG, Path::new("/home/user/.config/invoke-llm"))?;
+    config.merge(Config::from_file(file.path())?);
+
+    assert_eq!(config.sources, vec![file.path().to_path_buf()]);
+    assert_eq!(config.default_profile.as_deref(), Some("review"));
+
+    let review = &config.profiles["review"];
+    assert_eq!(review.model.as_deref(), Some("local-model"));
+    assert_eq!(review.system_role, Some(true));
+    assert_eq!(review.endpoint.as_deref(), Some("hf"));
+    assert_eq!(review.tokens, Some(20000));
+
+    Ok(())
+}
+
+#[test]
+fn test_config_profile_selection() -> Result<()> {
+    let config = Config::parse(CONFIG, Path::new(""))?;
+
+    let (name, _) = config.profile(None)?.expect("default profile");
+    assert_eq!(name, "review");
+    let (name, profile) = config.profile(Some("reasoning"))?.expect("selected profile");
+    assert_eq!(name, "reasoning");
+    assert_eq!(profile.reasoning, Some(true));
+
+    let missing = config.profile(Some("missing"));
+    assert!(missing.is_err_and(|error| error.to_string().contains("available: reasoning, review")));
+
+    assert!(Config::default().profile(None)?.is_none());
+    assert!(Config::default().profile(Some("review")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_merge_and_builder() -> Result<()> {
+    let profile = Profile {
+        endpoint: Some("hf".to_owned()),
+        model: Some("profile-model".to_owned()),
+        tokens: Some(20000),
+        api_key_env: Some("INVOKE_LLM_TEST_UNSET_PROFILE_TOKEN".to_owned()),
+        ..Default::default()
+    };
+    let merged = profile.merge(Profile {
+        model: Some("cli-model".to_owned()),
+        reasoning: Some(true),
+        ..Default::default()
+    });
+
+    assert_eq!(merged.endpoint.as_deref(), Some("hf"));
+    assert_eq!(merged.model.as_deref(), Some("cli-model"));
+    assert_eq!(merged.tokens, Some(20000));
+    assert_eq!(merged.reasoning, Some(true));
+
+    let missing_key = merged.builder(EndpointRegistry::builtin())?.build();
+    assert!(matches!(missing_key, Err(Error::MissingApiKey(name)) if name == "INVOKE_LLM_TEST_UNSET_PROFILE_TOKEN"));
+
+    let client = merged
+        .builder(EndpointRegistry::builtin())?
+        .api_token("secret")
+        .build()?;
+    assert_eq!(client.model(), "cli-model");
+    assert_eq!(client.tokens(), 20000);
+
+    let missing_model = Profile::default()
+        .builder(EndpointRegistry::builtin())?
+        .endpoint("hf")
+        .tokens(1)
+        .api_token("t")
+        .build();
+    assert!(matches!(missing_model, Err(Error::Config(_))));
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+
+use super::server::{http_response, mock_server};
+use crate::{
+    InvokeClient,
+    envelope::{ENVELOPE_VERSION, Envelope},
+    schema::ApiResponse,
+    tools::{FunctionDefinition, Tool},
+};
+
+#[tokio::test]
+async fn test_envelope_from_response() -> Result<()> {
+    let body = json!({
+        "id": "chatcmpl-7",
+        "object": "chat.completion",
+        "created": 1_700_000_000,
+        "model": "test-model-2025",
+        "system_fingerprint": "fp_1",
+        "choices": [
+            {"index": 0, "message": {"role": "assistant", "content": "First"}, "finish_reason": "length"},
+            {"index": 1, "message": {"role": "assistant", "content": "Second"}, "finish_reason": "stop"}
+        ],
+        "usage": {"prompt_tokens": 12, "completion_tokens": 4, "total_tokens": 16}
+    });
+    let server = mock_server(vec![http_response("200 OK", &[], &body.to_string())]).await;
+    let client = InvokeClient::builder()
+        .endpoint(&server.url)
+        .api_token("secret")
+        .model("test-model")
+        .tokens(64)
+        .system_role(true)
+        .schema(json!({"name": "answer", "schema": {"type": "object"}}))
+        .tools(vec![Tool {
+            r#type: "function".to_owned(),
+            function: FunctionDefinition {
+                name: "lookup".to_owned(),
+                description: None,
+                parameters: json!({"type": "object"}),
+                strict: None,
+            },
+        }])
+        .build()?;
+
+    let response = client.complete("Prompt", "Input").await?;
+    let envelope = Envelope::new(response, &client, Duration::from_millis(1250), false);
+
+    assert_eq!(envelope.version, ENVELOPE_VERSION);
+    assert_eq!(envelope.id, "chatcmpl-7");
+    assert_eq!(envelope.model, "test-model-2025");
+    assert_eq!(envelope.content, "First");
+    assert_eq!(envelope.finish_reason.as_deref(), Some("length"));
+    assert_eq!(envelope.choices.len(), 2);
+    assert_eq!(envelope.usage.total_tokens, 16);
+    assert_eq!(envelope.system_fingerprint.as_deref(), Some("fp_1"));
+    assert_eq!(envelope.latency_ms, 1250);
+    assert_eq!(envelope.endpoint.name, server.url);
+    assert_eq!(envelope.endpoint.url, server.url);
+    assert_eq!(envelope.params.model, "test-model");
+    assert_eq!(envelope.params.tokens, 64);
+    assert!(envelope.params.system_role);
+    assert_eq!(envelope.params.schema.as_deref(), Some("answer"));
+    assert_eq!(envelope.params.tools, vec!["lookup"]);
+
+    // Every field is serialized, unknown ones as null
+    let value = serde_json::to_value(&envelope)?;
+    assert_eq!(value["service_tier"], serde_json::Value::Null);
+    assert_eq!(value["cost"], serde_json::Value::Null);
+    assert_eq!(value["choices"][1]["message"]["content"], "Second");
+    assert_eq!(value["params"]["stream"], false);
+    assert_eq!(serde_json::from_value::<Envelope>(value)?, envelope);
+
+    Ok(())
+}
+
+#[test]
+fn test_envelope_without_choices() -> Result<()> {
+    let client = InvokeClient::builder()
+        .endpoint("openai")
+        .api_token("secret")
+        .model("gpt-4.1-mini")
+        .tokens(10)
+        .build()?;
+
+    let envelope = Envelope::new(ApiResponse::default(), &client, Duration::ZERO, true);
+    assert_eq!(envelope.content, "");
+    assert_eq!(envelope.finish_reason, None);
+    assert_eq!(envelope.endpoint.name, "openai");
+    assert_eq!(envelope.cost.map(|cost| cost.total), Some(0.0));
+    assert_eq!(envelope.params.schema, None);
+    assert!(envelope.params.tools.is_empty());
+    assert!(envelope.params.stream);
+
+    Ok(())
+}
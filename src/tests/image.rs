@@ -0,0 +1,156 @@
+use anyhow::Result;
+use std::fs;
+use tempfile::TempDir;
+
+use crate::{
+    ContentPart, MessageContent, RequestMessage, RequestPayload,
+    anthropic::MessagesRequest,
+    image::{MAX_IMAGE_SIZE, data_uri, detect_mime, embed_images, image_part},
+};
+
+/// The first bytes of a PNG file.
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+#[test]
+fn test_detect_mime() {
+    assert_eq!(detect_mime(PNG), Some("image/png"));
+    assert_eq!(detect_mime(b"\xff\xd8\xff\xe0\0\x10JFIF"), Some("image/jpeg"));
+    assert_eq!(detect_mime(b"GIF89a\x01\0"), Some("image/gif"));
+    assert_eq!(detect_mime(b"RIFF\x24\0\0\0WEBPVP8 "), Some("image/webp"));
+    assert_eq!(detect_mime(b"RIFF\x24\0\0\0WAVEfmt "), None);
+    assert_eq!(detect_mime(b"<svg xmlns="), None);
+    assert_eq!(detect_mime(b""), None);
+}
+
+#[test]
+fn test_data_uri_limits() -> Result<()> {
+    let dir = TempDir::new()?;
+    let png = dir.path().join("shot.png");
+    fs::write(&png, PNG)?;
+    assert_eq!(data_uri(&png)?, "data:image/png;base64,iVBORw0KGgoAAAANSUhEUg==");
+
+    let text = dir.path().join("notes.png");
+    fs::write(&text, "not an image")?;
+    assert!(data_uri(&text).is_err_and(|error| error.to_string().contains("Unsupported image format")));
+
+    let large = dir.path().join("large.png");
+    fs::File::create(&large)?.set_len(MAX_IMAGE_SIZE + 1)?;
+    assert!(data_uri(&large).is_err_and(|error| error.to_string().contains("too large")));
+
+    assert!(data_uri(&dir.path().join("missing.png")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_embed_images() -> Result<()> {
+    let dir = TempDir::new()?;
+    fs::write(dir.path().join("before.png"), PNG)?;
+    fs::write(dir.path().join("after.png"), PNG)?;
+    let uri = data_uri(&dir.path().join("before.png"))?;
+
+    // Inputs without local references are kept as text
+    let text = "Compare ![logo](https://example.com/logo.png) with the old one.".to_owned();
+    assert_eq!(
+        embed_images(text.clone(), dir.path(), &[])?,
+        MessageContent::Text(text.clone())
+    );
+
+    let content = embed_images(
+        "What changed between\n![before](before.png)\nand ![after](after.png \"Now\")?".to_owned(),
+        dir.path(),
+        &["https://example.com/chart.png".to_owned()],
+    )?;
+    assert_eq!(
+        content,
+        MessageContent::Parts(vec![
+            ContentPart::text("What changed between"),
+            ContentPart::image_url(&uri),
+            ContentPart::text("and"),
+            ContentPart::image_url(&uri),
+            ContentPart::text("?"),
+            ContentPart::image_url("https://example.com/chart.png"),
+        ])
+    );
+    assert_eq!(content.as_text(), "What changed between\nand\n?");
+    assert_eq!(
+        content.to_string(),
+        "What changed between\n[image]\nand\n[image]\n?\n[image]"
+    );
+
+    // URL references stay in the text when images are appended
+    let with_flag = embed_images(text.clone(), dir.path(), std::slice::from_ref(&uri))?;
+    assert_eq!(
+        with_flag,
+        MessageContent::Parts(vec![ContentPart::text(&text), ContentPart::image_url(&uri)])
+    );
+
+    // References that cannot be embedded are left in the text
+    fs::write(dir.path().join("diagram.svg"), "<svg/>")?;
+    let text = "See ![](missing.png) and ![](diagram.svg).".to_owned();
+    assert_eq!(
+        embed_images(text.clone(), dir.path(), &[])?,
+        MessageContent::Text(text.clone())
+    );
+    assert_eq!(
+        embed_images(text.clone(), dir.path(), std::slice::from_ref(&uri))?,
+        MessageContent::Parts(vec![ContentPart::text(&text), ContentPart::image_url(&uri)])
+    );
+    assert!(embed_images(text, dir.path(), &["missing.png".to_owned()]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_image_content_serialization() -> Result<()> {
+    let message = RequestMessage::new("user", vec![
+        ContentPart::text("Describe this."),
+        image_part("https://example.com/a.png")?,
+    ]);
+    let value = serde_json::to_value(&message)?;
+    assert_eq!(
+        value,
+        serde_json::json!({
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "Describe this."},
+                {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}
+            ]
+        })
+    );
+    assert_eq!(serde_json::from_value::<RequestMessage>(value)?, message);
+
+    // Plain text content keeps its string form
+    assert_eq!(
+        serde_json::to_value(RequestMessage::new("user", "Hi!"))?,
+        serde_json::json!({"role": "user", "content": "Hi!"})
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_anthropic_image_blocks() -> Result<()> {
+    let payload = RequestPayload {
+        messages: vec![RequestMessage::new("user", vec![
+            ContentPart::text("Describe these."),
+            ContentPart::image_url("data:image/png;base64,iVBORw0KGgo="),
+            ContentPart::image_url("https://example.com/a.png"),
+        ])],
+        model: "claude-sonnet-4-5",
+        max_tokens: Some(100),
+        ..Default::default()
+    };
+
+    let request = MessagesRequest::from_payload(&payload)?;
+    assert_eq!(
+        request.messages[0].content,
+        serde_json::json!([
+            {"type": "text", "text": "Describe these."},
+            {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}},
+            {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}}
+        ])
+    );
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::{
+    config::Config,
+    limits::{BUILTIN_LIMITS, ContextOverflow, LimitsTable, ModelLimits},
+};
+
+#[test]
+fn test_model_limits_check() {
+    let limits = ModelLimits {
+        context_window: 1000,
+        max_output: Some(100),
+    };
+    assert!(limits.check("local", 900, 100).is_ok());
+
+    let overflow = limits.check("local", 901, 100).unwrap_err();
+    assert_eq!(overflow, ContextOverflow {
+        model: "local".to_owned(),
+        prompt_tokens: 901,
+        max_tokens: 100,
+        context_window: 1000,
+    });
+    assert_eq!(
+        overflow.to_string(),
+        "The request needs 1001 tokens (901 in the prompt and 100 to generate), more than the context window of \
+         'local' (1000 tokens)"
+    );
+}
+
+#[test]
+fn test_limits_table_get() {
+    let table = LimitsTable::builtin();
+    assert_eq!(table.iter().count(), BUILTIN_LIMITS.len());
+
+    let gpt_4o = table.get("gpt-4o").copied();
+    assert_eq!(gpt_4o.map(|limits| limits.context_window), Some(128_000));
+    // Dated models, organization prefixes and provider suffixes fall back
+    assert_eq!(table.get("gpt-4o-2024-08-06").copied(), gpt_4o);
+    assert!(table.get("openai/gpt-oss-120b:cerebras").is_some());
+    assert!(table.get("claude-sonnet-4-5-20250929").is_some());
+    assert!(table.get("local-model").is_none());
+}
+
+#[test]
+fn test_config_limits() -> Result<()> {
+    let config = Config::parse(
+        r#"
+[limits]
+"qwen3-coder" = { context_window = 262144, max_output = 65536 }
+"gpt-4o" = { context_window = 64000 }
+"#,
+        Path::new(""),
+    )?;
+    let table = config.limits();
+
+    assert_eq!(
+        table.get("qwen3-coder").copied(),
+        Some(ModelLimits {
+            context_window: 262_144,
+            max_output: Some(65_536),
+        })
+    );
+    assert_eq!(
+        table.get("gpt-4o").copied(),
+        Some(ModelLimits {
+            context_window: 64_000,
+            max_output: None,
+        })
+    );
+    assert!(table.get("gpt-4.1").is_some());
+
+    assert!(Config::parse("[limits.local]\ncontext = 1000\n", Path::new("")).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, duplex};
+
+use crate::{
+    RequestMessage,
+    config::Config,
+    mcp::{McpClient, McpServer, McpServerConfig},
+    schema::{FunctionCall, ToolCall},
+    tools::ToolSet,
+};
+
+/// Answers the messages of a client like a stdio MCP server with two tools
+/// split over two `tools/list` pages: `echo` returns its arguments and `fail`
+/// reports an error. A notification and a ping precede every response.
+async fn stub_server(stream: DuplexStream) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let message: Value = serde_json::from_str(&line)?;
+        let Some(id) = message.get("id").cloned() else {
+            continue; // notifications/initialized
+        };
+        if message.get("method").is_none() {
+            continue; // answer to the ping
+        }
+
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({"protocolVersion": "2025-06-18", "serverInfo": {"name": "stub"}}),
+            "tools/list" if message["params"]["cursor"] == "2" => {
+                json!({"tools": [{"name": "fail", "inputSchema": {"type": "object"}}]})
+            },
+            "tools/list" => {
+                json!({
+                    "tools": [{"name": "echo", "description": "Echo", "inputSchema": {"type": "object"}}],
+                    "nextCursor": "2"
+                })
+            },
+            "tools/call" if message["params"]["name"] == "fail" => {
+                json!({"content": [{"type": "text", "text": "no such file"}], "isError": true})
+            },
+            "tools/call" => {
+                json!({"content": [
+                    {"type": "text", "text": message["params"]["arguments"].to_string()},
+                    {"type": "text", "text": "done"}
+                ]})
+            },
+            method => {
+                let error = json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32601, "message": method}});
+                writer.write_all(format!("{error}\n").as_bytes()).await?;
+                continue;
+            },
+        };
+
+        let preamble = [
+            json!({"jsonrpc": "2.0", "method": "notifications/message", "params": {"data": "working"}}),
+            json!({"jsonrpc": "2.0", "id": "s1", "method": "ping"}),
+        ];
+        for message in preamble {
+            writer.write_all(format!("{message}\n").as_bytes()).await?;
+        }
+        let response = json!({"jsonrpc": "2.0", "id": id, "result": result});
+        writer.write_all(format!("{response}\n").as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+/// Connects a client to a stub server.
+fn stub_client() -> McpClient<tokio::io::ReadHalf<DuplexStream>, tokio::io::WriteHalf<DuplexStream>> {
+    let (client, server) = duplex(4096);
+    tokio::spawn(stub_server(server));
+    let (reader, writer) = tokio::io::split(client);
+
+    McpClient::new("stub", reader, writer)
+}
+
+/// Connects an initialized server handle to a stub server.
+async fn stub_handle() -> Result<McpServer> {
+    let (client, server) = duplex(4096);
+    tokio::spawn(stub_server(server));
+    let (reader, writer) = tokio::io::split(client);
+
+    McpServer::connect("stub", reader, writer, Duration::from_secs(5)).await
+}
+
+#[tokio::test]
+async fn test_mcp_client_requests() -> Result<()> {
+    let mut client = stub_client();
+
+    let result = client.initialize().await?;
+    assert_eq!(result["serverInfo"]["name"], "stub");
+
+    let tools = client.list_tools().await?;
+    let names: Vec<_> = tools.iter().map(|tool| tool.name.as_str()).collect();
+    assert_eq!(names, vec!["echo", "fail"]);
+    assert_eq!(
+        serde_json::to_value(tools[0].definition())?,
+        json!({"type": "function", "function": {"name": "echo", "description": "Echo", "parameters": {"type": "object"}}})
+    );
+
+    let content = client.call_tool("echo", json!({"path": "a.txt"})).await?;
+    assert_eq!(content, "{\"path\":\"a.txt\"}\ndone");
+
+    let failed = client.call_tool("fail", json!({})).await;
+    assert!(failed.is_err_and(|error| error.to_string() == "no such file"));
+
+    let unknown = client.request("resources/list", json!({})).await;
+    assert!(unknown.is_err_and(|error| error.to_string().contains("code -32601")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mcp_client_resumes_after_timeout() -> Result<()> {
+    let (client, server) = duplex(4096);
+    let (reader, writer) = tokio::io::split(client);
+    let mut client = McpClient::new("slow", reader, writer);
+    let (server_reader, mut server_writer) = tokio::io::split(server);
+    let mut requests = BufReader::new(server_reader).lines();
+
+    // The request times out while its response is half written
+    let late = format!("{}\n", json!({"jsonrpc": "2.0", "id": 1, "result": {"tools": []}}));
+    server_writer.write_all(&late.as_bytes()[..10]).await?;
+    let timed_out = tokio::time::timeout(Duration::from_millis(50), client.request("tools/list", json!({}))).await;
+    assert!(timed_out.is_err());
+    assert!(
+        requests
+            .next_line()
+            .await?
+            .is_some_and(|line| line.contains("tools/list"))
+    );
+
+    // The next request skips the rest of the late response
+    server_writer.write_all(&late.as_bytes()[10..]).await?;
+    let response = json!({"jsonrpc": "2.0", "id": 2, "result": {"content": []}});
+    server_writer.write_all(format!("{response}\n").as_bytes()).await?;
+    assert_eq!(client.request("tools/call", json!({})).await?, json!({"content": []}));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mcp_client_closed_connection() {
+    let (client, server) = duplex(64);
+    drop(server);
+    let (reader, writer) = tokio::io::split(client);
+
+    let result = McpClient::new("gone", reader, writer).initialize().await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_tool_set_routes_mcp_calls() -> Result<()> {
+    let mut tools = ToolSet::default();
+    assert_eq!(tools.add_mcp_server(stub_handle().await?).await?, 2);
+    assert!(tools.contains("echo"));
+    assert!(tools.get("echo").is_none());
+    assert_eq!(tools.definitions().len(), 2);
+
+    let call = |id: &str, name: &str| {
+        ToolCall {
+            id: id.to_owned(),
+            r#type: "function".to_owned(),
+            function: FunctionCall {
+                name: name.to_owned(),
+                arguments: "{\"q\":1}".to_owned(),
+            },
+        }
+    };
+    assert_eq!(
+        tools.call(&call("call_1", "echo")).await,
+        RequestMessage::tool_result("call_1", "{\"q\":1}\ndone")
+    );
+    assert_eq!(tools.call(&call("call_2", "fail")).await.content, "Error: no such file");
+
+    // A second server advertising the same names is rejected
+    let duplicate = tools.add_mcp_server(stub_handle().await?).await;
+    assert!(duplicate.is_err_and(|error| error.to_string().contains("already declared")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mcp_server_spawn_failure() {
+    let config = McpServerConfig {
+        command: vec!["invoke-llm-missing-mcp-server".to_owned()],
+        ..Default::default()
+    };
+    assert!(McpServer::spawn("missing", &config).await.is_err());
+    assert!(McpServer::spawn("empty", &McpServerConfig::default()).await.is_err());
+}
+
+#[test]
+fn test_config_mcp_servers() -> Result<()> {
+    let config = Config::parse(
+        r#"
+[mcp_servers.files]
+command = ["npx", "-y", "@modelcontextprotocol/server-filesystem", "."]
+env = { LOG_LEVEL = "error" }
+timeout = 30
+"#,
+        Path::new(""),
+    )?;
+
+    let server = config.mcp_server("files")?;
+    assert_eq!(server.command[0], "npx");
+    assert_eq!(server.env["LOG_LEVEL"], "error");
+    assert_eq!(server.timeout, Some(30));
+    assert!(
+        config
+            .mcp_server("git")
+            .is_err_and(|error| error.to_string().contains("available: files"))
+    );
+
+    assert!(Config::parse("[mcp_servers.files]\nargs = []\n", Path::new("")).is_err());
+
+    // The project-local file may add servers, but not replace the user's ones
+    let mut merged = config.clone();
+    merged.merge_local(Config::parse(
+        "[mcp_servers.git]\ncommand = [\"mcp-git\"]\n",
+        Path::new(""),
+    )?)?;
+    assert_eq!(merged.mcp_server("git")?.command, ["mcp-git"]);
+    let replaced = Config::parse(
+        "[mcp_servers.files]\ncommand = [\"sh\", \"-c\", \"true\"]\n",
+        Path::new(""),
+    )?;
+    assert!(merged.merge_local(replaced).is_err());
+    assert_eq!(merged.mcp_server("files")?.command[0], "npx");
+
+    Ok(())
+}
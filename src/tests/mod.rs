@@ -1,5 +1,32 @@
+mod anthropic;
+mod audio;
+mod batch;
+mod budget;
+mod cache;
+mod chat;
+mod chunk;
+mod client;
+mod config;
+mod envelope;
+mod image;
+mod limits;
+mod mcp;
+mod openai_batch;
+mod params;
+mod pricing;
+mod provider;
+mod registry;
+mod retry;
+mod schema_lint;
+mod server;
+mod session;
+mod stream;
+mod template;
+mod tokenizer;
+mod tools;
+mod validation;
+
 use anyhow::Result;
-use serde_json;
 use std::{io::Write, path::Path};
 use tempfile::NamedTempFile;
 
@@ -136,7 +163,8 @@ fn test_env_api_key_with_custom_endpoint() {
 fn test_request_message_serialization() -> Result<()> {
     let message = RequestMessage {
         role: "assistant".to_owned(),
-        content: "Hello, world!".to_owned(),
+        content: "Hello, world!".into(),
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&message)?;
@@ -149,12 +177,14 @@ fn test_request_message_serialization() -> Result<()> {
 fn test_request_payload_serialization() -> Result<()> {
     let mut messages = vec![RequestMessage {
         role: "assistant".to_owned(),
-        content: "Hello, world!".to_owned(),
+        content: "Hello, world!".into(),
+        ..Default::default()
     }];
 
     messages.push(RequestMessage {
         role: "user".to_owned(),
-        content: "Hi!".to_owned(),
+        content: "Hi!".into(),
+        ..Default::default()
     });
 
     let payload = RequestPayload {
@@ -163,6 +193,7 @@ fn test_request_payload_serialization() -> Result<()> {
         max_tokens: Some(100),
         max_completion_tokens: None,
         response_format: None,
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&payload)?;
@@ -199,12 +230,14 @@ fn test_api_response_deserialization() -> Result<()> {
 fn test_request_payload_serialization_with_different_token_settings() -> Result<()> {
     let mut messages = vec![RequestMessage {
         role: "assistant".to_owned(),
-        content: "Hello, world!".to_owned(),
+        content: "Hello, world!".into(),
+        ..Default::default()
     }];
 
     messages.push(RequestMessage {
         role: "user".to_owned(),
-        content: "Hi!".to_owned(),
+        content: "Hi!".into(),
+        ..Default::default()
     });
 
     // Test with max_tokens
@@ -214,6 +247,7 @@ fn test_request_payload_serialization_with_different_token_settings() -> Result<
         max_tokens: Some(100),
         max_completion_tokens: None,
         response_format: None,
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&payload)?;
@@ -230,6 +264,7 @@ fn test_request_payload_serialization_with_different_token_settings() -> Result<
         max_tokens: None,
         max_completion_tokens: Some(100),
         response_format: None,
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&payload)?;
@@ -246,6 +281,7 @@ fn test_request_payload_serialization_with_different_token_settings() -> Result<
         max_tokens: Some(100),
         max_completion_tokens: Some(100),
         response_format: None,
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&payload)?;
@@ -267,6 +303,7 @@ fn test_request_payload_serialization_with_empty_messages() -> Result<()> {
         max_tokens: Some(100),
         max_completion_tokens: None,
         response_format: None,
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&payload)?;
@@ -281,7 +318,8 @@ fn test_request_payload_serialization_with_multiple_messages() -> Result<()> {
     for i in 0..10 {
         messages.push(RequestMessage {
             role: "assistant".to_owned(),
-            content: format!("Message {}", i),
+            content: format!("Message {}", i).into(),
+            ..Default::default()
         });
     }
 
@@ -291,6 +329,7 @@ fn test_request_payload_serialization_with_multiple_messages() -> Result<()> {
         max_tokens: Some(100),
         max_completion_tokens: None,
         response_format: None,
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&payload)?;
@@ -388,12 +427,14 @@ fn test_response_format_serialization() -> Result<()> {
 fn test_request_payload_with_response_format() -> Result<()> {
     let mut messages = vec![RequestMessage {
         role: "assistant".to_owned(),
-        content: "Hello, world!".to_owned(),
+        content: "Hello, world!".into(),
+        ..Default::default()
     }];
 
     messages.push(RequestMessage {
         role: "user".to_owned(),
-        content: "Hi!".to_owned(),
+        content: "Hi!".into(),
+        ..Default::default()
     });
 
     let schema = serde_json::json!({
@@ -420,6 +461,7 @@ fn test_request_payload_with_response_format() -> Result<()> {
         max_tokens: Some(100),
         max_completion_tokens: None,
         response_format,
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&payload)?;
@@ -436,12 +478,14 @@ fn test_request_payload_with_response_format() -> Result<()> {
 fn test_request_payload_without_response_format() -> Result<()> {
     let mut messages = vec![RequestMessage {
         role: "assistant".to_owned(),
-        content: "Hello, world!".to_owned(),
+        content: "Hello, world!".into(),
+        ..Default::default()
     }];
 
     messages.push(RequestMessage {
         role: "user".to_owned(),
-        content: "Hi!".to_owned(),
+        content: "Hi!".into(),
+        ..Default::default()
     });
 
     let payload = RequestPayload {
@@ -450,6 +494,7 @@ fn test_request_payload_without_response_format() -> Result<()> {
         max_tokens: Some(100),
         max_completion_tokens: None,
         response_format: None,
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&payload)?;
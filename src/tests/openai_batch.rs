@@ -0,0 +1,116 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    RequestMessage, RequestPayload, ResponseFormat, TokenLimitField,
+    openai_batch::{
+        BATCH_URL, BatchInputLine, custom_id_for, custom_ids_for, file_name_for, file_names_for, parse_batch_output,
+    },
+};
+
+#[test]
+fn test_batch_input_line() -> Result<()> {
+    let mut payload = RequestPayload {
+        messages: RequestMessage::prompt_and_input("Be brief.", "Hi!", true),
+        model: "gpt-4.1-mini",
+        response_format: Some(ResponseFormat::json_schema(serde_json::json!({"name": "answer"}))),
+        ..Default::default()
+    };
+    payload.set_token_limit(TokenLimitField::MaxCompletionTokens, 200);
+
+    let line = BatchInputLine::new("review", &payload)?;
+    let json = serde_json::to_value(&line)?;
+
+    assert_eq!(json["custom_id"], "review");
+    assert_eq!(json["method"], "POST");
+    assert_eq!(json["url"], BATCH_URL);
+    assert_eq!(json["body"]["model"], "gpt-4.1-mini");
+    assert_eq!(json["body"]["max_completion_tokens"], 200);
+    assert_eq!(json["body"]["messages"][0]["role"], "system");
+    assert_eq!(json["body"]["messages"][1]["content"], "Hi!");
+    assert_eq!(json["body"]["response_format"]["json_schema"]["name"], "answer");
+    assert!(json["body"].get("stream").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_batch_output() -> Result<()> {
+    let success = serde_json::json!({
+        "id": "batch_req_1",
+        "custom_id": "first",
+        "response": {
+            "status_code": 200,
+            "request_id": "req_1",
+            "body": {
+                "id": "chatcmpl-1",
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4.1-mini",
+                "choices": [{"index": 0, "message": {"role": "assistant", "content": "Done"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4}
+            }
+        },
+        "error": null
+    });
+    let status_error = serde_json::json!({
+        "id": "batch_req_2",
+        "custom_id": "second",
+        "response": {"status_code": 400, "request_id": "req_2", "body": {"error": {"message": "bad"}}},
+        "error": null
+    });
+    let request_error = serde_json::json!({
+        "id": "batch_req_3",
+        "custom_id": "third",
+        "response": null,
+        "error": {"code": "batch_expired", "message": "expired"}
+    });
+    let content = format!("{success}\n\n{status_error}\n{request_error}\n");
+
+    let mut lines = parse_batch_output(&content)?.into_iter();
+    assert_eq!(lines.len(), 3);
+
+    let first = lines.next().expect("first line");
+    assert_eq!(first.custom_id, "first");
+    let response = first.into_api_response()?;
+    assert_eq!(response.choices[0].message.content, "Done");
+    assert_eq!(response.usage.total_tokens, 4);
+
+    let second = lines.next().expect("second line").into_api_response();
+    assert!(second.is_err_and(|error| error.to_string().contains("400")));
+
+    let third = lines.next().expect("third line").into_api_response();
+    assert!(third.is_err_and(|error| error.to_string().contains("batch_expired")));
+
+    assert!(parse_batch_output("not json").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_id_and_file_name() {
+    assert_eq!(custom_id_for(Path::new("inputs/review.md")), "review");
+    assert_eq!(custom_id_for(Path::new("inputs/notes")), "notes");
+
+    assert_eq!(file_name_for("review-1_a.b"), "review-1_a.b");
+    assert_eq!(file_name_for("../etc/passwd"), ".._etc_passwd");
+    assert_eq!(file_name_for(".."), "_..");
+    assert_eq!(file_name_for(""), "_");
+}
+
+#[test]
+fn test_unique_custom_ids_and_file_names() -> Result<()> {
+    let paths = [PathBuf::from("inputs/review.md"), PathBuf::from("inputs/notes.md")];
+    assert_eq!(custom_ids_for(&paths)?, ["review", "notes"]);
+
+    // Files differing only by their extension would share a custom id
+    let paths = [PathBuf::from("inputs/review.md"), PathBuf::from("inputs/review.txt")];
+    let error = custom_ids_for(&paths).unwrap_err().to_string();
+    assert!(error.contains("inputs/review.md and inputs/review.txt"), "{error}");
+
+    assert_eq!(file_names_for(["review", "a/b"])?, ["review", "a_b"]);
+    let error = file_names_for(["a/b", "a_b"]).unwrap_err().to_string();
+    assert!(error.contains("'a/b' and 'a_b'"), "{error}");
+
+    Ok(())
+}
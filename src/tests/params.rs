@@ -0,0 +1,102 @@
+use anyhow::Result;
+use serde_json::json;
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+use crate::{
+    RequestMessage, RequestPayload,
+    params::{SamplingParams, merge_json, parse_logit_bias, parse_set, read_extra_body},
+};
+
+#[test]
+fn test_sampling_params_serialization() -> Result<()> {
+    let payload = RequestPayload {
+        messages: vec![RequestMessage::new("user", "Hi!")],
+        model: "test_model",
+        sampling: SamplingParams {
+            temperature: Some(0.7),
+            stop: Some(vec!["\n\n".to_owned()]),
+            logit_bias: Some([("50256".to_owned(), -100)].into_iter().collect()),
+            reasoning_effort: Some("low".to_owned()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let json = serde_json::to_value(&payload)?;
+    assert_eq!(json["temperature"], 0.7);
+    assert_eq!(json["stop"], json!(["\n\n"]));
+    assert_eq!(json["logit_bias"]["50256"], -100);
+    assert_eq!(json["reasoning_effort"], "low");
+    assert!(json.get("top_p").is_none());
+    assert!(json.get("n").is_none());
+
+    assert!(SamplingParams::default().is_empty());
+    assert!(!payload.sampling.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_json() {
+    let mut body = json!({"model": "m", "max_tokens": 10, "extra_body": {"google": {"a": 1}}});
+    merge_json(
+        &mut body,
+        &json!({"max_tokens": null, "extra_body": {"google": {"b": 2}}, "stop": ["x"]}),
+    );
+
+    assert_eq!(
+        body,
+        json!({"model": "m", "extra_body": {"google": {"a": 1, "b": 2}}, "stop": ["x"]})
+    );
+
+    let mut scalar = json!(1);
+    merge_json(&mut scalar, &json!({"a": true}));
+    assert_eq!(scalar, json!({"a": true}));
+}
+
+#[test]
+fn test_parse_set() {
+    assert_eq!(parse_set("top_k=40"), Ok(json!({"top_k": 40})));
+    assert_eq!(parse_set("user=alice"), Ok(json!({"user": "alice"})));
+    assert_eq!(
+        parse_set("provider.order=[\"novita\"]"),
+        Ok(json!({"provider": {"order": ["novita"]}}))
+    );
+    assert_eq!(parse_set("max_tokens=null"), Ok(json!({"max_tokens": null})));
+    assert_eq!(parse_set("note=a=b"), Ok(json!({"note": "a=b"})));
+
+    assert!(parse_set("no-assignment").is_err());
+    assert!(parse_set("a..b=1").is_err());
+    assert!(parse_set("=1").is_err());
+}
+
+#[test]
+fn test_parse_logit_bias() {
+    assert_eq!(parse_logit_bias("50256=-100"), Ok(("50256".to_owned(), -100)));
+    assert_eq!(parse_logit_bias(" 15 = 5 "), Ok(("15".to_owned(), 5)));
+
+    assert!(parse_logit_bias("50256").is_err());
+    assert!(parse_logit_bias("token=1").is_err());
+    assert!(parse_logit_bias("1=101").is_err());
+}
+
+#[test]
+fn test_read_extra_body() -> Result<()> {
+    let mut file = NamedTempFile::new()?;
+    write!(
+        file,
+        "{{\"extra_body\": {{\"google\": {{\"thinking_config\": {{\"thinking_budget\": 0}}}}}}}}"
+    )?;
+    let extra_body = read_extra_body(file.path())?;
+    assert_eq!(
+        extra_body["extra_body"]["google"]["thinking_config"]["thinking_budget"],
+        0
+    );
+
+    let mut array = NamedTempFile::new()?;
+    write!(array, "[1, 2]")?;
+    assert!(read_extra_body(array.path()).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use std::path::Path;
+
+use super::server::{http_response, mock_server};
+use crate::{
+    InvokeClient,
+    config::Config,
+    pricing::{BUILTIN_PRICES, Cost, ModelPrice, PricingTable},
+    schema::{CompletionTokensDetails, PromptTokensDetails, Usage},
+};
+
+const CONFIG: &str = r#"
+[pricing.openai]
+"gpt-4.1-mini" = { input = 1.0, output = 2.0 }
+
+[pricing.vllm]
+"qwen3-coder" = { input = 0.5, cached_input = 0.05, output = 1.5, reasoning = 3.0 }
+"#;
+
+fn usage(prompt: i64, cached: i64, completion: i64, reasoning: i64) -> Usage {
+    Usage {
+        prompt_tokens: prompt,
+        completion_tokens: completion,
+        total_tokens: prompt + completion,
+        prompt_tokens_details: Some(PromptTokensDetails {
+            cached_tokens: Some(cached),
+            audio_tokens: None,
+        }),
+        completion_tokens_details: Some(CompletionTokensDetails {
+            reasoning_tokens: Some(reasoning),
+            ..Default::default()
+        }),
+    }
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-12, "{actual} != {expected}");
+}
+
+#[test]
+fn test_model_price_cost() {
+    let price = ModelPrice {
+        input: 2.0,
+        cached_input: Some(0.5),
+        output: 8.0,
+        reasoning: Some(10.0),
+    };
+
+    let cost = price.cost(&usage(1_000_000, 200_000, 500_000, 100_000));
+    assert_close(cost.input, 1.6);
+    assert_close(cost.cached_input, 0.1);
+    assert_close(cost.output, 3.2);
+    assert_close(cost.reasoning, 1.0);
+    assert_close(cost.total, 5.9);
+
+    // Without details, every token is priced as input or output
+    let plain = ModelPrice {
+        cached_input: None,
+        reasoning: None,
+        ..price
+    };
+    let cost = plain.cost(&Usage {
+        prompt_tokens: 500_000,
+        completion_tokens: 250_000,
+        total_tokens: 750_000,
+        ..Default::default()
+    });
+    assert_close(cost.total, 3.0);
+    assert_eq!(cost.to_string(), "$3.000000");
+}
+
+#[test]
+fn test_cost_add() {
+    let mut total = Cost::default();
+    total.add(&Cost {
+        input: 1.0,
+        output: 2.0,
+        total: 3.0,
+        ..Default::default()
+    });
+    total.add(&Cost {
+        cached_input: 0.5,
+        reasoning: 0.25,
+        total: 0.75,
+        ..Default::default()
+    });
+
+    assert_eq!(total, Cost {
+        input: 1.0,
+        cached_input: 0.5,
+        output: 2.0,
+        reasoning: 0.25,
+        total: 3.75,
+    });
+}
+
+#[test]
+fn test_builtin_pricing() {
+    let table = PricingTable::builtin();
+    assert_eq!(table.iter().count(), BUILTIN_PRICES.len());
+
+    let mini = table.get("openai", "gpt-4.1-mini").copied();
+    assert_eq!(mini.map(|price| price.output), Some(1.6));
+    assert_eq!(table.get("openai", "gpt-4.1-mini-2025-04-14").copied(), mini);
+    assert!(table.get("anthropic", "claude-sonnet-4-5-20250929").is_some());
+
+    assert!(table.get("openai", "gpt-4.1-mini-high").is_none());
+    assert!(table.get("hf", "gpt-4.1-mini").is_none());
+    assert!(table.cost("openai", "unknown-model", &Usage::default()).is_none());
+}
+
+#[test]
+fn test_user_pricing() -> Result<()> {
+    let config = Config::parse(CONFIG, Path::new(""))?;
+    let table = config.pricing();
+
+    assert_eq!(table.iter().count(), BUILTIN_PRICES.len() + 1);
+    assert_eq!(table.get("openai", "gpt-4.1-mini").map(|price| price.input), Some(1.0));
+    assert_eq!(
+        table.get("openai", "gpt-4.1-mini").and_then(|price| price.cached_input),
+        None
+    );
+
+    let cost = table.cost("vllm", "qwen3-coder", &usage(2_000_000, 1_000_000, 1_000_000, 500_000));
+    assert_eq!(cost.map(|cost| cost.total), Some(0.5 + 0.05 + 0.75 + 1.5));
+
+    let mut merged = Config::parse(CONFIG, Path::new(""))?;
+    merged.merge(Config::parse(
+        "[pricing.openai]\n\"gpt-4.1\" = { input = 3.0, output = 9.0 }\n",
+        Path::new(""),
+    )?);
+    assert_eq!(merged.pricing["openai"].len(), 2);
+
+    assert!(Config::parse("[pricing.openai.m]\ninput = 1.0\n", Path::new("")).is_err());
+    assert!(
+        Config::parse(
+            "[pricing.openai.m]\ninput = 1.0\noutput = 1.0\ncache = 1.0\n",
+            Path::new("")
+        )
+        .is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_local_pricing() -> Result<()> {
+    let mut config = Config::parse(CONFIG, Path::new(""))?;
+    config.merge_local(Config::parse(
+        r#"
+[pricing.openai]
+"gpt-4.1-mini" = { input = 0.0, output = 0.0 }
+"gpt-4.1" = { input = 3.0, cached_input = 0.5, output = 9.0 }
+"gpt-5" = { input = 2.0, cached_input = 0.0, output = 20.0 }
+
+[pricing.vllm]
+"qwen3-coder" = { input = 1.0, cached_input = 0.1, output = 2.0, reasoning = 4.0 }
+"qwen3-small" = { input = 0.0, output = 0.0 }
+"#,
+        Path::new(""),
+    )?)?;
+    let table = config.pricing();
+
+    // Prices lower than the user's or the built-in ones are ignored
+    assert_eq!(table.get("openai", "gpt-4.1-mini").map(|price| price.input), Some(1.0));
+    assert_eq!(table.get("openai", "gpt-5").map(|price| price.input), Some(1.25));
+
+    // Higher prices and prices of new models are taken
+    assert_eq!(table.get("openai", "gpt-4.1").map(|price| price.output), Some(9.0));
+    assert_eq!(table.get("vllm", "qwen3-coder").map(|price| price.input), Some(1.0));
+    assert_eq!(table.get("vllm", "qwen3-small").map(|price| price.output), Some(0.0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_cost() -> Result<()> {
+    let body = serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "local-model-2025-01-31",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 1000, "completion_tokens": 1000, "total_tokens": 2000}
+    });
+    let server = mock_server(vec![http_response("200 OK", &[], &body.to_string())]).await;
+
+    let mut pricing = PricingTable::builtin();
+    pricing.insert(&server.url, "local-model", ModelPrice {
+        input: 1.0,
+        cached_input: None,
+        output: 4.0,
+        reasoning: None,
+    });
+    let client = InvokeClient::builder()
+        .endpoint(&server.url)
+        .api_token("secret")
+        .model("local")
+        .tokens(10)
+        .pricing(pricing)
+        .build()?;
+
+    let response = client.complete("Prompt", "Input").await?;
+    assert!(client.cost("local", &response.usage).is_none());
+
+    // The requested model has no price, so the reported one is priced
+    let cost = client.response_cost(&response).map(|cost| cost.total);
+    assert_eq!(cost, Some(0.001 + 0.004));
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use anyhow::Result;
+
+use crate::{
+    AuthScheme, OpenAiCompatible, Provider, RequestMessage, RequestPayload, TokenLimitField, resolve_provider,
+};
+
+#[test]
+fn test_resolve_provider_builtin_endpoints() {
+    let openai = resolve_provider("openai");
+    assert_eq!(openai.name(), "openai");
+    assert_eq!(openai.url(), "https://api.openai.com/v1/chat/completions");
+    assert_eq!(openai.api_key_env(), "API_TOKEN_OAI");
+
+    let google = resolve_provider("google");
+    assert_eq!(
+        google.url(),
+        "https://generativelanguage.googleapis.com/v1beta/chat/completions"
+    );
+    assert_eq!(google.api_key_env(), "API_TOKEN_GOOGLE");
+
+    let hf = resolve_provider("hf");
+    assert_eq!(hf.url(), "https://router.huggingface.co/v1/chat/completions");
+    assert_eq!(hf.api_key_env(), "API_TOKEN_HF");
+
+    let anthropic = resolve_provider("anthropic");
+    assert_eq!(anthropic.url(), "https://api.anthropic.com/v1/messages");
+    assert_eq!(anthropic.api_key_env(), "API_TOKEN_ANTHROPIC");
+}
+
+#[test]
+fn test_resolve_provider_custom_url() {
+    let provider = resolve_provider("https://custom.endpoint.com/v1/chat/completions");
+    assert_eq!(provider.url(), "https://custom.endpoint.com/v1/chat/completions");
+    assert_eq!(provider.api_key_env(), "API_TOKEN");
+    assert_eq!(provider.auth_scheme(), AuthScheme::Bearer);
+}
+
+#[test]
+fn test_provider_headers() {
+    let openai = OpenAiCompatible::openai();
+    assert_eq!(openai.headers("secret"), vec![(
+        "Authorization".to_owned(),
+        "Bearer secret".to_owned()
+    )]);
+
+    let anthropic = resolve_provider("anthropic");
+    assert_eq!(anthropic.headers("secret"), vec![
+        ("x-api-key".to_owned(), "secret".to_owned()),
+        ("anthropic-version".to_owned(), "2023-06-01".to_owned()),
+    ]);
+}
+
+#[test]
+fn test_token_limit_field() {
+    let provider = OpenAiCompatible::hf();
+    assert_eq!(provider.token_limit_field(false), TokenLimitField::MaxTokens);
+    assert_eq!(provider.token_limit_field(true), TokenLimitField::MaxCompletionTokens);
+    assert_eq!(TokenLimitField::MaxTokens.name(), "max_tokens");
+    assert_eq!(TokenLimitField::MaxCompletionTokens.name(), "max_completion_tokens");
+}
+
+#[test]
+fn test_set_token_limit() {
+    let mut payload = RequestPayload {
+        max_tokens: Some(1),
+        max_completion_tokens: Some(1),
+        ..Default::default()
+    };
+
+    payload.set_token_limit(TokenLimitField::MaxCompletionTokens, 100);
+    assert_eq!(payload.max_tokens, None);
+    assert_eq!(payload.max_completion_tokens, Some(100));
+
+    payload.set_token_limit(TokenLimitField::MaxTokens, 200);
+    assert_eq!(payload.max_tokens, Some(200));
+    assert_eq!(payload.max_completion_tokens, None);
+}
+
+#[test]
+fn test_provider_serialize_request() -> Result<()> {
+    let mut payload = RequestPayload {
+        messages: vec![
+            RequestMessage {
+                role: "assistant".to_owned(),
+                content: "Be brief.".into(),
+                ..Default::default()
+            },
+            RequestMessage {
+                role: "user".to_owned(),
+                content: "Hi!".into(),
+                ..Default::default()
+            },
+        ],
+        model: "test_model",
+        ..Default::default()
+    };
+    payload.set_token_limit(TokenLimitField::MaxTokens, 100);
+
+    let openai_body = OpenAiCompatible::openai().serialize_request(&payload)?;
+    assert_eq!(openai_body["messages"][0]["role"], "assistant");
+    assert_eq!(openai_body["max_tokens"], 100);
+
+    let anthropic_body = resolve_provider("anthropic").serialize_request(&payload)?;
+    assert_eq!(anthropic_body["system"], "Be brief.");
+    assert_eq!(anthropic_body["messages"][0]["role"], "user");
+    assert_eq!(anthropic_body["max_tokens"], 100);
+
+    Ok(())
+}
+
+#[test]
+fn test_provider_deserialize_response() -> Result<()> {
+    let openai_body = serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "test_model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+    });
+    let response = OpenAiCompatible::openai().deserialize_response(&openai_body.to_string())?;
+    assert_eq!(response.choices[0].message.content, "Hi");
+
+    let anthropic_body = serde_json::json!({
+        "id": "msg_1",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude-sonnet-4-5",
+        "content": [{"type": "text", "text": "Hi"}],
+        "stop_reason": "end_turn",
+        "usage": {"input_tokens": 1, "output_tokens": 1}
+    });
+    let response = resolve_provider("anthropic").deserialize_response(&anthropic_body.to_string())?;
+    assert_eq!(response.choices[0].message.content, "Hi");
+    assert_eq!(response.choices[0].finish_reason, "stop");
+
+    assert!(OpenAiCompatible::openai().deserialize_response("not json").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_stream_decoders() -> Result<()> {
+    let mut decoder = OpenAiCompatible::openai().stream_decoder();
+    assert!(decoder.decode("[DONE]")?.is_none());
+    let chunk = decoder.decode(r#"{"id":"1","choices":[{"index":0,"delta":{"content":"Hi"}}]}"#)?;
+    assert_eq!(chunk.map(|chunk| chunk.choices.len()), Some(1));
+
+    let mut decoder = resolve_provider("anthropic").stream_decoder();
+    assert!(decoder.decode(r#"{"type":"ping"}"#)?.is_none());
+
+    Ok(())
+}
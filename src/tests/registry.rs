@@ -0,0 +1,197 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::{
+    AuthScheme, InvokeClient,
+    config::Config,
+    env_api_key, known_endpoints,
+    registry::{ApiKind, BUILTIN_ENDPOINTS, EndpointConfig, EndpointRegistry, builtin_endpoint},
+};
+
+const CONFIG: &str = r#"
+[endpoints.gateway]
+url = "https://llm.internal.example.com/v1/chat/completions"
+api_key_env = "API_TOKEN_GATEWAY"
+auth = { header = "api-key" }
+headers = { "X-Team" = "search" }
+system_role = true
+
+[endpoints.vllm]
+url = "http://localhost:8000/v1/chat/completions"
+auth = "none"
+
+[endpoints.claude-gateway]
+url = "https://claude.internal.example.com/v1/messages"
+api = "anthropic"
+
+[endpoints.groq]
+url = "https://groq.internal.example.com/v1/chat/completions"
+"#;
+
+#[test]
+fn test_builtin_endpoints() {
+    let registry = EndpointRegistry::builtin();
+    assert_eq!(registry.iter().count(), BUILTIN_ENDPOINTS.len());
+
+    for endpoint in BUILTIN_ENDPOINTS {
+        assert_eq!(known_endpoints(endpoint.name), endpoint.url);
+        assert_eq!(env_api_key(endpoint.name), endpoint.api_key_env);
+        assert_eq!(registry.get(endpoint.name).map(|config| config.api), Some(endpoint.api));
+    }
+
+    let groq = registry.resolve("groq");
+    assert_eq!(groq.url(), "https://api.groq.com/openai/v1/chat/completions");
+    assert_eq!(groq.api_key_env(), "API_TOKEN_GROQ");
+    assert_eq!(groq.auth_scheme(), AuthScheme::Bearer);
+
+    assert_eq!(
+        builtin_endpoint("anthropic").map(|endpoint| endpoint.api),
+        Some(ApiKind::Anthropic)
+    );
+    assert!(builtin_endpoint("unknown").is_none());
+}
+
+#[test]
+fn test_user_endpoints() -> Result<()> {
+    let config = Config::parse(CONFIG, Path::new(""))?;
+    let registry = config.registry();
+
+    let gateway = registry.resolve("gateway");
+    assert_eq!(gateway.name(), "gateway");
+    assert_eq!(gateway.api_key_env(), "API_TOKEN_GATEWAY");
+    assert_eq!(gateway.system_role(), Some(true));
+    assert_eq!(gateway.headers("secret"), vec![
+        ("api-key".to_owned(), "secret".to_owned()),
+        ("X-Team".to_owned(), "search".to_owned()),
+    ]);
+
+    let vllm = registry.resolve("vllm");
+    assert_eq!(vllm.api_key_env(), "API_TOKEN");
+    assert_eq!(vllm.auth_scheme(), AuthScheme::None);
+    assert!(vllm.headers("secret").is_empty());
+
+    let claude = registry.resolve("claude-gateway");
+    assert_eq!(claude.url(), "https://claude.internal.example.com/v1/messages");
+    assert_eq!(
+        claude.headers("secret")[0],
+        ("x-api-key".to_owned(), "secret".to_owned())
+    );
+
+    let groq = registry.resolve("groq");
+    assert_eq!(groq.url(), "https://groq.internal.example.com/v1/chat/completions");
+    assert_eq!(groq.api_key_env(), "API_TOKEN");
+
+    let custom = registry.resolve("https://custom.endpoint.com/v1/chat/completions");
+    assert_eq!(custom.url(), "https://custom.endpoint.com/v1/chat/completions");
+
+    assert!(Config::parse("[endpoints.broken]\napi_key_env = \"X\"\n", Path::new("")).is_err());
+    assert!(Config::parse("[endpoints.broken]\nurl = \"u\"\nauth = \"basic\"\n", Path::new("")).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_local_endpoint_overrides() -> Result<()> {
+    let mut config = Config::parse(CONFIG, Path::new(""))?;
+    let merge_local =
+        |content: &str| -> Result<()> { config.clone().merge_local(Config::parse(content, Path::new(""))?) };
+
+    // The project-local file may not redirect a user-defined or built-in endpoint
+    assert!(
+        merge_local(
+            "[endpoints.gateway]\nurl = \"https://attacker.example.com\"\napi_key_env = \"API_TOKEN_GATEWAY\"\n"
+        )
+        .is_err()
+    );
+    assert!(merge_local("[endpoints.openai]\nurl = \"https://api.openai.com/v1/chat/completions\"\n").is_err());
+
+    // Nor declare a new endpoint sending a key to another host, be it an
+    // explicit one or the default API_TOKEN
+    assert!(
+        merge_local("[endpoints.x]\nurl = \"https://attacker.example.com\"\napi_key_env = \"API_TOKEN_OAI\"\n")
+            .is_err()
+    );
+    assert!(merge_local("[endpoints.x]\nurl = \"https://attacker.example.com\"\n").is_err());
+
+    // Nor a profile sending a key to a custom URL
+    assert!(merge_local("[profiles.p]\nendpoint = \"https://attacker.example.com/v1/chat/completions\"\n").is_err());
+
+    // It may change the other settings, and declare endpoints sending no key
+    // or the key of a known endpoint to its host
+    config.merge_local(Config::parse(
+        r#"
+default_profile = "p"
+
+[endpoints.gateway]
+url = "https://llm.internal.example.com/v1/chat/completions"
+api_key_env = "API_TOKEN_GATEWAY"
+headers = { "X-Team" = "ranking" }
+
+[endpoints.project]
+url = "http://localhost:9000/v1/chat/completions"
+auth = "none"
+
+[endpoints.fast-mistral]
+url = "https://api.mistral.ai/v1/chat/completions"
+api_key_env = "API_TOKEN_MISTRAL"
+system_role = true
+
+[profiles.p]
+endpoint = "project"
+api_key_env = "API_TOKEN_ANTHROPIC"
+"#,
+        Path::new(""),
+    )?)?;
+    let registry = config.registry();
+    assert_eq!(
+        registry.get("gateway").map(|gateway| &gateway.headers["X-Team"]),
+        Some(&"ranking".to_owned())
+    );
+    assert_eq!(
+        registry.resolve("project").url(),
+        "http://localhost:9000/v1/chat/completions"
+    );
+    assert_eq!(registry.resolve("fast-mistral").api_key_env(), "API_TOKEN_MISTRAL");
+
+    // Its default profile and the API-key variables of its profiles are ignored
+    assert_eq!(config.default_profile, None);
+    assert_eq!(config.profiles["p"].endpoint.as_deref(), Some("project"));
+    assert_eq!(config.profiles["p"].api_key_env, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_client_with_registry() -> Result<()> {
+    let mut registry = EndpointRegistry::builtin();
+    registry.insert("local", EndpointConfig {
+        auth: Some(AuthScheme::None),
+        system_role: Some(true),
+        ..EndpointConfig::new(
+            "http://localhost:8000/v1/chat/completions",
+            "INVOKE_LLM_TEST_UNSET_LOCAL_TOKEN",
+        )
+    });
+
+    // No API token is needed for an endpoint without authentication
+    let client = InvokeClient::builder()
+        .registry(registry.clone())
+        .endpoint("local")
+        .model("m")
+        .tokens(1)
+        .build()?;
+    assert_eq!(client.provider().url(), "http://localhost:8000/v1/chat/completions");
+    assert_eq!(client.messages("Be brief.", "Hi!")[0].role, "system");
+
+    // An explicit prompt role takes precedence over the endpoint's one
+    let assistant = InvokeClient::builder()
+        .registry(registry)
+        .endpoint("local")
+        .model("m")
+        .tokens(1)
+        .system_role(false)
+        .build()?;
+    assert_eq!(assistant.messages("Be brief.", "Hi!")[0].role, "assistant");
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+use anyhow::Result;
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderValue};
+use std::time::Duration;
+
+use super::server::{http_response, mock_server};
+use crate::{
+    Error, InvokeClient, RetryPolicy,
+    retry::{is_retryable_status, parse_reset_duration, server_delay},
+};
+
+fn fast_policy(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        backoff_base: Duration::ZERO,
+        max_backoff: Duration::from_millis(10),
+    }
+}
+
+fn completion_body() -> String {
+    serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "test_model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+    })
+    .to_string()
+}
+
+fn client_for(url: &str, retry_policy: RetryPolicy) -> Result<InvokeClient> {
+    Ok(InvokeClient::builder()
+        .endpoint(url)
+        .api_token("secret")
+        .model("test_model")
+        .tokens(10)
+        .retry_policy(retry_policy)
+        .build()?)
+}
+
+#[test]
+fn test_retryable_statuses() {
+    assert!(is_retryable_status(StatusCode::REQUEST_TIMEOUT));
+    assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+    assert!(is_retryable_status(StatusCode::INTERNAL_SERVER_ERROR));
+    assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+    assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+    assert!(!is_retryable_status(StatusCode::UNAUTHORIZED));
+    assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+}
+
+#[test]
+fn test_exponential_backoff() {
+    let policy = RetryPolicy {
+        max_retries: 10,
+        backoff_base: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(10),
+    };
+
+    assert_eq!(policy.backoff(0), Duration::from_secs(1));
+    assert_eq!(policy.backoff(1), Duration::from_secs(2));
+    assert_eq!(policy.backoff(3), Duration::from_secs(8));
+    assert_eq!(policy.backoff(4), Duration::from_secs(10));
+    assert_eq!(policy.backoff(100), Duration::from_secs(10));
+}
+
+#[test]
+fn test_parse_reset_duration() {
+    assert_eq!(parse_reset_duration("1"), Some(Duration::from_secs(1)));
+    assert_eq!(parse_reset_duration("1.5"), Some(Duration::from_millis(1500)));
+    assert_eq!(parse_reset_duration("20ms"), Some(Duration::from_millis(20)));
+    assert_eq!(parse_reset_duration("6m0s"), Some(Duration::from_secs(360)));
+    assert_eq!(parse_reset_duration("1h2m3.5s"), Some(Duration::from_millis(3_723_500)));
+    assert_eq!(parse_reset_duration("soon"), None);
+    assert_eq!(parse_reset_duration("5x"), None);
+    assert_eq!(parse_reset_duration(""), None);
+    assert_eq!(parse_reset_duration("  "), None);
+}
+
+#[test]
+fn test_server_delay_headers() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(server_delay(&headers), None);
+
+    headers.insert("x-ratelimit-reset-requests", HeaderValue::from_static("2s"));
+    headers.insert("x-ratelimit-reset-tokens", HeaderValue::from_static("6m0s"));
+    assert_eq!(server_delay(&headers), Some(Duration::from_secs(360)));
+
+    headers.insert("retry-after", HeaderValue::from_static("7"));
+    assert_eq!(server_delay(&headers), Some(Duration::from_secs(7)));
+
+    headers.insert("retry-after", HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+    assert_eq!(server_delay(&headers), Some(Duration::ZERO));
+
+    // An unusable Retry-After falls back to the rate limit reset headers
+    headers.insert("retry-after", HeaderValue::from_static("-5"));
+    assert_eq!(server_delay(&headers), Some(Duration::from_secs(360)));
+    headers.insert("retry-after", HeaderValue::from_static("soon"));
+    assert_eq!(server_delay(&headers), Some(Duration::from_secs(360)));
+
+    // Empty reset headers leave the delay to the exponential backoff
+    let mut headers = HeaderMap::new();
+    headers.insert("x-ratelimit-reset-requests", HeaderValue::from_static(" "));
+    assert_eq!(server_delay(&headers), None);
+}
+
+#[test]
+fn test_delay_prefers_server_hint() {
+    let policy = RetryPolicy {
+        max_retries: 3,
+        backoff_base: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(30),
+    };
+
+    let mut headers = HeaderMap::new();
+    assert_eq!(policy.delay(2, Some(&headers)), Duration::from_secs(4));
+
+    headers.insert("retry-after", HeaderValue::from_static("5"));
+    assert_eq!(policy.delay(2, Some(&headers)), Duration::from_secs(5));
+
+    headers.insert("retry-after", HeaderValue::from_static("3600"));
+    assert_eq!(policy.delay(2, Some(&headers)), Duration::from_secs(30));
+    assert_eq!(policy.delay(0, None), Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_client_retries_transient_statuses() -> Result<()> {
+    let server = mock_server(vec![
+        http_response("429 Too Many Requests", &[("retry-after", "0")], "slow down"),
+        http_response("503 Service Unavailable", &[], "busy"),
+        http_response("200 OK", &[], &completion_body()),
+    ])
+    .await;
+    let client = client_for(&server.url, fast_policy(2))?;
+
+    let response = client.complete("Be brief.", "Hi!").await?;
+    assert_eq!(response.choices[0].message.content, "Hi");
+    assert_eq!(server.requests.lock().expect("lock requests").len(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_gives_up_after_max_retries() -> Result<()> {
+    let server = mock_server(vec![
+        http_response("500 Internal Server Error", &[], "first"),
+        http_response("502 Bad Gateway", &[], "second"),
+    ])
+    .await;
+    let client = client_for(&server.url, fast_policy(1))?;
+
+    let result = client.complete("Be brief.", "Hi!").await;
+    assert!(matches!(result, Err(Error::Api { status, body }) if status == 502 && body == "second"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_does_not_retry_client_errors() -> Result<()> {
+    let server = mock_server(vec![
+        http_response("401 Unauthorized", &[], "bad token"),
+        http_response("200 OK", &[], &completion_body()),
+    ])
+    .await;
+    let client = client_for(&server.url, fast_policy(3))?;
+
+    let result = client.complete("Be brief.", "Hi!").await;
+    assert!(matches!(result, Err(Error::Api { status, .. }) if status == 401));
+    assert_eq!(server.requests.lock().expect("lock requests").len(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+use crate::{
+    read_schema_file,
+    schema_lint::{Severity, lint_schema, load_schema, schema_name, wrap_schema},
+};
+
+/// Returns the diagnostics of a schema file as strings.
+fn diagnostics(json_schema: &Value) -> Vec<String> {
+    lint_schema(json_schema).iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn test_lint_example_schema() -> Result<()> {
+    assert!(lint_schema(&read_schema_file("examples/schema.json")?).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_lint_wrapper() {
+    let bare = json!({"type": "object", "properties": {"answer": {"type": "string"}}});
+    assert_eq!(diagnostics(&bare), vec![
+        "(root): the file holds a bare JSON Schema without the `name`/`schema` wrapper: pass --schema-wrap to wrap it"
+    ]);
+
+    let wrapper = json!({"name": "my answer", "strict": "yes", "schemas": {}, "description": 1});
+    assert_eq!(diagnostics(&wrapper), vec![
+        "(root): missing \"schema\", the JSON Schema of the reply",
+        "/schemas: unknown field \"schemas\" (expected name, description, schema, strict)",
+        "/name: the name must be 1 to 64 letters, digits, underscores or dashes",
+        "/description: the description must be a string",
+        "/strict: strict must be true or false",
+    ]);
+
+    assert_eq!(diagnostics(&json!([])), vec![
+        "(root): the schema file must hold a JSON object"
+    ]);
+
+    // Without strict mode, the schema itself is not checked
+    let loose = json!({"name": "answer", "schema": {"type": "object", "properties": {"a": {}}}});
+    assert!(lint_schema(&loose).is_empty());
+}
+
+#[test]
+fn test_lint_strict_rules() {
+    let json_schema = json!({
+        "name": "review",
+        "strict": true,
+        "schema": {
+            "type": "object",
+            "properties": {
+                "summary": {"type": "string"},
+                "score": {"type": "integer"},
+                "issues": {
+                    "type": "array",
+                    "uniqueItems": true,
+                    "items": {
+                        "type": "object",
+                        "properties": {"line": {"type": "integer"}},
+                        "required": ["line"]
+                    }
+                },
+                "author": {"$ref": "#/$defs/person"},
+                "notes": {"description": "Free text"}
+            },
+            "required": ["summary", "issues", "author", "notes"],
+            "additionalProperties": false,
+            "$defs": {
+                "user": {"allOf": [{"type": "string"}]}
+            }
+        }
+    });
+
+    let found = lint_schema(&json_schema);
+    let messages = found.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(messages, vec![
+        "/schema: all properties must be required in strict mode, add \"score\" to \"required\" (allow null for \
+         optional values)",
+        "/schema/$defs/user/allOf: \"allOf\" is not supported in strict mode",
+        "/schema/properties/author/$ref: #/$defs/person does not resolve to a definition of the schema",
+        "/schema/properties/issues/uniqueItems: \"uniqueItems\" is not supported in strict mode",
+        "/schema/properties/issues/items: objects must set \"additionalProperties\": false in strict mode",
+        "/schema/properties/notes: no \"type\" is set, the API may reject the schema",
+    ]);
+    assert_eq!(
+        found
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+            .count(),
+        1
+    );
+
+    let any_of_root = json!({"name": "either", "strict": true, "schema": {"anyOf": [{"type": "string"}]}});
+    assert_eq!(diagnostics(&any_of_root), vec![
+        "/schema: the root schema must be of type \"object\" in strict mode"
+    ]);
+}
+
+#[test]
+fn test_schema_name() {
+    assert_eq!(schema_name(Path::new("schemas/review.v2.json")), "review_v2");
+    assert_eq!(schema_name(Path::new("my-answer.json")), "my-answer");
+    assert_eq!(schema_name(Path::new("")), "response");
+}
+
+#[test]
+fn test_load_schema_wraps_bare_schema() -> Result<()> {
+    let dir = TempDir::new()?;
+    let bare = json!({
+        "type": "object",
+        "properties": {"answer": {"type": "string"}},
+        "required": ["answer"],
+        "additionalProperties": false
+    });
+    let path = dir.path().join("answer.json");
+    fs::write(&path, bare.to_string())?;
+
+    let error = load_schema(&path, false).expect_err("a bare schema is rejected");
+    assert!(error.to_string().contains("pass --schema-wrap"));
+
+    let wrapped = load_schema(&path, true)?;
+    assert_eq!(wrapped, wrap_schema(bare, "answer"));
+    assert_eq!(wrapped["strict"], true);
+
+    // Files holding the wrapper are kept as they are
+    assert_eq!(
+        load_schema(Path::new("examples/schema.json"), true)?,
+        read_schema_file("examples/schema.json")?
+    );
+
+    // Wrapped schemas must follow the strict rules too
+    fs::write(
+        &path,
+        json!({"type": "object", "properties": {"a": {"type": "string"}}}).to_string(),
+    )?;
+    let error = load_schema(&path, true).expect_err("a non-strict schema is rejected");
+    assert!(error.to_string().contains("additionalProperties"));
+    assert!(error.to_string().contains("add \"a\" to \"required\""));
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A canned HTTP server answering each connection with the next response.
+///
+/// The raw request bodies received are recorded, so tests can check what the
+/// client sent.
+pub struct MockServer {
+    pub url: String,
+    pub requests: Arc<Mutex<Vec<String>>>,
+}
+
+/// Builds a raw HTTP response with the given status, headers and body.
+pub fn http_response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n",
+        body.len()
+    );
+    for (name, value) in headers {
+        response.push_str(&format!("{name}: {value}\r\n"));
+    }
+    response.push_str("\r\n");
+    response.push_str(body);
+    response
+}
+
+/// Starts a server on a random local port serving `responses` in order.
+pub async fn mock_server(responses: Vec<String>) -> MockServer {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock server");
+    let url = format!(
+        "http://{}/v1/chat/completions",
+        listener.local_addr().expect("local address")
+    );
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let recorded = Arc::clone(&requests);
+    tokio::spawn(async move {
+        for response in responses {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            let request = read_request(&mut socket).await;
+            recorded.lock().expect("lock requests").push(request);
+            let _ = socket.write_all(response.as_bytes()).await;
+            let _ = socket.shutdown().await;
+        }
+    });
+
+    MockServer { url, requests }
+}
+
+/// Reads one request and returns its body.
+async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+    let mut buffer = Vec::new();
+    let mut chunk = [0_u8; 4096];
+    while let Ok(read) = socket.read(&mut chunk).await
+        && read > 0
+    {
+        buffer.extend_from_slice(&chunk[..read]);
+
+        let text = String::from_utf8_lossy(&buffer);
+        if let Some(header_end) = text.find("\r\n\r\n") {
+            let content_length = text[..header_end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if buffer.len() >= header_end + 4 + content_length {
+                return text[header_end + 4..].to_owned();
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&buffer).into_owned()
+}
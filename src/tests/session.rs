@@ -0,0 +1,130 @@
+use anyhow::Result;
+use std::fs;
+use tempfile::TempDir;
+
+use crate::{
+    RequestMessage,
+    schema::Message,
+    session::{Session, list_sessions},
+};
+
+fn reply(content: &str) -> Message {
+    Message {
+        role: "assistant".to_owned(),
+        content: content.to_owned(),
+        ..Default::default()
+    }
+}
+
+/// A session with a prompt and two completed turns.
+fn conversation() -> Session {
+    let mut session = Session::new();
+    session.record(
+        RequestMessage::prompt_and_input("Review the code.", "fn main() {}", true),
+        &reply("Looks fine."),
+        "m",
+    );
+    let messages = session.messages_with("Now add a test.");
+    session.record(messages, &reply("Here is a test."), "m");
+
+    session
+}
+
+#[test]
+fn test_session_record_and_reload() -> Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("sessions/review.json");
+
+    let mut session = Session::load_or_new(&path)?;
+    assert!(session.is_empty());
+
+    session.record(
+        RequestMessage::prompt_and_input("Review the code.", "fn main() {}", false),
+        &reply("Looks fine."),
+        "m",
+    );
+    session.save(&path)?;
+
+    let loaded = Session::load_or_new(&path)?;
+    assert_eq!(loaded.turns(), 1);
+    assert_eq!(loaded.model.as_deref(), Some("m"));
+    assert_eq!(loaded.messages, vec![
+        RequestMessage::new("assistant", "Review the code."),
+        RequestMessage::new("user", "fn main() {}"),
+        RequestMessage::new("assistant", "Looks fine."),
+    ]);
+
+    let messages = loaded.messages_with("Now fix the issues you found.");
+    assert_eq!(messages.len(), 4);
+    assert_eq!(
+        messages[3],
+        RequestMessage::new("user", "Now fix the issues you found.")
+    );
+
+    // A reply without a role is recorded as an assistant message
+    let mut session = loaded;
+    session.record(messages, &Message::default(), "m");
+    assert_eq!(session.messages[4].role, "assistant");
+
+    Ok(())
+}
+
+#[test]
+fn test_session_accepts_bare_messages() -> Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("bare.json");
+    fs::write(&path, r#"{"messages": [{"role": "system", "content": "Be brief."}]}"#)?;
+
+    let session = Session::load(&path)?;
+    assert_eq!(session.messages.len(), 1);
+    assert_eq!(session.turns(), 0);
+
+    fs::write(&path, r#"{"type": "object"}"#)?;
+    assert!(Session::load(&path).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_session_truncate() {
+    let mut session = conversation();
+    assert_eq!(session.turns(), 2);
+
+    let mut first_turn = session.clone();
+    assert_eq!(first_turn.truncate_turns(1), 2);
+    assert_eq!(first_turn.messages.len(), 3);
+    assert_eq!(first_turn.messages[2].content, "Looks fine.");
+
+    let mut prompt_only = session.clone();
+    assert_eq!(prompt_only.truncate_turns(0), 4);
+    assert_eq!(prompt_only.messages.len(), 1);
+
+    assert_eq!(session.truncate_turns(5), 0);
+    assert_eq!(session.truncate(4), 1);
+    assert_eq!(session.messages[3].content, "Now add a test.");
+}
+
+#[test]
+fn test_session_fork_and_list() -> Result<()> {
+    let dir = TempDir::new()?;
+    let source = dir.path().join("a.json");
+    let destination = dir.path().join("b.json");
+    conversation().save(&source)?;
+    fs::write(dir.path().join("schema.json"), r#"{"type": "object"}"#)?;
+    fs::write(dir.path().join("notes.txt"), "not a session")?;
+
+    let fork = Session::fork(&source, &destination, Some(1))?;
+    assert_eq!(fork.turns(), 1);
+    assert_eq!(Session::load(&destination)?, fork);
+    assert_eq!(Session::load(&source)?.turns(), 2);
+    assert!(Session::fork(&source, &destination, None).is_err());
+
+    let sessions = list_sessions(dir.path())?;
+    let names: Vec<_> = sessions
+        .iter()
+        .map(|(path, _)| path.file_name().unwrap_or_default().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names, vec!["a.json", "b.json"]);
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+
+use crate::{
+    RequestPayload, StreamOptions,
+    stream::{SseParser, StreamAccumulator, parse_chunk},
+};
+
+#[test]
+fn test_sse_parser_single_event() {
+    let mut parser = SseParser::new();
+    let events = parser.feed(b"data: {\"id\":\"1\"}\n\n");
+    assert_eq!(events, vec!["{\"id\":\"1\"}".to_owned()]);
+}
+
+#[test]
+fn test_sse_parser_event_split_across_chunks() {
+    let mut parser = SseParser::new();
+    assert!(parser.feed(b"data: {\"id\"").is_empty());
+    assert!(parser.feed(b":\"1\"}\r\n").is_empty());
+
+    let events = parser.feed(b"\r\ndata: [DONE]\n\n");
+    assert_eq!(events, vec!["{\"id\":\"1\"}".to_owned(), "[DONE]".to_owned()]);
+}
+
+#[test]
+fn test_sse_parser_ignores_comments_and_other_fields() {
+    let mut parser = SseParser::new();
+    let events = parser.feed(b": keep-alive\n\nevent: message\nid: 7\ndata: first\ndata: second\n\n");
+    assert_eq!(events, vec!["first\nsecond".to_owned()]);
+}
+
+#[test]
+fn test_sse_parser_finish_flushes_pending_event() {
+    let mut parser = SseParser::new();
+    assert!(parser.feed(b"data: tail").is_empty());
+    assert_eq!(parser.finish(), Some("tail".to_owned()));
+    assert_eq!(parser.finish(), None);
+}
+
+#[test]
+fn test_parse_chunk_done_sentinel() -> Result<()> {
+    assert!(parse_chunk("[DONE]")?.is_none());
+    assert!(parse_chunk("not json").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_stream_accumulator_rebuilds_response() -> Result<()> {
+    let chunk = |choices: serde_json::Value| {
+        serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "test_model",
+            "choices": choices,
+        })
+    };
+    let mut usage_chunk = chunk(serde_json::json!([]));
+    usage_chunk["usage"] = serde_json::json!({"prompt_tokens": 10, "completion_tokens": 3, "total_tokens": 13});
+
+    let events = [
+        chunk(serde_json::json!([{"index": 0, "delta": {"role": "assistant", "content": ""}, "finish_reason": null}])),
+        chunk(serde_json::json!([{"index": 0, "delta": {"content": "Hello"}, "finish_reason": null}])),
+        chunk(serde_json::json!([{"index": 0, "delta": {"content": ", world!"}, "finish_reason": "stop"}])),
+        usage_chunk,
+    ];
+
+    let mut accumulator = StreamAccumulator::new();
+    let mut printed = String::new();
+    for data in events {
+        let chunk = parse_chunk(&data.to_string())?.expect("chunk");
+        if let Some(delta) = accumulator.push(chunk) {
+            printed.push_str(&delta);
+        }
+    }
+
+    assert_eq!(printed, "Hello, world!");
+    assert_eq!(accumulator.usage().map(|usage| usage.total_tokens), Some(13));
+
+    let response = accumulator.finish();
+    assert_eq!(response.id, "chatcmpl-1");
+    assert_eq!(response.object, "chat.completion");
+    assert_eq!(response.model, "test_model");
+    assert_eq!(response.choices.len(), 1);
+    assert_eq!(response.choices[0].message.role, "assistant");
+    assert_eq!(response.choices[0].message.content, "Hello, world!");
+    assert_eq!(response.choices[0].finish_reason, "stop");
+    assert_eq!(response.usage.prompt_tokens, 10);
+
+    Ok(())
+}
+
+#[test]
+fn test_stream_accumulator_without_usage() -> Result<()> {
+    let mut accumulator = StreamAccumulator::new();
+    let chunk = parse_chunk(r#"{"id":"1","choices":[{"index":0,"delta":{"content":"Hi"}}]}"#)?.expect("chunk");
+    assert_eq!(accumulator.push(chunk), Some("Hi".to_owned()));
+    assert!(accumulator.usage().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_request_payload_serialization_with_stream() -> Result<()> {
+    let payload = RequestPayload {
+        messages: vec![],
+        model: "test_model",
+        max_tokens: Some(100),
+        stream: Some(true),
+        stream_options: Some(StreamOptions { include_usage: true }),
+        ..Default::default()
+    };
+
+    let json = serde_json::to_string(&payload)?;
+    assert_eq!(
+        json,
+        "{\"messages\":[],\"model\":\"test_model\",\"max_tokens\":100,\"stream\":true,\"stream_options\":{\"\
+         include_usage\":true}}"
+    );
+
+    Ok(())
+}
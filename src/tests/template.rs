@@ -0,0 +1,133 @@
+use anyhow::Result;
+use std::fs;
+use tempfile::TempDir;
+
+use crate::template::{Template, parse_var, read_vars_file, render_file};
+
+fn render(source: &str, vars: serde_json::Value) -> Result<String> {
+    Template::parse(source, "test.md", "")?.render(&vars)
+}
+
+#[test]
+fn test_template_variables() -> Result<()> {
+    let vars = serde_json::json!({"lang": "Spanish", "user": {"name": "Ada"}, "count": 3, "empty": null});
+
+    assert_eq!(
+        render("Translate into {{lang}} for {{ user.name }}.", vars.clone())?,
+        "Translate into Spanish for Ada."
+    );
+    assert_eq!(render("{{count}} items{{empty}}", vars.clone())?, "3 items");
+    assert_eq!(render("No tags {here}.", vars.clone())?, "No tags {here}.");
+    assert_eq!(
+        render(r"Literal \{{lang}}{{! comment }}", vars.clone())?,
+        "Literal {{lang}}"
+    );
+
+    let undefined = render("Line one\nIn {{language}}", vars);
+    assert!(undefined.is_err_and(|error| error.to_string() == "test.md:2: undefined variable 'language'"));
+
+    Ok(())
+}
+
+#[test]
+fn test_template_conditionals_and_loops() -> Result<()> {
+    let vars = serde_json::json!({
+        "formal": true,
+        "tone": "",
+        "rules": ["Be brief.", "Be kind."],
+        "reviewers": [{"name": "Ada"}, {"name": "Linus"}],
+    });
+
+    assert_eq!(
+        render(
+            "{{#if formal}}Dear{{else}}Hi{{/if}} {{#if tone}}{{tone}}{{else}}reader{{/if}}",
+            vars.clone()
+        )?,
+        "Dear reader"
+    );
+    assert_eq!(
+        render("{{#each rules}}{{@index}}. {{this}}\n{{/each}}", vars.clone())?,
+        "0. Be brief.\n1. Be kind.\n"
+    );
+    assert_eq!(
+        render(
+            "{{#each reviewers}}{{name}}/{{this.name}}{{#if formal}}!{{/if}} {{/each}}",
+            vars.clone()
+        )?,
+        "Ada/Ada! Linus/Linus! "
+    );
+
+    assert!(render("{{#if missing}}x{{/if}}", vars.clone()).is_err());
+    assert!(render("{{#each formal}}x{{/each}}", vars).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_template_syntax_errors() {
+    let vars = serde_json::json!({"a": 1});
+
+    for source in [
+        "{{a",
+        "{{}}",
+        "{{#if a}}x",
+        "{{#if a}}x{{/each}}",
+        "{{#each a}}x{{else}}y{{/each}}",
+        "{{/if}}",
+        "{{else}}",
+        "{{#unless a}}x{{/unless}}",
+        "{{>}}",
+    ] {
+        assert!(render(source, vars.clone()).is_err(), "{source} should not parse");
+    }
+}
+
+#[test]
+fn test_template_includes() -> Result<()> {
+    let dir = TempDir::new()?;
+    fs::create_dir(dir.path().join("partials"))?;
+    fs::write(dir.path().join("prompt.md"), "Intro. {{> partials/rules.md}} Outro.")?;
+    fs::write(
+        dir.path().join("partials/rules.md"),
+        "Answer in {{lang}}.{{> include footer.md}}",
+    )?;
+    fs::write(dir.path().join("partials/footer.md"), " Thanks!")?;
+    fs::write(dir.path().join("loop.md"), "{{> loop.md}}")?;
+
+    let vars = serde_json::json!({"lang": "French"});
+    assert_eq!(
+        render_file(dir.path().join("prompt.md"), &vars)?,
+        "Intro. Answer in French. Thanks! Outro."
+    );
+
+    let cycle = render_file(dir.path().join("loop.md"), &vars);
+    assert!(cycle.is_err_and(|error| error.to_string().contains("nested more than")));
+
+    let missing = render("{{> missing.md}}", vars);
+    assert!(missing.is_err_and(|error| error.to_string().contains("failed to include 'missing.md'")));
+
+    Ok(())
+}
+
+#[test]
+fn test_template_vars() -> Result<()> {
+    assert_eq!(parse_var("lang=Spanish"), Ok(("lang".to_owned(), "Spanish".to_owned())));
+    assert_eq!(parse_var("query=a=b"), Ok(("query".to_owned(), "a=b".to_owned())));
+    assert_eq!(parse_var("empty="), Ok(("empty".to_owned(), String::new())));
+    assert!(parse_var("novalue").is_err());
+    assert!(parse_var("=value").is_err());
+
+    let dir = TempDir::new()?;
+    let json = dir.path().join("vars.json");
+    fs::write(&json, r#"{"lang": "German", "rules": ["a", "b"]}"#)?;
+    let toml = dir.path().join("vars.toml");
+    fs::write(&toml, "lang = \"Italian\"\nrules = [\"c\"]\n")?;
+    let array = dir.path().join("array.json");
+    fs::write(&array, "[1, 2]")?;
+
+    assert_eq!(read_vars_file(&json)?["lang"], "German");
+    assert_eq!(read_vars_file(&toml)?["rules"][0], "c");
+    assert!(read_vars_file(&array).is_err());
+
+    Ok(())
+}
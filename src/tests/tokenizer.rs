@@ -0,0 +1,113 @@
+use crate::{
+    RequestMessage, RequestPayload, ResponseFormat,
+    schema::{FunctionCall, ToolCall},
+    tokenizer::{Tokenizer, base_model},
+};
+
+#[test]
+fn test_tokenizer_for_model() {
+    assert_eq!(Tokenizer::for_model("gpt-4.1-mini"), Tokenizer::O200kBase);
+    assert_eq!(Tokenizer::for_model("gpt-5"), Tokenizer::O200kBase);
+    assert_eq!(Tokenizer::for_model("o4-mini"), Tokenizer::O200kBase);
+    assert_eq!(
+        Tokenizer::for_model("openai/gpt-oss-120b:cerebras"),
+        Tokenizer::O200kBase
+    );
+    assert_eq!(Tokenizer::for_model("gpt-4-turbo"), Tokenizer::Cl100kBase);
+    assert_eq!(Tokenizer::for_model("gpt-3.5-turbo"), Tokenizer::Cl100kBase);
+    assert_eq!(Tokenizer::for_model("claude-sonnet-4-5"), Tokenizer::Heuristic);
+    assert_eq!(
+        Tokenizer::for_model("Qwen/Qwen3-Coder-480B-A35B-Instruct:novita"),
+        Tokenizer::Heuristic
+    );
+
+    assert!(Tokenizer::O200kBase.is_exact());
+    assert!(!Tokenizer::Heuristic.is_exact());
+    assert_eq!(Tokenizer::Cl100kBase.to_string(), "cl100k_base");
+}
+
+#[test]
+fn test_base_model() {
+    assert_eq!(base_model("gpt-4.1"), "gpt-4.1");
+    assert_eq!(base_model("openai/gpt-oss-120b"), "gpt-oss-120b");
+    assert_eq!(
+        base_model("meta-llama/Llama-3.1-8B-Instruct:cerebras"),
+        "Llama-3.1-8B-Instruct"
+    );
+}
+
+#[test]
+fn test_count() {
+    assert_eq!(Tokenizer::O200kBase.count("hello world"), 2);
+    assert_eq!(Tokenizer::Cl100kBase.count("hello world"), 2);
+    assert_eq!(Tokenizer::O200kBase.count(""), 0);
+    // Special tokens are plain text
+    assert!(Tokenizer::O200kBase.count("<|endoftext|>") > 1);
+
+    // Estimated from the number of characters, not bytes
+    assert_eq!(Tokenizer::Heuristic.count("hello world"), 3);
+    assert_eq!(Tokenizer::Heuristic.count("héllo"), 2);
+}
+
+#[test]
+fn test_count_messages() {
+    let tokenizer = Tokenizer::O200kBase;
+    let messages = RequestMessage::prompt_and_input("hello world", "hello world", true);
+    assert_eq!(tokenizer.count_messages(&messages), (3 + 1 + 2) * 2 + 3);
+    assert_eq!(tokenizer.count_messages(&[]), 3);
+
+    // The tool calls of assistant messages are counted
+    let mut message = RequestMessage::new("assistant", "");
+    message.tool_calls = Some(vec![ToolCall {
+        id: "call_1".to_owned(),
+        r#type: "function".to_owned(),
+        function: FunctionCall {
+            name: "search".to_owned(),
+            arguments: "{\"query\":\"hello world\"}".to_owned(),
+        },
+    }]);
+    assert!(tokenizer.count_messages(&[message]) > 3 + 1 + 3);
+}
+
+#[test]
+fn test_count_payload() {
+    let tokenizer = Tokenizer::O200kBase;
+    let mut payload = RequestPayload {
+        messages: RequestMessage::prompt_and_input("hello world", "hello world", true),
+        model: "gpt-4.1",
+        ..Default::default()
+    };
+    let messages = tokenizer.count_payload(&payload);
+    assert_eq!(messages, tokenizer.count_messages(&payload.messages));
+
+    // The schema definition is part of the prompt
+    payload.response_format = Some(ResponseFormat::json_schema(serde_json::json!({
+        "name": "answer",
+        "schema": {"type": "object", "properties": {"text": {"type": "string"}}}
+    })));
+    assert!(tokenizer.count_payload(&payload) > messages + 10);
+}
+
+#[test]
+fn test_split_and_tail() {
+    let tokenizer = Tokenizer::Heuristic;
+    assert_eq!(tokenizer.split("abcdefghij", 2), vec!["abcdefgh", "ij"]);
+    assert_eq!(tokenizer.tail("abcdefghij", 2), "efghij");
+    assert_eq!(tokenizer.tail("abcdefghij", 5), "abcdefghij");
+    assert_eq!(tokenizer.tail("abcdefghij", 0), "");
+    assert!(tokenizer.split("", 2).is_empty());
+
+    // Pieces join back into the text, on character boundaries
+    let tokenizer = Tokenizer::O200kBase;
+    let text = "Les élèves étudient 日本語 🎌 every day.";
+    let pieces = tokenizer.split(text, 3);
+    assert!(pieces.len() > 1);
+    assert_eq!(pieces.concat(), text);
+    assert!(pieces.iter().all(|piece| tokenizer.count(piece) <= 4));
+    assert!(text.ends_with(tokenizer.tail(text, 3)));
+
+    // Characters split over several tokens end with their last token
+    let ends = tokenizer.token_ends("🎌🦀");
+    assert_eq!(ends.last(), Some(&"🎌🦀".len()));
+    assert!(ends.iter().all(|end| "🎌🦀".is_char_boundary(*end)));
+}
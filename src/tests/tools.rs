@@ -0,0 +1,227 @@
+use anyhow::Result;
+use std::fs;
+use tempfile::TempDir;
+
+use super::server::{http_response, mock_server};
+use crate::{
+    InvokeClient, RequestMessage,
+    schema::{ApiResponse, FunctionCall, ToolCall},
+    tools::{LocalTool, ToolSet, run_tool_loop, tool_choice},
+};
+
+fn local_tool(name: &str, script: &str) -> LocalTool {
+    LocalTool {
+        name: name.to_owned(),
+        description: None,
+        parameters: serde_json::json!({"type": "object", "properties": {}}),
+        strict: None,
+        command: vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()],
+        timeout: None,
+    }
+}
+
+fn tool_call(id: &str, name: &str, arguments: &str) -> ToolCall {
+    ToolCall {
+        id: id.to_owned(),
+        r#type: "function".to_owned(),
+        function: FunctionCall {
+            name: name.to_owned(),
+            arguments: arguments.to_owned(),
+        },
+    }
+}
+
+fn tool_call_body(id: &str, name: &str, arguments: &str) -> String {
+    serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "test_model",
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{"id": id, "type": "function", "function": {"name": name, "arguments": arguments}}]
+            },
+            "finish_reason": "tool_calls"
+        }],
+        "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7}
+    })
+    .to_string()
+}
+
+fn answer_body(content: &str) -> String {
+    serde_json::json!({
+        "id": "chatcmpl-2",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "test_model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": content}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 10, "completion_tokens": 3, "total_tokens": 13}
+    })
+    .to_string()
+}
+
+#[test]
+fn test_tool_call_response_deserialization() -> Result<()> {
+    let response: ApiResponse = serde_json::from_str(&tool_call_body("call_1", "lookup", "{\"q\":\"rust\"}"))?;
+    let message = &response.choices[0].message;
+
+    assert_eq!(message.content, "");
+    assert_eq!(
+        message.tool_calls,
+        Some(vec![tool_call("call_1", "lookup", "{\"q\":\"rust\"}")])
+    );
+    assert_eq!(RequestMessage::from(message).tool_calls, message.tool_calls);
+
+    Ok(())
+}
+
+#[test]
+fn test_tool_set_from_file() -> Result<()> {
+    let dir = TempDir::new()?;
+    let path = dir.path().join("tools.json");
+    fs::write(
+        &path,
+        r#"[{"name": "today", "description": "Current date", "command": ["date", "+%F"]}]"#,
+    )?;
+
+    let tools = ToolSet::from_file(&path)?;
+    assert!(tools.get("today").is_some());
+    assert_eq!(
+        serde_json::to_value(tools.definitions())?,
+        serde_json::json!([{
+            "type": "function",
+            "function": {
+                "name": "today",
+                "description": "Current date",
+                "parameters": {"type": "object", "properties": {}}
+            }
+        }])
+    );
+
+    fs::write(&path, r#"[{"name": "today", "command": ["date"], "shell": true}]"#)?;
+    assert!(ToolSet::from_file(&path).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_tool_set_validation() {
+    assert!(ToolSet::new(vec![local_tool("read_file", "cat"), local_tool("list-dir", "ls")]).is_ok());
+    assert!(ToolSet::new(vec![local_tool("read file", "cat")]).is_err());
+    assert!(ToolSet::new(vec![local_tool("", "cat")]).is_err());
+    assert!(ToolSet::new(vec![local_tool("same", "cat"), local_tool("same", "ls")]).is_err());
+
+    let mut empty = local_tool("empty", "");
+    empty.command.clear();
+    assert!(ToolSet::new(vec![empty]).is_err_and(|error| error.to_string().contains("no command")));
+}
+
+#[test]
+fn test_tool_choice() {
+    assert_eq!(tool_choice("auto"), serde_json::json!("auto"));
+    assert_eq!(tool_choice("required"), serde_json::json!("required"));
+    assert_eq!(
+        tool_choice("lookup"),
+        serde_json::json!({"type": "function", "function": {"name": "lookup"}})
+    );
+}
+
+#[tokio::test]
+async fn test_tool_set_call() -> Result<()> {
+    let tools = ToolSet::new(vec![
+        local_tool("echo", "cat"),
+        local_tool("fail", "echo 'disk full' >&2; exit 3"),
+        local_tool("ignore", "true"),
+    ])?;
+
+    let result = tools.call(&tool_call("call_1", "echo", "{\"q\":1}")).await;
+    assert_eq!(result, RequestMessage::tool_result("call_1", "{\"q\":1}"));
+    assert_eq!(
+        serde_json::to_value(&result)?,
+        serde_json::json!({"role": "tool", "content": "{\"q\":1}", "tool_call_id": "call_1"})
+    );
+
+    let failed = tools.call(&tool_call("call_2", "fail", "{}")).await;
+    assert!(failed.content.as_text().starts_with("Error:"));
+    assert!(failed.content.as_text().contains("disk full"));
+
+    let unknown = tools.call(&tool_call("call_3", "missing", "{}")).await;
+    assert!(unknown.content.as_text().contains("Unknown tool 'missing'"));
+
+    let invalid = tools.call(&tool_call("call_4", "echo", "{not json")).await;
+    assert!(invalid.content.as_text().contains("Invalid JSON arguments"));
+
+    // A command exiting without reading large arguments is not a failure
+    let arguments = serde_json::json!({"text": "x".repeat(1 << 20)}).to_string();
+    let ignored = tools.call(&tool_call("call_5", "ignore", &arguments)).await;
+    assert_eq!(ignored.content, "");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_tool_loop() -> Result<()> {
+    let server = mock_server(vec![
+        http_response("200 OK", &[], &tool_call_body("call_1", "echo", "{\"q\":\"rust\"}")),
+        http_response("200 OK", &[], &answer_body("Rust is a language.")),
+    ])
+    .await;
+    let tools = ToolSet::new(vec![local_tool("echo", "cat")])?;
+    let client = InvokeClient::builder()
+        .endpoint(&server.url)
+        .api_token("secret")
+        .model("test_model")
+        .tokens(100)
+        .tools(tools.definitions())
+        .tool_choice(tool_choice("auto"))
+        .build()?;
+
+    let messages = vec![RequestMessage::new("user", "What is Rust?")];
+    let outcome = run_tool_loop(&client, messages, &tools, 4).await?;
+
+    assert_eq!(outcome.iterations, 2);
+    assert_eq!(outcome.response.choices[0].message.content, "Rust is a language.");
+    assert_eq!(outcome.response.usage.total_tokens, 20);
+    assert_eq!(outcome.messages.len(), 3);
+    assert_eq!(
+        outcome.messages[2],
+        RequestMessage::tool_result("call_1", "{\"q\":\"rust\"}")
+    );
+
+    let requests = server.requests.lock().expect("lock requests").clone();
+    let first: serde_json::Value = serde_json::from_str(&requests[0])?;
+    assert_eq!(first["tools"][0]["function"]["name"], "echo");
+    assert_eq!(first["tool_choice"], "auto");
+
+    let second: serde_json::Value = serde_json::from_str(&requests[1])?;
+    assert_eq!(second["messages"][1]["tool_calls"][0]["id"], "call_1");
+    assert_eq!(second["messages"][2]["role"], "tool");
+    assert_eq!(second["messages"][2]["tool_call_id"], "call_1");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_tool_loop_iteration_cap() -> Result<()> {
+    let server = mock_server(vec![
+        http_response("200 OK", &[], &tool_call_body("call_1", "echo", "{}")),
+        http_response("200 OK", &[], &tool_call_body("call_2", "echo", "{}")),
+    ])
+    .await;
+    let tools = ToolSet::new(vec![local_tool("echo", "cat")])?;
+    let client = InvokeClient::builder()
+        .endpoint(&server.url)
+        .api_token("secret")
+        .model("test_model")
+        .tokens(100)
+        .tools(tools.definitions())
+        .build()?;
+
+    let result = run_tool_loop(&client, vec![RequestMessage::new("user", "Loop")], &tools, 2).await;
+    assert!(result.is_err_and(|error| error.to_string().contains("after 2 requests")));
+
+    Ok(())
+}
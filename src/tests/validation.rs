@@ -0,0 +1,224 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+
+use super::server::{http_response, mock_server};
+use crate::{
+    InvokeClient, RequestMessage, read_schema_file,
+    validation::{SchemaMismatch, ValidationError, send_validated, validate, validate_reply},
+};
+
+/// Builds a chat completion response with the given content.
+fn answer_body(content: &str) -> String {
+    json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1,
+        "model": "test_model",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": content}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15}
+    })
+    .to_string()
+}
+
+/// Returns the pointers of the errors of a validation.
+fn pointers(schema: &Value, instance: &Value) -> Vec<String> {
+    validate(schema, instance)
+        .into_iter()
+        .map(|error| error.pointer)
+        .collect()
+}
+
+#[test]
+fn test_validate_example_schema() -> Result<()> {
+    let schema = read_schema_file("examples/schema.json")?;
+
+    let valid = r#"{"translated_text": "Bonjour", "source_language": "en", "target_language": "fr"}"#;
+    assert!(validate_reply(&schema, valid).is_empty());
+
+    let errors = validate_reply(
+        &schema,
+        r#"{"translated_text": 1, "target_language": "fr", "extra": true}"#,
+    );
+    assert_eq!(errors, vec![
+        ValidationError {
+            pointer: String::new(),
+            message: "missing required property \"source_language\"".to_owned(),
+        },
+        ValidationError {
+            pointer: "/extra".to_owned(),
+            message: "unexpected property".to_owned(),
+        },
+        ValidationError {
+            pointer: "/translated_text".to_owned(),
+            message: "expected string, found integer".to_owned(),
+        },
+    ]);
+
+    let errors = validate_reply(&schema, "Bonjour");
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().starts_with("(root): invalid JSON"));
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_keywords() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "age": {"type": "integer", "minimum": 0, "maximum": 150},
+            "ratio": {"type": "number", "exclusiveMaximum": 1, "multipleOf": 0.25},
+            "name": {"type": "string", "minLength": 2, "maxLength": 5, "pattern": "^[A-Z]"},
+            "mode": {"enum": ["fast", "slow"]},
+            "version": {"const": 2},
+            "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 2, "uniqueItems": true},
+            "note": {"type": ["string", "null"]},
+            "a/b": {"type": "boolean"}
+        }
+    });
+
+    let valid = json!({"age": 30.0, "ratio": 0.75, "name": "Ann", "mode": "fast", "version": 2.0, "note": null});
+    assert!(validate(&schema, &valid).is_empty());
+
+    let invalid = json!({
+        "age": -1,
+        "ratio": 1.0,
+        "name": "ann-marie",
+        "mode": "medium",
+        "version": 3,
+        "tags": ["a", 1, "a"],
+        "note": 5,
+        "a/b": "yes"
+    });
+    assert_eq!(pointers(&schema, &invalid), vec![
+        "/a~1b", "/age", "/mode", "/name", "/name", "/note", "/ratio", "/tags", "/tags/2", "/tags/1", "/version",
+    ]);
+
+    let errors = validate(&schema, &json!({"ratio": 0.3}));
+    assert_eq!(errors[0].to_string(), "/ratio: 0.3 is not a multiple of 0.25");
+}
+
+#[test]
+fn test_validate_combinators_and_refs() {
+    let schema = json!({
+        "$defs": {
+            "node": {
+                "type": "object",
+                "properties": {
+                    "value": {"anyOf": [{"type": "integer"}, {"type": "string", "minLength": 1}]},
+                    "children": {"type": "array", "items": {"$ref": "#/$defs/node"}}
+                },
+                "required": ["value"],
+                "additionalProperties": false
+            }
+        },
+        "$ref": "#/$defs/node"
+    });
+
+    let tree = json!({"value": 1, "children": [{"value": "a", "children": [{"value": 2}]}]});
+    assert!(validate(&schema, &tree).is_empty());
+
+    let tree = json!({"value": 1, "children": [{"value": "", "children": [{"label": 2}]}]});
+    assert_eq!(pointers(&schema, &tree), vec![
+        "/children/0/children/0",
+        "/children/0/children/0/label",
+        "/children/0/value",
+    ]);
+
+    let one_of = json!({"oneOf": [{"type": "number"}, {"type": "integer"}]});
+    assert!(validate(&one_of, &json!(1.5)).is_empty());
+    assert_eq!(
+        validate(&one_of, &json!(1))[0].message,
+        "value matches 2 schemas of oneOf instead of exactly one"
+    );
+    assert!(validate(&json!({"not": {"type": "null"}}), &json!(null)).len() == 1);
+    assert!(
+        validate(&json!({"$ref": "#/$defs/missing"}), &json!(1))[0]
+            .message
+            .contains("unresolved")
+    );
+
+    // A schema referencing itself without consuming the value stops
+    assert!(!validate(&json!({"$ref": "#"}), &json!(1)).is_empty());
+}
+
+#[tokio::test]
+async fn test_send_validated_retries() -> Result<()> {
+    let server = mock_server(vec![
+        http_response("200 OK", &[], &answer_body("Sure! Here it is.")),
+        http_response("200 OK", &[], &answer_body(r#"{"answer": "42"}"#)),
+        http_response("200 OK", &[], &answer_body(r#"{"answer": 42}"#)),
+    ])
+    .await;
+    let schema = json!({
+        "name": "answer",
+        "strict": true,
+        "schema": {
+            "type": "object",
+            "properties": {"answer": {"type": "integer"}},
+            "required": ["answer"],
+            "additionalProperties": false
+        }
+    });
+    let client = InvokeClient::builder()
+        .endpoint(&server.url)
+        .api_token("secret")
+        .model("test_model")
+        .tokens(100)
+        .schema(schema.clone())
+        .build()?;
+
+    let messages = vec![RequestMessage::new("user", "What is the answer?")];
+    let send = async |messages: Vec<RequestMessage>| Ok((client.send(messages.clone()).await?, messages));
+    let validated = send_validated(&schema, messages, 2, send).await?;
+
+    assert!(validated.errors.is_empty());
+    assert_eq!(validated.response.choices[0].message.content, r#"{"answer": 42}"#);
+    assert_eq!(validated.response.usage.total_tokens, 45);
+    assert_eq!(validated.messages.len(), 5);
+
+    let requests = server.requests.lock().expect("lock requests").clone();
+    let last: Value = serde_json::from_str(&requests[2])?;
+    assert_eq!(last["messages"][3]["role"], "assistant");
+    assert_eq!(last["messages"][3]["content"], r#"{"answer": "42"}"#);
+    assert_eq!(last["messages"][4]["role"], "user");
+    assert!(
+        last["messages"][4]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("/answer: expected integer, found string")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_send_validated_gives_up() -> Result<()> {
+    let server = mock_server(vec![
+        http_response("200 OK", &[], &answer_body("[]")),
+        http_response("200 OK", &[], &answer_body("[]")),
+    ])
+    .await;
+    let schema = json!({"schema": {"type": "object"}});
+    let client = InvokeClient::builder()
+        .endpoint(&server.url)
+        .api_token("secret")
+        .model("test_model")
+        .tokens(100)
+        .build()?;
+
+    let messages = vec![RequestMessage::new("user", "Answer.")];
+    let send = async |messages: Vec<RequestMessage>| Ok((client.send(messages.clone()).await?, messages));
+    let validated = send_validated(&schema, messages, 1, send).await?;
+
+    assert_eq!(server.requests.lock().expect("lock requests").len(), 2);
+    let mismatch = SchemaMismatch {
+        errors: validated.errors,
+    };
+    assert_eq!(
+        mismatch.to_string(),
+        "The reply does not match the schema:\n  (root): expected object, found array"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+use tiktoken_rs::{CoreBPE, cl100k_base_singleton, o200k_base_singleton};
+
+use crate::{RequestMessage, RequestPayload};
+
+/// Average number of characters of a token, for models without a known
+/// tokenizer.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens added by the chat format around every message.
+const TOKENS_PER_MESSAGE: usize = 3;
+
+/// Tokens priming the reply of the model.
+const TOKENS_PER_REPLY: usize = 3;
+
+/// Model name prefixes using the `o200k_base` encoding.
+const O200K_BASE_PREFIXES: &[&str] = &["gpt-5", "gpt-4.1", "gpt-4o", "chatgpt-4o", "gpt-oss", "o1", "o3", "o4"];
+
+/// Model name prefixes using the `cl100k_base` encoding.
+const CL100K_BASE_PREFIXES: &[&str] = &["gpt-4", "gpt-3.5", "gpt-35", "text-embedding-"];
+
+/// Counts the tokens of a text the way a model does, or estimates them.
+///
+/// `OpenAI` models are counted exactly with their byte pair encoding, as
+/// tiktoken does. Other models have tokenizers that are not available
+/// locally, so their counts are estimated from the length of the text (see
+/// [`CHARS_PER_TOKEN`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tokenizer {
+    /// The encoding of the GPT-4o, GPT-4.1, GPT-5 and o-series models.
+    O200kBase,
+    /// The encoding of the GPT-4 and GPT-3.5 models.
+    Cl100kBase,
+    /// An estimate from the number of characters.
+    Heuristic,
+}
+
+impl Tokenizer {
+    /// Returns the tokenizer of a model.
+    ///
+    /// An organization prefix (e.g. `openai/gpt-oss-120b`) and a provider
+    /// suffix (e.g. `gpt-oss-120b:cerebras`) are ignored.
+    ///
+    /// # Arguments
+    /// * `model` - The model identifier
+    pub fn for_model(model: &str) -> Self {
+        let model = base_model(model);
+        let starts_with = |prefixes: &[&str]| prefixes.iter().any(|prefix| model.starts_with(prefix));
+
+        if starts_with(O200K_BASE_PREFIXES) {
+            Self::O200kBase
+        } else if starts_with(CL100K_BASE_PREFIXES) {
+            Self::Cl100kBase
+        } else {
+            Self::Heuristic
+        }
+    }
+
+    /// Returns whether the counts are exact rather than estimated.
+    pub fn is_exact(self) -> bool {
+        self != Self::Heuristic
+    }
+
+    /// Returns the byte pair encoding of the tokenizer, loaded on first use.
+    pub fn bpe(self) -> Option<&'static CoreBPE> {
+        match self {
+            Self::O200kBase => Some(o200k_base_singleton()),
+            Self::Cl100kBase => Some(cl100k_base_singleton()),
+            Self::Heuristic => None,
+        }
+    }
+
+    /// Counts the tokens of a text.
+    ///
+    /// Special tokens such as `<|endoftext|>` are counted as plain text.
+    ///
+    /// # Arguments
+    /// * `text` - The text to count
+    pub fn count(self, text: &str) -> usize {
+        match self.bpe() {
+            Some(bpe) => bpe.encode_ordinary(text).len(),
+            None => text.chars().count().div_ceil(CHARS_PER_TOKEN),
+        }
+    }
+
+    /// Returns the byte offsets where the tokens of a text end.
+    ///
+    /// A character spread over several tokens ends with its last token, so
+    /// that every offset is a character boundary of the text.
+    ///
+    /// # Arguments
+    /// * `text` - The text to tokenize
+    pub fn token_ends(self, text: &str) -> Vec<usize> {
+        let mut ends = Vec::new();
+        let mut push = |mut end: usize| {
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            if ends.last() != Some(&end) {
+                ends.push(end);
+            }
+        };
+
+        match self.bpe() {
+            Some(bpe) => {
+                // The tokens of a character spread over several tokens only
+                // decode together
+                let mut end = 0;
+                let mut pending = Vec::new();
+                for token in bpe.encode_ordinary(text) {
+                    pending.push(token);
+                    if let Ok(decoded) = bpe.decode(pending.clone()) {
+                        end += decoded.len();
+                        push(end);
+                        pending.clear();
+                    }
+                }
+                if !pending.is_empty() {
+                    push(text.len());
+                }
+            },
+            None => {
+                let chars: Vec<_> = text.char_indices().map(|(index, _)| index).collect();
+                for start in chars.iter().step_by(CHARS_PER_TOKEN).skip(1) {
+                    push(*start);
+                }
+                if !text.is_empty() {
+                    push(text.len());
+                }
+            },
+        }
+
+        ends
+    }
+
+    /// Splits a text into pieces of at most `size` tokens, on token
+    /// boundaries.
+    ///
+    /// # Arguments
+    /// * `text` - The text to split
+    /// * `size` - Maximum number of tokens of a piece (at least 1)
+    pub fn split(self, text: &str, size: usize) -> Vec<&str> {
+        let mut start = 0;
+        self.token_ends(text)
+            .chunks(size.max(1))
+            .filter_map(|ends| {
+                let end = *ends.last()?;
+                let piece = &text[start..end];
+                start = end;
+                Some(piece)
+            })
+            .collect()
+    }
+
+    /// Returns the last `tokens` tokens of a text.
+    ///
+    /// # Arguments
+    /// * `text` - The text to take the end of
+    /// * `tokens` - Number of tokens to take
+    pub fn tail(self, text: &str, tokens: usize) -> &str {
+        if tokens == 0 {
+            return "";
+        }
+        let ends = self.token_ends(text);
+        match ends.len().checked_sub(tokens + 1) {
+            Some(index) => &text[ends[index]..],
+            None => text,
+        }
+    }
+
+    /// Counts the tokens of a message history, including the tokens the chat
+    /// format adds around every message and before the reply.
+    ///
+    /// Only the text of the messages and of their tool calls is counted, not
+    /// their images or audio.
+    ///
+    /// # Arguments
+    /// * `messages` - The messages to count
+    pub fn count_messages(self, messages: &[RequestMessage]) -> usize {
+        let tokens: usize = messages
+            .iter()
+            .map(|message| {
+                let tool_calls = message
+                    .tool_calls
+                    .iter()
+                    .flatten()
+                    .map(|call| self.count(&call.function.name) + self.count(&call.function.arguments))
+                    .sum::<usize>();
+                TOKENS_PER_MESSAGE + self.count(&message.role) + self.count(&message.content.as_text()) + tool_calls
+            })
+            .sum();
+
+        tokens + TOKENS_PER_REPLY
+    }
+
+    /// Counts the prompt tokens of a request: its messages, and the
+    /// definitions of its tools and of its structured output schema.
+    ///
+    /// # Arguments
+    /// * `payload` - The request payload
+    pub fn count_payload(self, payload: &RequestPayload<'_>) -> usize {
+        let json = |value: Result<String, serde_json::Error>| value.map_or(0, |json| self.count(&json));
+        let tools = payload
+            .tools
+            .as_ref()
+            .map_or(0, |tools| json(serde_json::to_string(tools)));
+        let schema = payload
+            .response_format
+            .as_ref()
+            .map_or(0, |format| json(serde_json::to_string(&format.json_schema)));
+
+        self.count_messages(&payload.messages) + tools + schema
+    }
+}
+
+impl fmt::Display for Tokenizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::O200kBase => f.write_str("o200k_base"),
+            Self::Cl100kBase => f.write_str("cl100k_base"),
+            Self::Heuristic => write!(f, "estimate ({CHARS_PER_TOKEN} characters per token)"),
+        }
+    }
+}
+
+/// Returns the name of a model without its organization prefix and provider
+/// suffix (e.g. `gpt-oss-120b` for `openai/gpt-oss-120b:cerebras`).
+///
+/// # Arguments
+/// * `model` - The model identifier
+pub fn base_model(model: &str) -> &str {
+    let model = model.rsplit_once('/').map_or(model, |(_, name)| name);
+    model.split_once(':').map_or(model, |(name, _)| name)
+}
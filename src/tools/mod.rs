@@ -0,0 +1,385 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::mcp::{McpServer, McpTool};
+use crate::schema::{ApiResponse, ToolCall, Usage};
+use crate::{InvokeClient, RequestMessage};
+
+/// Default maximum number of requests sent by the tool loop.
+pub const DEFAULT_MAX_TOOL_ITERATIONS: usize = 8;
+
+/// Default time limit for a tool command.
+pub const DEFAULT_TOOL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Maximum length of a function name accepted by the API.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// A function the model may call, as sent in the `tools` field of the
+/// request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tool {
+    pub r#type: String,
+    pub function: FunctionDefinition,
+}
+
+/// The description of a function sent to the model.
+///
+/// # Fields
+/// * `name` - Name of the function
+/// * `description` - What the function does, used by the model to decide when
+///   to call it
+/// * `parameters` - JSON schema of the arguments object
+/// * `strict` - Whether the arguments must follow the schema exactly
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub parameters: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+/// A function mapped to a local command.
+///
+/// Entries are declared in the tools file, a JSON array such as:
+///
+/// ```json
+/// [
+///   {
+///     "name": "read_file",
+///     "description": "Read a file of the repository",
+///     "parameters": {
+///       "type": "object",
+///       "properties": { "path": { "type": "string" } },
+///       "required": ["path"]
+///     },
+///     "command": ["python3", "tools/read_file.py"]
+///   }
+/// ]
+/// ```
+///
+/// # Fields
+/// * `name` - Name of the function
+/// * `description` - What the function does
+/// * `parameters` - JSON schema of the arguments object (an object without
+///   properties if not provided)
+/// * `strict` - Whether the arguments must follow the schema exactly
+/// * `command` - The program and its arguments, run without a shell; the call
+///   arguments are written as JSON to its stdin and its stdout is the result
+/// * `timeout` - Time limit in seconds (60 if not provided)
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LocalTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default = "empty_parameters")]
+    pub parameters: Value,
+    #[serde(default)]
+    pub strict: Option<bool>,
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+impl LocalTool {
+    /// Returns the function definition sent to the model.
+    pub fn definition(&self) -> Tool {
+        Tool {
+            r#type: "function".to_owned(),
+            function: FunctionDefinition {
+                name: self.name.clone(),
+                description: self.description.clone(),
+                parameters: self.parameters.clone(),
+                strict: self.strict,
+            },
+        }
+    }
+
+    /// Runs the command with the call arguments on its stdin.
+    ///
+    /// # Arguments
+    /// * `arguments` - The JSON-encoded arguments sent by the model
+    ///
+    /// # Returns
+    /// * `Ok(String)` - The standard output of the command
+    /// * `Err` - An error if the command could not be run, timed out or exited
+    ///   with a failure status
+    pub async fn run(&self, arguments: &str) -> Result<String> {
+        let Some((program, args)) = self.command.split_first() else {
+            bail!("Tool '{}' has no command", self.name);
+        };
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to run '{program}'"))?;
+        // Commands that ignore their arguments may exit before reading them
+        if let Some(mut stdin) = child.stdin.take()
+            && let Err(error) = stdin.write_all(arguments.as_bytes()).await
+            && error.kind() != ErrorKind::BrokenPipe
+        {
+            return Err(error.into());
+        }
+
+        let timeout = self.timeout.map_or(DEFAULT_TOOL_TIMEOUT, Duration::from_secs);
+        let output = tokio::time::timeout(timeout, child.wait_with_output())
+            .await
+            .with_context(|| format!("'{program}' did not finish within {}s", timeout.as_secs()))??;
+        if !output.status.success() {
+            bail!(
+                "'{program}' failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Returns the schema of a function without arguments.
+fn empty_parameters() -> Value {
+    serde_json::json!({"type": "object", "properties": {}})
+}
+
+/// The tools the model may call: local commands and tools of MCP servers.
+#[derive(Debug, Clone, Default)]
+pub struct ToolSet {
+    tools: Vec<LocalTool>,
+    mcp_tools: Vec<(Arc<McpServer>, McpTool)>,
+}
+
+impl ToolSet {
+    /// Creates a tool set, checking the names and commands of the tools.
+    ///
+    /// # Arguments
+    /// * `tools` - The tools
+    ///
+    /// # Returns
+    /// * `Ok(ToolSet)` - The tool set
+    /// * `Err` - An error if a name is invalid or duplicated, or a command is
+    ///   empty
+    pub fn new(tools: Vec<LocalTool>) -> Result<Self> {
+        let mut names = HashSet::new();
+        for tool in &tools {
+            check_name(&tool.name)?;
+            if !names.insert(tool.name.as_str()) {
+                bail!("Tool '{}' is declared more than once", tool.name);
+            }
+            if tool.command.is_empty() {
+                bail!("Tool '{}' has no command", tool.name);
+            }
+        }
+
+        Ok(Self {
+            tools,
+            mcp_tools: Vec::new(),
+        })
+    }
+
+    /// Adds the tools of an MCP server, listed with `tools/list`.
+    ///
+    /// # Arguments
+    /// * `server` - The initialized server
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Number of tools added
+    /// * `Err` - An error if the tools could not be listed, or a tool name is
+    ///   invalid or already used by another tool
+    pub async fn add_mcp_server(&mut self, server: McpServer) -> Result<usize> {
+        let server = Arc::new(server);
+        let tools = server.list_tools().await?;
+        for tool in &tools {
+            check_name(&tool.name).with_context(|| format!("MCP server '{}'", server.name()))?;
+            if self.contains(&tool.name) {
+                bail!(
+                    "Tool '{}' of MCP server '{}' is already declared",
+                    tool.name,
+                    server.name()
+                );
+            }
+        }
+
+        let count = tools.len();
+        self.mcp_tools
+            .extend(tools.into_iter().map(|tool| (Arc::clone(&server), tool)));
+
+        Ok(count)
+    }
+
+    /// Loads a tools file.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the JSON array of [`LocalTool`]s
+    ///
+    /// # Returns
+    /// * `Ok(ToolSet)` - The declared tools
+    /// * `Err` - An error if the file could not be read or parsed, or declares
+    ///   invalid tools
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read tools file {}", path.display()))?;
+        let tools =
+            serde_json::from_str(&content).with_context(|| format!("Failed to parse tools file {}", path.display()))?;
+
+        ToolSet::new(tools)
+    }
+
+    /// Returns the function definitions sent to the model.
+    pub fn definitions(&self) -> Vec<Tool> {
+        self.tools
+            .iter()
+            .map(LocalTool::definition)
+            .chain(self.mcp_tools.iter().map(|(_, tool)| tool.definition()))
+            .collect()
+    }
+
+    /// Returns the local tool with the given name.
+    pub fn get(&self, name: &str) -> Option<&LocalTool> {
+        self.tools.iter().find(|tool| tool.name == name)
+    }
+
+    /// Returns whether a local or MCP tool has the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some() || self.mcp_tools.iter().any(|(_, tool)| tool.name == name)
+    }
+
+    /// Runs a tool call and wraps its result into a "tool" message.
+    ///
+    /// Local tools run their command, MCP tools are called with `tools/call`
+    /// on their server. Failures (an unknown tool, invalid arguments or a
+    /// failed call) are reported to the model in the message rather than
+    /// returned, so that it can recover from them.
+    ///
+    /// # Arguments
+    /// * `call` - The call requested by the model
+    pub async fn call(&self, call: &ToolCall) -> RequestMessage {
+        let name = &call.function.name;
+        let result = match serde_json::from_str::<Value>(&call.function.arguments) {
+            Err(error) => Err(anyhow::anyhow!("Invalid JSON arguments: {error}")),
+            Ok(arguments) => {
+                match (
+                    self.get(name),
+                    self.mcp_tools.iter().find(|(_, tool)| &tool.name == name),
+                ) {
+                    (Some(tool), _) => tool.run(&call.function.arguments).await,
+                    (None, Some((server, _))) => server.call_tool(name, arguments).await,
+                    (None, None) => Err(anyhow::anyhow!("Unknown tool '{name}'")),
+                }
+            },
+        };
+
+        let content = result.unwrap_or_else(|error| {
+            warn!("Tool call {} failed: {error:#}", call.function.name);
+            format!("Error: {error:#}")
+        });
+
+        RequestMessage::tool_result(&call.id, content)
+    }
+}
+
+/// Checks that a tool name is accepted by the API.
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        bail!("Invalid tool name '{name}': use 1 to {MAX_NAME_LENGTH} letters, digits, '_' or '-'");
+    }
+
+    Ok(())
+}
+
+/// Parses the `--tool-choice` value into the `tool_choice` request field.
+///
+/// # Arguments
+/// * `value` - `"auto"`, `"none"`, `"required"` or the name of a function
+pub fn tool_choice(value: &str) -> Value {
+    match value {
+        "auto" | "none" | "required" => Value::String(value.to_owned()),
+        name => serde_json::json!({"type": "function", "function": {"name": name}}),
+    }
+}
+
+/// The result of a tool loop.
+///
+/// # Fields
+/// * `response` - The final response, with the usage summed over every request
+///   of the loop
+/// * `messages` - The messages sent with the last request, including the tool
+///   calls and their results
+/// * `iterations` - Number of requests sent
+#[derive(Debug, Clone)]
+pub struct ToolLoopOutcome {
+    pub response: ApiResponse,
+    pub messages: Vec<RequestMessage>,
+    pub iterations: usize,
+}
+
+/// Queries the endpoint until the model answers without calling a tool.
+///
+/// Whenever the first choice of a response requests tool calls, the calls are
+/// run in order, their results are appended as "tool" messages after the
+/// assistant message, and the conversation is sent again.
+///
+/// # Arguments
+/// * `client` - The client, built with the definitions of `tools`
+/// * `messages` - The message history to send
+/// * `tools` - The tools the model may call
+/// * `max_iterations` - Maximum number of requests to send
+///
+/// # Returns
+/// * `Ok(ToolLoopOutcome)` - The final response and the whole conversation
+/// * `Err` - An error if a request failed or the model still calls tools after
+///   `max_iterations` requests
+pub async fn run_tool_loop(
+    client: &InvokeClient,
+    mut messages: Vec<RequestMessage>,
+    tools: &ToolSet,
+    max_iterations: usize,
+) -> Result<ToolLoopOutcome> {
+    let mut usage = Usage::default();
+    for iteration in 1..=max_iterations {
+        let mut response = client.send(messages.clone()).await?;
+        usage.add(&response.usage);
+
+        let calls = response
+            .choices
+            .first()
+            .and_then(|choice| choice.message.tool_calls.clone())
+            .unwrap_or_default();
+        if calls.is_empty() {
+            response.usage = usage;
+            return Ok(ToolLoopOutcome {
+                response,
+                messages,
+                iterations: iteration,
+            });
+        }
+
+        messages.push(RequestMessage::from(&response.choices[0].message));
+        for call in &calls {
+            info!("Running tool {} ({})", call.function.name, call.id);
+            messages.push(tools.call(call).await);
+        }
+    }
+
+    bail!("The model was still calling tools after {max_iterations} requests")
+}
@@ -0,0 +1,521 @@
+use anyhow::Result;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::fmt;
+use tracing::warn;
+
+use crate::RequestMessage;
+use crate::schema::{ApiResponse, Usage};
+
+/// Maximum depth of nested `$ref` resolutions, guarding against schemas
+/// referencing themselves without consuming the instance.
+const MAX_REF_DEPTH: usize = 64;
+
+/// A place where a value does not match its schema.
+///
+/// # Fields
+/// * `pointer` - JSON pointer (RFC 6901) to the offending value, empty for the
+///   root value
+/// * `message` - What is wrong with the value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pointer.is_empty() {
+            write!(f, "(root): {}", self.message)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.message)
+        }
+    }
+}
+
+/// Error returned when a reply still does not match the schema.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("The reply does not match the schema:\n{}", format_errors(.errors))]
+pub struct SchemaMismatch {
+    pub errors: Vec<ValidationError>,
+}
+
+/// Lists validation errors, one per line.
+fn format_errors(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("  {error}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the schema the content of a reply must match.
+///
+/// Schema files hold the `json_schema` response format, with the schema itself
+/// under `schema`; a file holding a bare schema is used as is.
+///
+/// # Arguments
+/// * `json_schema` - The schema file as loaded by [`crate::read_schema_file`]
+pub fn response_schema(json_schema: &Value) -> &Value {
+    json_schema.get("schema").unwrap_or(json_schema)
+}
+
+/// Validates a value against a JSON schema.
+///
+/// The validation keywords of JSON Schema draft 2020-12 that structured output
+/// schemas use are supported: `type`, `enum`, `const`, the numeric, string,
+/// array and object constraints, `anyOf`, `oneOf`, `allOf`, `not` and local
+/// `$ref`s (`#/$defs/...`). Annotations such as `format` or `description` are
+/// ignored.
+///
+/// # Arguments
+/// * `schema` - The JSON schema
+/// * `instance` - The value to validate
+///
+/// # Returns
+/// * `Vec<ValidationError>` - The mismatches, empty if the value is valid
+pub fn validate(schema: &Value, instance: &Value) -> Vec<ValidationError> {
+    let mut validator = Validator {
+        root: schema,
+        errors: Vec::new(),
+    };
+    validator.check(schema, instance, "", 0);
+
+    validator.errors
+}
+
+/// Parses the content of a reply and validates it against a schema file.
+///
+/// # Arguments
+/// * `json_schema` - The schema file as loaded by [`crate::read_schema_file`]
+/// * `content` - The content of the reply
+///
+/// # Returns
+/// * `Vec<ValidationError>` - The mismatches, or a single error at the root if
+///   the content is not JSON; empty if the content is valid
+pub fn validate_reply(json_schema: &Value, content: &str) -> Vec<ValidationError> {
+    match serde_json::from_str::<Value>(content) {
+        Ok(instance) => validate(response_schema(json_schema), &instance),
+        Err(error) => {
+            vec![ValidationError {
+                pointer: String::new(),
+                message: format!("invalid JSON ({error})"),
+            }]
+        },
+    }
+}
+
+/// Builds the message asking the model to correct a reply.
+///
+/// # Arguments
+/// * `errors` - The validation errors of the reply
+pub fn correction_message(errors: &[ValidationError]) -> RequestMessage {
+    RequestMessage::new(
+        "user",
+        format!(
+            "Your reply does not match the required JSON schema:\n{}\nReply again with only the corrected JSON.",
+            format_errors(errors)
+        ),
+    )
+}
+
+/// The outcome of [`send_validated`].
+///
+/// # Fields
+/// * `response` - The last response, with the usage of every attempt
+/// * `messages` - The conversation leading to the last response, including the
+///   rejected replies and the corrections asked for
+/// * `errors` - The validation errors of the last response, empty if it matches
+///   the schema
+#[derive(Debug, Clone)]
+pub struct ValidatedResponse {
+    pub response: ApiResponse,
+    pub messages: Vec<RequestMessage>,
+    pub errors: Vec<ValidationError>,
+}
+
+/// Sends a conversation until the reply matches a schema.
+///
+/// When the content of the first choice does not match, the reply and a user
+/// message listing the validation errors are appended to the conversation,
+/// which is sent again, at most `retries` times.
+///
+/// # Arguments
+/// * `json_schema` - The schema file as loaded by [`crate::read_schema_file`]
+/// * `messages` - The message history to send
+/// * `retries` - Maximum number of corrections to ask for
+/// * `send` - Sends a conversation and returns the response with the
+///   conversation leading to it (e.g. [`crate::tools::run_tool_loop`])
+///
+/// # Returns
+/// * `Ok(ValidatedResponse)` - The last response and its validation errors
+/// * `Err` - An error if a request failed
+pub async fn send_validated(
+    json_schema: &Value,
+    mut messages: Vec<RequestMessage>,
+    retries: usize,
+    mut send: impl AsyncFnMut(Vec<RequestMessage>) -> Result<(ApiResponse, Vec<RequestMessage>)>,
+) -> Result<ValidatedResponse> {
+    let mut usage = Usage::default();
+    let mut attempt = 0;
+    loop {
+        let (mut response, sent) = send(messages).await?;
+        usage.add(&response.usage);
+        messages = sent;
+
+        let errors = match response.choices.first() {
+            Some(choice) => validate_reply(json_schema, &choice.message.content),
+            None => Vec::new(),
+        };
+        if errors.is_empty() || attempt == retries {
+            response.usage = usage;
+            return Ok(ValidatedResponse {
+                response,
+                messages,
+                errors,
+            });
+        }
+
+        attempt += 1;
+        warn!(
+            "The reply does not match the schema ({} errors), asking for a correction ({attempt} of {retries})",
+            errors.len()
+        );
+        messages.push(RequestMessage::from(&response.choices[0].message));
+        messages.push(correction_message(&errors));
+    }
+}
+
+/// Walks a schema and an instance together, collecting the mismatches.
+struct Validator<'a> {
+    root: &'a Value,
+    errors: Vec<ValidationError>,
+}
+
+impl<'a> Validator<'a> {
+    /// Records a mismatch.
+    fn error(&mut self, pointer: &str, message: impl Into<String>) {
+        self.errors.push(ValidationError {
+            pointer: pointer.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    /// Returns whether an instance matches a schema, without recording the
+    /// mismatches.
+    fn matches(&self, schema: &'a Value, instance: &Value, depth: usize) -> bool {
+        let mut validator = Validator {
+            root: self.root,
+            errors: Vec::new(),
+        };
+        validator.check(schema, instance, "", depth);
+
+        validator.errors.is_empty()
+    }
+
+    /// Checks an instance against a schema.
+    fn check(&mut self, schema: &'a Value, instance: &Value, pointer: &str, depth: usize) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => return self.error(pointer, "no value is allowed here"),
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match self.resolve(reference) {
+                Some(_) if depth >= MAX_REF_DEPTH => self.error(pointer, format!("$ref {reference} nests too deeply")),
+                Some(target) => self.check(target, instance, pointer, depth + 1),
+                None => self.error(pointer, format!("unresolved $ref {reference}")),
+            }
+        }
+
+        if let Some(types) = schema.get("type")
+            && !type_names(types).any(|name| has_type(instance, name))
+        {
+            let expected = type_names(types).collect::<Vec<_>>().join(" or ");
+            return self.error(pointer, format!("expected {expected}, found {}", type_name(instance)));
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array)
+            && !values.iter().any(|value| json_equal(value, instance))
+        {
+            self.error(
+                pointer,
+                format!("{instance} is not one of {}", Value::from(values.clone())),
+            );
+        }
+        if let Some(value) = schema.get("const")
+            && !json_equal(value, instance)
+        {
+            self.error(pointer, format!("expected {value}, found {instance}"));
+        }
+
+        match instance {
+            Value::Number(number) => self.check_number(schema, number.as_f64().unwrap_or_default(), pointer),
+            Value::String(string) => self.check_string(schema, string, pointer),
+            Value::Array(items) => self.check_array(schema, items, pointer, depth),
+            Value::Object(object) => self.check_object(schema, object, pointer, depth),
+            _ => {},
+        }
+
+        self.check_combinators(schema, instance, pointer, depth);
+    }
+
+    /// Checks the numeric constraints.
+    fn check_number(&mut self, schema: &Map<String, Value>, number: f64, pointer: &str) {
+        let limit = |keyword| schema.get(keyword).and_then(Value::as_f64);
+        if let Some(minimum) = limit("minimum")
+            && number < minimum
+        {
+            self.error(pointer, format!("{number} is less than {minimum}"));
+        }
+        if let Some(maximum) = limit("maximum")
+            && number > maximum
+        {
+            self.error(pointer, format!("{number} is greater than {maximum}"));
+        }
+        if let Some(minimum) = limit("exclusiveMinimum")
+            && number <= minimum
+        {
+            self.error(pointer, format!("{number} is not greater than {minimum}"));
+        }
+        if let Some(maximum) = limit("exclusiveMaximum")
+            && number >= maximum
+        {
+            self.error(pointer, format!("{number} is not less than {maximum}"));
+        }
+        if let Some(divisor) = limit("multipleOf")
+            && divisor > 0.0
+            && (number / divisor).fract().abs() > f64::EPSILON
+        {
+            self.error(pointer, format!("{number} is not a multiple of {divisor}"));
+        }
+    }
+
+    /// Checks the string constraints.
+    fn check_string(&mut self, schema: &Map<String, Value>, string: &str, pointer: &str) {
+        let length = string.chars().count();
+        if let Some(min_length) = schema.get("minLength").and_then(Value::as_u64)
+            && (length as u64) < min_length
+        {
+            self.error(pointer, format!("string is shorter than {min_length} characters"));
+        }
+        if let Some(max_length) = schema.get("maxLength").and_then(Value::as_u64)
+            && (length as u64) > max_length
+        {
+            self.error(pointer, format!("string is longer than {max_length} characters"));
+        }
+        if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+            match Regex::new(pattern) {
+                Ok(regex) if !regex.is_match(string) => {
+                    self.error(pointer, format!("string does not match the pattern {pattern}"));
+                },
+                Ok(_) => {},
+                Err(_) => self.error(pointer, format!("invalid pattern {pattern} in the schema")),
+            }
+        }
+    }
+
+    /// Checks the array constraints and the items.
+    fn check_array(&mut self, schema: &'a Map<String, Value>, items: &[Value], pointer: &str, depth: usize) {
+        if let Some(min_items) = schema.get("minItems").and_then(Value::as_u64)
+            && (items.len() as u64) < min_items
+        {
+            self.error(pointer, format!("array has fewer than {min_items} items"));
+        }
+        if let Some(max_items) = schema.get("maxItems").and_then(Value::as_u64)
+            && (items.len() as u64) > max_items
+        {
+            self.error(pointer, format!("array has more than {max_items} items"));
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            for (index, item) in items.iter().enumerate() {
+                if items[..index].iter().any(|previous| json_equal(previous, item)) {
+                    self.error(&format!("{pointer}/{index}"), "duplicate item");
+                }
+            }
+        }
+
+        // Items past the prefix (older drafts use an array of "items" for it)
+        // match "items"
+        let (prefix, rest) = match (schema.get("prefixItems"), schema.get("items")) {
+            (Some(Value::Array(prefix)), rest) => (prefix.as_slice(), rest),
+            (None, Some(Value::Array(prefix))) => (prefix.as_slice(), None),
+            (_, rest) => (&[][..], rest),
+        };
+        for (index, item) in items.iter().enumerate() {
+            let item_schema = prefix.get(index).or(rest);
+            if let Some(item_schema) = item_schema {
+                self.check(item_schema, item, &format!("{pointer}/{index}"), depth);
+            }
+        }
+
+        if let Some(contains) = schema.get("contains")
+            && !items.iter().any(|item| self.matches(contains, item, depth))
+        {
+            self.error(pointer, "no item matches the contains schema");
+        }
+    }
+
+    /// Checks the object constraints and the properties.
+    fn check_object(
+        &mut self,
+        schema: &'a Map<String, Value>,
+        object: &Map<String, Value>,
+        pointer: &str,
+        depth: usize,
+    ) {
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    self.error(pointer, format!("missing required property \"{name}\""));
+                }
+            }
+        }
+        if let Some(min_properties) = schema.get("minProperties").and_then(Value::as_u64)
+            && (object.len() as u64) < min_properties
+        {
+            self.error(pointer, format!("object has fewer than {min_properties} properties"));
+        }
+        if let Some(max_properties) = schema.get("maxProperties").and_then(Value::as_u64)
+            && (object.len() as u64) > max_properties
+        {
+            self.error(pointer, format!("object has more than {max_properties} properties"));
+        }
+
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let patterns = schema
+            .get("patternProperties")
+            .and_then(Value::as_object)
+            .map(|patterns| {
+                patterns
+                    .iter()
+                    .filter_map(|(pattern, schema)| Some((Regex::new(pattern).ok()?, schema)))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for (name, value) in object {
+            let property_pointer = format!("{pointer}/{}", escape_pointer(name));
+            let mut declared = false;
+            if let Some(property_schema) = properties.and_then(|properties| properties.get(name)) {
+                declared = true;
+                self.check(property_schema, value, &property_pointer, depth);
+            }
+            for (regex, property_schema) in &patterns {
+                if regex.is_match(name) {
+                    declared = true;
+                    self.check(property_schema, value, &property_pointer, depth);
+                }
+            }
+            if declared {
+                continue;
+            }
+            match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => self.error(&property_pointer, "unexpected property"),
+                Some(additional) => self.check(additional, value, &property_pointer, depth),
+                None => {},
+            }
+        }
+    }
+
+    /// Checks `allOf`, `anyOf`, `oneOf` and `not`.
+    fn check_combinators(&mut self, schema: &'a Map<String, Value>, instance: &Value, pointer: &str, depth: usize) {
+        if let Some(schemas) = schema.get("allOf").and_then(Value::as_array) {
+            for sub_schema in schemas {
+                self.check(sub_schema, instance, pointer, depth);
+            }
+        }
+        if let Some(schemas) = schema.get("anyOf").and_then(Value::as_array)
+            && !schemas
+                .iter()
+                .any(|sub_schema| self.matches(sub_schema, instance, depth))
+        {
+            self.error(pointer, "value does not match any schema of anyOf");
+        }
+        if let Some(schemas) = schema.get("oneOf").and_then(Value::as_array) {
+            let count = schemas
+                .iter()
+                .filter(|sub_schema| self.matches(sub_schema, instance, depth))
+                .count();
+            if count != 1 {
+                self.error(
+                    pointer,
+                    format!("value matches {count} schemas of oneOf instead of exactly one"),
+                );
+            }
+        }
+        if let Some(sub_schema) = schema.get("not")
+            && self.matches(sub_schema, instance, depth)
+        {
+            self.error(pointer, "value matches the schema of not");
+        }
+    }
+
+    /// Resolves a local reference (`#` or `#/<pointer>`) against the root
+    /// schema.
+    fn resolve(&self, reference: &str) -> Option<&'a Value> {
+        let pointer = reference.strip_prefix('#')?;
+        if pointer.is_empty() {
+            return Some(self.root);
+        }
+
+        self.root.pointer(pointer)
+    }
+}
+
+/// Returns the type names of a `type` keyword, a name or a list of names.
+fn type_names(types: &Value) -> impl Iterator<Item=&str> {
+    let names = match types {
+        Value::Array(names) => names.as_slice(),
+        name => std::slice::from_ref(name),
+    };
+
+    names.iter().filter_map(Value::as_str)
+}
+
+/// Returns whether a value has a JSON Schema type.
+fn has_type(instance: &Value, name: &str) -> bool {
+    match name {
+        "integer" => {
+            instance.is_i64() || instance.is_u64() || instance.as_f64().is_some_and(|number| number.fract() == 0.0)
+        },
+        "number" => instance.is_number(),
+        name => type_name(instance) == name,
+    }
+}
+
+/// Returns the JSON Schema type of a value.
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Compares two values, numbers being equal when their values are (`1` and
+/// `1.0`).
+fn json_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64() == right.as_f64(),
+        (Value::Array(left), Value::Array(right)) => {
+            left.len() == right.len() && left.iter().zip(right).all(|(left, right)| json_equal(left, right))
+        },
+        (Value::Object(left), Value::Object(right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .all(|(key, value)| right.get(key).is_some_and(|other| json_equal(value, other)))
+        },
+        (left, right) => left == right,
+    }
+}
+
+/// Escapes a property name as a JSON pointer token.
+pub(crate) fn escape_pointer(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}